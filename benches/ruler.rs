@@ -0,0 +1,169 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::io::Write;
+use tivilsta::{EndsStrategy, Pipeline, Ruler};
+
+/// Generates a set of rules that share the same first characters - which is
+/// the worst case of a prefix based index.
+fn rules(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|index| format!("google{}.example.org", index))
+        .collect()
+}
+
+/// Generates a set of `ALL ` rules that share the same extension.
+fn all_rules(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|index| format!("ALL .google{}.example.org", index))
+        .collect()
+}
+
+/// Generates a set of subjects where only half of them are whitelisted.
+fn subjects(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|index| {
+            if index % 2 == 0 {
+                format!("google{}.example.org", index)
+            } else {
+                format!("googleads{}.example.com", index)
+            }
+        })
+        .collect()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let rules = rules(10_000);
+
+    c.bench_function("parse 10k plain rules", |b| {
+        b.iter(|| {
+            let mut ruler = Ruler::new(true);
+
+            ruler.parse_vec(black_box(&rules));
+        })
+    });
+}
+
+fn bench_load(c: &mut Criterion) {
+    let mut whitelist = tempfile::NamedTempFile::new().unwrap();
+
+    for rule in rules(100_000) {
+        writeln!(whitelist, "{}", rule).unwrap();
+    }
+
+    let path = whitelist.path().to_str().unwrap().to_string();
+
+    c.bench_function("load 100k plain rules through the pipeline", |b| {
+        b.iter(|| {
+            Pipeline::new(Ruler::new(true))
+                .whitelist(black_box(&path))
+                .load()
+                .unwrap();
+        })
+    });
+}
+
+fn bench_is_whitelisted(c: &mut Criterion) {
+    let mut ruler = Ruler::new(true);
+    let subjects = subjects(10_000);
+
+    ruler.parse_vec(&rules(10_000));
+
+    c.bench_function("is_whitelisted 10k subjects", |b| {
+        b.iter(|| {
+            for subject in &subjects {
+                black_box(ruler.is_whitelisted(black_box(subject)));
+            }
+        })
+    });
+}
+
+fn bench_is_whitelisted_domain(c: &mut Criterion) {
+    let mut ruler = Ruler::new(true);
+    let subjects = subjects(10_000);
+
+    ruler.parse_vec(&rules(10_000));
+
+    c.bench_function("is_whitelisted_domain 10k subjects", |b| {
+        b.iter(|| {
+            for subject in &subjects {
+                black_box(ruler.is_whitelisted_domain(black_box(subject)));
+            }
+        })
+    });
+}
+
+fn bench_ends_strategies(c: &mut Criterion) {
+    // Subdomains - so that the subjects are not caught by the strict rules.
+    let subjects: Vec<String> = subjects(10_000)
+        .iter()
+        .map(|subject| format!("api.{}", subject))
+        .collect();
+
+    for (name, strategy) in [
+        ("buckets", EndsStrategy::Buckets),
+        ("automaton", EndsStrategy::Automaton),
+    ] {
+        let mut ruler = Ruler::new(true).with_ends_strategy(strategy);
+
+        ruler.parse_vec(&all_rules(10_000));
+
+        c.bench_function(&format!("is_whitelisted 10k ALL rules ({})", name), |b| {
+            b.iter(|| {
+                for subject in &subjects {
+                    black_box(ruler.is_whitelisted(black_box(subject)));
+                }
+            })
+        });
+    }
+}
+
+fn bench_frozen(c: &mut Criterion) {
+    let subjects: Vec<String> = subjects(10_000)
+        .iter()
+        .map(|subject| format!("api.{}", subject))
+        .collect();
+    let mut ruler = Ruler::new(true);
+
+    ruler.parse_vec(&rules(10_000));
+    ruler.parse_vec(&all_rules(10_000));
+
+    let frozen = ruler.freeze();
+
+    c.bench_function("is_whitelisted 10k subjects (frozen)", |b| {
+        b.iter(|| {
+            for subject in &subjects {
+                black_box(frozen.is_whitelisted(black_box(subject)));
+            }
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_parse,
+    bench_load,
+    bench_is_whitelisted,
+    bench_is_whitelisted_domain,
+    bench_ends_strategies,
+    bench_frozen
+);
+criterion_main!(benches);
@@ -0,0 +1,200 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use aho_corasick::{AhoCorasick, Anchored, Input, MatchKind, StartKind};
+
+/// The rules of the `ALL ` and `SUB ` flags - compiled into a single
+/// Aho-Corasick automaton.
+///
+/// The records and the subjects are reversed, so that a record matching the end
+/// of a subject becomes a pattern matching the start of the reversed subject -
+/// which is looked up in a single anchored pass.
+#[derive(Debug)]
+pub(crate) struct EndsAutomaton {
+    automaton: AhoCorasick,
+    /// The records - indexed by their pattern identifier.
+    records: Vec<String>,
+}
+
+impl EndsAutomaton {
+    /// Compiles the given records into a new automaton.
+    pub(crate) fn new<'a>(records: impl Iterator<Item = &'a String>) -> EndsAutomaton {
+        let records: Vec<String> = records.cloned().collect();
+        let automaton = AhoCorasick::builder()
+            .start_kind(StartKind::Anchored)
+            .build(records.iter().map(|record| Self::reverse(record)))
+            .expect("Couldn't compile the ends automaton.");
+
+        EndsAutomaton { automaton, records }
+    }
+
+    fn reverse(element: &str) -> Vec<u8> {
+        element.bytes().rev().collect()
+    }
+
+    /// Provides the first record - among the `usable` ones - that matches the
+    /// end of the given `subject`.
+    pub(crate) fn find(&self, subject: &str, usable: impl Fn(&String) -> bool) -> Option<&String> {
+        let reversed = Self::reverse(subject);
+        let found = self
+            .automaton
+            .find(Input::new(&reversed).anchored(Anchored::Yes))
+            .map(|found| &self.records[found.pattern().as_usize()])?;
+
+        if usable(found) {
+            return Some(found);
+        }
+
+        // The automaton reports a single record - the other ones are checked
+        // one by one.
+        self.records
+            .iter()
+            .find(|record| usable(record) && subject.ends_with(record.as_str()))
+    }
+}
+
+/// The rules of the `KEY ` flag - compiled into a single Aho-Corasick
+/// automaton.
+///
+/// Unlike an alternation of regular expressions, a subject is looked up in a
+/// single pass - whatever the number of keywords.
+#[derive(Debug, Clone)]
+pub(crate) struct KeywordAutomaton {
+    automaton: AhoCorasick,
+    /// The records - indexed by their pattern identifier.
+    records: Vec<String>,
+}
+
+impl KeywordAutomaton {
+    /// Compiles the given records into a new automaton.
+    pub(crate) fn new<'a>(records: impl Iterator<Item = &'a String>) -> KeywordAutomaton {
+        let records: Vec<String> = records.cloned().collect();
+        let automaton = AhoCorasick::builder()
+            .match_kind(MatchKind::LeftmostFirst)
+            .build(&records)
+            .expect("Couldn't compile the keyword automaton.");
+
+        KeywordAutomaton { automaton, records }
+    }
+
+    /// Provides the first record - among the `usable` ones - found in the
+    /// given `subject`.
+    pub(crate) fn find(&self, subject: &str, usable: impl Fn(&String) -> bool) -> Option<&String> {
+        let found = self
+            .automaton
+            .find(subject)
+            .map(|found| &self.records[found.pattern().as_usize()])?;
+
+        if usable(found) {
+            return Some(found);
+        }
+
+        // The automaton reports a single record - the other ones are checked
+        // one by one.
+        self.records
+            .iter()
+            .find(|record| usable(record) && subject.contains(record.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let records = [
+            String::from(".example.org"),
+            String::from(".co.uk"),
+            String::from("ample.net"),
+            String::from(".bücher.de"),
+        ];
+        let automaton = EndsAutomaton::new(records.iter());
+
+        assert_eq!(
+            automaton.find("api.example.org", |_| true),
+            Some(&String::from(".example.org"))
+        );
+        assert_eq!(
+            automaton.find("example.co.uk", |_| true),
+            Some(&String::from(".co.uk"))
+        );
+        assert_eq!(
+            automaton.find("example.net", |_| true),
+            Some(&String::from("ample.net"))
+        );
+        assert_eq!(
+            automaton.find("www.bücher.de", |_| true),
+            Some(&String::from(".bücher.de"))
+        );
+        assert_eq!(automaton.find("example.org", |_| true), None);
+        assert_eq!(automaton.find("example.org.evil", |_| true), None);
+        assert_eq!(
+            automaton.find("api.example.co.uk", |record| record != ".co.uk"),
+            None
+        );
+        assert_eq!(
+            automaton.find("api.example.org", |record| record != ".co.uk"),
+            Some(&String::from(".example.org"))
+        );
+        assert_eq!(automaton.find("", |_| true), None);
+
+        let automaton = EndsAutomaton::new([].iter());
+
+        assert_eq!(automaton.find("example.org", |_| true), None);
+    }
+
+    #[test]
+    fn test_find_keyword() {
+        let records = [
+            String::from("tracker"),
+            String::from("ads"),
+            String::from("bücher"),
+        ];
+        let automaton = KeywordAutomaton::new(records.iter());
+
+        assert_eq!(
+            automaton.find("tracker.example.org", |_| true),
+            Some(&String::from("tracker"))
+        );
+        assert_eq!(
+            automaton.find("example.tracker", |_| true),
+            Some(&String::from("tracker"))
+        );
+        assert_eq!(
+            automaton.find("ads.tracker.example.org", |_| true),
+            Some(&String::from("ads"))
+        );
+        assert_eq!(
+            automaton.find("www.bücher.de", |_| true),
+            Some(&String::from("bücher"))
+        );
+        assert_eq!(automaton.find("example.org", |_| true), None);
+        assert_eq!(automaton.find("", |_| true), None);
+        assert_eq!(
+            automaton.find("ads.tracker.example.org", |record| record != "ads"),
+            Some(&String::from("tracker"))
+        );
+
+        let automaton = KeywordAutomaton::new([].iter());
+
+        assert_eq!(automaton.find("tracker.example.org", |_| true), None);
+    }
+}
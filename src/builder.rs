@@ -0,0 +1,334 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use crate::{EndsStrategy, Reduction, RegexLimits, RuleFormat, Ruler, TivilstaError};
+
+/// A whitelisting schema - or rule - loaded while building the ruler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RuleSource {
+    File(String),
+    Link(String),
+    Rule(String),
+}
+
+/// Configures - and loads - a [`Ruler`] in one fluent chain.
+///
+/// # Example
+///
+/// ```rust
+/// use tivilsta::{Reduction, RulerBuilder};
+///
+/// let ruler = RulerBuilder::new()
+///     .handle_complement(true)
+///     .complement_prefixes(&[String::from("www."), String::from("m.")])
+///     .reductions(&[Reduction::StripPrefixes, Reduction::Lowercase])
+///     .extensions(&[String::from("org")])
+///     .regex_backtrack_limit(10_000)
+///     .rule("example.org")
+///     .rule("RZD example")
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(ruler.is_whitelisted(&String::from("M.EXAMPLE.ORG")), true);
+/// assert_eq!(ruler.is_whitelisted(&String::from("example.com")), false);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RulerBuilder {
+    handle_complement: bool,
+    complement_prefixes: Option<Vec<String>>,
+    reductions: Option<Vec<Reduction>>,
+    ends_strategy: Option<EndsStrategy>,
+    rule_format: Option<RuleFormat>,
+    idna: Option<bool>,
+    idna_cache_capacity: Option<usize>,
+    extensions: Vec<String>,
+    regex_limits: RegexLimits,
+    keep_downloads: bool,
+    sources: Vec<RuleSource>,
+}
+
+impl Default for RulerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RulerBuilder {
+    /// Creates a new RulerBuilder object - producing the same ruler as
+    /// `Ruler::new(false)` until configured.
+    pub fn new() -> RulerBuilder {
+        RulerBuilder {
+            handle_complement: false,
+            complement_prefixes: None,
+            reductions: None,
+            ends_strategy: None,
+            rule_format: None,
+            idna: None,
+            idna_cache_capacity: None,
+            extensions: vec![],
+            regex_limits: RegexLimits::default(),
+            keep_downloads: false,
+            sources: vec![],
+        }
+    }
+
+    /// Sets whether we follow and cleanup complements - see [`Ruler::new`].
+    pub fn handle_complement(mut self, handle_complement: bool) -> RulerBuilder {
+        self.handle_complement = handle_complement;
+
+        self
+    }
+
+    /// Sets the prefixes that should be considered as complements - see
+    /// [`Ruler::with_complement_prefixes`].
+    pub fn complement_prefixes(mut self, prefixes: &[String]) -> RulerBuilder {
+        self.complement_prefixes = Some(prefixes.to_vec());
+
+        self
+    }
+
+    /// Sets the normalization chain - see [`Ruler::with_reductions`].
+    pub fn reductions(mut self, reductions: &[Reduction]) -> RulerBuilder {
+        self.reductions = Some(reductions.to_vec());
+
+        self
+    }
+
+    /// Sets how the rules of the `ALL ` and `SUB ` flags are looked up - see
+    /// [`Ruler::with_ends_strategy`].
+    pub fn ends_strategy(mut self, strategy: EndsStrategy) -> RulerBuilder {
+        self.ends_strategy = Some(strategy);
+
+        self
+    }
+
+    /// Sets the format of the whitelisting schemas and rules - see
+    /// [`Ruler::with_rule_format`].
+    pub fn rule_format(mut self, format: RuleFormat) -> RulerBuilder {
+        self.rule_format = Some(format);
+
+        self
+    }
+
+    /// Sets whether the rules and subjects are IDNAzed - see
+    /// [`Ruler::with_idna`].
+    pub fn idna(mut self, idna: bool) -> RulerBuilder {
+        self.idna = Some(idna);
+
+        self
+    }
+
+    /// Sets the maximum number of IDNAzed subjects to keep in memory - see
+    /// [`Ruler::with_idna_cache_capacity`].
+    pub fn idna_cache_capacity(mut self, capacity: usize) -> RulerBuilder {
+        self.idna_cache_capacity = Some(capacity);
+
+        self
+    }
+
+    /// Sets the extensions and public suffixes the `RZD ` rules are expanded
+    /// with - instead of the ones of the IANA and PSL registries, which are
+    /// then never fetched.
+    pub fn extensions(mut self, extensions: &[String]) -> RulerBuilder {
+        self.extensions = extensions.to_vec();
+
+        self
+    }
+
+    /// Sets the limits applied to the regular expressions of the `REG ` and
+    /// `RZD ` flags.
+    pub fn regex_limits(mut self, limits: RegexLimits) -> RulerBuilder {
+        self.regex_limits = limits;
+
+        self
+    }
+
+    /// Sets the maximum number of backtracking steps while matching a subject
+    /// against the regular expressions - see [`RegexLimits::backtrack_limit`].
+    pub fn regex_backtrack_limit(mut self, limit: usize) -> RulerBuilder {
+        self.regex_limits.backtrack_limit = Some(limit);
+
+        self
+    }
+
+    /// Sets the approximate maximum size - in bytes - of the compiled regular
+    /// expressions - see [`RegexLimits::size_limit`].
+    pub fn regex_size_limit(mut self, limit: usize) -> RulerBuilder {
+        self.regex_limits.size_limit = Some(limit);
+
+        self
+    }
+
+    /// Sets whether the downloaded files are kept when the ruler is dropped -
+    /// see [`Ruler::set_keep_downloads`].
+    pub fn keep_downloads(mut self, keep: bool) -> RulerBuilder {
+        self.keep_downloads = keep;
+
+        self
+    }
+
+    /// Adds a whitelisting schema - a file path - to load.
+    pub fn file(mut self, path: &str) -> RulerBuilder {
+        self.sources.push(RuleSource::File(path.to_string()));
+
+        self
+    }
+
+    /// Adds a whitelisting schema - a URL or named list - to download and load.
+    pub fn link(mut self, url: &str) -> RulerBuilder {
+        self.sources.push(RuleSource::Link(url.to_string()));
+
+        self
+    }
+
+    /// Adds a single rule to load.
+    pub fn rule(mut self, rule: &str) -> RulerBuilder {
+        self.sources.push(RuleSource::Rule(rule.to_string()));
+
+        self
+    }
+
+    /// Adds the given rules to load.
+    pub fn rules<S: AsRef<str>>(mut self, rules: &[S]) -> RulerBuilder {
+        self.sources.extend(
+            rules
+                .iter()
+                .map(|rule| RuleSource::Rule(rule.as_ref().to_string())),
+        );
+
+        self
+    }
+
+    /// Builds the ruler and loads the whitelisting schemas and rules - in the
+    /// order they were given.
+    ///
+    /// # Returns
+    ///
+    /// The ruler or the first error raised while loading the schemas and
+    /// rules.
+    pub fn build(self) -> Result<Ruler, TivilstaError> {
+        let mut ruler = Ruler::new(self.handle_complement);
+
+        if let Some(prefixes) = &self.complement_prefixes {
+            ruler.set_complement_prefixes(prefixes);
+        }
+
+        if let Some(reductions) = &self.reductions {
+            ruler.set_reductions(reductions);
+        }
+
+        if let Some(strategy) = self.ends_strategy {
+            ruler.set_ends_strategy(strategy);
+        }
+
+        if let Some(format) = self.rule_format {
+            ruler.set_rule_format(format);
+        }
+
+        if let Some(idna) = self.idna {
+            ruler.set_idna(idna);
+        }
+
+        if let Some(capacity) = self.idna_cache_capacity {
+            ruler.set_idna_cache_capacity(capacity);
+        }
+
+        ruler.settings.extensions = self.extensions;
+        ruler.settings.regex_limits = self.regex_limits;
+        ruler.set_keep_downloads(self.keep_downloads);
+
+        for source in &self.sources {
+            match source {
+                RuleSource::File(path) => ruler.try_parse_file(path)?,
+                RuleSource::Link(url) => ruler.try_parse_link(url)?,
+                RuleSource::Rule(rule) => ruler.try_parse(rule)?,
+            }
+        }
+
+        Ok(ruler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_build() {
+        let ruler = RulerBuilder::new().build().unwrap();
+
+        assert_eq!(ruler.handle_complement(), false);
+        assert_eq!(ruler.complement_prefixes(), &[String::from("www.")]);
+        assert_eq!(ruler.reductions(), &[Reduction::StripPrefixes]);
+        assert_eq!(ruler.ends_strategy(), EndsStrategy::Buckets);
+        assert_eq!(ruler.rule_format(), RuleFormat::Tivilsta);
+        assert_eq!(ruler.idna(), true);
+        assert_eq!(ruler.regex_limits(), RegexLimits::default());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("whitelist.list");
+
+        fs::write(&path, "ALL .example.net\n").unwrap();
+
+        let ruler = RulerBuilder::new()
+            .handle_complement(true)
+            .ends_strategy(EndsStrategy::Automaton)
+            .idna(false)
+            .idna_cache_capacity(0)
+            .regex_size_limit(1_000_000)
+            .keep_downloads(true)
+            .file(path.to_str().unwrap())
+            .rules(&[String::from("example.org"), String::from("REG ^ads\\.")])
+            .build()
+            .unwrap();
+
+        assert_eq!(ruler.handle_complement(), true);
+        assert_eq!(ruler.ends_strategy(), EndsStrategy::Automaton);
+        assert_eq!(ruler.idna(), false);
+        assert_eq!(ruler.idna_cache_capacity(), 0);
+        assert_eq!(ruler.regex_limits().size_limit, Some(1_000_000));
+        assert_eq!(ruler.keep_downloads(), true);
+        assert!(ruler.is_whitelisted(&String::from("api.example.net")));
+        assert!(ruler.is_whitelisted(&String::from("www.example.org")));
+        assert!(ruler.is_whitelisted(&String::from("ads.example.com")));
+    }
+
+    #[test]
+    fn test_build_errors() {
+        assert!(matches!(
+            RulerBuilder::new().rule("REG ^(example").build(),
+            Err(TivilstaError::Regex { .. })
+        ));
+        assert!(matches!(
+            RulerBuilder::new()
+                .regex_size_limit(10)
+                .rule("REG ^(ads|tracker|telemetry)[0-9]{1,64}\\.")
+                .build(),
+            Err(TivilstaError::Regex { .. })
+        ));
+        assert!(matches!(
+            RulerBuilder::new()
+                .file("/non/existent/whitelist.list")
+                .build(),
+            Err(TivilstaError::Io { .. })
+        ));
+    }
+}
@@ -0,0 +1,185 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use crate::utils;
+use crate::Ruler;
+
+/// Rulers - or layers - evaluated in order - e.g. an organization-wide
+/// whitelist followed by a per-project overlay.
+///
+/// The first layer whose rules match a line decides - the following layers
+/// are only consulted when it doesn't. A layer whose `EXC ` rule exempts the
+/// line decides too - the line is then not whitelisted. Each layer keeps its own settings and
+/// can be updated independently.
+///
+/// # Example
+///
+/// ```rust
+/// use tivilsta::chain::RulerChain;
+/// use tivilsta::Ruler;
+///
+/// let mut organization = Ruler::new(false);
+/// let mut project = Ruler::new(true);
+///
+/// organization.parse(&String::from("ALL .example.org"));
+/// project.parse(&String::from("example.net"));
+///
+/// let mut chain = RulerChain::new()
+///     .with_layer(organization)
+///     .with_layer(project);
+///
+/// assert_eq!(chain.deciding_layer(&String::from("api.example.org")), Some(0));
+/// assert_eq!(chain.deciding_layer(&String::from("www.example.net")), Some(1));
+/// assert_eq!(chain.is_whitelisted(&String::from("example.com")), false);
+/// ```
+#[derive(Debug, Default)]
+pub struct RulerChain {
+    layers: Vec<Ruler>,
+}
+
+impl RulerChain {
+    /// Creates a new RulerChain object - without any layer.
+    pub fn new() -> RulerChain {
+        RulerChain { layers: vec![] }
+    }
+
+    /// Appends the given `ruler` as the last - least precedent - layer.
+    pub fn with_layer(mut self, ruler: Ruler) -> RulerChain {
+        self.push_layer(ruler);
+
+        self
+    }
+
+    /// Appends the given `ruler` as the last - least precedent - layer.
+    pub fn push_layer(&mut self, ruler: Ruler) {
+        self.layers.push(ruler);
+    }
+
+    /// Provides the layers - in order of precedence.
+    pub fn layers(&self) -> &[Ruler] {
+        &self.layers
+    }
+
+    /// Provides the layers - in order of precedence - to update them.
+    pub fn layers_mut(&mut self) -> &mut [Ruler] {
+        &mut self.layers
+    }
+
+    /// Provides the position of the first layer whose rules match - or
+    /// exempt - the given `line`.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to check. See [`Ruler::is_whitelisted`].
+    ///
+    /// # Returns
+    ///
+    /// The position of the layer or `None` if no layer decides for the line.
+    pub fn deciding_layer(&self, line: &str) -> Option<usize> {
+        let netloc = utils::extract_netloc(line);
+
+        self.layers
+            .iter()
+            .position(|layer| layer.is_whitelisted(line) || layer.find_exception(&netloc).is_some())
+    }
+
+    /// Checks the given `line` against the layers - in order of precedence.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to check. See [`Ruler::is_whitelisted`].
+    ///
+    /// # Returns
+    ///
+    /// Whether one of the layers whitelists the line.
+    pub fn is_whitelisted(&self, line: &str) -> bool {
+        self.deciding_layer(line)
+            .is_some_and(|position| self.layers[position].is_whitelisted(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain() {
+        let mut chain = RulerChain::new();
+
+        assert_eq!(chain.is_whitelisted(&String::from("example.org")), false);
+
+        let mut organization = Ruler::new(false);
+        let mut project = Ruler::new(false);
+
+        organization.parse_vec(&[String::from("example.org"), String::from("example.net")]);
+        project.parse_vec(&[
+            String::from("example.net"),
+            String::from("ALL .example.com"),
+        ]);
+
+        chain.push_layer(organization);
+        chain.push_layer(project);
+
+        assert_eq!(chain.layers().len(), 2);
+        assert_eq!(chain.deciding_layer(&String::from("example.org")), Some(0));
+        assert_eq!(chain.deciding_layer(&String::from("example.net")), Some(0));
+        assert_eq!(
+            chain.deciding_layer(&String::from("api.example.com")),
+            Some(1)
+        );
+        assert_eq!(chain.deciding_layer(&String::from("example.de")), None);
+
+        chain.layers_mut()[0].unparse(&String::from("example.net"));
+
+        assert_eq!(chain.deciding_layer(&String::from("example.net")), Some(1));
+    }
+
+    #[test]
+    fn test_chain_exception() {
+        let mut project = Ruler::new(false);
+        let mut organization = Ruler::new(false);
+
+        project.parse(&String::from("EXC ads.example.org"));
+        organization.parse(&String::from("ALL .example.org"));
+
+        let chain = RulerChain::new()
+            .with_layer(project)
+            .with_layer(organization);
+
+        // The exception of the first layer prevails over the second one.
+        assert_eq!(
+            chain.deciding_layer(&String::from("ads.example.org")),
+            Some(0)
+        );
+        assert_eq!(
+            chain.is_whitelisted(&String::from("ads.example.org")),
+            false
+        );
+        assert_eq!(
+            chain.is_whitelisted(&String::from("https://ads.example.org/banner")),
+            false
+        );
+        assert_eq!(
+            chain.deciding_layer(&String::from("api.example.org")),
+            Some(1)
+        );
+        assert_eq!(chain.is_whitelisted(&String::from("api.example.org")), true);
+    }
+}
@@ -0,0 +1,357 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// A network of the `CIDR ` flag - its address, with the host bits cleared, and
+/// the length of its prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Network {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    /// Parses the given `10.0.0.0/8`-like network. A bare address is read as
+    /// the network holding only that address.
+    ///
+    /// # Returns
+    ///
+    /// The reason why the given network is invalid - on failure.
+    pub(crate) fn parse(network: &str) -> Result<Network, String> {
+        let (address, prefix) = match network.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (network, None),
+        };
+        let address =
+            parse_address(address).ok_or_else(|| format!("invalid address: {address}"))?;
+        let width = width(address);
+
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= width)
+                .ok_or_else(|| format!("invalid prefix length: {prefix}"))?,
+            None => width,
+        };
+
+        let address = match address {
+            IpAddr::V4(address) => IpAddr::V4(Ipv4Addr::from(
+                ((bits(address.into()) & mask(prefix)) >> 96) as u32,
+            )),
+            IpAddr::V6(address) => IpAddr::V6(Ipv6Addr::from(bits(address.into()) & mask(prefix))),
+        };
+
+        Ok(Network { address, prefix })
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+/// Parses the given subject as an IP address - in its canonical form: an
+/// IPv4-mapped IPv6 address (`::ffff:10.0.0.1`) is read as its IPv4 address.
+/// See [`parse_address`].
+pub(crate) fn parse_ip(subject: &str) -> Option<IpAddr> {
+    parse_address(subject).map(|address| address.to_canonical())
+}
+
+/// Provides the canonical form of the given subject - if it is an IP address.
+/// E.g. `::1` for `0:0:0:0:0:0:0:1` and `10.0.0.1` for `010.000.000.001`.
+pub(crate) fn canonical_ip(subject: &str) -> Option<String> {
+    // Most subjects are domains - which we don't want to fully parse.
+    if !subject
+        .bytes()
+        .all(|x| x.is_ascii_hexdigit() || matches!(x, b'.' | b':' | b'[' | b']'))
+    {
+        return None;
+    }
+
+    parse_ip(subject).map(|address| address.to_string())
+}
+
+/// Parses the given subject as an IP address - brackets around an IPv6
+/// address (`[::1]`) and leading zeros in an IPv4 address (`010.0.0.1`) are
+/// accepted.
+fn parse_address(subject: &str) -> Option<IpAddr> {
+    let subject = subject
+        .strip_prefix('[')
+        .and_then(|subject| subject.strip_suffix(']'))
+        .unwrap_or(subject);
+
+    if let Ok(address) = subject.parse() {
+        return Some(address);
+    }
+
+    // The standard library rejects leading zeros - which hosts files keep.
+    let octets: Vec<u8> = subject
+        .split('.')
+        .map(|octet| {
+            (!octet.is_empty() && octet.len() <= 3 && octet.bytes().all(|x| x.is_ascii_digit()))
+                .then(|| octet.parse().ok())
+                .flatten()
+        })
+        .collect::<Option<_>>()?;
+
+    <[u8; 4]>::try_from(octets)
+        .ok()
+        .map(|octets| IpAddr::V4(Ipv4Addr::from(octets)))
+}
+
+/// The number of bits of the given address.
+fn width(address: IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+/// The bits of the given address - left-aligned, so that both families are
+/// walked from the most significant bit.
+fn bits(address: IpAddr) -> u128 {
+    match address {
+        IpAddr::V4(address) => (u32::from(address) as u128) << 96,
+        IpAddr::V6(address) => u128::from(address),
+    }
+}
+
+/// The left-aligned mask of the given prefix length.
+fn mask(prefix: u8) -> u128 {
+    u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0)
+}
+
+/// A node of a [`BitTrie`].
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    children: [Option<u32>; 2],
+    /// The record of the network ending at this node.
+    record: Option<usize>,
+}
+
+/// A binary trie of networks - one bit of the address per level.
+#[derive(Debug, Clone)]
+struct BitTrie {
+    nodes: Vec<TrieNode>,
+}
+
+impl BitTrie {
+    fn new() -> BitTrie {
+        BitTrie {
+            nodes: vec![TrieNode::default()],
+        }
+    }
+
+    fn bit(bits: u128, depth: u8) -> usize {
+        (bits >> (127 - depth as u32) & 1) as usize
+    }
+
+    fn insert(&mut self, bits: u128, prefix: u8, record: usize) {
+        let mut node = 0;
+
+        for depth in 0..prefix {
+            let bit = Self::bit(bits, depth);
+
+            node = match self.nodes[node].children[bit] {
+                Some(child) => child as usize,
+                None => {
+                    self.nodes.push(TrieNode::default());
+
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children[bit] = Some(child as u32);
+                    child
+                }
+            };
+        }
+
+        self.nodes[node].record.get_or_insert(record);
+    }
+
+    /// Provides the record - among the `usable` ones - of the most specific
+    /// network holding the given bits.
+    fn find(&self, bits: u128, width: u8, usable: impl Fn(usize) -> bool) -> Option<usize> {
+        let mut node = 0;
+        let mut found = self.nodes[node].record.filter(|record| usable(*record));
+
+        for depth in 0..width {
+            match self.nodes[node].children[Self::bit(bits, depth)] {
+                Some(child) => node = child as usize,
+                None => break,
+            }
+
+            found = self.nodes[node]
+                .record
+                .filter(|record| usable(*record))
+                .or(found);
+        }
+
+        found
+    }
+}
+
+/// The rules of the `CIDR ` flag - one prefix trie per address family.
+#[derive(Debug, Clone)]
+pub(crate) struct CidrTrie {
+    v4: BitTrie,
+    v6: BitTrie,
+    /// The records - indexed by the nodes of the tries.
+    records: Vec<String>,
+}
+
+impl CidrTrie {
+    /// Builds a new trie out of the given records. Records that aren't valid
+    /// networks are ignored.
+    pub(crate) fn new<'a>(records: impl Iterator<Item = &'a String>) -> CidrTrie {
+        let mut trie = CidrTrie {
+            v4: BitTrie::new(),
+            v6: BitTrie::new(),
+            records: vec![],
+        };
+
+        for record in records {
+            let Ok(network) = Network::parse(record) else {
+                continue;
+            };
+            let index = trie.records.len();
+
+            match network.address {
+                IpAddr::V4(_) => &mut trie.v4,
+                IpAddr::V6(_) => &mut trie.v6,
+            }
+            .insert(bits(network.address), network.prefix, index);
+
+            trie.records.push(record.clone());
+        }
+
+        trie
+    }
+
+    /// Provides the record - among the `usable` ones - of the most specific
+    /// network holding the given address.
+    pub(crate) fn find(
+        &self,
+        address: IpAddr,
+        usable: impl Fn(&String) -> bool,
+    ) -> Option<&String> {
+        match address {
+            IpAddr::V4(_) => &self.v4,
+            IpAddr::V6(_) => &self.v6,
+        }
+        .find(bits(address), width(address), |index| {
+            usable(&self.records[index])
+        })
+        .map(|index| &self.records[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network_parse() {
+        let parse = |network: &str| Network::parse(network).map(|network| network.to_string());
+
+        assert_eq!(parse("10.0.0.0/8"), Ok(String::from("10.0.0.0/8")));
+        assert_eq!(parse("10.1.2.3/8"), Ok(String::from("10.0.0.0/8")));
+        assert_eq!(parse("192.168.1.1"), Ok(String::from("192.168.1.1/32")));
+        assert_eq!(parse("0.0.0.0/0"), Ok(String::from("0.0.0.0/0")));
+        assert_eq!(parse("2001:db8::1/32"), Ok(String::from("2001:db8::/32")));
+        assert_eq!(parse("[::1]"), Ok(String::from("::1/128")));
+
+        assert!(parse("10.0.0.0/33").is_err());
+        assert!(parse("10.0.0.0/").is_err());
+        assert!(parse("10.0.0/8").is_err());
+        assert!(parse("example.org").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn test_canonical_ip() {
+        assert_eq!(canonical_ip("10.0.0.1"), Some(String::from("10.0.0.1")));
+        assert_eq!(
+            canonical_ip("010.000.000.001"),
+            Some(String::from("10.0.0.1"))
+        );
+        assert_eq!(canonical_ip("0:0:0:0:0:0:0:1"), Some(String::from("::1")));
+        assert_eq!(canonical_ip("[::1]"), Some(String::from("::1")));
+        assert_eq!(
+            canonical_ip("2001:0DB8:0000::0001"),
+            Some(String::from("2001:db8::1"))
+        );
+        assert_eq!(
+            canonical_ip("::ffff:10.0.0.1"),
+            Some(String::from("10.0.0.1"))
+        );
+
+        for subject in [
+            "example.org",
+            "cafe.be",
+            "10.0.0",
+            "10.0.0.256",
+            "0010.0.0.1",
+            "",
+        ] {
+            assert_eq!(canonical_ip(subject), None, "{subject}");
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let records = [
+            String::from("10.0.0.0/8"),
+            String::from("10.1.0.0/16"),
+            String::from("192.168.1.1/32"),
+            String::from("2001:db8::/32"),
+        ];
+        let trie = CidrTrie::new(records.iter());
+        let find = |address: &str| trie.find(address.parse().unwrap(), |_| true);
+
+        assert_eq!(find("10.2.3.4"), Some(&String::from("10.0.0.0/8")));
+        assert_eq!(find("10.1.3.4"), Some(&String::from("10.1.0.0/16")));
+        assert_eq!(find("192.168.1.1"), Some(&String::from("192.168.1.1/32")));
+        assert_eq!(find("192.168.1.2"), None);
+        assert_eq!(find("11.0.0.1"), None);
+        assert_eq!(find("2001:db8::1"), Some(&String::from("2001:db8::/32")));
+        assert_eq!(find("2001:db9::1"), None);
+        assert_eq!(find("::ffff:10.0.0.1"), None);
+        assert_eq!(
+            trie.find(parse_ip("::ffff:10.0.0.1").unwrap(), |_| true),
+            Some(&String::from("10.0.0.0/8"))
+        );
+        assert_eq!(
+            trie.find("10.1.3.4".parse().unwrap(), |record| record
+                != "10.1.0.0/16"),
+            Some(&String::from("10.0.0.0/8"))
+        );
+
+        let trie = CidrTrie::new([String::from("0.0.0.0/0")].iter());
+
+        assert_eq!(
+            trie.find("203.0.113.7".parse().unwrap(), |_| true),
+            Some(&String::from("0.0.0.0/0"))
+        );
+        assert_eq!(trie.find("::1".parse().unwrap(), |_| true), None);
+    }
+}
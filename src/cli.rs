@@ -19,242 +19,906 @@
 //      limitations under the License.
 
 use crate::Arguments;
+use indicatif::{ProgressBar, ProgressStyle};
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::io::{BufRead, BufReader, Write};
-use std::{fs::File, path::PathBuf};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tempfile::NamedTempFile;
-use tivilsta::Ruler;
+use tivilsta::coverage::Coverage;
+use tivilsta::diff::RulesDiff;
+use tivilsta::pipeline::{
+    self, FilterHeader, OutputFormat, OutputWriter, PipelineReport, Sample, UnverifiedAction,
+};
+use tivilsta::stats::RulesStats;
+use tivilsta::{ExpiryDate, MatchKind, Pipeline, Reduction, RuleFormat, Ruler};
 
-use crate::utils;
+use tivilsta::data::{cache, iana, psl, registry};
+use tivilsta::utils;
 
-#[derive(Debug)]
-struct CLIHandlerSettings {
-    output_given: bool,
-}
+/// The exit code used when `--skip-unchanged` is given and the inputs didn't
+/// change since the last run.
+pub const UNCHANGED_EXIT_CODE: i32 = 3;
+
+/// The warning printed on `stderr` when `--insecure` is given.
+const INSECURE_WARNING: &str = "WARNING: --insecure is given - the certificates of the \
+    remote servers are NOT verified and the downloaded files can be tampered with.";
 
 #[derive(Debug)]
 struct CLIHandlerTmp {
     output: NamedTempFile,
 }
 
-#[derive(Debug)]
-struct CLIHandlerPaths {
-    source: PathBuf,
-    output: PathBuf,
-    whitelist: Vec<String>,
-    all_prefixed: Vec<String>,
-    reg_prefixed: Vec<String>,
-    rzd_prefixed: Vec<String>,
-    tmps: Vec<String>,
-}
-
 #[derive(Debug)]
 pub struct CLIHandler {
-    source: File,
-    whitelist: Vec<File>,
-    all_prefixed: Vec<File>,
-    reg_prefixed: Vec<File>,
-    rzd_prefixed: Vec<File>,
-    ruler: Ruler,
-    settings: CLIHandlerSettings,
+    pipeline: Pipeline,
+    output: Option<PathBuf>,
+    echo: bool,
+    top_removed: Option<usize>,
+    report_unused: bool,
+    verify_dns: bool,
+    header_sources: Option<Vec<String>>,
+    comment_marker: &'static str,
+    digest: Option<(PathBuf, String)>,
+    unchanged: bool,
     tmp: CLIHandlerTmp,
-    paths: CLIHandlerPaths,
+}
+
+/// A writer that writes everything into both of the given writers.
+struct Tee<A: Write, B: Write>(A, B);
+
+impl<A: Write, B: Write> Write for Tee<A, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(buf)?;
+        self.1.write_all(buf)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()?;
+        self.1.flush()
+    }
 }
 
 impl CLIHandler {
-    /// Returns a person with the name given them
+    /// Creates a new CLIHandler object from the given arguments and loads all
+    /// the given whitelisting schemas.
     ///
     /// # Arguments
     ///
     /// * `args` - A set of parsed arguments.
     ///
-    /// # Examples
+    /// # Returns
     ///
-    /// ```
-    /// use tivilsta::cli::CLIHandler;
+    /// A new CLIHandler object or an error naming the path or URL that can't
+    /// be read.
     ///
-    /// let args = Arguments::parse();
-    /// let mut handler = CLIHandler::new(args);
+    /// # Examples
     ///
-    /// // handler already do this for you. But you can force it to reload all (new?) datasets by doing this.
-    /// handler.load_all();
+    /// ```ignore
+    /// let args = Arguments::parse();
+    /// let mut handler = CLIHandler::new(args)?;
     ///
     /// // Process the whitelisting + output based on all inputs.
     /// handler.cleanup();
     /// ```
-    pub fn new(args: Arguments) -> CLIHandler {
-        let mut paths = CLIHandlerPaths {
-            source: PathBuf::new(),
-            output: PathBuf::new(),
-            whitelist: vec![],
-            all_prefixed: vec![],
-            reg_prefixed: vec![],
-            rzd_prefixed: vec![],
-            tmps: vec![],
-        };
-        let tmp = CLIHandlerTmp {
-            output: NamedTempFile::new().unwrap(),
-        };
-        let mut settings = CLIHandlerSettings {
-            output_given: false,
-        };
+    pub fn new(args: Arguments) -> Result<CLIHandler, Box<dyn std::error::Error>> {
+        let mut ruler = Ruler::new(args.allow_complements);
 
-        settings.output_given = args.output.is_some();
-        paths.source = args.source;
-        paths.output = args.output.unwrap_or_default();
+        if !args.complement_prefixes.is_empty() {
+            ruler = ruler.with_complement_prefixes(&args.complement_prefixes);
+        }
 
-        let mut whitelist: Vec<File> = vec![];
-        let mut all_prefixed: Vec<File> = vec![];
-        let mut reg_prefixed: Vec<File> = vec![];
-        let mut rzd_prefixed: Vec<File> = vec![];
+        if !args.canonicalize.is_empty() {
+            ruler = with_canonicalization(ruler, &args.canonicalize);
+        }
 
-        if !args.whitelist.is_empty() {
-            for file in args.whitelist {
-                let (path, downloaded) = utils::download_file(&file);
+        if args.report_unused {
+            ruler = ruler.with_track_usage(true);
+        }
 
-                if downloaded {
-                    paths.tmps.push(path.clone())
-                }
+        match args.whitelist_format.as_str() {
+            "adblock" => ruler = ruler.with_rule_format(RuleFormat::Adblock),
+            "adguard" => ruler = ruler.with_rule_format(RuleFormat::Adguard),
+            "hosts" => ruler = ruler.with_rule_format(RuleFormat::Hosts),
+            _ => {}
+        }
 
-                whitelist.push(File::open(&path).unwrap());
-                paths.whitelist.push(path.clone());
-            }
+        for group in &args.disable_group {
+            ruler = ruler.with_group_enabled(group, false);
         }
 
-        if !args.all.is_empty() {
-            for file in args.all {
-                let (path, downloaded) = utils::download_file(&file);
+        // Any change of the arguments - e.g. `--allow-complements` - may
+        // change the output as much as a change of the inputs.
+        let configuration = format!("{:?}", args);
+        let source = args.source.ok_or("The source is required.")?;
+        let mut pipeline = Pipeline::new(ruler)
+            .source(&source.to_string_lossy())
+            .threads(args.threads)
+            .channel_capacity(args.channel_capacity)
+            .chunk_size(args.chunk_size)
+            .on_download_progress(download_progress());
+
+        let output_format = match args.output_format.as_str() {
+            "hosts" => OutputFormat::Hosts(args.hosts_ip.clone()),
+            "domains" => OutputFormat::Domains,
+            "dnsmasq" => OutputFormat::Dnsmasq,
+            "rpz" => OutputFormat::Rpz {
+                origin: args.rpz_origin.clone(),
+                ttl: args.rpz_ttl,
+            },
+            "adguard-home" => {
+                OutputFormat::AdguardHome(args.filter_title.as_ref().map(|title| FilterHeader {
+                    title: title.to_string(),
+                    version: args.filter_version.clone(),
+                }))
+            }
+            _ => OutputFormat::Source,
+        };
+        let comment_marker = output_format.comment_marker();
 
-                if downloaded {
-                    paths.tmps.push(path.clone())
-                }
+        pipeline = pipeline.output_format(output_format);
 
-                all_prefixed.push(File::open(&path).unwrap());
-                paths.all_prefixed.push(path.clone())
+        match args.verify_dns.as_deref() {
+            Some("annotate") => {
+                pipeline = pipeline.verify_removals(pipeline::resolves, UnverifiedAction::Annotate)
+            }
+            Some("retain") => {
+                pipeline = pipeline.verify_removals(pipeline::resolves, UnverifiedAction::Retain)
             }
+            _ => {}
         }
 
-        if !args.reg.is_empty() {
-            for file in args.reg {
-                let (path, downloaded) = utils::download_file(&file);
+        if let Some(rate) = args.max_download_rate {
+            pipeline = pipeline.max_download_rate(rate);
+        }
 
-                if downloaded {
-                    paths.tmps.push(path.clone())
-                }
+        if let Some(megabytes) = args.max_memory {
+            pipeline = pipeline.max_memory(megabytes.saturating_mul(1024 * 1024));
+        }
 
-                reg_prefixed.push(File::open(&path).unwrap());
-                paths.reg_prefixed.push(path.clone())
-            }
+        if let Some(temp_dir) = &args.temp_dir {
+            pipeline = pipeline.temp_dir(temp_dir);
         }
 
-        if !args.rzd.is_empty() {
-            for file in args.rzd {
-                let (path, downloaded) = utils::download_file(&file);
+        if let Some(ca_cert) = &args.ca_cert {
+            pipeline = pipeline.ca_cert(ca_cert);
+        }
 
-                if downloaded {
-                    paths.tmps.push(path.clone())
-                }
+        if args.insecure {
+            eprintln!("{}", INSECURE_WARNING);
+            pipeline = pipeline.insecure();
+        }
+
+        if let Some(spacing) = args.host_request_spacing {
+            pipeline = pipeline.host_request_spacing(spacing);
+        }
+
+        if args.top_removed.is_some() {
+            pipeline = pipeline.track_removed_domains();
+        }
+
+        if args.dedup_input {
+            pipeline = pipeline.dedup_input(args.dedup_capacity);
+        }
+
+        if let Some(lines) = args.limit {
+            pipeline = pipeline.limit(lines);
+        }
+
+        pipeline = pipeline.skip(args.skip);
+
+        if let Some(ratio) = args.sample {
+            pipeline = pipeline.sample(Sample::Ratio(ratio));
+        } else if let Some(lines) = args.sample_lines {
+            pipeline = pipeline.sample(Sample::Lines(lines));
+        }
+
+        if let Some(audit_log) = &args.audit_log {
+            let file = File::create(audit_log)
+                .map_err(|error| format!("{}: {}", audit_log.display(), error))?;
 
-                rzd_prefixed.push(File::open(&path).unwrap());
-                paths.rzd_prefixed.push(path.clone())
+            pipeline = pipeline.audit_log(file);
+        }
+
+        for input in &args.whitelist {
+            pipeline = pipeline.whitelist(input);
+        }
+
+        for input in &args.all {
+            pipeline = pipeline.all(input);
+        }
+
+        for input in &args.reg {
+            pipeline = pipeline.reg(input);
+        }
+
+        for input in &args.rzd {
+            pipeline = pipeline.rzd(input);
+        }
+
+        for name in &args.preset {
+            pipeline = pipeline.preset(name);
+        }
+
+        for rule in &args.rule {
+            pipeline = pipeline.rule(rule);
+        }
+
+        let header_sources = (!args.no_header).then(|| {
+            let mut sources = vec![source.to_string_lossy().to_string()];
+
+            sources.extend(args.whitelist.iter().cloned());
+            sources.extend(args.all.iter().map(|input| format!("ALL {}", input)));
+            sources.extend(args.reg.iter().map(|input| format!("REG {}", input)));
+            sources.extend(args.rzd.iter().map(|input| format!("RZD {}", input)));
+            sources.extend(args.preset.iter().map(|name| format!("preset {}", name)));
+
+            if !args.rule.is_empty() {
+                sources.push(format!("{} rule(s)", args.rule.len()));
             }
+
+            sources
+        });
+
+        let mut digest = None;
+        let mut unchanged = false;
+
+        if let (true, Some(output)) = (args.skip_unchanged, &args.output) {
+            let mut path = output.clone().into_os_string();
+            path.push(".sha256");
+
+            let path = PathBuf::from(path);
+            let current = format!(
+                "{:x}",
+                Sha256::new()
+                    .chain_update(pipeline.digest()?)
+                    .chain_update(configuration)
+                    .finalize()
+            );
+
+            unchanged = output.exists()
+                && fs::read_to_string(&path).is_ok_and(|previous| previous.trim() == current);
+            digest = Some((path, current));
         }
 
+        let tmp_output = match &args.temp_dir {
+            Some(temp_dir) => NamedTempFile::new_in(temp_dir)
+                .map_err(|error| format!("{}: {}", temp_dir.display(), error))?,
+            None => NamedTempFile::new()?,
+        };
+
         let mut result = CLIHandler {
-            source: File::open(&paths.source).unwrap(),
-            whitelist,
-            all_prefixed,
-            reg_prefixed,
-            rzd_prefixed,
-            ruler: Ruler::new(args.allow_complements),
-            settings,
-            tmp,
-            paths,
+            pipeline,
+            echo: !args.quiet && (args.tee || args.output.is_none()),
+            output: args.output,
+            top_removed: args.top_removed,
+            report_unused: args.report_unused,
+            verify_dns: args.verify_dns.is_some(),
+            header_sources,
+            comment_marker,
+            digest,
+            unchanged,
+            tmp: CLIHandlerTmp { output: tmp_output },
         };
 
-        result.load_all();
-        result
+        if !result.unchanged {
+            result.load_all()?;
+        }
+
+        Ok(result)
     }
 
-    fn load_whitelist(&mut self) -> bool {
-        for file in &self.whitelist {
-            let whitelist_file = BufReader::new(file);
+    /// Whether `--skip-unchanged` is given and the inputs didn't change since
+    /// the last run. The datasets are not loaded in that case.
+    pub fn is_unchanged(&self) -> bool {
+        self.unchanged
+    }
+
+    /// Loads all external datasets into the ruler.
+    /// This is done automatically when the handler is created.
+    pub fn load_all(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(self.pipeline.load()?)
+    }
+
+    /// Writes every line of the source that is not whitelisted into the
+    /// output and/or `stdout`.
+    ///
+    /// When only a sample of the source is checked, the extrapolated number
+    /// of lines the whole source would lose is reported on `stderr`.
+    ///
+    /// # Returns
+    ///
+    /// An error naming the path or URL that can't be read or written.
+    pub fn cleanup(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let report = match &self.output {
+            Some(_) if self.echo => self
+                .pipeline
+                .run(Tee(&mut self.tmp.output, io::stdout().lock()))?,
+            Some(_) => self.pipeline.run(&mut self.tmp.output)?,
+            None if self.echo => self.pipeline.run(io::stdout().lock())?,
+            None => self.pipeline.run(io::sink())?,
+        };
+
+        if let Some(output) = &self.output {
+            self.tmp.output.flush()?;
+
+            match &self.header_sources {
+                Some(sources) => {
+                    let mut file = File::create(output)
+                        .map_err(|error| format!("{}: {}", output.display(), error))?;
 
-            for line in whitelist_file.lines() {
-                self.ruler.parse(&line.unwrap())
+                    file.write_all(header(sources, &report, self.comment_marker).as_bytes())?;
+                    io::copy(&mut self.tmp.output.reopen()?, &mut file)?;
+                }
+                None => {
+                    fs::copy(self.tmp.output.path(), output)
+                        .map_err(|error| format!("{}: {}", output.display(), error))?;
+                }
             }
         }
 
-        for file in &self.all_prefixed {
-            let whitelist_file = BufReader::new(file);
+        if let Some((path, digest)) = &self.digest {
+            fs::write(path, format!("{}\n", digest))
+                .map_err(|error| format!("{}: {}", path.display(), error))?;
+        }
+
+        if report.is_sampled() {
+            report_sample(&report);
+        }
 
-            for line in whitelist_file.lines() {
-                self.ruler.parse(&format!("ALL {}", &line.unwrap()))
+        if self.verify_dns {
+            eprintln!(
+                "{} whitelisted lines don't resolve.",
+                format_count(report.unverified)
+            );
+
+            for (rule, count) in self.pipeline.unverified_rules() {
+                eprintln!(
+                    "Only matches non-resolving subjects: {} ({} lines)",
+                    rule,
+                    format_count(count)
+                );
             }
         }
 
-        for file in &self.reg_prefixed {
-            let whitelist_file = BufReader::new(file);
+        if let Some(limit) = self.top_removed {
+            eprintln!("Top {} removed registrable domains:", limit);
 
-            for line in whitelist_file.lines() {
-                self.ruler.parse(&format!("REG {}", &line.unwrap()))
+            for (domain, count) in self.pipeline.top_removed_domains(limit) {
+                eprintln!("{}: {} lines", domain, format_count(count));
             }
         }
 
-        for file in &self.rzd_prefixed {
-            let whitelist_file = BufReader::new(file);
+        if self.report_unused {
+            let unused = self.pipeline.ruler().unused_rules();
+
+            eprintln!("{} rules never matched.", format_count(unused.len()));
 
-            for line in whitelist_file.lines() {
-                self.ruler.parse(&format!("RZD {}", &line.unwrap()))
+            for found in unused {
+                match found.origin {
+                    Some(origin) => eprintln!("Never matched: {} ({})", found.rule, origin),
+                    None => eprintln!("Never matched: {}", found.rule),
+                }
             }
         }
 
-        true
+        Ok(())
     }
+}
 
-    /// Loads all external datasets into the ruler.
-    /// This is done automatically when the handler is created.
-    ///
-    /// However, if you - for example - add a new file you can call this method
-    /// to force it to load and parse your newly added file.
-    pub fn load_all(&mut self) -> bool {
-        self.load_whitelist()
+/// Provides the header prepended to the output file - commented out through
+/// the given `marker`.
+///
+/// The date is read from the `SOURCE_DATE_EPOCH` environment variable - when
+/// given - so that the output can be reproduced.
+fn header(sources: &[String], report: &PipelineReport, marker: &str) -> String {
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        });
+
+    format!(
+        "{} Generated by tivilsta v{} on {}\n{} Sources: {}\n{} Kept {} of {} lines.\n",
+        marker,
+        env!("CARGO_PKG_VERSION"),
+        format_date(timestamp),
+        marker,
+        sources.join(", "),
+        marker,
+        format_count(report.written),
+        format_count(report.source_lines)
+    )
+}
+
+/// Applies the given canonicalization `stages` - and only them - to the given
+/// ruler.
+fn with_canonicalization(ruler: Ruler, stages: &[String]) -> Ruler {
+    let mut reductions = vec![Reduction::StripPrefixes];
+
+    for stage in stages {
+        match stage.as_str() {
+            "trim" => reductions.push(Reduction::Trim),
+            "lowercase" => reductions.push(Reduction::Lowercase),
+            "strip-scheme" => reductions.push(Reduction::StripScheme),
+            "strip-port" => reductions.push(Reduction::StripPort),
+            "strip-trailing-dot" => reductions.push(Reduction::StripTrailingDot),
+            _ => {}
+        }
     }
 
-    pub fn cleanup(&mut self) -> bool {
-        let src = BufReader::new(&self.source);
+    ruler
+        .with_idna(stages.iter().any(|stage| stage == "idna"))
+        .with_reductions(&reductions)
+}
 
-        for line in src.lines() {
-            let line = self.ruler.idnaze_line(&line.unwrap());
+/// Reports - on `stderr` - the number of lines the whole source would lose,
+/// as extrapolated from the checked sample.
+fn report_sample(report: &PipelineReport) {
+    eprintln!(
+        "Sampled {} of {} lines: {} whitelisted.",
+        report.read, report.source_lines, report.whitelisted
+    );
+    eprintln!(
+        "Estimated: {} of {} lines whitelisted ({:.2}%) - {} kept.",
+        report.estimated_whitelisted(),
+        report.source_lines,
+        report.estimated_whitelisted() as f64 * 100.0 / report.source_lines as f64,
+        report.estimated_written()
+    );
+}
 
-            if self.ruler.is_whitelisted(&line) {
-                continue;
+/// Provides a function that renders the progress of the downloads on `stderr`.
+///
+/// **Note:** Nothing is rendered when `stderr` is not a terminal.
+fn download_progress() -> impl FnMut(&str, u64, Option<u64>) {
+    let mut current: Option<(String, ProgressBar)> = None;
+
+    move |url, downloaded, total| {
+        if current
+            .as_ref()
+            .map(|(current_url, _)| current_url.as_str())
+            != Some(url)
+        {
+            let bar = match total {
+                Some(total) => ProgressBar::new(total).with_style(
+                    ProgressStyle::with_template(
+                        "{msg} {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+                    )
+                    .unwrap(),
+                ),
+                None => ProgressBar::new_spinner().with_style(
+                    ProgressStyle::with_template("{msg} {bytes} ({bytes_per_sec})").unwrap(),
+                ),
+            };
+
+            if let Some((_, previous)) = current.take() {
+                previous.finish_and_clear();
             }
 
-            let _ = self
-                .tmp
-                .output
-                .write((line.to_string() + "\n").as_bytes())
-                .unwrap();
+            current = Some((url.to_string(), bar.with_message(url.to_string())));
+        }
+
+        if let Some((_, bar)) = &current {
+            bar.set_position(downloaded);
+
+            if total == Some(downloaded) {
+                bar.finish_and_clear();
+            }
+        }
+    }
+}
+
+/// Checks that the given `user_input` - a file path or URL - can be read.
+///
+/// The names of the registry - e.g. `@ultimate-hosts-blacklist/whitelist` -
+/// are resolved first. URLs are checked through a `HEAD` request while file
+/// paths are opened.
+fn check_input(user_input: &str, client: &reqwest::blocking::Client) -> Result<(), String> {
+    let user_input = &registry::resolve(user_input).map_err(|error| error.to_string())?;
+
+    if !user_input.contains("://") {
+        return match File::open(user_input) {
+            Ok(file) if file.metadata().is_ok_and(|x| x.is_dir()) => {
+                Err(String::from("Is a directory."))
+            }
+            Ok(_) => Ok(()),
+            Err(error) => Err(error.to_string()),
+        };
+    }
+
+    let response = client
+        .head(user_input)
+        .send()
+        .map_err(|error| error.to_string())?;
+
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        status if status == 401 || status == 403 => {
+            Err(format!("Authentication required ({}).", status))
+        }
+        status => Err(format!("Unexpected HTTP status ({}).", status)),
+    }
+}
+
+/// Checks that every given source and whitelisting schema can be read and
+/// reports the problems - without running the cleanup.
+///
+/// # Arguments
+///
+/// * `args` - A set of parsed arguments.
+///
+/// # Returns
+///
+/// An error if at least one of the inputs can't be read.
+pub fn validate_sources(args: &Arguments) -> Result<(), Box<dyn std::error::Error>> {
+    let mut inputs: Vec<(&str, String)> = vec![];
+
+    if let Some(source) = &args.source {
+        inputs.push(("source", source.to_string_lossy().to_string()));
+    }
+
+    for (kind, user_inputs) in [
+        ("whitelist", &args.whitelist),
+        ("all", &args.all),
+        ("reg", &args.reg),
+        ("rzd", &args.rzd),
+    ] {
+        for user_input in user_inputs {
+            inputs.push((kind, user_input.to_string()));
+        }
+    }
+
+    if args.insecure {
+        eprintln!("{}", INSECURE_WARNING);
+    }
+
+    let client = utils::http_client(args.ca_cert.as_deref(), args.insecure)?;
+    let mut failures = 0;
 
-            if !self.settings.output_given {
-                println!("{}", &line)
+    for (kind, user_input) in &inputs {
+        match check_input(user_input, &client) {
+            Ok(_) => println!("OK    {}: {}", kind, user_input),
+            Err(reason) => {
+                failures += 1;
+                println!("ERROR {}: {} - {}", kind, user_input, reason)
             }
         }
+    }
 
-        if self.settings.output_given {
-            let _ = fs::copy(self.tmp.output.path(), &self.paths.output).unwrap();
+    if failures > 0 {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} of {} input(s) can't be read.", failures, inputs.len()),
+        )));
+    }
+
+    Ok(())
+}
+
+/// Formats the given `count` with thousands separators - e.g. `45,231`.
+fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut result = String::with_capacity(digits.len() + digits.len() / 3);
+
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            result.push(',');
         }
 
-        true
+        result.push(digit);
     }
+
+    result
+}
+
+/// Formats the given `age` in a human readable way - e.g. `2d 3h 4m`.
+fn format_age(age: Duration) -> String {
+    let minutes = age.as_secs() / 60;
+
+    format!(
+        "{}d {}h {}m",
+        minutes / 1440,
+        (minutes / 60) % 24,
+        minutes % 60
+    )
 }
 
-impl Drop for CLIHandler {
-    /// Ensures that all temporary files or downloaded files are cleaned up.
-    fn drop(&mut self) {
-        for file in &self.paths.tmps {
-            let _ = fs::remove_file(file);
+/// Formats the given UNIX `timestamp` as an UTC date - e.g.
+/// `2024-03-01 12:34:56 UTC`.
+fn format_date(timestamp: u64) -> String {
+    let seconds = timestamp % 86400;
+
+    format!(
+        "{} {:02}:{:02}:{:02} UTC",
+        ExpiryDate::from_timestamp(timestamp),
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
+
+/// Fetches, validates and stores the IANA and PSL registries - and the
+/// registry of named lists - into the cache directory.
+///
+/// # Arguments
+///
+/// * `check` - Only print the location and age of the current snapshots.
+pub fn update_data(check: bool) -> Result<(), Box<dyn std::error::Error>> {
+    for (name, snapshot) in [
+        ("IANA", iana::SNAPSHOT_NAME),
+        ("PSL", psl::SNAPSHOT_NAME),
+        ("Registry", registry::SNAPSHOT_NAME),
+    ] {
+        if check {
+            match cache::snapshot_age(snapshot) {
+                Some(age) => println!(
+                    "{}: {} ({} old)",
+                    name,
+                    cache::snapshot_path(snapshot).display(),
+                    format_age(age)
+                ),
+                None => println!("{}: No snapshot found.", name),
+            }
+
+            continue;
         }
+
+        let path = match snapshot {
+            iana::SNAPSHOT_NAME => iana::update()?,
+            psl::SNAPSHOT_NAME => psl::update()?,
+            _ => registry::update()?,
+        };
+
+        println!("{}: Snapshot written into {}", name, path.display());
+    }
+
+    Ok(())
+}
+
+/// Reads the lines of the given `user_input` - a file path or URL.
+fn read_rules(user_input: &String) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let (path, downloaded) = utils::try_download_file(user_input)
+        .map_err(|error| format!("{}: {}", user_input, error))?;
+    let file = File::open(&path).map_err(|error| format!("{}: {}", user_input, error));
+
+    if downloaded {
+        let _ = fs::remove_file(&path);
     }
+
+    BufReader::new(file?)
+        .lines()
+        .collect::<Result<_, _>>()
+        .map_err(|error| format!("{}: {}", user_input, error).into())
+}
+
+/// Reports which rules of the given whitelisting schemas whitelisted at least
+/// one entry of the given corpus - and which never fired.
+///
+/// # Arguments
+///
+/// * `whitelist` - The whitelisting schemas in form of file paths or URLs.
+/// * `corpus` - The file - one subject per line - to check the rules against.
+/// * `allow_complements` - Whether we consider complements while parsing rules.
+pub fn coverage(
+    whitelist: &[String],
+    corpus: &Path,
+    allow_complements: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rules: Vec<String> = vec![];
+
+    for user_input in whitelist {
+        rules.append(&mut read_rules(user_input)?);
+    }
+
+    let mut coverage = Coverage::new(&Ruler::new(allow_complements), &rules)?;
+    let file = File::open(corpus).map_err(|error| format!("{}: {}", corpus.display(), error))?;
+
+    coverage
+        .check(BufReader::new(file))
+        .map_err(|error| format!("{}: {}", corpus.display(), error))?;
+
+    let mut stdout = io::stdout().lock();
+
+    for rule in coverage.rules() {
+        let status = if rule.hits > 0 { "COVERED" } else { "NEVER" };
+
+        writeln!(
+            stdout,
+            "{:<8}{:>8} hit(s)  {}",
+            status, rule.hits, rule.rule
+        )?;
+    }
+
+    let never_fired = coverage.never_fired().len();
+    let total = coverage.rules().len();
+
+    writeln!(
+        stdout,
+        "\nRules: {} covered, {} never fired ({:.2}% covered).",
+        total - never_fired,
+        never_fired,
+        coverage.covered_percentage()
+    )?;
+    writeln!(
+        stdout,
+        "Corpus: {} of {} entries whitelisted ({:.2}%).",
+        coverage.whitelisted(),
+        coverage.corpus_size(),
+        if coverage.corpus_size() == 0 {
+            0.0
+        } else {
+            coverage.whitelisted() as f64 * 100.0 / coverage.corpus_size() as f64
+        }
+    )?;
+
+    Ok(())
+}
+
+/// Reports the rules added, removed or changed between the two given
+/// whitelisting schemas - and optionally the subjects of the given corpus whose
+/// decision differs.
+///
+/// # Arguments
+///
+/// * `old` - The old whitelisting schema in form of a file path or URL.
+/// * `new` - The new whitelisting schema in form of a file path or URL.
+/// * `corpus` - The file - one subject per line - to check both schemas against.
+/// * `allow_complements` - Whether we consider complements while parsing rules.
+pub fn diff(
+    old: &String,
+    new: &String,
+    corpus: Option<&Path>,
+    allow_complements: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut diff = RulesDiff::new(
+        &Ruler::new(allow_complements),
+        &read_rules(old)?,
+        &read_rules(new)?,
+    )?;
+    let mut stdout = io::stdout().lock();
+
+    for (kind, record) in &diff.added {
+        writeln!(stdout, "+ {:<11}{}", kind.as_str(), record)?;
+    }
+
+    for (kind, record) in &diff.removed {
+        writeln!(stdout, "- {:<11}{}", kind.as_str(), record)?;
+    }
+
+    for changed in &diff.changed {
+        let kinds = |kinds: &[MatchKind]| {
+            kinds
+                .iter()
+                .map(|kind| kind.as_str())
+                .collect::<Vec<&str>>()
+                .join(",")
+        };
+
+        writeln!(
+            stdout,
+            "~ {:<11}{} (was {})",
+            kinds(&changed.new),
+            changed.record,
+            kinds(&changed.old)
+        )?;
+    }
+
+    writeln!(
+        stdout,
+        "\nRules: {} added, {} removed, {} changed.",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len()
+    )?;
+
+    let Some(corpus) = corpus else {
+        return Ok(());
+    };
+
+    let file = File::open(corpus).map_err(|error| format!("{}: {}", corpus.display(), error))?;
+    let effect = diff
+        .corpus_effect(BufReader::new(file))
+        .map_err(|error| format!("{}: {}", corpus.display(), error))?;
+
+    writeln!(stdout)?;
+
+    for line in &effect.newly_whitelisted {
+        writeln!(stdout, "+ {}", line)?;
+    }
+
+    for line in &effect.no_longer_whitelisted {
+        writeln!(stdout, "- {}", line)?;
+    }
+
+    writeln!(
+        stdout,
+        "\nCorpus: {} of {} entries newly whitelisted, {} no longer whitelisted.",
+        effect.newly_whitelisted.len(),
+        effect.checked,
+        effect.no_longer_whitelisted.len()
+    )?;
+
+    Ok(())
+}
+
+/// Reports the health of the given whitelisting schemas - what their rules
+/// hold and what they cost.
+///
+/// # Arguments
+///
+/// * `whitelist` - The whitelisting schemas in form of file paths or URLs.
+/// * `allow_complements` - Whether we consider complements while parsing rules.
+pub fn stats(
+    whitelist: &[String],
+    allow_complements: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rules: Vec<String> = vec![];
+
+    for user_input in whitelist {
+        rules.append(&mut read_rules(user_input)?);
+    }
+
+    let stats = RulesStats::new(&Ruler::new(allow_complements), &rules)?;
+    let mut stdout = io::stdout().lock();
+
+    writeln!(stdout, "Rules by type:")?;
+
+    for (flag, count) in &stats.counts {
+        writeln!(stdout, "  {:<12}{:>8}", flag, count)?;
+    }
+
+    writeln!(stdout, "  {:<12}{:>8}", "skipped", stats.skipped)?;
+
+    if !stats.duplicates.is_empty() {
+        writeln!(stdout, "\nDuplicate rules:")?;
+
+        for (rule, count) in &stats.duplicates {
+            writeln!(stdout, "  {:>6}x  {}", count, rule)?;
+        }
+    }
+
+    if !stats.shadowed.is_empty() {
+        writeln!(stdout, "\nShadowed rules:")?;
+
+        for shadowed in &stats.shadowed {
+            writeln!(stdout, "  {} (by {})", shadowed.rule, shadowed.by)?;
+        }
+    }
+
+    writeln!(
+        stdout,
+        "\nRecords: {} ({} from RZD rules).",
+        stats.records, stats.rzd_records
+    )?;
+    writeln!(
+        stdout,
+        "Regular expressions: {} ({} bytes, compiled in {:.2?}).",
+        stats.regexes, stats.regex_length, stats.regex_compile_time
+    )?;
+    writeln!(
+        stdout,
+        "Estimated memory: {:.2} KiB.",
+        stats.estimated_memory as f64 / 1024.0
+    )?;
+    writeln!(
+        stdout,
+        "Rules: {} duplicate, {} shadowed.",
+        stats
+            .duplicates
+            .iter()
+            .map(|(_, count)| count - 1)
+            .sum::<usize>(),
+        stats.shadowed.len()
+    )?;
+
+    Ok(())
 }
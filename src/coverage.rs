@@ -0,0 +1,262 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use fancy_regex::Regex;
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use crate::utils;
+use crate::{MatchKind, Ruler, TivilstaError};
+
+/// A rule and the number of corpus entries it whitelisted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleCoverage {
+    /// The rule - as given.
+    pub rule: String,
+    /// The number of corpus entries whitelisted by the rule.
+    pub hits: usize,
+}
+
+/// Which rules of a whitelist fire against a corpus - essentially test
+/// coverage for whitelist files.
+///
+/// A rule is covered when it is the one that whitelisted - or, for the `EXC `
+/// rules, exempted - at least one entry of the corpus.
+#[derive(Debug)]
+pub struct Coverage {
+    ruler: Ruler,
+    rules: Vec<RuleCoverage>,
+    records: HashMap<(MatchKind, String), Vec<usize>>,
+    regexes: Vec<(usize, Regex)>,
+    corpus_size: usize,
+    whitelisted: usize,
+}
+
+impl Coverage {
+    /// Creates a new Coverage object.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The ruler whose settings are used to parse the rules.
+    /// Its rules are ignored.
+    /// * `rules` - The rules to cover.
+    ///
+    /// # Returns
+    ///
+    /// A new Coverage object or an error if one of the rules is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::coverage::Coverage;
+    /// use tivilsta::Ruler;
+    ///
+    /// let rules = vec![String::from("ALL .example.org"), String::from("example.net")];
+    /// let mut coverage = Coverage::new(&Ruler::new(false), &rules).unwrap();
+    ///
+    /// coverage.check("api.example.org\nexample.com\n".as_bytes()).unwrap();
+    ///
+    /// assert_eq!(coverage.rules()[0].hits, 1);
+    /// assert_eq!(coverage.rules()[1].hits, 0);
+    /// assert_eq!(coverage.covered_percentage(), 50.0);
+    /// ```
+    pub fn new(template: &Ruler, rules: &[String]) -> Result<Coverage, TivilstaError> {
+        let mut result = Coverage {
+            ruler: template.empty_like(),
+            rules: vec![],
+            records: HashMap::new(),
+            regexes: vec![],
+            corpus_size: 0,
+            whitelisted: 0,
+        };
+
+        for rule in rules {
+            if rule.is_empty() || rule.starts_with('#') {
+                continue;
+            }
+
+            let index = result.rules.len();
+            let single =
+                result
+                    .ruler
+                    .try_parse_alone(rule)
+                    .map_err(|error| TivilstaError::Input {
+                        input: rule.to_string(),
+                        source: Box::new(error),
+                    })?;
+
+            for (kind, record) in single.records() {
+                if kind == MatchKind::Regex {
+                    let regex =
+                        single
+                            .compile_regex(record)
+                            .map_err(|error| TivilstaError::Input {
+                                input: rule.to_string(),
+                                source: Box::new(error),
+                            })?;
+
+                    result.regexes.push((index, regex));
+                    continue;
+                }
+
+                result
+                    .records
+                    .entry((kind, record.to_string()))
+                    .or_default()
+                    .push(index);
+            }
+
+            result.rules.push(RuleCoverage {
+                rule: rule.to_string(),
+                hits: 0,
+            });
+            result.ruler.merge(single);
+        }
+
+        Ok(result)
+    }
+
+    /// Checks every line of the given `corpus` against the rules.
+    pub fn check<R: BufRead>(&mut self, corpus: R) -> std::io::Result<()> {
+        for line in corpus.lines() {
+            let line = self.ruler.prepare_line(&line?);
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            self.corpus_size += 1;
+
+            let netloc = utils::extract_netloc(&line);
+
+            if let Some(record) = self.ruler.find_exception(&netloc) {
+                let key = (MatchKind::Exception, record.to_string());
+
+                for index in self.records.get(&key).into_iter().flatten() {
+                    self.rules[*index].hits += 1;
+                }
+
+                continue;
+            }
+
+            let Some(found) = self.ruler.find_line_match(&line) else {
+                continue;
+            };
+
+            self.whitelisted += 1;
+
+            if found.kind == MatchKind::Regex {
+                let subject = self.ruler.normalize(&netloc);
+
+                for (index, regex) in &self.regexes {
+                    if regex.is_match(&subject).unwrap_or(false) {
+                        self.rules[*index].hits += 1;
+                    }
+                }
+            } else if let Some(indexes) = self.records.get(&(found.kind, found.rule.to_string())) {
+                for index in indexes {
+                    self.rules[*index].hits += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Provides the rules - in the given order - with their number of hits.
+    pub fn rules(&self) -> &[RuleCoverage] {
+        &self.rules
+    }
+
+    /// Provides the rules that never fired.
+    pub fn never_fired(&self) -> Vec<&RuleCoverage> {
+        self.rules.iter().filter(|rule| rule.hits == 0).collect()
+    }
+
+    /// Provides the percentage of rules that fired at least once.
+    pub fn covered_percentage(&self) -> f64 {
+        if self.rules.is_empty() {
+            return 0.0;
+        }
+
+        let covered = self.rules.len() - self.never_fired().len();
+
+        covered as f64 * 100.0 / self.rules.len() as f64
+    }
+
+    /// Provides the number of checked corpus entries.
+    pub fn corpus_size(&self) -> usize {
+        self.corpus_size
+    }
+
+    /// Provides the number of corpus entries that were whitelisted.
+    pub fn whitelisted(&self) -> usize {
+        self.whitelisted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        let rules = vec![
+            String::from("example.org"),
+            String::from("# A comment."),
+            String::from("ALL .example.net"),
+            String::from("REG ^ads\\."),
+            String::from("REG ^never\\."),
+            String::from("SUB example.com"),
+            String::from("EXC cdn.example.net"),
+        ];
+        let corpus =
+            "example.org\napi.example.net\nads.example.info\nexample.info\ncdn.example.net\n\n";
+
+        let mut coverage = Coverage::new(&Ruler::new(false), &rules).unwrap();
+
+        coverage.check(corpus.as_bytes()).unwrap();
+
+        assert_eq!(
+            coverage
+                .rules()
+                .iter()
+                .map(|rule| rule.hits)
+                .collect::<Vec<usize>>(),
+            vec![1, 1, 1, 0, 0, 1]
+        );
+        assert_eq!(
+            coverage
+                .never_fired()
+                .iter()
+                .map(|rule| rule.rule.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["REG ^never\\.", "SUB example.com"]
+        );
+        assert_eq!(coverage.covered_percentage(), 4.0 * 100.0 / 6.0);
+        assert_eq!(coverage.corpus_size(), 5);
+        assert_eq!(coverage.whitelisted(), 3);
+    }
+
+    #[test]
+    fn test_coverage_invalid_rule() {
+        assert!(Coverage::new(&Ruler::new(false), &[String::from("REG ^(ads")]).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::error::TivilstaError;
+
+/// Provides the directory where the dataset snapshots are stored.
+///
+/// The following locations are tried - in order:
+///
+/// 1. `$TIVILSTA_CACHE_DIR`
+/// 2. `$XDG_CACHE_HOME/tivilsta`
+/// 3. `$HOME/.cache/tivilsta`
+/// 4. `[temporary directory]/tivilsta`
+pub fn directory() -> PathBuf {
+    if let Some(path) = env::var_os("TIVILSTA_CACHE_DIR") {
+        return PathBuf::from(path);
+    }
+
+    if let Some(path) = env::var_os("XDG_CACHE_HOME") {
+        return PathBuf::from(path).join("tivilsta");
+    }
+
+    if let Some(path) = env::var_os("HOME") {
+        return PathBuf::from(path).join(".cache").join("tivilsta");
+    }
+
+    env::temp_dir().join("tivilsta")
+}
+
+/// Provides the path of the snapshot with the given `name`.
+pub fn snapshot_path(name: &str) -> PathBuf {
+    directory().join(name)
+}
+
+/// Reads and parses the snapshot with the given `name`.
+///
+/// # Returns
+///
+/// `None` if the snapshot doesn't exist or isn't a valid JSON file.
+pub fn read_snapshot(name: &str) -> Option<Value> {
+    let content = fs::read_to_string(snapshot_path(name)).ok()?;
+
+    serde_json::from_str(&content).ok()
+}
+
+/// Writes the given `content` as the snapshot with the given `name`.
+///
+/// The content is first written into a temporary file that is then moved into
+/// place. Therefore, a reader never sees a partially written snapshot.
+///
+/// # Returns
+///
+/// The path of the written snapshot.
+pub fn write_snapshot(name: &str, content: &Value) -> Result<PathBuf, TivilstaError> {
+    let destination = snapshot_path(name);
+    let tmp_destination = directory().join(format!(".{}.tmp", name));
+
+    fs::create_dir_all(directory())
+        .map_err(|source| TivilstaError::io(directory().display(), source))?;
+    fs::write(&tmp_destination, content.to_string())
+        .map_err(|source| TivilstaError::io(tmp_destination.display(), source))?;
+    fs::rename(&tmp_destination, &destination)
+        .map_err(|source| TivilstaError::io(destination.display(), source))?;
+
+    Ok(destination)
+}
+
+/// Provides the age of the snapshot with the given `name`.
+///
+/// # Returns
+///
+/// `None` if the snapshot doesn't exist.
+pub fn snapshot_age(name: &str) -> Option<Duration> {
+    let modified = fs::metadata(snapshot_path(name)).ok()?.modified().ok()?;
+
+    Some(
+        SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default(),
+    )
+}
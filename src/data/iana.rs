@@ -18,28 +18,126 @@
 //      See the License for the specific language governing permissions and
 //      limitations under the License.
 
-#![allow(dead_code)]
+//! The IANA Root Zone Database - as maintained by the PyFunceble project.
+//!
+//! The registry maps every TLD to its WHOIS server. It is read from the
+//! snapshot stored by `tivilsta update-data` when there is one - and fetched
+//! otherwise - once per process.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use tivilsta::data::iana;
+//!
+//! let servers = iana::whois_servers().unwrap();
+//!
+//! assert_eq!(servers["com"].as_deref(), Some("whois.verisign-grs.com"));
+//! ```
 
 use serde_json::Value;
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
 
-use crate::utils;
+use crate::data;
+use crate::data::cache;
+use crate::error::TivilstaError;
 
-/// Fetches the IANA registry of the PyFunceble project and provide the `reqwest` response
-/// for other to use.
-fn fetch_mapping() -> Result<reqwest::blocking::Response, Box<dyn std::error::Error>> {
-    utils::fetch_url(
-        &String::from(
-            "https://raw.githubusercontent.com/PyFunceble/iana/master/iana-domains-db.json",
-        ),
-        String::from("Failed to fetch IANA extensions. Is GitHub down?"),
+/// The name of the snapshot of the IANA registry in the cache directory.
+pub const SNAPSHOT_NAME: &str = "iana-domains-db.json";
+
+/// The location of the IANA registry of the PyFunceble project.
+pub const DEFAULT_URL: &str =
+    "https://raw.githubusercontent.com/PyFunceble/iana/master/iana-domains-db.json";
+
+/// The environment variable holding the - comma or space separated - URLs of
+/// mirrors of the IANA registry to try in order instead of [`DEFAULT_URL`].
+pub const URLS_VARIABLE: &str = "TIVILSTA_IANA_URLS";
+
+/// Provides the URLs the IANA registry is fetched from - in order of
+/// preference. See [`URLS_VARIABLE`].
+pub fn urls() -> Vec<String> {
+    data::mirrors(URLS_VARIABLE, DEFAULT_URL)
+}
+
+/// Provides the IANA registry. The snapshot stored in the cache directory -
+/// see `tivilsta update-data` - is preferred over the remote registry and its
+/// mirrors.
+///
+/// The registry is only read - or fetched - once per process.
+fn mapping() -> Result<&'static Value, TivilstaError> {
+    static MAPPING: OnceLock<Value> = OnceLock::new();
+
+    if let Some(mapping) = MAPPING.get() {
+        return Ok(mapping);
+    }
+
+    let mapping = match cache::read_snapshot(SNAPSHOT_NAME) {
+        Some(snapshot) if validate(&snapshot).is_ok() => snapshot,
+        _ => fetch()?,
+    };
+
+    Ok(MAPPING.get_or_init(|| mapping))
+}
+
+/// Fetches the IANA registry of the PyFunceble project and validates it.
+///
+/// Its URLs - see [`urls`] - are tried in order until one serves a valid
+/// registry.
+pub fn fetch() -> Result<Value, TivilstaError> {
+    data::fetch_first(
+        "IANA registry",
+        &urls(),
+        validate,
+        "Failed to fetch IANA extensions. Is GitHub down?",
     )
 }
 
+/// Fetches the IANA registry of the PyFunceble project and stores it into the
+/// cache directory. Subsequent runs will use it instead of reaching the network.
+///
+/// **Note:** The registry already read by the current process is kept.
+///
+/// # Returns
+///
+/// The path of the stored snapshot.
+pub fn update() -> Result<PathBuf, TivilstaError> {
+    cache::write_snapshot(SNAPSHOT_NAME, &fetch()?)
+}
+
+/// Validates the given IANA registry.
+///
+/// A valid registry is a non-empty JSON object mapping each TLD to its WHOIS
+/// server - or `null` when there is none.
+pub fn validate(mapping: &Value) -> Result<(), TivilstaError> {
+    let invalid = |message: &str| {
+        Err(TivilstaError::InvalidDataset {
+            name: String::from("IANA registry"),
+            message: message.to_string(),
+        })
+    };
+
+    let Some(mapping) = mapping.as_object() else {
+        return invalid("not a JSON object.");
+    };
+
+    if mapping.is_empty() {
+        return invalid("no extension found.");
+    }
+
+    for (extension, server) in mapping {
+        if !server.is_string() && !server.is_null() {
+            return invalid(&format!("unexpected WHOIS server for `{}`.", extension));
+        }
+    }
+
+    Ok(())
+}
+
 /// Fetches the IANA registry of the PyFunceble project, parse it and return
 /// all known TLDs.
-pub fn extensions() -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let response: Value = fetch_mapping()?.json()?;
+pub fn extensions() -> Result<Vec<String>, TivilstaError> {
+    let response = mapping()?;
     let mut result: Vec<String> = Vec::new();
 
     for (key, _) in response.as_object().unwrap() {
@@ -48,18 +146,6 @@ pub fn extensions() -> Result<Vec<String>, Box<dyn std::error::Error>> {
     Ok(result)
 }
 
-/// Get all known TLDs and parse them as a huge Regex String with the following
-/// format:
-///
-/// ```txt
-/// ((?:\.(?:com)))|((?:\.(?:de)))
-/// ```
-///
-/// Where `com` and `de` are TLDs.
-pub fn extensions_regex_string() -> String {
-    utils::to_regex_string(extensions())
-}
-
 /// Read the IANA registry of the PyFunceble project and return all known
 /// TLDs and their whois server.
 ///
@@ -73,16 +159,45 @@ pub fn extensions_regex_string() -> String {
 /// ```
 ///
 /// Where `com` is the Top Level Domain (TlD) and `whois.nic.com` is the WHOIS server.
-pub fn extensions_and_whois() -> Result<HashMap<String, Option<String>>, Box<dyn std::error::Error>>
-{
-    let response: Value = fetch_mapping()?.json()?;
+/// The TLDs without WHOIS server are mapped to `None`.
+pub fn extensions_and_whois() -> Result<HashMap<String, Option<String>>, TivilstaError> {
+    let response = mapping()?;
     let mut result: HashMap<String, Option<String>> = HashMap::new();
 
     for (key, value) in response.as_object().unwrap() {
-        result.insert(
-            key.to_string(),
-            Some(value.as_str().unwrap_or("").to_string()),
-        );
+        result.insert(key.to_string(), value.as_str().map(String::from));
     }
     Ok(result)
 }
+
+/// Provides the cached mapping of every TLD to its WHOIS server - see
+/// [`extensions_and_whois`].
+pub fn whois_servers() -> Result<&'static HashMap<String, Option<String>>, TivilstaError> {
+    static SERVERS: OnceLock<HashMap<String, Option<String>>> = OnceLock::new();
+
+    if let Some(servers) = SERVERS.get() {
+        return Ok(servers);
+    }
+
+    let servers = extensions_and_whois()?;
+
+    Ok(SERVERS.get_or_init(|| servers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&json!({"com": "whois.nic.com", "example": null})).is_ok());
+    }
+
+    #[test]
+    fn test_validate_invalid() {
+        assert!(validate(&json!([])).is_err());
+        assert!(validate(&json!({})).is_err());
+        assert!(validate(&json!({"com": ["whois.nic.com"]})).is_err());
+    }
+}
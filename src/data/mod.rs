@@ -18,5 +18,183 @@
 //      See the License for the specific language governing permissions and
 //      limitations under the License.
 
+//! The datasets - IANA Root Zone Database, Public Suffix List, etc. - used
+//! by tivilsta.
+//!
+//! The datasets are fetched - or read from the snapshots stored by
+//! `tivilsta update-data` - and parsed once per process. Applications
+//! embedding tivilsta can reuse them instead of fetching and parsing them
+//! separately.
+
+pub mod cache;
 pub mod iana;
+pub mod presets;
 pub mod psl;
+pub mod registry;
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+
+use crate::error::TivilstaError;
+use crate::utils;
+
+/// Provides the WHOIS server of the TLD of the given `domain` - e.g.
+/// `whois.nic.uk` for `example.co.uk` - as listed by the IANA Root Zone
+/// Database.
+///
+/// # Arguments
+///
+/// * `domain` - The domain to look up.
+///
+/// # Returns
+///
+/// The WHOIS server, `None` if the TLD is unknown or has no WHOIS server, or
+/// an error if the IANA dataset can't be fetched.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use tivilsta::data::whois_server_for;
+///
+/// assert_eq!(
+///     whois_server_for("example.co.uk").unwrap(),
+///     Some(String::from("whois.nic.uk"))
+/// );
+/// ```
+pub fn whois_server_for(domain: &str) -> Result<Option<String>, TivilstaError> {
+    Ok(lookup_whois_server(iana::whois_servers()?, domain))
+}
+
+/// Provides the WHOIS server of the TLD of the given `domain` from the given
+/// TLD to WHOIS server mapping.
+fn lookup_whois_server(servers: &HashMap<String, Option<String>>, domain: &str) -> Option<String> {
+    let tld = domain.trim().trim_end_matches('.').rsplit('.').next()?;
+    let tld = idna::domain_to_ascii(tld).ok()?;
+
+    servers.get(&tld)?.clone()
+}
+
+/// Provides the URLs a dataset is fetched from - in order of preference.
+///
+/// They are read - comma or space separated - from the given environment
+/// `variable`. The given `default` URL is used when it is not set or empty.
+pub(crate) fn mirrors(variable: &str, default: &str) -> Vec<String> {
+    parse_mirrors(env::var(variable).ok().as_deref(), default)
+}
+
+/// Splits the given - comma or space separated - list of URLs.
+fn parse_mirrors(urls: Option<&str>, default: &str) -> Vec<String> {
+    let urls: Vec<String> = urls
+        .unwrap_or_default()
+        .split(|x: char| x == ',' || x.is_whitespace())
+        .filter(|url| !url.is_empty())
+        .map(String::from)
+        .collect();
+
+    if urls.is_empty() {
+        return vec![default.to_string()];
+    }
+
+    urls
+}
+
+/// Fetches the first of the given `urls` whose content is a valid dataset -
+/// according to the given `validate` function. The next URL is tried when
+/// one can't be reached or serves an invalid dataset.
+///
+/// # Returns
+///
+/// The dataset or an error mentioning why each URL was rejected.
+pub(crate) fn fetch_first(
+    name: &str,
+    urls: &[String],
+    validate: fn(&Value) -> Result<(), TivilstaError>,
+    error_message: &str,
+) -> Result<Value, TivilstaError> {
+    let mut errors = vec![];
+
+    for url in urls {
+        let dataset = utils::fetch_url(url, error_message.to_string())
+            .and_then(|response| {
+                response
+                    .json::<Value>()
+                    .map_err(|source| TivilstaError::Http {
+                        url: Some(url.to_string()),
+                        source,
+                    })
+            })
+            .and_then(|dataset| validate(&dataset).map(|_| dataset));
+
+        match dataset {
+            Ok(dataset) => return Ok(dataset),
+            Err(error) => errors.push((url.to_string(), error)),
+        }
+    }
+
+    Err(TivilstaError::DataSource {
+        name: name.to_string(),
+        errors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_whois_server() {
+        let servers = HashMap::from([
+            (String::from("uk"), Some(String::from("whois.nic.uk"))),
+            (
+                String::from("xn--p1ai"),
+                Some(String::from("whois.tcinet.ru")),
+            ),
+            (String::from("example"), None),
+        ]);
+
+        assert_eq!(
+            lookup_whois_server(&servers, "example.co.uk"),
+            Some(String::from("whois.nic.uk"))
+        );
+        assert_eq!(
+            lookup_whois_server(&servers, "EXAMPLE.UK."),
+            Some(String::from("whois.nic.uk"))
+        );
+        assert_eq!(
+            lookup_whois_server(&servers, "пример.рф"),
+            Some(String::from("whois.tcinet.ru"))
+        );
+        assert_eq!(lookup_whois_server(&servers, "test.example"), None);
+        assert_eq!(lookup_whois_server(&servers, "example.org"), None);
+    }
+
+    #[test]
+    fn test_parse_mirrors() {
+        let default = "https://example.org/psl.json";
+
+        assert_eq!(parse_mirrors(None, default), vec![default]);
+        assert_eq!(parse_mirrors(Some(" , "), default), vec![default]);
+        assert_eq!(
+            parse_mirrors(
+                Some("https://mirror.example.net/psl.json, https://example.com/psl.json"),
+                default
+            ),
+            vec![
+                "https://mirror.example.net/psl.json",
+                "https://example.com/psl.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fetch_first() {
+        let urls = vec![String::from("not a url"), String::from("neither")];
+        let error = fetch_first("test dataset", &urls, |_| Ok(()), "Failed.")
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.starts_with("not a url: "));
+        assert!(error.contains(" neither: "));
+    }
+}
@@ -0,0 +1,67 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+/// The built-in presets - their name and their rules.
+///
+/// The presets are shipped with the crate so that every user doesn't have to
+/// maintain their own copy of the same baseline rules.
+pub const PRESETS: [(&str, &str); 3] = [
+    ("core", include_str!("presets/core.list")),
+    ("connectivity", include_str!("presets/connectivity.list")),
+    ("pki", include_str!("presets/pki.list")),
+];
+
+/// Provides the names of the built-in presets.
+pub fn names() -> Vec<&'static str> {
+    PRESETS.iter().map(|(name, _)| *name).collect()
+}
+
+/// Provides the rules of the given preset.
+///
+/// # Arguments
+///
+/// * `name` - The name of the preset.
+///
+/// # Returns
+///
+/// The rules of the preset - comments and empty lines included - or `None`
+/// if there is no such preset.
+pub fn rules(name: &str) -> Option<Vec<String>> {
+    PRESETS
+        .iter()
+        .find(|(preset, _)| *preset == name)
+        .map(|(_, content)| content.lines().map(String::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        assert_eq!(names(), vec!["core", "connectivity", "pki"]);
+        assert!(rules("unknown").is_none());
+
+        let core = rules("core").unwrap();
+
+        assert!(core.contains(&String::from("localhost")));
+        assert!(core.contains(&String::from("ALL .test")));
+    }
+}
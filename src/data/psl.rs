@@ -18,28 +18,132 @@
 //      See the License for the specific language governing permissions and
 //      limitations under the License.
 
-#![allow(dead_code)]
+//! The Public Suffix List - as maintained by the PyFunceble project.
+//!
+//! The registry maps every TLD to its public suffixes. It is read from the
+//! snapshot stored by `tivilsta update-data` when there is one - and fetched
+//! otherwise - once per process.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use tivilsta::data::psl;
+//!
+//! assert_eq!(psl::public_suffix("a.b.example.co.uk").unwrap(), Some("co.uk"));
+//! assert_eq!(
+//!     psl::registrable_domain("a.b.example.co.uk").unwrap(),
+//!     Some("example.co.uk")
+//! );
+//! ```
 
 use serde_json::Value;
-use std::collections::HashMap;
-
-use crate::utils;
-
-/// Fetches the PSL registry of the PyFunceble project and provide the `reqwest` response
-/// for other to use.
-fn fetch_mapping() -> Result<reqwest::blocking::Response, Box<dyn std::error::Error>> {
-    utils::fetch_url(
-        &String::from(
-            "https://raw.githubusercontent.com/PyFunceble/public-suffix/master/public-suffix.json",
-        ),
-        "Failed to fetch PSL. Is GitHub down?".to_string(),
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::data;
+use crate::data::cache;
+use crate::error::TivilstaError;
+
+/// The name of the snapshot of the PSL registry in the cache directory.
+pub const SNAPSHOT_NAME: &str = "public-suffix.json";
+
+/// The location of the PSL registry of the PyFunceble project.
+pub const DEFAULT_URL: &str =
+    "https://raw.githubusercontent.com/PyFunceble/public-suffix/master/public-suffix.json";
+
+/// The environment variable holding the - comma or space separated - URLs of
+/// mirrors of the PSL registry to try in order instead of [`DEFAULT_URL`].
+pub const URLS_VARIABLE: &str = "TIVILSTA_PSL_URLS";
+
+/// Provides the URLs the PSL registry is fetched from - in order of
+/// preference. See [`URLS_VARIABLE`].
+pub fn urls() -> Vec<String> {
+    data::mirrors(URLS_VARIABLE, DEFAULT_URL)
+}
+
+/// Provides the PSL registry. The snapshot stored in the cache directory -
+/// see `tivilsta update-data` - is preferred over the remote registry and its
+/// mirrors.
+///
+/// The registry is only read - or fetched - once per process.
+fn mapping() -> Result<&'static Value, TivilstaError> {
+    static MAPPING: OnceLock<Value> = OnceLock::new();
+
+    if let Some(mapping) = MAPPING.get() {
+        return Ok(mapping);
+    }
+
+    let mapping = match cache::read_snapshot(SNAPSHOT_NAME) {
+        Some(snapshot) if validate(&snapshot).is_ok() => snapshot,
+        _ => fetch()?,
+    };
+
+    Ok(MAPPING.get_or_init(|| mapping))
+}
+
+/// Fetches the PSL registry of the PyFunceble project and validates it.
+///
+/// Its URLs - see [`urls`] - are tried in order until one serves a valid
+/// registry.
+pub fn fetch() -> Result<Value, TivilstaError> {
+    data::fetch_first(
+        "PSL registry",
+        &urls(),
+        validate,
+        "Failed to fetch PSL. Is GitHub down?",
     )
 }
 
+/// Fetches the PSL registry of the PyFunceble project and stores it into the
+/// cache directory. Subsequent runs will use it instead of reaching the network.
+///
+/// **Note:** The registry already read by the current process is kept.
+///
+/// # Returns
+///
+/// The path of the stored snapshot.
+pub fn update() -> Result<PathBuf, TivilstaError> {
+    cache::write_snapshot(SNAPSHOT_NAME, &fetch()?)
+}
+
+/// Validates the given PSL registry.
+///
+/// A valid registry is a non-empty JSON object mapping each extension to the
+/// list of its public suffixes.
+pub fn validate(mapping: &Value) -> Result<(), TivilstaError> {
+    let invalid = |message: &str| {
+        Err(TivilstaError::InvalidDataset {
+            name: String::from("PSL registry"),
+            message: message.to_string(),
+        })
+    };
+
+    let Some(mapping) = mapping.as_object() else {
+        return invalid("not a JSON object.");
+    };
+
+    if mapping.is_empty() {
+        return invalid("no extension found.");
+    }
+
+    for (extension, suffixes) in mapping {
+        let valid = suffixes
+            .as_array()
+            .is_some_and(|suffixes| suffixes.iter().all(|x| x.is_string()));
+
+        if !valid {
+            return invalid(&format!("unexpected suffixes for `{}`.", extension));
+        }
+    }
+
+    Ok(())
+}
+
 /// Fetches the PSL registry of the PyFunceble project, parse it and return
 /// all known TLDs.
-pub fn extensions() -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let response: Value = fetch_mapping()?.json()?;
+pub fn extensions() -> Result<Vec<String>, TivilstaError> {
+    let response = mapping()?;
     let mut result: Vec<String> = Vec::new();
 
     for (extension, _) in response.as_object().unwrap() {
@@ -50,8 +154,8 @@ pub fn extensions() -> Result<Vec<String>, Box<dyn std::error::Error>> {
 
 /// Fetches the PSL registry of the PyFunceble project, parse it and return
 /// all known public suffixes.
-pub fn suffixes() -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let response: Value = fetch_mapping()?.json()?;
+pub fn suffixes() -> Result<Vec<String>, TivilstaError> {
+    let response = mapping()?;
     let mut result: Vec<String> = Vec::new();
 
     for (_, suffixes) in response.as_object().unwrap() {
@@ -62,32 +166,8 @@ pub fn suffixes() -> Result<Vec<String>, Box<dyn std::error::Error>> {
     Ok(result)
 }
 
-/// Get all known suffixes and parse them as a huge Regex String with the following
-/// format:
-///
-/// ```txt
-/// ((?:\.(?:\.co\.uk)))|((?:\.(?:de.example)))
-/// ```
-///
-/// Where `co.uk` and `de.example` are suffixes.
-pub fn suffixes_regex_string() -> String {
-    utils::to_regex_string(suffixes())
-}
-
-/// Get all known suffixes and parse them as a huge Regex String with the following
-/// format:
-///
-/// ```txt
-/// ((?:\.(?:com)))|((?:\.(?:de)))
-/// ```
-///
-/// Where `com` and `de` are TLDs.
-pub fn extensions_regex_string() -> String {
-    utils::to_regex_string(extensions())
-}
-
-/// Read the IANA registry of the PyFunceble project and return all known
-/// TLDs and their whois server.
+/// Read the PSL registry of the PyFunceble project and return all known
+/// TLDs and their public suffixes.
 ///
 /// The registry is a JSON file that has the following format:
 ///
@@ -102,9 +182,8 @@ pub fn extensions_regex_string() -> String {
 ///
 /// Where `com` is the Top Level Domain (TlD) and `xx.com`+`xy.com` public suffixes.
 
-pub fn extensions_and_suffixes() -> Result<HashMap<String, Vec<String>>, Box<dyn std::error::Error>>
-{
-    let response: Value = fetch_mapping()?.json()?;
+pub fn extensions_and_suffixes() -> Result<HashMap<String, Vec<String>>, TivilstaError> {
+    let response = mapping()?;
     let mut result: HashMap<String, Vec<String>> = HashMap::new();
 
     for (extension, suffixes) in response.as_object().unwrap() {
@@ -120,3 +199,161 @@ pub fn extensions_and_suffixes() -> Result<HashMap<String, Vec<String>>, Box<dyn
     }
     Ok(result)
 }
+
+/// The extensions and public suffixes of the PSL registry.
+#[derive(Debug, Default)]
+pub struct PublicSuffixes {
+    suffixes: HashSet<String>,
+}
+
+impl PublicSuffixes {
+    /// Loads the extensions and public suffixes of the PSL registry.
+    pub fn load() -> Result<PublicSuffixes, TivilstaError> {
+        let mut suffixes: HashSet<String> = HashSet::new();
+
+        for (extension, extension_suffixes) in extensions_and_suffixes()? {
+            suffixes.insert(extension);
+            suffixes.extend(extension_suffixes);
+        }
+
+        Ok(PublicSuffixes { suffixes })
+    }
+
+    /// Creates a new PublicSuffixes object from the given suffixes.
+    pub fn from_suffixes(suffixes: &[&str]) -> PublicSuffixes {
+        PublicSuffixes {
+            suffixes: suffixes.iter().map(|suffix| suffix.to_string()).collect(),
+        }
+    }
+
+    /// Provides the public suffix of the given `subject` - e.g. `co.uk` for
+    /// `api.example.co.uk`.
+    ///
+    /// # Returns
+    ///
+    /// The longest known suffix of the subject - which may be the subject
+    /// itself - or `None` when its extension is unknown.
+    pub fn public_suffix<'a>(&self, subject: &'a str) -> Option<&'a str> {
+        let subject = subject.trim_end_matches('.');
+        let mut start = 0;
+
+        loop {
+            let candidate = &subject[start..];
+
+            // The first match is the longest suffix.
+            if self.suffixes.contains(candidate) {
+                return Some(candidate);
+            }
+
+            start += candidate.find('.')? + 1;
+        }
+    }
+
+    /// Provides the registrable domain of the given `subject` - e.g.
+    /// `example.co.uk` for `api.example.co.uk`.
+    ///
+    /// # Returns
+    ///
+    /// `None` when the subject is itself a public suffix or when its
+    /// extension is unknown.
+    pub fn registrable_domain<'a>(&self, subject: &'a str) -> Option<&'a str> {
+        let subject = subject.trim_end_matches('.');
+        let mut previous: Option<usize> = None;
+        let mut start = 0;
+
+        loop {
+            let candidate = &subject[start..];
+
+            // The first match is the longest suffix.
+            if self.suffixes.contains(candidate) {
+                return previous.map(|index| &subject[index..]);
+            }
+
+            previous = Some(start);
+            start += candidate.find('.')? + 1;
+        }
+    }
+}
+
+/// Provides the public suffix of the given `subject` - e.g. `co.uk` for
+/// `a.b.example.co.uk` - see [`PublicSuffixes::public_suffix`].
+///
+/// # Returns
+///
+/// The public suffix, `None` when the extension of the subject is unknown or
+/// an error if the PSL registry can't be fetched.
+pub fn public_suffix(subject: &str) -> Result<Option<&str>, TivilstaError> {
+    Ok(public_suffixes()?.public_suffix(subject))
+}
+
+/// Provides the registrable domain of the given `subject` - e.g.
+/// `example.co.uk` for `a.b.example.co.uk` - see
+/// [`PublicSuffixes::registrable_domain`].
+///
+/// # Returns
+///
+/// The registrable domain, `None` when the subject is itself a public suffix
+/// or when its extension is unknown, or an error if the PSL registry can't be
+/// fetched.
+pub fn registrable_domain(subject: &str) -> Result<Option<&str>, TivilstaError> {
+    Ok(public_suffixes()?.registrable_domain(subject))
+}
+
+/// Provides the cached extensions and public suffixes of the PSL registry -
+/// see [`PublicSuffixes::load`].
+pub fn public_suffixes() -> Result<&'static PublicSuffixes, TivilstaError> {
+    static SUFFIXES: OnceLock<PublicSuffixes> = OnceLock::new();
+
+    if let Some(suffixes) = SUFFIXES.get() {
+        return Ok(suffixes);
+    }
+
+    let suffixes = PublicSuffixes::load()?;
+
+    Ok(SUFFIXES.get_or_init(|| suffixes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&json!({"uk": ["co.uk", "gov.uk"], "example": []})).is_ok());
+    }
+
+    #[test]
+    fn test_registrable_domain() {
+        let suffixes = PublicSuffixes::from_suffixes(&["uk", "co.uk", "com"]);
+
+        assert_eq!(
+            suffixes.registrable_domain("api.example.co.uk"),
+            Some("example.co.uk")
+        );
+        assert_eq!(
+            suffixes.registrable_domain("example.com."),
+            Some("example.com")
+        );
+        assert_eq!(suffixes.registrable_domain("co.uk"), None);
+        assert_eq!(suffixes.registrable_domain("example.invalid"), None);
+    }
+
+    #[test]
+    fn test_public_suffix() {
+        let suffixes = PublicSuffixes::from_suffixes(&["uk", "co.uk", "com"]);
+
+        assert_eq!(suffixes.public_suffix("a.b.example.co.uk"), Some("co.uk"));
+        assert_eq!(suffixes.public_suffix("example.uk."), Some("uk"));
+        assert_eq!(suffixes.public_suffix("co.uk"), Some("co.uk"));
+        assert_eq!(suffixes.public_suffix("example.invalid"), None);
+    }
+
+    #[test]
+    fn test_validate_invalid() {
+        assert!(validate(&json!("uk")).is_err());
+        assert!(validate(&json!({})).is_err());
+        assert!(validate(&json!({"uk": "co.uk"})).is_err());
+        assert!(validate(&json!({"uk": [1]})).is_err());
+    }
+}
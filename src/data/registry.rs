@@ -0,0 +1,166 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::data::cache;
+use crate::error::TivilstaError;
+use crate::utils;
+
+/// The name of the snapshot of the registry in the cache directory.
+pub const SNAPSHOT_NAME: &str = "registry.json";
+
+/// The registry shipped with the crate. It is used until a snapshot is
+/// stored - see `tivilsta update-data`.
+const BUNDLED: &str = include_str!("registry.json");
+
+/// The URL of the registry of the tivilsta project.
+const URL: &str =
+    "https://raw.githubusercontent.com/funilrys/tivilsta/master/src/data/registry.json";
+
+/// Fetches the registry of the tivilsta project and provide the `reqwest`
+/// response for other to use.
+fn fetch_mapping() -> Result<reqwest::blocking::Response, TivilstaError> {
+    utils::fetch_url(
+        &String::from(URL),
+        String::from("Failed to fetch the registry. Is GitHub down?"),
+    )
+}
+
+/// Provides the registry. The snapshot stored in the cache directory is
+/// preferred over the bundled registry.
+fn mapping() -> Value {
+    match cache::read_snapshot(SNAPSHOT_NAME) {
+        Some(snapshot) if validate(&snapshot).is_ok() => snapshot,
+        _ => serde_json::from_str(BUNDLED).unwrap(),
+    }
+}
+
+/// Fetches the registry of the tivilsta project and validates it.
+pub fn fetch() -> Result<Value, TivilstaError> {
+    let response: Value = fetch_mapping()?
+        .json()
+        .map_err(|source| TivilstaError::Http {
+            url: Some(URL.to_string()),
+            source,
+        })?;
+
+    validate(&response)?;
+    Ok(response)
+}
+
+/// Fetches the registry of the tivilsta project and stores it into the cache
+/// directory. Subsequent runs will use it instead of the bundled registry.
+///
+/// # Returns
+///
+/// The path of the stored snapshot.
+pub fn update() -> Result<PathBuf, TivilstaError> {
+    cache::write_snapshot(SNAPSHOT_NAME, &fetch()?)
+}
+
+/// Validates the given registry.
+///
+/// A valid registry is a non-empty JSON object mapping each name to the URL
+/// of its list.
+pub fn validate(mapping: &Value) -> Result<(), TivilstaError> {
+    let invalid = |message: &str| {
+        Err(TivilstaError::InvalidDataset {
+            name: String::from("registry"),
+            message: message.to_string(),
+        })
+    };
+
+    let Some(mapping) = mapping.as_object() else {
+        return invalid("not a JSON object.");
+    };
+
+    if mapping.is_empty() {
+        return invalid("no entry found.");
+    }
+
+    for (name, url) in mapping {
+        if !url.as_str().is_some_and(|url| url.contains("://")) {
+            return invalid(&format!("unexpected URL for `{}`.", name));
+        }
+    }
+
+    Ok(())
+}
+
+/// Provides the names - without the leading `@` - and URLs of the known
+/// lists.
+pub fn entries() -> BTreeMap<String, String> {
+    mapping()
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(name, url)| (name.to_string(), url.as_str().unwrap().to_string()))
+        .collect()
+}
+
+/// Resolves the given `user_input` into the URL of the list it names - when
+/// it starts with `@` - e.g. `@ultimate-hosts-blacklist/whitelist`.
+///
+/// # Returns
+///
+/// The URL of the named list, the unchanged `user_input` when it doesn't
+/// start with `@` or an error when the name is unknown.
+pub fn resolve(user_input: &str) -> Result<String, TivilstaError> {
+    let Some(name) = user_input.strip_prefix('@') else {
+        return Ok(user_input.to_string());
+    };
+
+    entries()
+        .remove(&name.to_lowercase())
+        .ok_or_else(|| TivilstaError::UnknownList {
+            name: user_input.to_string(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled() {
+        assert!(validate(&serde_json::from_str(BUNDLED).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&serde_json::json!({"a/b": "https://example.org/b.list"})).is_ok());
+        assert!(validate(&serde_json::json!({})).is_err());
+        assert!(validate(&serde_json::json!({"a/b": "b.list"})).is_err());
+        assert!(validate(&serde_json::json!(["https://example.org/b.list"])).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("whitelist.list").unwrap(), "whitelist.list");
+        assert_eq!(
+            resolve("@Ultimate-Hosts-Blacklist/whitelist").unwrap(),
+            "https://raw.githubusercontent.com/Ultimate-Hosts-Blacklist/whitelist/master/domains.list"
+        );
+        assert!(resolve("@unknown/list").is_err());
+    }
+}
@@ -0,0 +1,271 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::BufRead;
+
+use crate::{MatchKind, Ruler, TivilstaError};
+
+/// A record - as stored after the prefix and complement expansion - whose
+/// kinds differ between the two sets of rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedRule {
+    /// The record.
+    pub record: String,
+    /// The kinds of the record in the old rules.
+    pub old: Vec<MatchKind>,
+    /// The kinds of the record in the new rules.
+    pub new: Vec<MatchKind>,
+}
+
+/// The lines of a corpus whose decision differs between the two sets of
+/// rules.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CorpusEffect {
+    /// The number of checked corpus entries.
+    pub checked: usize,
+    /// The entries that are only whitelisted by the new rules.
+    pub newly_whitelisted: Vec<String>,
+    /// The entries that are only whitelisted by the old rules.
+    pub no_longer_whitelisted: Vec<String>,
+}
+
+/// The semantic difference between two sets of rules.
+///
+/// The rules are compared through their *effective* records - after the
+/// prefix and complement expansion - instead of their text. Reordering a
+/// whitelist or rewriting a rule into an equivalent one - e.g. `example.org` +
+/// `SUB example.org` instead of `ALL .example.org` - is therefore not reported.
+#[derive(Debug)]
+pub struct RulesDiff {
+    /// The records only held by the new rules.
+    pub added: Vec<(MatchKind, String)>,
+    /// The records only held by the old rules.
+    pub removed: Vec<(MatchKind, String)>,
+    /// The records held by both rules - with different kinds.
+    pub changed: Vec<ChangedRule>,
+    old: Ruler,
+    new: Ruler,
+}
+
+/// The kinds of each record of a ruler.
+type Records = BTreeMap<String, BTreeSet<MatchKind>>;
+
+/// Parses the given `rules` into a new ruler and provides the kinds of each
+/// of its records.
+fn effective_rules(template: &Ruler, rules: &[String]) -> Result<(Ruler, Records), TivilstaError> {
+    let mut ruler = template.empty_like();
+    let mut records = Records::new();
+
+    for rule in rules {
+        if rule.is_empty() || rule.starts_with('#') {
+            continue;
+        }
+
+        let single = ruler
+            .try_parse_alone(rule)
+            .map_err(|error| TivilstaError::Input {
+                input: rule.to_string(),
+                source: Box::new(error),
+            })?;
+
+        for (kind, record) in single.records() {
+            records.entry(record.to_string()).or_default().insert(kind);
+        }
+
+        ruler.merge(single);
+    }
+
+    Ok((ruler, records))
+}
+
+impl RulesDiff {
+    /// Creates a new RulesDiff object.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The ruler whose settings are used to parse the rules.
+    /// Its rules are ignored.
+    /// * `old` - The old rules.
+    /// * `new` - The new rules.
+    ///
+    /// # Returns
+    ///
+    /// A new RulesDiff object or an error if one of the rules is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::diff::RulesDiff;
+    /// use tivilsta::{MatchKind, Ruler};
+    ///
+    /// let old = vec![String::from("example.org"), String::from("example.net")];
+    /// let new = vec![String::from("example.net"), String::from("SUB example.org")];
+    ///
+    /// let diff = RulesDiff::new(&Ruler::new(false), &old, &new).unwrap();
+    ///
+    /// assert_eq!(diff.added, vec![(MatchKind::Ends, String::from(".example.org"))]);
+    /// assert_eq!(diff.removed, vec![(MatchKind::Strict, String::from("example.org"))]);
+    /// assert!(diff.changed.is_empty());
+    /// ```
+    pub fn new(
+        template: &Ruler,
+        old: &[String],
+        new: &[String],
+    ) -> Result<RulesDiff, TivilstaError> {
+        let (old_ruler, old_records) = effective_rules(template, old)?;
+        let (new_ruler, new_records) = effective_rules(template, new)?;
+
+        let mut result = RulesDiff {
+            added: vec![],
+            removed: vec![],
+            changed: vec![],
+            old: old_ruler,
+            new: new_ruler,
+        };
+
+        for (record, kinds) in &new_records {
+            match old_records.get(record) {
+                None => result
+                    .added
+                    .extend(kinds.iter().map(|kind| (*kind, record.to_string()))),
+                Some(old_kinds) if old_kinds != kinds => result.changed.push(ChangedRule {
+                    record: record.to_string(),
+                    old: old_kinds.iter().copied().collect(),
+                    new: kinds.iter().copied().collect(),
+                }),
+                Some(_) => {}
+            }
+        }
+
+        for (record, kinds) in &old_records {
+            if !new_records.contains_key(record) {
+                result
+                    .removed
+                    .extend(kinds.iter().map(|kind| (*kind, record.to_string())));
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Whether both sets of rules hold the same records.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Checks every line of the given `corpus` against both sets of rules.
+    ///
+    /// # Returns
+    ///
+    /// The lines whose decision differs between the two sets of rules.
+    pub fn corpus_effect<R: BufRead>(&mut self, corpus: R) -> std::io::Result<CorpusEffect> {
+        let mut result = CorpusEffect::default();
+
+        for line in corpus.lines() {
+            let line = self.old.prepare_line(&line?);
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            result.checked += 1;
+
+            match (self.old.matches_line(&line), self.new.matches_line(&line)) {
+                (false, true) => result.newly_whitelisted.push(line),
+                (true, false) => result.no_longer_whitelisted.push(line),
+                _ => {}
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old = vec![
+            String::from("example.org"),
+            String::from("# A comment."),
+            String::from("example.net"),
+            String::from("REG ^ads\\."),
+        ];
+        let new = vec![
+            String::from("example.net"),
+            String::from("example.org"),
+            String::from("APEX example.com"),
+            String::from("ALL .example.org"),
+        ];
+
+        let mut diff = RulesDiff::new(&Ruler::new(true), &old, &new).unwrap();
+
+        assert_eq!(
+            diff.added,
+            vec![
+                (MatchKind::Ends, String::from(".example.org")),
+                (MatchKind::Strict, String::from("example.com")),
+                (MatchKind::Strict, String::from("www.example.com")),
+            ]
+        );
+        assert_eq!(
+            diff.removed,
+            vec![(MatchKind::Regex, String::from("^ads\\."))]
+        );
+        assert!(diff.changed.is_empty());
+        assert!(!diff.is_empty());
+
+        let effect = diff
+            .corpus_effect("api.example.org\nads.example.net\nexample.net\n".as_bytes())
+            .unwrap();
+
+        assert_eq!(effect.checked, 3);
+        assert_eq!(
+            effect.newly_whitelisted,
+            vec![String::from("api.example.org")]
+        );
+        assert_eq!(
+            effect.no_longer_whitelisted,
+            vec![String::from("ads.example.net")]
+        );
+    }
+
+    #[test]
+    fn test_diff_changed() {
+        let old = vec![String::from(".example.org")];
+        let new = vec![String::from("SUB example.org")];
+
+        let diff = RulesDiff::new(&Ruler::new(false), &old, &new).unwrap();
+
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(
+            diff.changed,
+            vec![ChangedRule {
+                record: String::from(".example.org"),
+                old: vec![MatchKind::Strict],
+                new: vec![MatchKind::Ends],
+            }]
+        );
+    }
+}
@@ -0,0 +1,342 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::data::presets;
+
+/// The errors of the ruler, the download helpers and the data sources.
+#[derive(Debug)]
+pub enum TivilstaError {
+    /// A file couldn't be opened, read or written.
+    Io {
+        /// The path of the file.
+        path: String,
+        /// The underlying error.
+        source: io::Error,
+    },
+    /// A remote server couldn't be reached - or the HTTP client couldn't be
+    /// built.
+    Http {
+        /// The requested URL - if any.
+        url: Option<String>,
+        /// The underlying error.
+        source: reqwest::Error,
+    },
+    /// A remote server answered with an unsuccessful status.
+    Status {
+        /// The requested URL.
+        url: String,
+        /// The HTTP status code.
+        status: u16,
+        /// What we were trying to do.
+        message: String,
+    },
+    /// A remote file couldn't be downloaded.
+    Download {
+        /// The URL - or named list - of the file.
+        url: String,
+        /// The underlying error.
+        source: Box<TivilstaError>,
+    },
+    /// The given bundle of certificate authorities can't be used.
+    Certificate {
+        /// The path of the bundle.
+        path: String,
+        /// What is wrong with it.
+        message: String,
+    },
+    /// An archive - or one of its members - couldn't be read.
+    Archive {
+        /// The path of the member inside the archive - if any.
+        member: Option<String>,
+        /// What is wrong with it.
+        message: String,
+    },
+    /// A compiled rule set couldn't be written - or read back.
+    Compiled {
+        /// The path of the compiled rule set.
+        path: String,
+        /// What is wrong with it.
+        message: String,
+    },
+    /// A regular expression couldn't be compiled.
+    Regex {
+        /// The regular expression.
+        pattern: String,
+        /// The underlying error.
+        source: Box<fancy_regex::Error>,
+    },
+    /// The expiry date of a rule couldn't be parsed.
+    Expiry {
+        /// The date.
+        date: String,
+        /// What is wrong with it.
+        message: String,
+    },
+    /// The network of a `CIDR ` rule couldn't be parsed.
+    Cidr {
+        /// The network.
+        network: String,
+        /// What is wrong with it.
+        message: String,
+    },
+    /// The extension of an `EXT ` rule is neither in the IANA Root Zone
+    /// Database nor in the Public Suffix List.
+    UnknownExtension {
+        /// The extension.
+        extension: String,
+    },
+    /// A dataset - e.g. the IANA registry - doesn't have the expected shape.
+    InvalidDataset {
+        /// The name of the dataset.
+        name: String,
+        /// What is wrong with it.
+        message: String,
+    },
+    /// A dataset couldn't be fetched from any of its URLs.
+    DataSource {
+        /// The name of the dataset.
+        name: String,
+        /// Each tried URL with the reason it was rejected.
+        errors: Vec<(String, TivilstaError)>,
+    },
+    /// The named list - e.g. `@ultimate-hosts-blacklist/whitelist` - is not
+    /// part of the registry.
+    UnknownList {
+        /// The name of the list - with its leading `@`.
+        name: String,
+    },
+    /// One of the given inputs - e.g. a whitelisting schema, an archive or a
+    /// rule - couldn't be used.
+    Input {
+        /// The input - e.g. the path of the schema or the rule.
+        input: String,
+        /// The underlying error.
+        source: Box<TivilstaError>,
+    },
+    /// The preset is not one of the built-in ones.
+    UnknownPreset {
+        /// The name of the preset.
+        name: String,
+    },
+    /// A pipeline was run without source.
+    MissingSource,
+    /// A rule couldn't be parsed - e.g. an invalid regular expression.
+    Rule {
+        /// The file - or URL - holding the rule.
+        origin: String,
+        /// The number of the line holding the rule - starting at 1.
+        line: usize,
+        /// The underlying error.
+        source: Box<TivilstaError>,
+    },
+}
+
+impl TivilstaError {
+    /// Creates a new `TivilstaError::Io` for the file at the given `path`.
+    pub(crate) fn io(path: impl ToString, source: io::Error) -> TivilstaError {
+        TivilstaError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for TivilstaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TivilstaError::Io { path, source } => write!(f, "{}: {}", path, source),
+            TivilstaError::Http { source, .. } => write!(f, "{}", source),
+            // Make rate limits and outages obvious - instead of a generic
+            // failure.
+            TivilstaError::Status {
+                status: 429,
+                message,
+                ..
+            } => write!(f, "{} Rate limited by the remote server (429).", message),
+            TivilstaError::Status {
+                status: 503,
+                message,
+                ..
+            } => write!(
+                f,
+                "{} Service unavailable - the remote server is overloaded or down (503).",
+                message
+            ),
+            TivilstaError::Status {
+                status, message, ..
+            } => write!(f, "{} ({})", message, status),
+            TivilstaError::Download { url, source } => {
+                write!(f, "{}: Couldn't download: {}", url, source)
+            }
+            TivilstaError::Certificate { path, message } => write!(f, "{}: {}", path, message),
+            TivilstaError::Archive {
+                member: Some(member),
+                message,
+            } => write!(f, "{}: {}", member, message),
+            TivilstaError::Archive { message, .. } => write!(f, "{}", message),
+            TivilstaError::Compiled { path, message } => {
+                write!(f, "{}: Invalid compiled rules: {}", path, message)
+            }
+            TivilstaError::Regex { pattern, source } => {
+                write!(f, "Invalid regular expression ({}): {}", pattern, source)
+            }
+            TivilstaError::Expiry { date, message } => {
+                write!(f, "Invalid expiry date ({}): {}", date, message)
+            }
+            TivilstaError::Cidr { network, message } => {
+                write!(f, "Invalid network ({}): {}", network, message)
+            }
+            TivilstaError::UnknownExtension { extension } => {
+                write!(
+                    f,
+                    "Unknown extension: {}. See `tivilsta update-data`.",
+                    extension
+                )
+            }
+            TivilstaError::InvalidDataset { name, message } => {
+                write!(f, "Invalid {}: {}", name, message)
+            }
+            TivilstaError::DataSource { errors, .. } => {
+                let errors: Vec<String> = errors
+                    .iter()
+                    .map(|(url, error)| format!("{}: {}", url, error))
+                    .collect();
+
+                write!(f, "{}", errors.join(" "))
+            }
+            TivilstaError::UnknownList { name } => {
+                write!(f, "Unknown list: {}. See `tivilsta update-data`.", name)
+            }
+            TivilstaError::Input { input, source } => write!(f, "{}: {}", input, source),
+            TivilstaError::UnknownPreset { name } => write!(
+                f,
+                "Unknown preset: {}. Expected one of: {}.",
+                name,
+                presets::names().join(", ")
+            ),
+            TivilstaError::MissingSource => write!(f, "No source given."),
+            TivilstaError::Rule {
+                origin,
+                line,
+                source,
+            } => write!(f, "{}:{}: {}", origin, line, source),
+        }
+    }
+}
+
+impl Error for TivilstaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TivilstaError::Io { source, .. } => Some(source),
+            TivilstaError::Http { source, .. } => Some(source),
+            TivilstaError::Regex { source, .. } => Some(source.as_ref()),
+            TivilstaError::Download { source, .. }
+            | TivilstaError::Input { source, .. }
+            | TivilstaError::Rule { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let status = |status| TivilstaError::Status {
+            url: String::from("https://example.org/whitelist.list"),
+            status,
+            message: String::from("Couldn't reach the remote server."),
+        };
+
+        assert_eq!(
+            status(404).to_string(),
+            "Couldn't reach the remote server. (404)"
+        );
+        assert_eq!(
+            status(429).to_string(),
+            "Couldn't reach the remote server. Rate limited by the remote server (429)."
+        );
+
+        let error = TivilstaError::Rule {
+            origin: String::from("whitelist.list"),
+            line: 3,
+            source: Box::new(TivilstaError::DataSource {
+                name: String::from("PSL registry"),
+                errors: vec![
+                    (String::from("https://a.example"), status(503)),
+                    (
+                        String::from("https://b.example"),
+                        TivilstaError::InvalidDataset {
+                            name: String::from("PSL registry"),
+                            message: String::from("no extension found."),
+                        },
+                    ),
+                ],
+            }),
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "whitelist.list:3: https://a.example: Couldn't reach the remote server. \
+             Service unavailable - the remote server is overloaded or down (503). \
+             https://b.example: Invalid PSL registry: no extension found."
+        );
+        assert!(error.source().is_some());
+        assert_eq!(
+            TivilstaError::UnknownList {
+                name: String::from("@unknown")
+            }
+            .to_string(),
+            "Unknown list: @unknown. See `tivilsta update-data`."
+        );
+        assert_eq!(
+            TivilstaError::UnknownExtension {
+                extension: String::from("example")
+            }
+            .to_string(),
+            "Unknown extension: example. See `tivilsta update-data`."
+        );
+        assert_eq!(
+            TivilstaError::Input {
+                input: String::from("rules.tar.gz"),
+                source: Box::new(TivilstaError::Archive {
+                    member: Some(String::from("rules/ads.list")),
+                    message: String::from("invalid UTF-8."),
+                }),
+            }
+            .to_string(),
+            "rules.tar.gz: rules/ads.list: invalid UTF-8."
+        );
+        assert_eq!(
+            TivilstaError::UnknownPreset {
+                name: String::from("unknown")
+            }
+            .to_string(),
+            "Unknown preset: unknown. Expected one of: core, connectivity, pki."
+        );
+        assert_eq!(TivilstaError::MissingSource.to_string(), "No source given.");
+    }
+}
@@ -0,0 +1,210 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A calendar date - in UTC - after which a rule is ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExpiryDate {
+    /// The number of days since 1970-01-01.
+    days: i64,
+}
+
+impl ExpiryDate {
+    /// Creates a new ExpiryDate - if the given date exists.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::expiry::ExpiryDate;
+    ///
+    /// assert!(ExpiryDate::new(2024, 2, 29).is_some());
+    /// assert!(ExpiryDate::new(2025, 2, 29).is_none());
+    /// ```
+    pub fn new(year: i64, month: u32, day: u32) -> Option<ExpiryDate> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+
+        Some(ExpiryDate {
+            days: days_from_civil(year, month, day),
+        })
+    }
+
+    /// Provides the current date - in UTC.
+    pub fn today() -> ExpiryDate {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+
+        ExpiryDate::from_timestamp(seconds)
+    }
+
+    /// Provides the date - in UTC - of the given UNIX `timestamp`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::expiry::ExpiryDate;
+    ///
+    /// assert_eq!(ExpiryDate::from_timestamp(1_709_296_496).to_string(), "2024-03-01");
+    /// ```
+    pub fn from_timestamp(seconds: u64) -> ExpiryDate {
+        ExpiryDate {
+            days: (seconds / 86_400) as i64,
+        }
+    }
+
+    /// Provides the date the given number of days later.
+    pub fn add_days(&self, days: u64) -> ExpiryDate {
+        ExpiryDate {
+            days: self.days.saturating_add(days as i64),
+        }
+    }
+
+    /// Whether the date is over - a rule is still valid on the day it
+    /// expires.
+    pub fn is_past(&self) -> bool {
+        *self < ExpiryDate::today()
+    }
+}
+
+impl FromStr for ExpiryDate {
+    type Err = String;
+
+    /// Parses a `YYYY-MM-DD` date.
+    fn from_str(date: &str) -> Result<ExpiryDate, String> {
+        let mut parts = date.splitn(3, '-');
+        let mut next = |length: usize| {
+            parts
+                .next()
+                .filter(|part| part.len() == length && part.bytes().all(|x| x.is_ascii_digit()))
+                .and_then(|part| part.parse::<u32>().ok())
+        };
+
+        match (next(4), next(2), next(2)) {
+            (Some(year), Some(month), Some(day)) => ExpiryDate::new(year as i64, month, day)
+                .ok_or_else(|| String::from("no such date.")),
+            _ => Err(String::from("expected YYYY-MM-DD.")),
+        }
+    }
+}
+
+impl fmt::Display for ExpiryDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = civil_from_days(self.days);
+
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Provides the number of days between 1970-01-01 and the given date - see
+/// <https://howardhinnant.github.io/date_algorithms.html>.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The inverse of [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let parse = |date: &str| date.parse::<ExpiryDate>().map(|date| date.to_string());
+
+        assert_eq!(parse("2025-12-31"), Ok(String::from("2025-12-31")));
+        assert_eq!(parse("2024-02-29"), Ok(String::from("2024-02-29")));
+        assert_eq!(parse("1970-01-01"), Ok(String::from("1970-01-01")));
+        assert_eq!(parse("1969-12-31"), Ok(String::from("1969-12-31")));
+
+        for date in [
+            "2025-02-29",
+            "2025-13-01",
+            "2025-00-10",
+            "2025-1-01",
+            "25-01-01",
+            "",
+        ] {
+            assert!(parse(date).is_err(), "{date}");
+        }
+    }
+
+    #[test]
+    fn test_days() {
+        assert_eq!(ExpiryDate::new(1970, 1, 1).unwrap().days, 0);
+        assert_eq!(ExpiryDate::new(2000, 3, 1).unwrap().days, 11_017);
+        assert_eq!(
+            ExpiryDate::new(2024, 12, 31).unwrap().add_days(1),
+            ExpiryDate::new(2025, 1, 1).unwrap()
+        );
+        assert!(ExpiryDate::new(2000, 1, 1).unwrap().is_past());
+        assert!(!ExpiryDate::today().is_past());
+        assert!(!ExpiryDate::today().add_days(1).is_past());
+        assert_eq!(
+            ExpiryDate::from_timestamp(86_399),
+            ExpiryDate::new(1970, 1, 1).unwrap()
+        );
+        assert_eq!(
+            ExpiryDate::from_timestamp(951_782_400),
+            ExpiryDate::new(2000, 2, 29).unwrap()
+        );
+    }
+}
@@ -0,0 +1,390 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use std::borrow::Cow;
+
+use crate::cidr;
+
+/// The options of the AdBlock Plus filters that don't narrow the hosts a
+/// filter applies to.
+const ADBLOCK_OPTIONS: [&str; 11] = [
+    "all",
+    "document",
+    "doc",
+    "important",
+    "third-party",
+    "3p",
+    "~third-party",
+    "first-party",
+    "1p",
+    "~first-party",
+    "match-case",
+];
+
+/// The separators of the AdBlock Plus cosmetic - and scriptlet - filters.
+const ADBLOCK_COSMETIC_SEPARATORS: [&str; 8] =
+    ["##", "#@#", "#?#", "#@?#", "#$#", "#@$#", "#%#", "#@%#"];
+
+/// The separators of the cosmetic - and HTML - filters only known by AdGuard.
+const ADGUARD_COSMETIC_SEPARATORS: [&str; 4] = ["#$?#", "#@$?#", "$$", "$@$"];
+
+/// The format of the whitelisting schemas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RuleFormat {
+    /// Our own format - see the flags.
+    #[default]
+    Tivilsta,
+    /// The AdBlock Plus - and uBlock Origin - filter syntax. The network
+    /// filters of whole hosts - like `||example.com^` or
+    /// `@@||example.com^$document` - whitelist the host and its subdomains,
+    /// while the filters of URLs - like `|https://example.com/api/` -
+    /// whitelist the URLs starting with them.
+    ///
+    /// The filters we can't express without whitelisting more than they
+    /// do - e.g. the cosmetic filters or the ones restricted to some
+    /// resources through their options - are ignored.
+    Adblock,
+    /// The AdGuard filter syntax - a superset of [`RuleFormat::Adblock`].
+    /// The hosts-style entries - like `0.0.0.0 example.com` - whitelist the
+    /// exact same hosts, the filters anchored at the start of the host - like
+    /// `|example.com^` - too, and the regular expressions - like
+    /// `/^ads[0-9]+\.example\.com$/` - are matched against the subjects.
+    Adguard,
+    /// The hosts file syntax - like `0.0.0.0 example.com # comment`. The IP
+    /// column and the trailing comments are stripped and each host whitelists
+    /// the exact same host.
+    ///
+    /// The lines without IP column are read as our own rules.
+    Hosts,
+}
+
+impl RuleFormat {
+    /// Converts the given line into rules of our own format.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to convert.
+    ///
+    /// # Returns
+    ///
+    /// The converted rules - none if the line has no equivalent.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::format::RuleFormat;
+    ///
+    /// assert_eq!(
+    ///     RuleFormat::Adblock.convert("@@||example.com^$important"),
+    ///     vec!["ALL .example.com"]
+    /// );
+    /// assert_eq!(
+    ///     RuleFormat::Adguard.convert("0.0.0.0 example.com example.net"),
+    ///     vec!["example.com", "example.net"]
+    /// );
+    /// assert!(RuleFormat::Adblock.convert("example.com##.banner").is_empty());
+    /// ```
+    pub fn convert<'a>(&self, line: &'a str) -> Vec<Cow<'a, str>> {
+        match self {
+            RuleFormat::Tivilsta => vec![Cow::Borrowed(line)],
+            RuleFormat::Adblock => convert_adblock(line.trim()).into_iter().collect(),
+            RuleFormat::Adguard => convert_adguard(line.trim()),
+            RuleFormat::Hosts => convert_hosts(line.trim()),
+        }
+    }
+}
+
+/// Converts the given AdBlock Plus filter.
+fn convert_adblock(line: &str) -> Option<Cow<'_, str>> {
+    if line.is_empty() {
+        return Some(Cow::Borrowed(line));
+    }
+
+    // E.g. `! @group corp`.
+    if let Some(comment) = line.strip_prefix('!') {
+        return Some(Cow::Owned(format!("#{}", comment)));
+    }
+
+    // E.g. `[Adblock Plus 2.0]`.
+    if line.starts_with('[')
+        || ADBLOCK_COSMETIC_SEPARATORS
+            .iter()
+            .any(|separator| line.contains(separator))
+    {
+        return None;
+    }
+
+    let filter = line.strip_prefix("@@").unwrap_or(line);
+
+    // A regular expression is matched against the whole URL.
+    if filter.starts_with('/') {
+        return None;
+    }
+
+    let pattern = strip_options(filter)?;
+
+    if let Some(host) = pattern.strip_prefix("||") {
+        return convert_host(host).map(|host| match host.strip_prefix("*.") {
+            Some(host) => Cow::Owned(format!("SUB {}", host)),
+            None => Cow::Owned(format!("ALL .{}", host)),
+        });
+    }
+
+    if let Some(url) = pattern.strip_prefix('|') {
+        let (scheme, rest) = url.split_once("://")?;
+
+        if !matches!(scheme, "http" | "https")
+            || !rest.contains('/')
+            || url.contains(['*', '^', '|'])
+        {
+            return None;
+        }
+
+        return Some(Cow::Borrowed(url));
+    }
+
+    // Like uBlock Origin, a bare host is read as `||host^`.
+    (pattern.contains('.') && is_host(pattern)).then(|| Cow::Owned(format!("ALL .{}", pattern)))
+}
+
+/// Converts the given AdGuard filter.
+fn convert_adguard(line: &str) -> Vec<Cow<'_, str>> {
+    if ADGUARD_COSMETIC_SEPARATORS
+        .iter()
+        .any(|separator| line.contains(separator))
+    {
+        return vec![];
+    }
+
+    // The comments of the hosts files - e.g. `# @group corp`.
+    if line.starts_with('#') && !line.starts_with("##") {
+        return vec![Cow::Borrowed(line)];
+    }
+
+    if let Some(hosts) = convert_hosts_entry(line) {
+        return hosts;
+    }
+
+    let filter = line.strip_prefix("@@").unwrap_or(line);
+
+    if let Some(regex) = filter.strip_prefix('/') {
+        return convert_regex(regex).into_iter().collect();
+    }
+
+    if let Some(host) = strip_options(filter)
+        .and_then(|pattern| pattern.strip_prefix('|'))
+        .filter(|pattern| !pattern.starts_with('|') && !pattern.contains("://"))
+    {
+        return convert_host(host).map(Cow::Borrowed).into_iter().collect();
+    }
+
+    convert_adblock(line).into_iter().collect()
+}
+
+/// Converts the given hosts file line.
+fn convert_hosts(line: &str) -> Vec<Cow<'_, str>> {
+    // E.g. `# @group corp`.
+    if line.starts_with('#') {
+        return vec![Cow::Borrowed(line)];
+    }
+
+    if let Some(hosts) = convert_hosts_entry(line) {
+        return hosts;
+    }
+
+    let rule = line
+        .split_once(" #")
+        .map_or(line, |(rule, _)| rule)
+        .trim_end();
+
+    vec![Cow::Borrowed(rule)]
+}
+
+/// Provides the hosts of the given hosts file entry - e.g.
+/// `0.0.0.0 example.com example.net # comment` - if it is one.
+fn convert_hosts_entry(line: &str) -> Option<Vec<Cow<'_, str>>> {
+    let mut fields = line.split('#').next().unwrap_or(line).split_whitespace();
+
+    cidr::parse_ip(fields.next()?)?;
+
+    Some(
+        fields
+            .filter(|host| is_host(host))
+            .map(Cow::Borrowed)
+            .collect(),
+    )
+}
+
+/// Converts the given regular expression - without its leading slash - and
+/// its options.
+fn convert_regex(regex: &str) -> Option<Cow<'_, str>> {
+    let (pattern, options) = regex.rsplit_once('/')?;
+
+    if pattern.is_empty() {
+        return None;
+    }
+
+    if let Some(options) = options.strip_prefix('$') {
+        if !is_known_options(options) {
+            return None;
+        }
+    } else if !options.is_empty() {
+        return None;
+    }
+
+    Some(Cow::Owned(format!("REG {}", pattern)))
+}
+
+/// Provides the given filter without its options - if we understand them.
+fn strip_options(filter: &str) -> Option<&str> {
+    match filter.rsplit_once('$') {
+        Some((pattern, options)) => is_known_options(options).then_some(pattern),
+        None => Some(filter),
+    }
+}
+
+/// Checks whether the given options don't narrow the hosts a filter applies
+/// to.
+fn is_known_options(options: &str) -> bool {
+    options
+        .split(',')
+        .all(|option| ADBLOCK_OPTIONS.contains(&option.trim()))
+}
+
+/// Provides the host of the given filter - without its leading anchor and its
+/// trailing separator.
+fn convert_host(pattern: &str) -> Option<&str> {
+    let host = pattern
+        .strip_suffix("^|")
+        .or_else(|| pattern.strip_suffix('^'))
+        .unwrap_or(pattern);
+    let bare = host.strip_prefix("*.").unwrap_or(host);
+
+    is_host(bare).then_some(host)
+}
+
+/// Checks whether the given string looks like a host name.
+fn is_host(host: &str) -> bool {
+    !host.is_empty()
+        && !host.starts_with(['.', '-'])
+        && host
+            .chars()
+            .all(|x| x.is_alphanumeric() || matches!(x, '.' | '-' | '_'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_tivilsta() {
+        assert_eq!(
+            RuleFormat::Tivilsta.convert("ALL .example.com"),
+            vec!["ALL .example.com"]
+        );
+        assert_eq!(RuleFormat::Tivilsta.convert("# comment"), vec!["# comment"]);
+    }
+
+    #[test]
+    fn test_convert_adblock() {
+        let converted = |line| RuleFormat::Adblock.convert(line);
+
+        assert_eq!(converted("||example.com^"), vec!["ALL .example.com"]);
+        assert_eq!(converted("@@||example.com^"), vec!["ALL .example.com"]);
+        assert_eq!(
+            converted("@@||bücher.de^|$document,important"),
+            vec!["ALL .bücher.de"]
+        );
+        assert_eq!(converted(" ||example.net "), vec!["ALL .example.net"]);
+        assert_eq!(converted("||*.example.net^"), vec!["SUB example.net"]);
+        assert_eq!(converted("example.org"), vec!["ALL .example.org"]);
+        assert_eq!(
+            converted("@@|https://example.com/api/"),
+            vec!["https://example.com/api/"]
+        );
+        assert_eq!(converted("! @group corp"), vec!["# @group corp"]);
+        assert_eq!(converted(""), vec![""]);
+
+        assert!(converted("[Adblock Plus 2.0]").is_empty());
+        assert!(converted("example.com##.banner").is_empty());
+        assert!(converted("example.com#@#.banner").is_empty());
+        assert!(converted("@@||example.com^$script").is_empty());
+        assert!(converted("@@||example.com^$domain=example.org").is_empty());
+        assert!(converted("||ads*.example.com^").is_empty());
+        assert!(converted("||example.com/ads/").is_empty());
+        assert!(converted("/^ads\\./").is_empty());
+        assert!(converted("|https://example.com^").is_empty());
+        assert!(converted("0.0.0.0 example.com").is_empty());
+        assert!(converted("ads").is_empty());
+    }
+
+    #[test]
+    fn test_convert_adguard() {
+        let converted = |line| RuleFormat::Adguard.convert(line);
+
+        assert_eq!(
+            converted("@@||example.com^$important"),
+            vec!["ALL .example.com"]
+        );
+        assert_eq!(converted("|example.com^"), vec!["example.com"]);
+        assert_eq!(
+            converted("0.0.0.0 example.com example.net # ads"),
+            vec!["example.com", "example.net"]
+        );
+        assert_eq!(converted("::1 example.org"), vec!["example.org"]);
+        assert_eq!(
+            converted("@@/^ads[0-9]+\\.example\\.com$/$important"),
+            vec!["REG ^ads[0-9]+\\.example\\.com$"]
+        );
+        assert_eq!(converted("# @group corp"), vec!["# @group corp"]);
+        assert_eq!(
+            converted("! Title: My whitelist"),
+            vec!["# Title: My whitelist"]
+        );
+
+        assert!(converted("example.com#$?#.banner { display: none; }").is_empty());
+        assert!(converted("example.com$$script[data-src=\"banner\"]").is_empty());
+        assert!(converted("##.banner").is_empty());
+        assert!(converted("@@||example.com^$dnstype=AAAA").is_empty());
+        assert!(converted("||example.com^$client=127.0.0.1").is_empty());
+        assert!(converted("/ads/i").is_empty());
+        assert!(converted("0.0.0.0").is_empty());
+    }
+
+    #[test]
+    fn test_convert_hosts() {
+        let converted = |line| RuleFormat::Hosts.convert(line);
+
+        assert_eq!(converted("0.0.0.0 example.com"), vec!["example.com"]);
+        assert_eq!(
+            converted("127.0.0.1\texample.com example.net# ads"),
+            vec!["example.com", "example.net"]
+        );
+        assert_eq!(converted("[::1] example.org"), vec!["example.org"]);
+        assert_eq!(converted("example.org # kept"), vec!["example.org"]);
+        assert_eq!(converted("ALL .example.org"), vec!["ALL .example.org"]);
+        assert_eq!(converted("# @group corp"), vec!["# @group corp"]);
+        assert_eq!(converted(""), vec![""]);
+
+        assert!(converted("0.0.0.0").is_empty());
+        assert!(converted("0.0.0.0 # example.com").is_empty());
+    }
+}
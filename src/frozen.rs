@@ -0,0 +1,465 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use std::cmp::Ordering;
+
+use crate::automaton::KeywordAutomaton;
+use crate::cidr::{self, CidrTrie};
+use crate::regex_set::RegexSetMatcher;
+use crate::{is_expired, utils, Expiries, Match, MatchKind, Origins, Ruler, RulerSettings};
+
+/// An immutable snapshot of the rules of a [`Ruler`] - only meant to check
+/// subjects. See [`Ruler::freeze`].
+///
+/// The rules are held in sorted tables - looked up through binary searches -
+/// instead of the hash sets a ruler needs to efficiently add and remove rules.
+/// The `ALL ` and `SUB ` rules are looked up once per label of the subject -
+/// whatever the number of rules sharing its extension.
+///
+/// It can be shared across threads. Like the ruler, it ignores the records
+/// whose expiry date is over.
+#[derive(Debug, Clone)]
+pub struct FrozenRuler {
+    settings: RulerSettings,
+    /// The strict and present records - sorted.
+    exact: Box<[(Box<str>, MatchKind)]>,
+    /// The records of the `ALL ` and `SUB ` flags - sorted.
+    ends: Box<[Box<str>]>,
+    /// The records of the `START ` flag - sorted.
+    starts: Box<[Box<str>]>,
+    /// The URL prefixes - sorted by host.
+    url_prefixes: UrlPrefixes,
+    /// The regular expressions - with their compiled matcher - if any.
+    regexes: Option<(Box<[Box<str>]>, RegexSetMatcher)>,
+    /// The subjects exempted from every other rule - sorted.
+    exceptions: Box<[Box<str>]>,
+    /// The prefix tries of the `CIDR ` rules - if any.
+    cidrs: Option<CidrTrie>,
+    /// The automaton of the `KEY ` rules - if any.
+    keywords: Option<KeywordAutomaton>,
+    /// Where the records come from - when known.
+    origins: Origins,
+    /// When the records expire - if they do.
+    expiries: Expiries,
+}
+
+/// A sorted table of URL hosts and their (sorted) path prefixes.
+type UrlPrefixes = Box<[(Box<str>, Box<[Box<str>]>)]>;
+
+/// Compares the records of a sorted table with the given `needle`.
+fn by_record<T>(needle: &str) -> impl Fn(&(Box<str>, T)) -> Ordering + '_ {
+    move |(record, _)| (**record).cmp(needle)
+}
+
+impl Ruler {
+    /// Freezes the ruler into an immutable snapshot optimized for checking
+    /// subjects.
+    ///
+    /// **Note:** The files downloaded by the ruler are removed - unless they
+    /// should be kept. See [`Ruler::set_keep_downloads`].
+    ///
+    /// **Note:** The records whose expiry date is over are dropped - see
+    /// [`Ruler::remove_expired`]. The other ones stop matching once their
+    /// date is over.
+    ///
+    /// # Returns
+    ///
+    /// A FrozenRuler that takes the same decisions as the ruler.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{MatchKind, Ruler};
+    ///
+    /// let mut ruler = Ruler::new(true);
+    ///
+    /// ruler.parse(&String::from("example.org"));
+    /// ruler.parse(&String::from("ALL .example.net"));
+    ///
+    /// let frozen = ruler.freeze();
+    ///
+    /// assert_eq!(frozen.is_whitelisted(&String::from("www.example.org")), true);
+    /// assert_eq!(frozen.is_whitelisted(&String::from("example.com")), false);
+    /// assert_eq!(
+    ///     frozen.check(&String::from("api.example.net")).unwrap().kind,
+    ///     MatchKind::Ends
+    /// );
+    /// ```
+    pub fn freeze(mut self) -> FrozenRuler {
+        self.remove_expired();
+
+        let mut exact: Vec<(Box<str>, MatchKind)> = std::mem::take(&mut self.strict)
+            .into_iter()
+            .map(|record| (record.into_boxed_str(), MatchKind::Strict))
+            .chain(
+                std::mem::take(&mut self.present)
+                    .into_iter()
+                    .map(|record| (record.into_boxed_str(), MatchKind::Present)),
+            )
+            .collect();
+
+        // The strict records come first - as they are checked first. Both are
+        // kept as they may expire at different dates.
+        exact.sort_unstable();
+
+        let mut ends: Vec<Box<str>> = std::mem::take(&mut self.ends)
+            .into_values()
+            .flatten()
+            .map(String::into_boxed_str)
+            .collect();
+
+        ends.sort_unstable();
+        ends.dedup();
+
+        let mut starts: Vec<Box<str>> = std::mem::take(&mut self.starts)
+            .into_values()
+            .flatten()
+            .map(String::into_boxed_str)
+            .collect();
+
+        starts.sort_unstable();
+
+        let mut url_prefixes: Vec<_> = std::mem::take(&mut self.url_prefixes)
+            .into_iter()
+            .map(|(host, prefixes)| {
+                let mut prefixes: Vec<Box<str>> =
+                    prefixes.into_iter().map(String::into_boxed_str).collect();
+
+                prefixes.sort_unstable();
+                (host.into_boxed_str(), prefixes.into_boxed_slice())
+            })
+            .collect();
+
+        url_prefixes.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        let regexes = (!self.regexes.is_empty()).then(|| {
+            let matcher = self.regex_matcher().clone();
+
+            (
+                std::mem::take(&mut self.regexes)
+                    .into_iter()
+                    .map(String::into_boxed_str)
+                    .collect(),
+                matcher,
+            )
+        });
+
+        let mut exceptions: Vec<Box<str>> = std::mem::take(&mut self.exceptions)
+            .into_iter()
+            .map(String::into_boxed_str)
+            .collect();
+
+        exceptions.sort_unstable();
+
+        let cidrs = (!self.cidrs.is_empty()).then(|| CidrTrie::new(self.cidrs.iter()));
+        let keywords =
+            (!self.keywords.is_empty()).then(|| KeywordAutomaton::new(self.keywords.iter()));
+
+        self.settings.extensions = vec![];
+
+        FrozenRuler {
+            settings: self.settings.clone(),
+            exact: exact.into_boxed_slice(),
+            ends: ends.into_boxed_slice(),
+            starts: starts.into_boxed_slice(),
+            url_prefixes: url_prefixes.into_boxed_slice(),
+            regexes,
+            exceptions: exceptions.into_boxed_slice(),
+            cidrs,
+            keywords,
+            origins: std::mem::take(&mut self.origins),
+            expiries: std::mem::take(&mut self.expiries),
+        }
+    }
+}
+
+impl FrozenRuler {
+    /// Checks the given `line` against the rules - see
+    /// [`Ruler::is_whitelisted`].
+    pub fn is_whitelisted(&self, line: &str) -> bool {
+        self.check(line).is_some()
+    }
+
+    /// Checks the given `domain` against the rules - without extracting its
+    /// network location. See [`Ruler::is_whitelisted_domain`].
+    pub fn is_whitelisted_domain(&self, domain: &str) -> bool {
+        !self.is_excepted(domain) && self.find_match(domain).is_some()
+    }
+
+    /// Checks the given `subjects` against the rules - see
+    /// [`Ruler::are_whitelisted`].
+    pub fn are_whitelisted<S: AsRef<str>>(&self, subjects: &[S]) -> Vec<bool> {
+        subjects
+            .iter()
+            .map(|subject| self.is_whitelisted(subject.as_ref()))
+            .collect()
+    }
+
+    /// Checks the given `subject` against the rules and explains the decision
+    /// - see [`Ruler::check`].
+    pub fn check(&self, subject: &str) -> Option<Match<'_>> {
+        if subject.is_empty() || subject.starts_with('#') {
+            return None;
+        }
+
+        let netloc = utils::extract_netloc(subject);
+
+        if self.is_excepted(&netloc) {
+            return None;
+        }
+
+        self.find_match(&netloc)
+            .or_else(|| self.find_url_prefix_match(subject))
+            .map(|found| found.with_origin(&self.origins))
+    }
+
+    /// Whether the given record - of the given kind - can still match - see
+    /// [`Ruler::remove_expired`].
+    fn is_live(&self, kind: MatchKind, record: &str) -> bool {
+        !is_expired(&self.expiries, kind, record)
+    }
+
+    /// Whether the given `domain` is exempted from every other rule - by an
+    /// `EXC ` rule.
+    fn is_excepted(&self, domain: &str) -> bool {
+        if self.exceptions.is_empty() {
+            return false;
+        }
+
+        let fline = self.settings.normalize(domain);
+
+        self.exceptions
+            .binary_search_by(|record| (**record).cmp(&fline))
+            .is_ok_and(|index| self.is_live(MatchKind::Exception, &self.exceptions[index]))
+    }
+
+    /// Provides the first rule that matches the given `domain`.
+    fn find_match(&self, domain: &str) -> Option<Match<'_>> {
+        if domain.is_empty() || domain.starts_with('#') {
+            return None;
+        }
+
+        let fline = self.settings.normalize(domain);
+
+        let start = self.exact.partition_point(|(record, _)| **record < *fline);
+        let exact = self.exact[start..]
+            .iter()
+            .take_while(|(record, _)| **record == *fline)
+            .find(|(record, kind)| self.is_live(*kind, record));
+
+        if let Some((record, kind)) = exact {
+            return Some(Match::new(*kind, record));
+        }
+
+        // The records always start with a dot.
+        let found = fline.match_indices('.').find_map(|(index, _)| {
+            self.ends
+                .binary_search_by(|record| (**record).cmp(&fline[index..]))
+                .ok()
+                .filter(|found| self.is_live(MatchKind::Ends, &self.ends[*found]))
+        });
+
+        if let Some(index) = found {
+            return Some(Match::new(MatchKind::Ends, &self.ends[index]));
+        }
+
+        // The records always end with a dot.
+        let found = fline.match_indices('.').find_map(|(index, _)| {
+            self.starts
+                .binary_search_by(|record| (**record).cmp(&fline[..=index]))
+                .ok()
+                .filter(|found| self.is_live(MatchKind::Starts, &self.starts[*found]))
+        });
+
+        if let Some(index) = found {
+            return Some(Match::new(MatchKind::Starts, &self.starts[index]));
+        }
+
+        let cidr = self
+            .cidrs
+            .as_ref()
+            .zip(cidr::parse_ip(&fline))
+            .and_then(|(cidrs, address)| {
+                cidrs.find(address, |record| self.is_live(MatchKind::Cidr, record))
+            });
+
+        if let Some(rule) = cidr {
+            return Some(Match::new(MatchKind::Cidr, rule));
+        }
+
+        let keyword = self.keywords.as_ref().and_then(|keywords| {
+            keywords.find(&fline, |record| self.is_live(MatchKind::Keyword, record))
+        });
+
+        if let Some(rule) = keyword {
+            return Some(Match::new(MatchKind::Keyword, rule));
+        }
+
+        let (regexes, matcher) = self.regexes.as_ref()?;
+
+        matcher
+            .find(&fline, |position| {
+                self.is_live(MatchKind::Regex, &regexes[position])
+            })
+            .map(|position| Match::new(MatchKind::Regex, &regexes[position]))
+    }
+
+    /// Provides the first URL-prefix rule that matches the given `url`.
+    fn find_url_prefix_match(&self, url: &str) -> Option<Match<'_>> {
+        if self.url_prefixes.is_empty() {
+            return None;
+        }
+
+        let (host, url) = self.settings.normalize_url(url)?;
+        let index = self.url_prefixes.binary_search_by(by_record(&host)).ok()?;
+
+        self.url_prefixes[index]
+            .1
+            .iter()
+            .find(|prefix| {
+                url.starts_with(&***prefix) && self.is_live(MatchKind::UrlPrefix, prefix)
+            })
+            .map(|prefix| Match::new(MatchKind::UrlPrefix, prefix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExpiryDate, Reduction};
+
+    #[test]
+    fn test_freeze() {
+        let rules: Vec<String> = [
+            "example.org",
+            "ALL .example.net",
+            "SUB example.com",
+            "APEX example.info",
+            "REG ^ads\\.",
+            "https://example.de/api/",
+            "ALL .co.uk",
+            "CIDR 10.0.0.0/8",
+            "CIDR 2001:db8::/32",
+            "KEY tracker",
+            "START mail.",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        let subjects: Vec<String> = [
+            "example.org",
+            "www.example.org",
+            "WWW.EXAMPLE.ORG",
+            "api.example.org",
+            "example.net",
+            "a.b.example.net",
+            "api.example.com",
+            "example.com",
+            "www.example.info",
+            "api.example.info",
+            "ads.example.fr",
+            "https://example.de/api/v1",
+            "https://example.de/apis",
+            "https://api.example.net:8080/",
+            "example.co.uk",
+            "10.1.2.3",
+            "11.1.2.3",
+            "2001:db8::1",
+            "tracker.example.fr",
+            "mail.example.fr",
+            "# example.org",
+            "",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        for reductions in [
+            vec![Reduction::StripPrefixes],
+            vec![Reduction::StripPrefixes, Reduction::Lowercase],
+        ] {
+            let mut ruler = Ruler::new(true).with_reductions(&reductions);
+
+            ruler.parse_vec(&rules);
+
+            let expected: Vec<Option<(MatchKind, String)>> = subjects
+                .iter()
+                .map(|subject| {
+                    ruler
+                        .check(subject)
+                        .map(|found| (found.kind, found.rule.to_string()))
+                })
+                .collect();
+            let frozen = ruler.freeze();
+
+            for (subject, expected) in subjects.iter().zip(expected) {
+                assert_eq!(
+                    frozen
+                        .check(subject)
+                        .map(|found| (found.kind, found.rule.to_string())),
+                    expected,
+                    "{}",
+                    subject
+                );
+            }
+        }
+
+        let frozen = Ruler::new(false).freeze();
+
+        assert_eq!(
+            frozen.are_whitelisted(&subjects),
+            vec![false; subjects.len()]
+        );
+        assert!(!frozen.is_whitelisted_domain(&String::from("example.org")));
+    }
+
+    #[test]
+    fn test_freeze_expiry() {
+        let mut ruler = Ruler::new(false);
+
+        ruler.parse_vec(&[
+            "example.org @expires=2999-12-31",
+            "example.org",
+            "example.com @expires=2999-12-31",
+            "ALL .example.net @expires=2999-12-31",
+            "KEY tracker @expires=2999-12-31",
+            "EXC api.example.info @expires=2999-12-31",
+            "ALL .example.info",
+        ]);
+
+        let mut frozen = ruler.freeze();
+
+        assert!(frozen.is_whitelisted("example.com"));
+        assert!(!frozen.is_whitelisted("api.example.info"));
+
+        // The records expire while the snapshot lives.
+        for records in frozen.expiries.values_mut() {
+            for expiry in records.values_mut() {
+                *expiry = ExpiryDate::new(2000, 1, 1).unwrap();
+            }
+        }
+
+        assert!(frozen.is_whitelisted("example.org"));
+        assert!(!frozen.is_whitelisted("example.com"));
+        assert!(!frozen.is_whitelisted("api.example.net"));
+        assert!(!frozen.is_whitelisted("tracker.example.fr"));
+        assert!(frozen.is_whitelisted("api.example.info"));
+    }
+}
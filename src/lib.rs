@@ -18,39 +18,507 @@
 //      See the License for the specific language governing permissions and
 //      limitations under the License.
 
-mod data;
-mod utils;
-
+mod automaton;
+pub mod builder;
+pub mod chain;
+mod cidr;
+pub mod coverage;
+pub mod data;
+pub mod diff;
+pub mod error;
+pub mod expiry;
+pub mod format;
+pub mod frozen;
+#[cfg(feature = "serde")]
+mod persistence;
+pub mod pipeline;
+mod regex_set;
+pub mod simulation;
+pub mod stats;
+// Shared with the CLI - not part of the public API.
+#[doc(hidden)]
+pub mod utils;
+
+pub use builder::RulerBuilder;
+pub use error::TivilstaError;
+pub use expiry::ExpiryDate;
+pub use format::RuleFormat;
+pub use frozen::FrozenRuler;
+pub use pipeline::Pipeline;
+
+use crate::automaton::{EndsAutomaton, KeywordAutomaton};
+use crate::cidr::{CidrTrie, Network};
 use crate::data::iana;
 use crate::data::psl;
-use fancy_regex::Regex;
+use crate::regex_set::RegexSetMatcher;
+use fancy_regex::{Regex, RegexBuilder};
+use lru::LruCache;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fs;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, OnceLock};
 
-#[derive(Debug)]
+/// The default number of IDNAzed subjects we keep in memory.
+const DEFAULT_IDNA_CACHE_CAPACITY: usize = 10_000;
+
+/// What the errors of [`Ruler::parse_reader`] report as their origin.
+const READER_ORIGIN: &str = "<reader>";
+
+/// A single step of the normalization chain applied - symmetrically - to
+/// rules and subjects before they are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Reduction {
+    /// Strips the first matching complement prefix - `www.` by default.
+    ///
+    /// **Note:** Complements are already generated while parsing, so this step
+    /// only affects how rules are indexed. It never makes a rule match a
+    /// subject it wouldn't match otherwise.
+    StripPrefixes,
+    /// Converts the element to lowercase.
+    Lowercase,
+    /// Strips the trailing dot(s) of fully qualified names - e.g. `example.org.`.
+    StripTrailingDot,
+    /// Strips the port - e.g. `example.org:8080`.
+    StripPort,
+    /// Strips the leading and trailing whitespaces.
+    Trim,
+    /// Strips the scheme - e.g. `https://example.org`.
+    StripScheme,
+    /// Applies the IDNA (UTS #46) mapping - which includes the Unicode case
+    /// folding - and converts internationalized names to their ASCII form.
+    /// E.g. `ÄXAMPLE.org` becomes `xn--xample-9ta.org`.
+    CaseFold,
+}
+
+/// How the rules of the `ALL ` and `SUB ` flags are looked up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EndsStrategy {
+    /// Looks up every label-boundary suffix of the subject - e.g.
+    /// `.example.org` and `.org` for `api.example.org` - among the rules that
+    /// share its last label.
+    ///
+    /// The rulers persisted with the former `LabelSuffixes` strategy - which
+    /// had become the same - are restored with this one.
+    #[cfg_attr(feature = "serde", serde(alias = "LabelSuffixes"))]
+    Buckets,
+    /// Matches every rule at once through an Aho-Corasick automaton - in a
+    /// single pass over the subject, whatever the number of rules.
+    ///
+    /// The automaton is compiled on the first lookup after the rules changed,
+    /// so it suits rulers that are fully loaded before being queried - with
+    /// tens of thousands of rules.
+    Automaton,
+}
+
+/// The kind of rule that matched a subject.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MatchKind {
+    /// A rule that only matches the exact same subject.
+    Strict,
+    /// A rule of the `RZD ` flag - matching the exact same subject once
+    /// expanded with every known extension (and its complements when
+    /// `handle_complement` is set).
+    Present,
+    /// A rule of the `ALL ` or `SUB ` flags - matching the end of the subject.
+    Ends,
+    /// A rule of the `REG ` flag - matching the subject against a regular
+    /// expression.
+    Regex,
+    /// A URL - e.g. `https://example.org/api/` - matching the start of the
+    /// subject.
+    UrlPrefix,
+    /// A rule of the `EXC ` (or `NOT `) flag - exempting the exact same
+    /// subject from every other rule. It never whitelists a subject.
+    Exception,
+    /// A rule of the `CIDR ` flag - matching the IP addresses of a network.
+    Cidr,
+    /// A rule of the `KEY ` flag - matching any subject containing it.
+    Keyword,
+    /// A rule of the `START ` flag - matching the start of the subject.
+    Starts,
+}
+
+impl MatchKind {
+    /// Provides the name of the kind - e.g. `ends`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MatchKind::Strict => "strict",
+            MatchKind::Present => "present",
+            MatchKind::Ends => "ends",
+            MatchKind::Regex => "regex",
+            MatchKind::UrlPrefix => "url-prefix",
+            MatchKind::Exception => "exception",
+            MatchKind::Cidr => "cidr",
+            MatchKind::Keyword => "keyword",
+            MatchKind::Starts => "starts",
+        }
+    }
+}
+
+/// The rule that matched a subject - see [`Ruler::check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match<'a> {
+    /// The kind of rule that matched.
+    pub kind: MatchKind,
+    /// The rule that matched - as we hold it. E.g. `.example.org` for
+    /// `ALL .example.org`. For regular expressions, this is the pattern of
+    /// the `REG ` rule.
+    pub rule: &'a str,
+    /// Where the rule comes from - if it was parsed from a file, a link or a
+    /// reader or through [`Ruler::try_parse_with_origin`].
+    pub origin: Option<&'a RuleOrigin>,
+}
+
+impl<'a> Match<'a> {
+    fn new(kind: MatchKind, rule: &'a str) -> Match<'a> {
+        Match {
+            kind,
+            rule,
+            origin: None,
+        }
+    }
+
+    /// Attaches the origin of the rule - as known by the given `origins`.
+    fn with_origin(mut self, origins: &'a Origins) -> Match<'a> {
+        self.origin = origins
+            .get(&self.kind)
+            .and_then(|records| records.get(self.rule));
+        self
+    }
+}
+
+/// A record of a disabled group - set aside with its metadata until the group
+/// is enabled again. See [`Ruler::set_group_enabled`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct StashedRecord {
+    kind: MatchKind,
+    record: String,
+    /// Like the other origins, it is not persisted.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    origin: Option<RuleOrigin>,
+    expiry: Option<ExpiryDate>,
+}
+
+/// Where a rule comes from - the file (or link) it was parsed from and its
+/// line.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RuleOrigin {
+    path: Arc<str>,
+    line: usize,
+}
+
+impl RuleOrigin {
+    /// Creates the origin of a rule read at the given `line` - starting at 1 -
+    /// of the given `path`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::RuleOrigin;
+    ///
+    /// assert_eq!(RuleOrigin::new("command line", 2).to_string(), "command line:2");
+    /// ```
+    pub fn new(path: &str, line: usize) -> RuleOrigin {
+        RuleOrigin {
+            path: Arc::from(path),
+            line,
+        }
+    }
+
+    /// Provides the path - or the URL - of the whitelisting schema. Rules
+    /// parsed through [`Ruler::parse_reader`] come from `<reader>`, the ones
+    /// given through [`Ruler::try_parse_with_origin`] from wherever they were
+    /// said to come from.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Provides the line - starting at 1 - of the rule.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl std::fmt::Display for RuleOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.path, self.line)
+    }
+}
+
+/// Where the records parsed from files, links, readers - or with a given
+/// origin - come from - per kind of record.
+type Origins = HashMap<MatchKind, HashMap<String, RuleOrigin>>;
+
+/// When the records given with an `@expires=` date expire - per kind of
+/// record.
+type Expiries = HashMap<MatchKind, HashMap<String, ExpiryDate>>;
+
+/// Whether the given record - of the given kind - is past its expiry date.
+fn is_expired(expiries: &Expiries, kind: MatchKind, record: &str) -> bool {
+    expiries
+        .get(&kind)
+        .and_then(|records| records.get(record))
+        .is_some_and(|expiry| expiry.is_past())
+}
+
+/// The group - see `# @group` - of the records parsed within one - per kind of
+/// record.
+type Groups = HashMap<MatchKind, HashMap<String, Arc<str>>>;
+
+/// The number of subjects each record matched - per kind of record.
+type Usage = HashMap<MatchKind, HashMap<String, usize>>;
+
+/// The component of a URL that was whitelisted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrlComponent {
+    /// A rule matched the host of the URL.
+    Host,
+    /// A URL-prefix rule matched the start of the URL.
+    UrlPrefix,
+    /// A regular expression matched the whole URL.
+    Url,
+}
+
+/// Why a URL was whitelisted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlMatch {
+    /// The component of the URL that was whitelisted.
+    pub component: UrlComponent,
+    /// The kind of rule that matched.
+    pub kind: MatchKind,
+    /// The rule that matched. For regular expressions, this is the pattern of
+    /// the `REG ` rule.
+    pub rule: String,
+    /// Where the rule comes from - if it was parsed from a file, a link or a
+    /// reader or through [`Ruler::try_parse_with_origin`].
+    pub origin: Option<RuleOrigin>,
+}
+
+/// The number of records a ruler holds - per dataset. See [`Ruler::counts`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RuleCounts {
+    /// The records that only match the exact same subject - e.g. the plain and
+    /// `APEX ` rules.
+    pub strict: usize,
+    /// The records the `RZD ` rules expand into.
+    pub present: usize,
+    /// The records of the `ALL ` and `SUB ` rules.
+    pub ends: usize,
+    /// The URL-prefix rules.
+    pub url_prefixes: usize,
+    /// The rules of the `REG ` flag.
+    pub regexes: usize,
+    /// The records of the `EXC ` rules.
+    pub exceptions: usize,
+    /// The networks of the `CIDR ` rules.
+    pub cidrs: usize,
+    /// The keywords of the `KEY ` rules.
+    pub keywords: usize,
+    /// The records of the `START ` rules.
+    pub starts: usize,
+}
+
+impl RuleCounts {
+    /// Provides the number of records of every dataset.
+    pub fn total(&self) -> usize {
+        self.strict
+            + self.present
+            + self.ends
+            + self.url_prefixes
+            + self.regexes
+            + self.exceptions
+            + self.cidrs
+            + self.keywords
+            + self.starts
+    }
+}
+
+/// The limits applied to the regular expressions of the `REG ` and `RZD `
+/// flags. `None` keeps the default of the regular expression engine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegexLimits {
+    /// The maximum number of backtracking steps while matching a subject.
+    /// A subject that exceeds it is not whitelisted by the regular expressions.
+    pub backtrack_limit: Option<usize>,
+    /// The approximate maximum size - in bytes - of the compiled regular
+    /// expressions. A rule that exceeds it is rejected.
+    pub size_limit: Option<usize>,
+}
+
+impl RegexLimits {
+    /// Compiles the given `pattern` within the limits.
+    fn compile(&self, pattern: &str) -> Result<Regex, Box<fancy_regex::Error>> {
+        let mut builder = RegexBuilder::new(pattern);
+
+        if let Some(limit) = self.backtrack_limit {
+            builder.backtrack_limit(limit);
+        }
+
+        if let Some(limit) = self.size_limit {
+            builder.delegate_size_limit(limit);
+        }
+
+        builder.build().map_err(Box::new)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct RulerSettings {
     handle_complement: bool,
+    complement_prefixes: Vec<String>,
+    reductions: Vec<Reduction>,
+    ends_strategy: EndsStrategy,
+    idna: bool,
     extensions: Vec<String>,
+    regex_limits: RegexLimits,
+    rule_format: RuleFormat,
+}
+
+impl RulerSettings {
+    fn apply_reduction(&self, reduction: Reduction, element: String) -> String {
+        match reduction {
+            Reduction::StripPrefixes => {
+                for prefix in &self.complement_prefixes {
+                    if let Some(stripped) = element.strip_prefix(prefix.as_str()) {
+                        return stripped.to_string();
+                    }
+                }
+
+                element
+            }
+            Reduction::Lowercase => element.to_lowercase(),
+            Reduction::StripTrailingDot => element.trim_end_matches('.').to_string(),
+            Reduction::StripPort => match element.rsplit_once(':') {
+                Some((host, port))
+                    if !port.is_empty()
+                        && port.chars().all(|x| x.is_ascii_digit())
+                        && (!host.contains(':') || host.ends_with(']')) =>
+                {
+                    host.to_string()
+                }
+                _ => element,
+            },
+            Reduction::CaseFold => Ruler::case_fold(&element),
+            Reduction::Trim => element.trim().to_string(),
+            Reduction::StripScheme => match element.split_once("://") {
+                Some((_, rest)) => rest.to_string(),
+                None => element,
+            },
+        }
+    }
+
+    /// Applies the normalization chain - without the prefix stripping - to the
+    /// given element. This is what we store and compare.
+    ///
+    /// IP addresses are compared in their canonical form - e.g. `::1` for
+    /// `0:0:0:0:0:0:0:1`.
+    fn normalize(&self, element: &str) -> String {
+        let result = self
+            .reductions
+            .iter()
+            .filter(|reduction| **reduction != Reduction::StripPrefixes)
+            .fold(element.to_string(), |result, reduction| {
+                self.apply_reduction(*reduction, result)
+            });
+
+        cidr::canonical_ip(&result).unwrap_or(result)
+    }
+
+    fn normalize_url(&self, url: &str) -> Option<(String, String)> {
+        let url = url.trim();
+
+        if url.contains(char::is_whitespace) {
+            return None;
+        }
+
+        let (scheme, rest) = url.split_once("://")?;
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+
+        if scheme.is_empty() || authority.is_empty() {
+            return None;
+        }
+
+        let authority = authority.to_lowercase();
+        let host = self.normalize(&self.apply_reduction(Reduction::StripPort, authority.clone()));
+
+        Some((
+            host,
+            format!("{}://{}{}", scheme.to_lowercase(), authority, path),
+        ))
+    }
 }
 
 #[derive(Debug)]
 struct RulerTmps {
     downloaded_files: Vec<String>,
+    keep_downloads: bool,
+}
+
+#[derive(Debug)]
+struct RulerCaches {
+    idna: Option<LruCache<String, String>>,
+    /// The automaton of the `ALL ` and `SUB ` rules - compiled on demand. See
+    /// [`EndsStrategy::Automaton`].
+    ends: OnceLock<EndsAutomaton>,
+    /// The matcher of the `REG ` rules - compiled on demand.
+    regexes: OnceLock<RegexSetMatcher>,
+    /// The prefix tries of the `CIDR ` rules - built on demand.
+    cidrs: OnceLock<CidrTrie>,
+    /// The automaton of the `KEY ` rules - compiled on demand.
+    keywords: OnceLock<KeywordAutomaton>,
 }
 
 #[derive(Debug)]
 pub struct Ruler {
-    strict: HashMap<String, HashSet<String>>,
+    strict: HashSet<String>,
     ends: HashMap<String, HashSet<String>>,
-    present: HashMap<String, HashSet<String>>,
-    regex: String,
-    compiled_regex: Regex,
+    /// The records of the `START ` flag - indexed by their first label.
+    starts: HashMap<String, HashSet<String>>,
+    url_prefixes: HashMap<String, HashSet<String>>,
+    present: HashSet<String>,
+    /// The rules of the `REG ` flag.
+    regexes: Vec<String>,
+    /// The subjects exempted from every other rule - by the `EXC ` flag.
+    exceptions: HashSet<String>,
+    /// The networks of the `CIDR ` flag - e.g. `10.0.0.0/8`.
+    cidrs: HashSet<String>,
+    /// The keywords of the `KEY ` flag.
+    keywords: HashSet<String>,
+    /// Whether the compilation of the regular expressions is deferred - while
+    /// bulk loading.
+    defer_regex: bool,
+    /// Where our records come from - when known.
+    origins: Origins,
+    /// Where the rule being parsed comes from - when known.
+    origin: Option<RuleOrigin>,
+    /// When our records expire - if they do.
+    expiries: Expiries,
+    /// When the rule being parsed expires - if it does.
+    expiry: Option<ExpiryDate>,
+    /// The group of our records - if they were parsed within one.
+    groups: Groups,
+    /// The group of the rule being parsed - if any.
+    group: Option<Arc<str>>,
+    /// The disabled groups - with their records.
+    disabled_groups: HashMap<String, Vec<StashedRecord>>,
+    /// The records that matched a subject - when tracked. See
+    /// [`Ruler::set_track_usage`].
+    usage: Option<Mutex<Usage>>,
     settings: RulerSettings,
     tmps: RulerTmps,
+    caches: RulerCaches,
 }
 
 impl Ruler {
@@ -110,131 +578,797 @@ impl Ruler {
     /// ```
     pub fn new(handle_complement: bool) -> Ruler {
         Ruler {
-            strict: HashMap::new(),
+            strict: HashSet::new(),
             ends: HashMap::new(),
-            present: HashMap::new(),
-            regex: String::from(""),
-            compiled_regex: Regex::new("").unwrap(),
+            starts: HashMap::new(),
+            url_prefixes: HashMap::new(),
+            present: HashSet::new(),
+            regexes: vec![],
+            exceptions: HashSet::new(),
+            cidrs: HashSet::new(),
+            keywords: HashSet::new(),
+            defer_regex: false,
+            origins: HashMap::new(),
+            origin: None,
+            expiries: HashMap::new(),
+            expiry: None,
+            groups: HashMap::new(),
+            group: None,
+            disabled_groups: HashMap::new(),
+            usage: None,
             settings: RulerSettings {
                 handle_complement,
+                complement_prefixes: vec![String::from("www.")],
+                reductions: vec![Reduction::StripPrefixes],
+                ends_strategy: EndsStrategy::Buckets,
+                idna: true,
                 extensions: vec![],
+                regex_limits: RegexLimits::default(),
+                rule_format: RuleFormat::Tivilsta,
             },
             tmps: RulerTmps {
                 downloaded_files: vec![],
+                keep_downloads: false,
+            },
+            caches: RulerCaches {
+                idna: NonZeroUsize::new(DEFAULT_IDNA_CACHE_CAPACITY).map(LruCache::new),
+                ends: OnceLock::new(),
+                regexes: OnceLock::new(),
+                cidrs: OnceLock::new(),
+                keywords: OnceLock::new(),
             },
         }
     }
 
-    fn reduce(&self, element: &String) -> String {
-        if let Some(stripped) = element.strip_prefix("www.") {
-            stripped.to_string()
-        } else {
-            element.to_string()
-        }
+    /// Sets the prefixes that should be considered as complements.
+    ///
+    /// By default, only `www.` is considered.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefixes` - The prefixes to consider. A missing trailing dot is added.
+    ///
+    /// # Returns
+    ///
+    /// The updated Ruler object.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(true)
+    ///     .with_complement_prefixes(&[String::from("www."), String::from("m")]);
+    ///
+    /// ruler.parse(&String::from("example.org"));
+    ///
+    /// assert_eq!(ruler.is_whitelisted(&String::from("www.example.org")), true);
+    /// assert_eq!(ruler.is_whitelisted(&String::from("m.example.org")), true);
+    /// assert_eq!(ruler.is_whitelisted(&String::from("mail.example.org")), false);
+    /// ```
+    pub fn with_complement_prefixes(mut self, prefixes: &[String]) -> Ruler {
+        self.set_complement_prefixes(prefixes);
+
+        self
     }
 
-    fn extensions() -> Vec<String> {
-        let mut extensions: Vec<String> = Vec::new();
+    /// Sets whether the rules and lines are IDNAzed before being parsed or
+    /// checked.
+    ///
+    /// By default, they are - which also lowercases them. Disable it when the
+    /// canonical form of your consumers is given through
+    /// [`Ruler::with_reductions`] instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `idna` - Whether to IDNAze the rules and lines.
+    ///
+    /// # Returns
+    ///
+    /// The updated Ruler object.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false).with_idna(false);
+    ///
+    /// ruler.parse(&String::from("Example.org"));
+    ///
+    /// assert_eq!(ruler.is_whitelisted(&String::from("Example.org")), true);
+    /// assert_eq!(ruler.is_whitelisted(&String::from("example.org")), false);
+    /// ```
+    pub fn with_idna(mut self, idna: bool) -> Ruler {
+        self.set_idna(idna);
 
-        let mut iana_extensions = iana::extensions().unwrap();
-        let mut psl_suffixes = psl::suffixes().unwrap();
+        self
+    }
 
-        extensions.append(&mut iana_extensions);
-        extensions.append(&mut psl_suffixes);
+    /// Sets the maximum number of IDNAzed subjects to keep in memory.
+    ///
+    /// Block lists contain massive runs of similar subjects. Keeping the most
+    /// recently IDNAzed ones around avoids re-encoding them over and over.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The capacity of the cache. `0` disables the cache.
+    ///
+    /// # Returns
+    ///
+    /// The updated Ruler object.
+    pub fn with_idna_cache_capacity(mut self, capacity: usize) -> Ruler {
+        self.set_idna_cache_capacity(capacity);
 
-        extensions
+        self
     }
 
-    fn search_keys(&mut self, record: &str) -> (String, String) {
-        let common_search_key = record.chars().take(4).collect::<String>();
-        let ends_search_key = record
-            .chars()
-            .rev()
-            .take(3)
-            .collect::<Vec<_>>()
-            .into_iter()
-            .rev()
-            .collect::<String>();
+    /// Sets the normalization chain applied to rules and subjects.
+    ///
+    /// By default, only [`Reduction::StripPrefixes`] is applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `reductions` - The steps to apply - in the given order.
+    ///
+    /// # Returns
+    ///
+    /// The updated Ruler object.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{Reduction, Ruler};
+    ///
+    /// let mut ruler = Ruler::new(false).with_reductions(&[
+    ///     Reduction::StripPrefixes,
+    ///     Reduction::Lowercase,
+    ///     Reduction::StripTrailingDot,
+    ///     Reduction::StripPort,
+    /// ]);
+    ///
+    /// ruler.parse(&String::from("Example.org."));
+    ///
+    /// assert_eq!(ruler.is_whitelisted(&String::from("example.org")), true);
+    /// assert_eq!(ruler.is_whitelisted(&String::from("EXAMPLE.ORG:443")), true);
+    /// ```
+    pub fn with_reductions(mut self, reductions: &[Reduction]) -> Ruler {
+        self.set_reductions(reductions);
 
-        (common_search_key, ends_search_key)
+        self
     }
 
-    fn push_strict(&mut self, record: &String) {
-        let (search_key, _) = self.search_keys(&self.reduce(record));
-
-        match self.strict.entry(search_key) {
-            Entry::Occupied(mut entry) => {
-                entry.get_mut().insert(record.to_string());
-            }
-            Entry::Vacant(entry) => {
-                let mut dataset = HashSet::new();
+    /// Sets how the rules of the `ALL ` and `SUB ` flags are looked up.
+    ///
+    /// By default, [`EndsStrategy::Buckets`] is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `strategy` - The strategy to use.
+    ///
+    /// # Returns
+    ///
+    /// The updated Ruler object.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{EndsStrategy, Ruler};
+    ///
+    /// let mut ruler = Ruler::new(false).with_ends_strategy(EndsStrategy::Automaton);
+    ///
+    /// ruler.parse(&String::from("ALL .example.org"));
+    ///
+    /// assert_eq!(ruler.is_whitelisted(&String::from("api.example.org")), true);
+    /// assert_eq!(ruler.is_whitelisted(&String::from("apiexample.org")), false);
+    /// ```
+    pub fn with_ends_strategy(mut self, strategy: EndsStrategy) -> Ruler {
+        self.set_ends_strategy(strategy);
 
-                dataset.insert(record.to_string());
-                entry.insert(dataset);
-            }
-        }
+        self
     }
 
-    fn pull_strict(&mut self, record: &String) {
-        let (search_key, _) = self.search_keys(&self.reduce(record));
+    /// Sets the format of the whitelisting schemas we parse.
+    ///
+    /// By default, [`RuleFormat::Tivilsta`] is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format of the rules.
+    ///
+    /// # Returns
+    ///
+    /// The updated Ruler object.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{Ruler, RuleFormat};
+    ///
+    /// let mut ruler = Ruler::new(false).with_rule_format(RuleFormat::Adblock);
+    ///
+    /// ruler.parse("@@||example.org^$document");
+    ///
+    /// assert_eq!(ruler.is_whitelisted("api.example.org"), true);
+    /// assert_eq!(ruler.is_whitelisted("example.net"), false);
+    /// ```
+    pub fn with_rule_format(mut self, format: RuleFormat) -> Ruler {
+        self.set_rule_format(format);
 
-        match self.strict.entry(search_key) {
-            Entry::Occupied(mut entry) => {
-                entry.get_mut().remove(record);
-            }
-            Entry::Vacant(entry) => {
-                let _ = entry;
-            }
-        }
+        self
     }
 
-    fn push_present(&mut self, record: &String) {
-        let (search_key, _) = self.search_keys(&self.reduce(record));
+    /// Sets whether we remember which records matched a subject.
+    ///
+    /// # Arguments
+    ///
+    /// * `track` - Whether the usage of the records should be tracked. See
+    /// [`Ruler::set_track_usage`].
+    ///
+    /// # Returns
+    ///
+    /// The updated Ruler object.
+    pub fn with_track_usage(mut self, track: bool) -> Ruler {
+        self.set_track_usage(track);
 
-        match self.present.entry(search_key) {
-            Entry::Occupied(mut entry) => {
-                entry.get_mut().insert(record.to_string());
-            }
-            Entry::Vacant(entry) => {
-                let mut dataset = HashSet::new();
+        self
+    }
 
-                dataset.insert(record.to_string());
-                entry.insert(dataset);
-            }
-        }
+    /// Whether we follow and cleanup complements while parsing.
+    pub fn handle_complement(&self) -> bool {
+        self.settings.handle_complement
     }
 
-    fn pull_present(&mut self, record: &String) {
-        let (search_key, _) = self.search_keys(&self.reduce(record));
+    /// Sets whether we should follow and cleanup complements while parsing.
+    ///
+    /// **Note:** The rules that have already been parsed are left untouched.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler.parse(&String::from("example.org"));
+    /// ruler.set_handle_complement(true);
+    /// ruler.parse(&String::from("example.net"));
+    ///
+    /// assert_eq!(ruler.handle_complement(), true);
+    /// assert_eq!(ruler.is_whitelisted(&String::from("www.example.org")), false);
+    /// assert_eq!(ruler.is_whitelisted(&String::from("www.example.net")), true);
+    /// ```
+    pub fn set_handle_complement(&mut self, handle_complement: bool) {
+        self.settings.handle_complement = handle_complement;
+    }
 
-        match self.present.entry(search_key) {
-            Entry::Occupied(mut entry) => {
-                entry.get_mut().remove(record);
-            }
-            Entry::Vacant(entry) => {
-                let _ = entry;
-            }
-        }
+    /// The prefixes that are considered as complements.
+    pub fn complement_prefixes(&self) -> &[String] {
+        &self.settings.complement_prefixes
     }
 
-    fn push_ends(&mut self, record: &String) {
-        let (_, search_key) = self.search_keys(&self.reduce(record));
+    /// Sets the prefixes that should be considered as complements.
+    /// A missing trailing dot is added.
+    ///
+    /// **Note:** The rules that have already been parsed are left untouched.
+    pub fn set_complement_prefixes(&mut self, prefixes: &[String]) {
+        self.settings.complement_prefixes = prefixes
+            .iter()
+            .map(|prefix| prefix.trim())
+            .filter(|prefix| !prefix.is_empty())
+            .map(|prefix| {
+                if prefix.ends_with('.') {
+                    prefix.to_string()
+                } else {
+                    format!("{}.", prefix)
+                }
+            })
+            .collect();
+    }
 
-        match self.ends.entry(search_key) {
-            Entry::Occupied(mut entry) => {
-                entry.get_mut().insert(record.to_string());
-            }
-            Entry::Vacant(entry) => {
-                let mut dataset = HashSet::new();
+    /// The normalization chain applied to rules and subjects.
+    pub fn reductions(&self) -> &[Reduction] {
+        &self.settings.reductions
+    }
 
-                dataset.insert(record.to_string());
-                entry.insert(dataset);
-            }
-        }
+    /// Sets the normalization chain applied to rules and subjects.
+    ///
+    /// **Note:** The rules that have already been parsed are left untouched.
+    pub fn set_reductions(&mut self, reductions: &[Reduction]) {
+        self.settings.reductions = reductions.to_vec();
     }
 
-    fn pull_ends(&mut self, record: &String) {
-        let (_, search_key) = self.search_keys(&self.reduce(record));
+    /// How the rules of the `ALL ` and `SUB ` flags are looked up.
+    pub fn ends_strategy(&self) -> EndsStrategy {
+        self.settings.ends_strategy
+    }
+
+    /// Sets how the rules of the `ALL ` and `SUB ` flags are looked up.
+    pub fn set_ends_strategy(&mut self, strategy: EndsStrategy) {
+        self.settings.ends_strategy = strategy;
+    }
+
+    /// The format of the whitelisting schemas we parse.
+    pub fn rule_format(&self) -> RuleFormat {
+        self.settings.rule_format
+    }
+
+    /// Sets the format of the whitelisting schemas we parse.
+    ///
+    /// **Note:** The rules that have already been parsed are left untouched.
+    pub fn set_rule_format(&mut self, format: RuleFormat) {
+        self.settings.rule_format = format;
+    }
+
+    /// Whether the rules and lines are IDNAzed before being parsed or checked.
+    pub fn idna(&self) -> bool {
+        self.settings.idna
+    }
+
+    /// Sets whether the rules and lines are IDNAzed before being parsed or
+    /// checked.
+    ///
+    /// **Note:** The rules that have already been parsed are left untouched.
+    pub fn set_idna(&mut self, idna: bool) {
+        self.settings.idna = idna;
+    }
+
+    /// The maximum number of IDNAzed subjects we keep in memory.
+    pub fn idna_cache_capacity(&self) -> usize {
+        self.caches
+            .idna
+            .as_ref()
+            .map_or(0, |cache| cache.cap().get())
+    }
+
+    /// Sets the maximum number of IDNAzed subjects to keep in memory.
+    /// `0` disables the cache.
+    ///
+    /// **Note:** The cache is emptied.
+    pub fn set_idna_cache_capacity(&mut self, capacity: usize) {
+        self.caches.idna = NonZeroUsize::new(capacity).map(LruCache::new);
+    }
+
+    /// Whether we remember which records matched a subject.
+    pub fn track_usage(&self) -> bool {
+        self.usage.is_some()
+    }
+
+    /// Sets whether we remember which records matched a subject - and how
+    /// many times. See [`Ruler::unused_rules`] and [`Ruler::hit_counts`].
+    ///
+    /// Every check - e.g. [`Ruler::is_whitelisted`] - then updates a shared
+    /// table, which slows down the checks made in parallel.
+    ///
+    /// **Note:** The usage tracked so far is forgotten.
+    pub fn set_track_usage(&mut self, track: bool) {
+        self.usage = track.then(Mutex::default);
+    }
+
+    /// Provides the limits applied to our regular expressions - see
+    /// [`RulerBuilder::regex_limits`].
+    pub fn regex_limits(&self) -> RegexLimits {
+        self.settings.regex_limits
+    }
+
+    /// Whether the files downloaded by [`Ruler::parse_link`] and
+    /// [`Ruler::unparse_link`] are kept when we are dropped.
+    pub fn keep_downloads(&self) -> bool {
+        self.tmps.keep_downloads
+    }
+
+    /// Sets whether the files downloaded by [`Ruler::parse_link`] and
+    /// [`Ruler::unparse_link`] are kept - instead of being removed - when we
+    /// are dropped. Default: `false`.
+    pub fn set_keep_downloads(&mut self, keep: bool) {
+        self.tmps.keep_downloads = keep;
+    }
+
+    /// Provides the paths of the files downloaded by [`Ruler::parse_link`] and
+    /// [`Ruler::unparse_link`] - including the ones of the merged rulers.
+    pub fn downloaded_files(&self) -> &[String] {
+        &self.tmps.downloaded_files
+    }
+
+    /// Takes the paths of the downloaded files - see
+    /// [`Ruler::downloaded_files`]. They are then yours to cleanup - or
+    /// relocate.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// assert!(ruler.take_downloaded_files().is_empty());
+    /// assert!(ruler.downloaded_files().is_empty());
+    /// ```
+    pub fn take_downloaded_files(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.tmps.downloaded_files)
+    }
+
+    /// Creates a new empty Ruler object that shares our settings.
+    pub(crate) fn empty_like(&self) -> Ruler {
+        let mut result = Ruler::new(self.handle_complement())
+            .with_complement_prefixes(self.complement_prefixes())
+            .with_reductions(self.reductions())
+            .with_ends_strategy(self.ends_strategy())
+            .with_idna(self.idna())
+            .with_idna_cache_capacity(self.idna_cache_capacity());
+
+        result.settings.regex_limits = self.settings.regex_limits;
+        result.settings.rule_format = self.settings.rule_format;
+        result.disabled_groups = self
+            .disabled_groups
+            .keys()
+            .map(|group| (group.clone(), vec![]))
+            .collect();
+
+        result
+    }
+
+    /// Creates a new Ruler object that shares our settings - and holds a copy
+    /// of our rules.
+    pub(crate) fn duplicate(&self) -> Ruler {
+        let mut result = self.empty_like();
+
+        result.strict = self.strict.clone();
+        result.ends = self.ends.clone();
+        result.starts = self.starts.clone();
+        result.url_prefixes = self.url_prefixes.clone();
+        result.present = self.present.clone();
+        result.regexes = self.regexes.clone();
+        result.exceptions = self.exceptions.clone();
+        result.cidrs = self.cidrs.clone();
+        result.keywords = self.keywords.clone();
+        result.origins = self.origins.clone();
+        result.expiries = self.expiries.clone();
+        result.groups = self.groups.clone();
+        result.disabled_groups = self.disabled_groups.clone();
+        result.caches.regexes = self.caches.regexes.clone();
+        result.settings.extensions = self.settings.extensions.clone();
+
+        result
+    }
+
+    fn apply_reduction(&self, reduction: Reduction, element: String) -> String {
+        self.settings.apply_reduction(reduction, element)
+    }
+
+    fn case_fold(element: &str) -> String {
+        if element.is_ascii() {
+            return element.to_ascii_lowercase();
+        }
+
+        match idna::domain_to_ascii(element) {
+            Ok(result) => result,
+            Err(_) => idna::domain_to_unicode(element).0,
+        }
+    }
+
+    /// Applies the whole normalization chain to the given element.
+    fn reduce(&self, element: &str) -> String {
+        self.settings
+            .reductions
+            .iter()
+            .fold(element.to_string(), |result, reduction| {
+                self.apply_reduction(*reduction, result)
+            })
+    }
+
+    /// Applies the normalization chain - without the prefix stripping - to the
+    /// given element. This is what we store and compare.
+    fn normalize(&self, element: &str) -> String {
+        self.settings.normalize(element)
+    }
+
+    fn complements(&self, record: &str) -> Vec<String> {
+        // An IP address has no complement.
+        if cidr::canonical_ip(record).is_some() {
+            return vec![];
+        }
+
+        self.settings
+            .complement_prefixes
+            .iter()
+            .map(|prefix| format!("{}{}", prefix, record))
+            .collect()
+    }
+
+    /// Loads the known extensions - once. They stay unknown while their
+    /// datasets can't be fetched, so the rules needing them are ignored -
+    /// `try_parse` reports it.
+    fn load_extensions(&mut self) {
+        if self.settings.extensions.is_empty() {
+            self.settings.extensions = Ruler::try_extensions().unwrap_or_default();
+        }
+    }
+
+    fn try_extensions() -> Result<Vec<String>, TivilstaError> {
+        let mut extensions: Vec<String> = Vec::new();
+
+        let mut iana_extensions = iana::extensions()?;
+        let mut psl_suffixes = psl::suffixes()?;
+
+        extensions.append(&mut iana_extensions);
+        extensions.append(&mut psl_suffixes);
+
+        Ok(extensions)
+    }
+
+    /// Provides the key the rules of the `ALL ` and `SUB ` flags are indexed
+    /// by - their last label. E.g. `org` for `.example.org`.
+    ///
+    /// As those rules always start with a dot, a rule matching the end of a
+    /// subject always shares its last label.
+    fn ends_search_key<'a>(&self, record: &'a str) -> &'a str {
+        record.rsplit('.').next().unwrap_or(record)
+    }
+
+    /// Provides the key of the bucket of the given `START ` record - or
+    /// subject: its first label.
+    fn starts_search_key<'a>(&self, record: &'a str) -> &'a str {
+        record.split('.').next().unwrap_or(record)
+    }
+
+    /// Remembers where the given (normalized) record comes from - if known -
+    /// when it expires and its group.
+    ///
+    /// A record given more than once keeps its first origin and the group of
+    /// the last rule that produced it. See `push_expiry` for its expiry date.
+    fn push_metadata(&mut self, kind: MatchKind, record: &str) {
+        if let Some(origin) = &self.origin {
+            self.origins
+                .entry(kind)
+                .or_default()
+                .entry(record.to_string())
+                .or_insert_with(|| origin.clone());
+        }
+
+        self.push_expiry(kind, record, self.expiry);
+
+        match &self.group {
+            Some(group) => {
+                self.groups
+                    .entry(kind)
+                    .or_default()
+                    .insert(record.to_string(), group.clone());
+            }
+            None => {
+                if let Some(records) = self.groups.get_mut(&kind) {
+                    records.remove(record);
+                }
+            }
+        }
+    }
+
+    /// Remembers when the given (normalized) record - produced by a rule
+    /// expiring at the given date - expires.
+    ///
+    /// A record produced by a rule without expiry date is permanent - whatever
+    /// the other rules that produced it. Otherwise, it expires with the last
+    /// of its rules.
+    fn push_expiry(&mut self, kind: MatchKind, record: &str, expiry: Option<ExpiryDate>) {
+        let known = self
+            .expiries
+            .get(&kind)
+            .and_then(|records| records.get(record))
+            .copied();
+
+        let expiry = match (expiry, known) {
+            (None, _) => None,
+            // We already hold it - without expiry date.
+            (Some(_), None) if self.holds(kind, record) => None,
+            (Some(expiry), known) => Some(known.map_or(expiry, |known| known.max(expiry))),
+        };
+
+        match expiry {
+            Some(expiry) => {
+                self.expiries
+                    .entry(kind)
+                    .or_default()
+                    .insert(record.to_string(), expiry);
+            }
+            None => {
+                if let Some(records) = self.expiries.get_mut(&kind) {
+                    records.remove(record);
+                }
+            }
+        }
+    }
+
+    fn pull_metadata(&mut self, kind: MatchKind, record: &str) {
+        if let Some(records) = self.origins.get_mut(&kind) {
+            records.remove(record);
+        }
+
+        if let Some(records) = self.expiries.get_mut(&kind) {
+            records.remove(record);
+        }
+
+        if let Some(records) = self.groups.get_mut(&kind) {
+            records.remove(record);
+        }
+    }
+
+    /// Provides the group started by the given `# @group <name>` comment -
+    /// `None` when a bare `# @group` ends the current one.
+    fn group_directive(line: &str) -> Option<Option<&str>> {
+        let directive = line
+            .strip_prefix('#')?
+            .trim_start()
+            .strip_prefix("@group")?;
+
+        // E.g. `# @groups`.
+        if !directive.is_empty() && !directive.starts_with(char::is_whitespace) {
+            return None;
+        }
+
+        let name = directive.trim();
+
+        Some((!name.is_empty()).then_some(name))
+    }
+
+    /// Follows the `# @group` directive of the given comment - if any.
+    fn parse_comment(&mut self, line: &str) {
+        if let Some(group) = Ruler::group_directive(line) {
+            self.group = group.map(Arc::from);
+        }
+    }
+
+    /// Parses the given (IDNAzed) line - or sets its records aside if the
+    /// group being parsed is disabled.
+    fn parse_grouped(&mut self, idnazed_line: &str) {
+        let Some(group) = self
+            .group
+            .clone()
+            .filter(|group| self.disabled_groups.contains_key(&**group))
+        else {
+            self.parse_idnazed(idnazed_line);
+            return;
+        };
+
+        let mut single = self.empty_like();
+
+        single.settings.extensions = std::mem::take(&mut self.settings.extensions);
+        // The rule is only parsed to know its records.
+        single.defer_regex = true;
+        single.parse_idnazed(idnazed_line);
+        self.settings.extensions = std::mem::take(&mut single.settings.extensions);
+
+        let stashed: Vec<StashedRecord> = single
+            .records()
+            .map(|(kind, record)| StashedRecord {
+                kind,
+                record: record.to_string(),
+                origin: self.origin.clone(),
+                expiry: self.expiry,
+            })
+            .collect();
+
+        if let Some(records) = self.disabled_groups.get_mut(&*group) {
+            records.extend(stashed);
+        }
+    }
+
+    /// Splits the given line into its rule and its `@expires=` date - if any.
+    fn split_expiry(line: &str) -> (&str, Option<&str>) {
+        let trimmed = line.trim_end();
+
+        match trimmed.rsplit_once(char::is_whitespace) {
+            Some((rule, metadata)) => match metadata.strip_prefix("@expires=") {
+                Some(date) => (rule.trim_end(), Some(date)),
+                None => (line, None),
+            },
+            None => (line, None),
+        }
+    }
+
+    fn push_strict(&mut self, record: &str) {
+        let record = self.normalize(record);
+
+        self.push_metadata(MatchKind::Strict, &record);
+        self.strict.insert(record);
+    }
+
+    fn pull_strict(&mut self, record: &str) {
+        let record = self.normalize(record);
+
+        self.pull_metadata(MatchKind::Strict, &record);
+        self.strict.remove(&record);
+    }
+
+    fn push_present(&mut self, record: &str) {
+        let record = self.normalize(record);
+
+        self.push_metadata(MatchKind::Present, &record);
+        self.present.insert(record);
+    }
+
+    fn pull_present(&mut self, record: &str) {
+        let record = self.normalize(record);
+
+        self.pull_metadata(MatchKind::Present, &record);
+        self.present.remove(&record);
+    }
+
+    fn push_exception(&mut self, record: &str) {
+        let record = self.normalize(record);
+
+        self.push_metadata(MatchKind::Exception, &record);
+        self.exceptions.insert(record);
+    }
+
+    fn pull_exception(&mut self, record: &str) {
+        let record = self.normalize(record);
+
+        self.pull_metadata(MatchKind::Exception, &record);
+        self.exceptions.remove(&record);
+    }
+
+    fn push_cidr(&mut self, network: &Network) {
+        let record = network.to_string();
+
+        self.push_metadata(MatchKind::Cidr, &record);
+        self.cidrs.insert(record);
+        self.caches.cidrs.take();
+    }
+
+    fn pull_cidr(&mut self, network: &Network) {
+        let record = network.to_string();
+
+        self.pull_metadata(MatchKind::Cidr, &record);
+
+        if self.cidrs.remove(&record) {
+            self.caches.cidrs.take();
+        }
+    }
+
+    fn push_keyword(&mut self, record: &str) {
+        let record = self.normalize(record);
+
+        self.push_metadata(MatchKind::Keyword, &record);
+        self.keywords.insert(record);
+        self.caches.keywords.take();
+    }
+
+    fn pull_keyword(&mut self, record: &str) {
+        let record = self.normalize(record);
+
+        self.pull_metadata(MatchKind::Keyword, &record);
+
+        if self.keywords.remove(&record) {
+            self.caches.keywords.take();
+        }
+    }
+
+    fn push_ends(&mut self, record: &str) {
+        let record = &self.normalize(record);
+        let search_key = self.ends_search_key(record).to_string();
+
+        self.push_metadata(MatchKind::Ends, record);
+
+        match self.ends.entry(search_key) {
+            Entry::Occupied(mut entry) => {
+                entry.get_mut().insert(record.to_string());
+            }
+            Entry::Vacant(entry) => {
+                let mut dataset = HashSet::new();
+
+                dataset.insert(record.to_string());
+                entry.insert(dataset);
+            }
+        }
+
+        self.caches.ends.take();
+    }
+
+    fn pull_ends(&mut self, record: &str) {
+        let record = &self.normalize(record);
+        let search_key = self.ends_search_key(record).to_string();
+
+        self.pull_metadata(MatchKind::Ends, record);
 
         match self.ends.entry(search_key) {
             Entry::Occupied(mut entry) => {
@@ -244,28 +1378,95 @@ impl Ruler {
                 let _ = entry;
             }
         }
+
+        self.caches.ends.take();
     }
 
-    fn push_regex(&mut self, record: &String) {
-        if self.regex.is_empty() {
-            self.regex.push_str(&record.to_string());
-        } else {
-            self.regex.push_str(&format!("|{}", record));
+    fn push_starts(&mut self, record: &str) {
+        let record = self.normalize(record);
+        let search_key = self.starts_search_key(&record).to_string();
+
+        self.push_metadata(MatchKind::Starts, &record);
+        self.starts.entry(search_key).or_default().insert(record);
+    }
+
+    fn pull_starts(&mut self, record: &str) {
+        let record = self.normalize(record);
+
+        self.pull_metadata(MatchKind::Starts, &record);
+
+        if let Some(records) = self.starts.get_mut(self.starts_search_key(&record)) {
+            records.remove(&record);
         }
+    }
 
-        self.compiled_regex = Regex::new(&self.regex[..]).unwrap();
+    /// Compiles the given `pattern` within our regular expression limits.
+    fn compile_regex(&self, pattern: &str) -> Result<Regex, TivilstaError> {
+        self.settings
+            .regex_limits
+            .compile(pattern)
+            .map_err(|source| TivilstaError::Regex {
+                pattern: pattern.to_string(),
+                source,
+            })
     }
 
-    fn pull_regex(&mut self, record: &String) {
-        if self.regex.starts_with(record) && self.regex.ends_with(record) {
-            self.regex = String::from("");
-        } else if self.regex.starts_with(record) {
-            self.regex = self.regex.replace(&format!("{}|", record), "");
-        } else {
-            self.regex = self.regex.replace(&format!("|{}", record), "");
+    /// Compiles the rules of the `REG ` flag - at once - within our regular
+    /// expression limits.
+    fn compile_regex_set(&self) -> Result<RegexSetMatcher, TivilstaError> {
+        RegexSetMatcher::new(&self.regexes, &self.settings.regex_limits).map_err(
+            |(position, error)| TivilstaError::Regex {
+                pattern: self.regexes[position].to_string(),
+                source: error,
+            },
+        )
+    }
+
+    /// Provides the matcher of the rules of the `REG ` flag - compiled on the
+    /// first lookup after they changed.
+    fn regex_matcher(&self) -> &RegexSetMatcher {
+        // Every pattern was validated while being parsed.
+        self.caches
+            .regexes
+            .get_or_init(|| self.compile_regex_set().unwrap())
+    }
+
+    /// Provides the first rule of the `REG ` flag that matches the given
+    /// `subject`.
+    fn find_regex(&self, subject: &str) -> Option<&String> {
+        if self.regexes.is_empty() {
+            return None;
         }
 
-        self.compiled_regex = Regex::new(&self.regex[..]).unwrap();
+        self.regex_matcher()
+            .find(subject, |position| {
+                self.is_live(MatchKind::Regex, &self.regexes[position])
+            })
+            .map(|position| &self.regexes[position])
+    }
+
+    fn push_regex(&mut self, record: &str) {
+        // The whole set is compiled once - on the next lookup.
+        if !self.defer_regex {
+            self.compile_regex(record).unwrap();
+        }
+
+        self.push_metadata(MatchKind::Regex, record);
+        self.regexes.push(record.to_string());
+        self.caches.regexes.take();
+    }
+
+    fn pull_regex(&mut self, record: &str) {
+        let count = self.regexes.len();
+
+        // Like the other datasets, a rule given more than once is removed at
+        // once.
+        self.regexes.retain(|x| x != record);
+        self.pull_metadata(MatchKind::Regex, record);
+
+        if self.regexes.len() != count {
+            self.caches.regexes.take();
+        }
     }
 
     fn parse_all(&mut self, line: &str) -> bool {
@@ -282,13 +1483,15 @@ impl Ruler {
         if let Some(stripped) = record.strip_prefix('.') {
             if record.matches('.').count() > 1 {
                 if self.settings.handle_complement {
-                    self.push_strict(&format!("www.{}", stripped));
+                    for complement in self.complements(stripped) {
+                        self.push_strict(&complement);
+                    }
                 }
-                self.push_strict(&stripped.to_string());
+                self.push_strict(stripped);
             }
             self.push_ends(&record);
         } else {
-            self.parse(&format!("ALL .{}", record));
+            self.parse_rule(&format!("ALL .{}", record));
         }
 
         true
@@ -308,999 +1511,5324 @@ impl Ruler {
         if let Some(stripped) = record.strip_prefix('.') {
             if record.matches('.').count() > 1 {
                 if self.settings.handle_complement {
-                    self.pull_strict(&format!("www.{}", stripped));
+                    for complement in self.complements(stripped) {
+                        self.pull_strict(&complement);
+                    }
                 }
-                self.pull_strict(&stripped.to_string());
+                self.pull_strict(stripped);
             }
             self.pull_ends(&record);
         } else {
-            self.unparse(&format!("ALL .{}", record));
+            self.unparse_rule(&format!("ALL .{}", record));
         }
 
         true
     }
 
-    fn parse_root_zone_db(&mut self, line: &str) -> bool {
+    fn parse_sub(&mut self, line: &str) -> bool {
         let mut record: String;
 
-        if line.starts_with("RZD ") {
-            record = line.replacen("RZD ", "", 1).trim().to_string()
-        } else if line.starts_with("rzd ") {
-            record = line.replacen("rzd ", "", 1).trim().to_string()
+        if line.starts_with("SUB ") {
+            record = line.replacen("SUB ", "", 1).trim().to_string()
+        } else if line.starts_with("sub ") {
+            record = line.replacen("sub ", "", 1).trim().to_string()
         } else {
             return false;
         }
 
-        if self.settings.handle_complement && record.starts_with("www.") {
-            record = record.replacen("www.", "", 1).trim().to_string();
-        }
-
-        if self.settings.extensions.is_empty() {
-            self.settings.extensions = Ruler::extensions()
+        if self.settings.handle_complement {
+            record = self.reduce(&record);
         }
 
-        for extension in &self.settings.extensions.clone() {
-            self.push_present(&format!("{}.{}", record, extension));
-
-            if self.settings.handle_complement {
-                self.push_present(&format!("www.{}.{}", record, extension));
-            }
+        if record.starts_with('.') {
+            self.push_ends(&record);
+        } else {
+            self.push_ends(&format!(".{}", record));
         }
 
         true
     }
 
-    fn unparse_root_zone_db(&mut self, line: &str) -> bool {
+    fn unparse_sub(&mut self, line: &str) -> bool {
         let mut record: String;
 
-        if line.starts_with("RZD ") {
-            record = line.replacen("RZD ", "", 1).trim().to_string()
-        } else if line.starts_with("rzd ") {
-            record = line.replacen("rzd ", "", 1).trim().to_string()
+        if line.starts_with("SUB ") {
+            record = line.replacen("SUB ", "", 1).trim().to_string()
+        } else if line.starts_with("sub ") {
+            record = line.replacen("sub ", "", 1).trim().to_string()
         } else {
             return false;
         }
 
-        if self.settings.handle_complement && record.starts_with("www.") {
-            record = record.replacen("www.", "", 1).trim().to_string();
-        }
-
-        if self.settings.extensions.is_empty() {
-            self.settings.extensions = Ruler::extensions()
+        if self.settings.handle_complement {
+            record = self.reduce(&record);
         }
 
-        for extension in &self.settings.extensions.clone() {
-            self.pull_present(&format!("{}.{}", record, extension));
-
-            if self.settings.handle_complement {
-                self.pull_present(&format!("www.{}.{}", record, extension));
-            }
+        if record.starts_with('.') {
+            self.pull_ends(&record);
+        } else {
+            self.pull_ends(&format!(".{}", record));
         }
 
         true
     }
 
-    fn parse_regex(&mut self, line: &str) -> bool {
-        let record: String;
+    fn parse_apex(&mut self, line: &str) -> bool {
+        let mut record: String;
 
-        if line.starts_with("REG ") {
-            record = line.replacen("REG ", "", 1).trim().to_string()
-        } else if line.starts_with("reg ") {
-            record = line.replacen("reg ", "", 1).trim().to_string()
+        if line.starts_with("APEX ") {
+            record = line.replacen("APEX ", "", 1).trim().to_string()
+        } else if line.starts_with("apex ") {
+            record = line.replacen("apex ", "", 1).trim().to_string()
         } else {
             return false;
         }
 
-        self.push_regex(&record);
+        record = self.reduce(&record);
+
+        // The complements are always part of an apex - whatever the settings say.
+        for complement in self.complements(&record) {
+            self.push_strict(&complement);
+        }
+
+        self.push_strict(&record);
 
         true
     }
 
-    fn unparse_regex(&mut self, line: &str) -> bool {
-        let record: String;
+    fn unparse_apex(&mut self, line: &str) -> bool {
+        let mut record: String;
 
-        if line.starts_with("REG ") {
-            record = line.replacen("REG ", "", 1).trim().to_string()
-        } else if line.starts_with("reg ") {
-            record = line.replacen("reg ", "", 1).trim().to_string()
+        if line.starts_with("APEX ") {
+            record = line.replacen("APEX ", "", 1).trim().to_string()
+        } else if line.starts_with("apex ") {
+            record = line.replacen("apex ", "", 1).trim().to_string()
         } else {
             return false;
         }
 
-        self.pull_regex(&record);
+        record = self.reduce(&record);
+
+        for complement in self.complements(&record) {
+            self.pull_strict(&complement);
+        }
+
+        self.pull_strict(&record);
 
         true
     }
 
-    fn parse_plain(&mut self, line: &String) -> bool {
-        let record: String = if self.settings.handle_complement && line.starts_with("www.") {
-            line.replacen("www.", "", 1).trim().to_string()
-        } else {
-            line.to_string()
-        };
+    fn parse_root_zone_db(&mut self, line: &str) -> bool {
+        let mut record: String;
 
-        self.push_strict(&record);
+        if line.starts_with("RZD ") {
+            record = line.replacen("RZD ", "", 1).trim().to_string()
+        } else if line.starts_with("rzd ") {
+            record = line.replacen("rzd ", "", 1).trim().to_string()
+        } else {
+            return false;
+        }
 
         if self.settings.handle_complement {
-            self.push_strict(&format!("www.{}", record));
+            record = self.reduce(&record);
+        }
+
+        self.load_extensions();
+
+        for extension in &self.settings.extensions.clone() {
+            let subject = format!("{}.{}", record, extension);
+
+            if self.settings.handle_complement {
+                for complement in self.complements(&subject) {
+                    self.push_present(&complement);
+                }
+            }
+
+            self.push_present(&subject);
         }
 
         true
     }
 
-    fn unparse_plain(&mut self, line: &String) -> bool {
-        let record: &String = &self.reduce(line);
-        self.pull_strict(record);
+    fn unparse_root_zone_db(&mut self, line: &str) -> bool {
+        let mut record: String;
+
+        if line.starts_with("RZD ") {
+            record = line.replacen("RZD ", "", 1).trim().to_string()
+        } else if line.starts_with("rzd ") {
+            record = line.replacen("rzd ", "", 1).trim().to_string()
+        } else {
+            return false;
+        }
 
         if self.settings.handle_complement {
-            self.pull_strict(&format!("www.{}", record));
+            record = self.reduce(&record);
+        }
+
+        self.load_extensions();
+
+        for extension in &self.settings.extensions.clone() {
+            let subject = format!("{}.{}", record, extension);
+
+            if self.settings.handle_complement {
+                for complement in self.complements(&subject) {
+                    self.pull_present(&complement);
+                }
+            }
+
+            self.pull_present(&subject);
         }
 
         true
     }
 
-    /// Parses the given String into the ruler.
-    ///
-    /// # Arguments
-    ///
-    /// * `line` - The line to parse.
-    ///
-    /// # Returns
-    ///
-    /// Nothing.
-    pub fn parse(&mut self, line: &String) {
-        if line.is_empty() || line.starts_with('#') {
-            return;
+    /// Splits the given URL into its host - without the port - and its
+    /// normalized form. The scheme and the authority are lowercased while the
+    /// path is kept as-it-is.
+    fn normalize_url(&self, url: &str) -> Option<(String, String)> {
+        self.settings.normalize_url(url)
+    }
+
+    /// Translates the given glob-style wildcard rule - e.g. `*.example.org` or
+    /// `*.cdn.*.net` - into the equivalent flagged rule. A leading `*.` only
+    /// asks for the subdomains - like the `SUB ` flag - while any other
+    /// wildcard becomes a regular expression where `*` matches any sequence
+    /// of characters.
+    fn translate_wildcard(line: &str) -> Option<String> {
+        let line = line.trim();
+
+        if !line.contains('*') {
+            return None;
+        }
+
+        if let Some(record) = line
+            .strip_prefix("*.")
+            .filter(|record| !record.contains('*'))
+        {
+            return Some(format!("SUB {}", record));
         }
 
-        let idnazed_line = self.idnaze_line(line);
-        println!("REE {:?}", idnazed_line);
+        let pattern: Vec<String> = line.split('*').map(regex::escape).collect();
 
-        let _ = self.parse_all(&idnazed_line)
-            || self.parse_regex(&idnazed_line)
-            || self.parse_root_zone_db(&idnazed_line)
-            || self.parse_plain(&idnazed_line);
+        Some(format!("REG ^{}$", pattern.join(".*")))
     }
 
-    /// Parses the given Vector of Strings into the ruler.
-    ///
-    /// # Arguments
-    ///
-    /// * `lines` - The lines to parse.
-    ///
-    /// # Returns
-    ///
-    /// Nothing.
-    pub fn parse_vec(&mut self, lines: &[String]) {
-        for line in lines {
-            self.parse(line);
-        }
+    fn parse_wildcard(&mut self, line: &str) -> bool {
+        let Some(rule) = Ruler::translate_wildcard(line) else {
+            return false;
+        };
+
+        let _ = self.parse_sub(&rule) || self.parse_regex(&rule);
+
+        true
     }
 
-    /// Parses the content of the given file into the ruler.
-    ///
-    /// # Arguments
-    ///
-    /// * `file` - The file to parse.
-    ///
-    /// # Returns
-    ///
-    /// Nothing.
-    pub fn parse_file(&mut self, path: &str) {
-        let file = File::open(path).unwrap();
-        let reader = BufReader::new(file);
+    fn unparse_wildcard(&mut self, line: &str) -> bool {
+        let Some(rule) = Ruler::translate_wildcard(line) else {
+            return false;
+        };
 
-        for line in reader.lines() {
-            self.parse(&line.unwrap());
-        }
+        let _ = self.unparse_sub(&rule) || self.unparse_regex(&rule);
+
+        true
     }
 
-    /// Parses the content of the given URL (after downloading it) into the ruler.
-    ///
-    /// # Arguments
-    ///
-    /// * `url` - The URL to download and parse.
-    ///
-    /// # Returns
-    ///
-    /// Nothing.
-    pub fn parse_link(&mut self, url: &str) {
-        let (real_path, downloaded) = utils::download_file(&url.to_string());
+    fn parse_url_prefix(&mut self, line: &str) -> bool {
+        let Some((host, url)) = self.normalize_url(line) else {
+            return false;
+        };
 
-        if downloaded {
-            self.tmps.downloaded_files.push(real_path.clone());
-        }
+        self.push_metadata(MatchKind::UrlPrefix, &url);
+        self.url_prefixes.entry(host).or_default().insert(url);
 
-        self.parse_file(real_path.as_str());
+        true
     }
 
-    /// Unparses the given String into the ruler.
-    ///
-    /// # Arguments
-    ///
-    /// * `line` - The line to parse.
-    ///
-    /// # Returns
-    ///
-    /// Nothing.
-    pub fn unparse(&mut self, line: &String) {
-        if line.is_empty() || line.starts_with('#') {
-            return;
+    fn unparse_url_prefix(&mut self, line: &str) -> bool {
+        let Some((host, url)) = self.normalize_url(line) else {
+            return false;
+        };
+
+        if let Some(records) = self.url_prefixes.get_mut(&host) {
+            records.remove(&url);
         }
 
-        let _ = self.unparse_all(line)
-            || self.unparse_regex(line)
-            || self.unparse_root_zone_db(line)
-            || self.unparse_plain(line);
+        self.pull_metadata(MatchKind::UrlPrefix, &url);
+
+        true
     }
 
-    /// Unparses the given Vector of Strings into the ruler.
-    ///
-    /// # Arguments
-    ///
-    /// * `lines` - The lines to parse.
-    ///
-    /// # Returns
-    ///
-    /// Nothing.
-    pub fn unparse_vec(&mut self, lines: &[String]) {
-        for line in lines {
-            self.unparse(line);
+    fn parse_regex(&mut self, line: &str) -> bool {
+        let record: String;
+
+        if line.starts_with("REG ") {
+            record = line.replacen("REG ", "", 1).trim().to_string()
+        } else if line.starts_with("reg ") {
+            record = line.replacen("reg ", "", 1).trim().to_string()
+        } else {
+            return false;
         }
+
+        self.push_regex(&record);
+
+        true
     }
 
-    /// Unparses the content of the given file into the ruler.
-    ///
-    /// # Arguments
-    ///
-    /// * `file` - The file to parse.
-    ///
-    /// # Returns
-    ///
-    /// Nothing.
-    pub fn unparse_file(&mut self, path: &str) {
-        let file = File::open(path).unwrap();
-        let reader = BufReader::new(file);
+    fn unparse_regex(&mut self, line: &str) -> bool {
+        let record: String;
 
-        for line in reader.lines() {
-            self.unparse(&line.unwrap());
+        if line.starts_with("REG ") {
+            record = line.replacen("REG ", "", 1).trim().to_string()
+        } else if line.starts_with("reg ") {
+            record = line.replacen("reg ", "", 1).trim().to_string()
+        } else {
+            return false;
         }
+
+        self.pull_regex(&record);
+
+        true
     }
 
-    /// Unparses the content of the given URL (after downloading it) into the ruler.
-    ///
-    /// # Arguments
-    ///
-    /// * `url` - The URL to download and parse.
-    ///
-    /// # Returns
-    ///
-    /// Nothing.
-    pub fn unparse_link(&mut self, url: &str) {
-        let (real_path, downloaded) = utils::download_file(&url.to_string());
+    /// Provides the subject of the given `EXC ` - or `NOT ` - rule.
+    fn exception_record(line: &str) -> Option<&str> {
+        ["EXC ", "exc ", "NOT ", "not "]
+            .into_iter()
+            .find_map(|flag| line.strip_prefix(flag))
+            .map(str::trim)
+    }
 
-        if downloaded {
-            self.tmps.downloaded_files.push(real_path.clone());
+    /// Provides the network of the given `CIDR ` rule.
+    fn cidr_record(line: &str) -> Option<&str> {
+        ["CIDR ", "cidr "]
+            .into_iter()
+            .find_map(|flag| line.strip_prefix(flag))
+            .map(str::trim)
+    }
+
+    fn parse_cidr(&mut self, line: &str) -> bool {
+        let Some(record) = Ruler::cidr_record(line) else {
+            return false;
+        };
+
+        // Invalid networks are reported by `try_parse`.
+        if let Ok(network) = Network::parse(record) {
+            self.push_cidr(&network);
         }
 
-        self.unparse_file(real_path.as_str());
+        true
     }
 
-    /// IDNAze the given `subject`.
-    ///
-    /// # Arguments
-    ///
-    /// * `subject` - The subject to IDNAze.
-    ///
-    /// # Returns
-    ///
-    /// The IDNAzed subject.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use tivilsta::Ruler;
-    ///
-    /// let mut ruler = Ruler::new(false);
-    ///
-    /// let subject = String::from("www.äxample.org");
-    ///
-    /// assert_eq!(ruler.idnaze_subject(&subject), "www.xn--xample-9ta.org");
-    /// ```
-    pub fn idnaze_subject(&mut self, subject: &String) -> String {
-        match idna::domain_to_ascii(subject.as_str()) {
-            Ok(result) => result,
-            Err(_) => subject.to_string(),
+    fn unparse_cidr(&mut self, line: &str) -> bool {
+        let Some(record) = Ruler::cidr_record(line) else {
+            return false;
+        };
+
+        if let Ok(network) = Network::parse(record) {
+            self.pull_cidr(&network);
         }
+
+        true
     }
 
-    /// IDNAze the given `line`.
-    ///
-    /// # Arguments
-    ///
-    /// * `line` - The line to IDNAze.
-    ///
-    /// # Returns
-    ///
-    /// The IDNAzed line.
-    ///
-    /// # Example
-    ///
-    /// This example shows how to IDNAze a line, where the line contains a comment.
-    /// In such cases, the comment will be kept as is.
-    ///
-    /// ```rust
-    /// use tivilsta::Ruler;
-    ///
-    /// let mut ruler = Ruler::new(false);
-    ///
-    /// let line = String::from("www.äxample.org # äxample.org");
-    ///
-    /// assert_eq!(ruler.idnaze_line(&line), "www.xn--xample-9ta.org # äxample.org");
-    /// ```
-    pub fn idnaze_line(&mut self, line: &String) -> String {
-        let tab = "\t";
-        let space = " ";
+    /// Provides the record of the given `START ` rule - which always ends with
+    /// a dot.
+    fn starts_record(line: &str) -> Option<String> {
+        let record = ["START ", "start "]
+            .into_iter()
+            .find_map(|flag| line.strip_prefix(flag))?
+            .trim();
 
-        let separator;
+        Some(match record.ends_with('.') {
+            true => record.to_string(),
+            false => format!("{}.", record),
+        })
+    }
 
-        let regex_ignore = Regex::new(r"localhost$|localdomain$|local$|broadcasthost$|0\.0\.0\.0$|allhosts$|allnodes$|allrouters$|localnet$|loopback$|mcastprefix$").unwrap();
+    fn parse_starts(&mut self, line: &str) -> bool {
+        let Some(record) = Ruler::starts_record(line) else {
+            return false;
+        };
 
-        if line.is_empty() || line.starts_with('#') || regex_ignore.is_match(&line[..]).unwrap() {
-            return line.clone();
+        // A lone dot would match every subject.
+        if record != "." {
+            self.push_starts(&record);
         }
 
-        if line.contains(tab) {
-            separator = tab
-        } else if line.contains(space) {
-            separator = space
+        true
+    }
+
+    fn unparse_starts(&mut self, line: &str) -> bool {
+        let Some(record) = Ruler::starts_record(line) else {
+            return false;
+        };
+
+        self.pull_starts(&record);
+
+        true
+    }
+
+    /// Provides the extension of the given `EXT ` rule - without its leading
+    /// dot.
+    /// Whether the given rule - of our own format - is expanded with the
+    /// known extensions - that is, of the `RZD ` or `EXT ` flags.
+    fn needs_extensions(line: &str) -> bool {
+        line.starts_with("RZD ")
+            || line.starts_with("rzd ")
+            || Ruler::extension_record(line).is_some()
+    }
+
+    fn extension_record(line: &str) -> Option<&str> {
+        ["EXT ", "ext "]
+            .into_iter()
+            .find_map(|flag| line.strip_prefix(flag))
+            .map(|record| record.trim().trim_start_matches('.'))
+    }
+
+    /// Checks whether the given extension is in the IANA Root Zone Database
+    /// or in the Public Suffix List.
+    fn is_known_extension(&self, extension: &str) -> bool {
+        self.settings
+            .extensions
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(extension))
+    }
+
+    fn parse_extension(&mut self, line: &str) -> bool {
+        let Some(record) = Ruler::extension_record(line) else {
+            return false;
+        };
+
+        self.load_extensions();
+
+        // Unknown extensions are reported by `try_parse`.
+        if self.is_known_extension(record) {
+            self.push_ends(&format!(".{}", record));
+        }
+
+        true
+    }
+
+    fn unparse_extension(&mut self, line: &str) -> bool {
+        let Some(record) = Ruler::extension_record(line) else {
+            return false;
+        };
+
+        self.pull_ends(&format!(".{}", record));
+
+        true
+    }
+
+    /// Provides the keyword of the given `KEY ` rule.
+    fn keyword_record(line: &str) -> Option<&str> {
+        ["KEY ", "key "]
+            .into_iter()
+            .find_map(|flag| line.strip_prefix(flag))
+            .map(str::trim)
+    }
+
+    fn parse_keyword(&mut self, line: &str) -> bool {
+        let Some(record) = Ruler::keyword_record(line) else {
+            return false;
+        };
+
+        // An empty keyword would match every subject.
+        if !record.is_empty() {
+            self.push_keyword(record);
+        }
+
+        true
+    }
+
+    fn unparse_keyword(&mut self, line: &str) -> bool {
+        let Some(record) = Ruler::keyword_record(line) else {
+            return false;
+        };
+
+        self.pull_keyword(record);
+
+        true
+    }
+
+    fn parse_exception(&mut self, line: &str) -> bool {
+        let Some(record) = Ruler::exception_record(line) else {
+            return false;
+        };
+
+        let record = if self.settings.handle_complement {
+            self.reduce(record)
         } else {
-            separator = ""
+            record.to_string()
+        };
+
+        self.push_exception(&record);
+
+        if self.settings.handle_complement {
+            for complement in self.complements(&record) {
+                self.push_exception(&complement);
+            }
         }
 
-        if !separator.is_empty() {
-            let mut idnazed_data: Vec<String> = Vec::new();
+        true
+    }
 
-            let subjects: &str;
-            let mut comment = "";
+    fn unparse_exception(&mut self, line: &str) -> bool {
+        let Some(record) = Ruler::exception_record(line) else {
+            return false;
+        };
 
-            if line.contains('#') {
-                (subjects, comment) = line.split_once('#').unwrap();
-            } else {
-                subjects = line;
+        let record = if self.settings.handle_complement {
+            self.reduce(record)
+        } else {
+            record.to_string()
+        };
+
+        self.pull_exception(&record);
+
+        if self.settings.handle_complement {
+            for complement in self.complements(&record) {
+                self.pull_exception(&complement);
             }
+        }
 
-            let mut splitted_subject: Vec<&str> = subjects.split(separator).collect();
+        true
+    }
 
-            for data in splitted_subject.iter_mut() {
-                if data.is_empty() || regex_ignore.is_match(data).unwrap() {
-                    idnazed_data.push(data.to_string());
-                    continue;
+    fn parse_plain(&mut self, line: &str) -> bool {
+        let record: String = if self.settings.handle_complement {
+            self.reduce(line.trim())
+        } else {
+            line.to_string()
+        };
+
+        self.push_strict(&record);
+
+        if self.settings.handle_complement {
+            for complement in self.complements(&record) {
+                self.push_strict(&complement);
+            }
+        }
+
+        true
+    }
+
+    fn unparse_plain(&mut self, line: &str) -> bool {
+        let record: String = if self.settings.handle_complement {
+            self.reduce(line.trim())
+        } else {
+            line.to_string()
+        };
+
+        self.pull_strict(&record);
+
+        if self.settings.handle_complement {
+            for complement in self.complements(&record) {
+                self.pull_strict(&complement);
+            }
+        }
+
+        true
+    }
+
+    /// Parses the given String into the ruler.
+    ///
+    /// The invalid rules are ignored. See [`Ruler::try_parse`] to report them.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to parse.
+    ///
+    /// # Returns
+    ///
+    /// Nothing.
+    pub fn parse(&mut self, line: &str) {
+        for rule in self.settings.rule_format.convert(line) {
+            self.parse_rule(&rule);
+        }
+    }
+
+    /// Parses the given rule - of our own format - into the ruler.
+    pub(crate) fn parse_rule(&mut self, line: &str) {
+        if line.is_empty() || line.starts_with('#') {
+            self.parse_comment(line);
+            return;
+        }
+
+        let (line, expiry) = Ruler::split_expiry(line);
+
+        // Invalid dates are reported by `try_parse`. Here, the rule is kept -
+        // without expiry date - rather than silently dropped.
+        let expiry = expiry.and_then(|date| date.parse::<ExpiryDate>().ok());
+
+        if expiry.is_some_and(|expiry| expiry.is_past()) {
+            return;
+        }
+
+        let idnazed_line = self.prepare_line(line);
+
+        // An invalid regular expression would fail every lookup.
+        if (idnazed_line.starts_with("REG ") || idnazed_line.starts_with("reg "))
+            && self.compile_regex(idnazed_line[4..].trim()).is_err()
+        {
+            return;
+        }
+
+        self.expiry = expiry;
+        self.parse_grouped(&idnazed_line);
+        self.expiry = None;
+    }
+
+    /// Parses the given String into the ruler - without panicking.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to parse.
+    ///
+    /// # Returns
+    ///
+    /// A `TivilstaError::Regex` if the line is an invalid regular expression
+    /// rule, a `TivilstaError::Expiry` if its expiry date is invalid, a
+    /// `TivilstaError::Cidr` if it is an invalid network rule, a
+    /// `TivilstaError::UnknownExtension` if it whitelists an unknown extension
+    /// or the error of the data source if the datasets needed by a `RZD ` - or
+    /// `EXT ` - rule can't be fetched.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{Ruler, TivilstaError};
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// assert!(ruler.try_parse(&String::from("REG ^example\\.")).is_ok());
+    /// assert!(matches!(
+    ///     ruler.try_parse(&String::from("REG ^(example")),
+    ///     Err(TivilstaError::Regex { .. })
+    /// ));
+    /// ```
+    pub fn try_parse(&mut self, line: &str) -> Result<(), TivilstaError> {
+        for rule in self.settings.rule_format.convert(line) {
+            self.try_parse_rule(&rule)?;
+        }
+
+        Ok(())
+    }
+
+    /// Parses the given line into the ruler - like [`Ruler::try_parse`] - and
+    /// records the given `origin` as the one of its rules.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to parse.
+    /// * `origin` - Where the line comes from - e.g. `command line:1`.
+    ///
+    /// # Returns
+    ///
+    /// The same errors as [`Ruler::try_parse`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{RuleOrigin, Ruler};
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler
+    ///     .try_parse_with_origin("example.org", RuleOrigin::new("command line", 1))
+    ///     .unwrap();
+    ///
+    /// let found = ruler.check("example.org").unwrap();
+    ///
+    /// assert_eq!(found.origin.unwrap().to_string(), "command line:1");
+    /// ```
+    pub fn try_parse_with_origin(
+        &mut self,
+        line: &str,
+        origin: RuleOrigin,
+    ) -> Result<(), TivilstaError> {
+        self.with_origin(origin, |ruler| ruler.try_parse(line))
+    }
+
+    /// Parses the given rule - of our own format - into the ruler - without
+    /// panicking - and records the given `origin` as the one of its records.
+    pub(crate) fn try_parse_rule_with_origin(
+        &mut self,
+        line: &str,
+        origin: RuleOrigin,
+    ) -> Result<(), TivilstaError> {
+        self.with_origin(origin, |ruler| ruler.try_parse_rule(line))
+    }
+
+    /// Runs `parse` with `origin` as the origin of the parsed records. The
+    /// previous origin is restored afterwards.
+    fn with_origin<T>(&mut self, origin: RuleOrigin, parse: impl FnOnce(&mut Ruler) -> T) -> T {
+        let previous = self.origin.replace(origin);
+        let result = parse(self);
+
+        self.origin = previous;
+
+        result
+    }
+
+    /// Parses the given rule - of our own format - into the ruler - without
+    /// panicking.
+    pub(crate) fn try_parse_rule(&mut self, line: &str) -> Result<(), TivilstaError> {
+        if line.is_empty() || line.starts_with('#') {
+            self.parse_comment(line);
+            return Ok(());
+        }
+
+        let (line, expiry) = Ruler::split_expiry(line);
+        let expiry = expiry
+            .map(|date| {
+                date.parse::<ExpiryDate>()
+                    .map_err(|message| TivilstaError::Expiry {
+                        date: date.to_string(),
+                        message,
+                    })
+            })
+            .transpose()?;
+
+        // An expired rule is ignored.
+        if expiry.is_some_and(|expiry| expiry.is_past()) {
+            return Ok(());
+        }
+
+        let idnazed_line = self.prepare_line(line);
+
+        if idnazed_line.starts_with("REG ") || idnazed_line.starts_with("reg ") {
+            let record = idnazed_line[4..].trim();
+
+            self.compile_regex(record)?;
+        } else if (idnazed_line.starts_with("RZD ") || idnazed_line.starts_with("rzd "))
+            && self.settings.extensions.is_empty()
+        {
+            self.settings.extensions = Ruler::try_extensions()?;
+        } else if let Some(record) = Ruler::extension_record(&idnazed_line) {
+            if self.settings.extensions.is_empty() {
+                self.settings.extensions = Ruler::try_extensions()?;
+            }
+
+            if !self.is_known_extension(record) {
+                return Err(TivilstaError::UnknownExtension {
+                    extension: record.to_string(),
+                });
+            }
+        } else if let Some(record) = Ruler::cidr_record(&idnazed_line) {
+            Network::parse(record).map_err(|message| TivilstaError::Cidr {
+                network: record.to_string(),
+                message,
+            })?;
+        }
+
+        self.expiry = expiry;
+        self.parse_grouped(&idnazed_line);
+        self.expiry = None;
+
+        Ok(())
+    }
+
+    /// Parses every line of the given `content` - prefixed with the given
+    /// `flag` - into the ruler. The rules are remembered as coming from the
+    /// given `origin`.
+    ///
+    /// This is the fast path used to load large whitelisting schemas. The sets
+    /// are pre-sized from the number of lines, the line buffer is reused and
+    /// the regular expressions are compiled once - after the last line.
+    ///
+    /// # Returns
+    ///
+    /// A `TivilstaError::Rule` pointing to the first invalid line.
+    pub(crate) fn bulk_parse(
+        &mut self,
+        content: &str,
+        flag: &str,
+        origin: &str,
+    ) -> Result<(), TivilstaError> {
+        let lines = content.lines().count();
+        let records_per_line = if self.settings.handle_complement {
+            1 + self.settings.complement_prefixes.len()
+        } else {
+            1
+        };
+
+        // Most rules are plain ones.
+        self.strict.reserve(lines * records_per_line);
+        self.defer_regex = true;
+
+        let mut rule = String::with_capacity(flag.len() + 256);
+        let mut result = Ok(());
+        let path: Arc<str> = Arc::from(origin);
+
+        for (index, line) in content.lines().enumerate() {
+            self.origin = Some(RuleOrigin {
+                path: path.clone(),
+                line: index + 1,
+            });
+
+            if let Err(error) = self.parse_flagged(line, flag, &mut rule) {
+                result = Err(TivilstaError::Rule {
+                    origin: origin.to_string(),
+                    line: index + 1,
+                    source: Box::new(error),
+                });
+                break;
+            }
+        }
+
+        self.origin = None;
+        self.group = None;
+
+        let compiled = self.compile_deferred_regex();
+
+        result.and(compiled)
+    }
+
+    /// Same as `bulk_parse` - with the lines read one after the other from the
+    /// given `reader` instead of being held in memory at once.
+    ///
+    /// # Returns
+    ///
+    /// A `TivilstaError::Rule` pointing to the first invalid line or a
+    /// `TivilstaError::Io` if the `reader` can't be read.
+    pub(crate) fn stream_parse<R: BufRead>(
+        &mut self,
+        mut reader: R,
+        flag: &str,
+        origin: &str,
+    ) -> Result<(), TivilstaError> {
+        self.defer_regex = true;
+
+        let mut rule = String::with_capacity(flag.len() + 256);
+        let mut line = String::new();
+        let mut index = 0;
+        let mut result = Ok(());
+        let path: Arc<str> = Arc::from(origin);
+
+        loop {
+            line.clear();
+            index += 1;
+
+            match reader.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(error) => {
+                    result = Err(TivilstaError::io(origin, error));
+                    break;
                 }
+            }
 
-                let idnazed = if data.contains('#') {
-                    let (element, comment) = data.split_once('#').unwrap();
-                    let idnazed_line =
-                        format!("{} #{}", self.idnaze_subject(&element.to_string()), comment);
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+
+            self.origin = Some(RuleOrigin {
+                path: path.clone(),
+                line: index,
+            });
+
+            if let Err(error) = self.parse_flagged(line, flag, &mut rule) {
+                result = Err(TivilstaError::Rule {
+                    origin: origin.to_string(),
+                    line: index,
+                    source: Box::new(error),
+                });
+                break;
+            }
+        }
+
+        self.origin = None;
+        self.group = None;
+
+        let compiled = self.compile_deferred_regex();
+
+        result.and(compiled)
+    }
+
+    /// Parses the given `line` - prefixed with the given `flag` - through the
+    /// given reusable `rule` buffer.
+    fn parse_flagged(
+        &mut self,
+        line: &str,
+        flag: &str,
+        rule: &mut String,
+    ) -> Result<(), TivilstaError> {
+        if flag.is_empty() {
+            return self.try_parse(line);
+        }
+
+        // The flagged schemas are always of our own format - and the flag
+        // would turn the comments into - match-all - rules.
+        if line.is_empty() || line.starts_with('#') {
+            return self.try_parse_rule(line);
+        }
+
+        rule.clear();
+        rule.push_str(flag);
+        rule.push_str(line);
+
+        self.try_parse_rule(rule)
+    }
+
+    /// Compiles the regular expressions whose compilation was deferred while
+    /// bulk parsing.
+    ///
+    /// # Returns
+    ///
+    /// A `TivilstaError::Regex` if one of them can't be compiled.
+    fn compile_deferred_regex(&mut self) -> Result<(), TivilstaError> {
+        self.defer_regex = false;
+
+        if !self.regexes.is_empty() {
+            let matcher = self.compile_regex_set()?;
+
+            self.caches.regexes = OnceLock::from(matcher);
+        }
+
+        Ok(())
+    }
+
+    /// Parses the given `rule` into a new ruler that shares our settings - and
+    /// the datasets already fetched for the `RZD ` flag.
+    pub(crate) fn try_parse_alone(&mut self, rule: &str) -> Result<Ruler, TivilstaError> {
+        let mut single = self.empty_like();
+
+        single.settings.extensions = std::mem::take(&mut self.settings.extensions);
+
+        let result = single.try_parse(rule);
+
+        self.settings.extensions = std::mem::take(&mut single.settings.extensions);
+        result.map(|_| single)
+    }
+
+    /// Provides every record we hold with its kind.
+    pub(crate) fn records(&self) -> impl Iterator<Item = (MatchKind, &str)> {
+        self.strict
+            .iter()
+            .map(|record| (MatchKind::Strict, record.as_str()))
+            .chain(
+                self.present
+                    .iter()
+                    .map(|record| (MatchKind::Present, record.as_str())),
+            )
+            .chain(
+                self.ends
+                    .values()
+                    .flatten()
+                    .map(|record| (MatchKind::Ends, record.as_str())),
+            )
+            .chain(
+                self.url_prefixes
+                    .values()
+                    .flatten()
+                    .map(|record| (MatchKind::UrlPrefix, record.as_str())),
+            )
+            .chain(
+                self.regexes
+                    .iter()
+                    .map(|record| (MatchKind::Regex, record.as_str())),
+            )
+            .chain(
+                self.exceptions
+                    .iter()
+                    .map(|record| (MatchKind::Exception, record.as_str())),
+            )
+            .chain(
+                self.cidrs
+                    .iter()
+                    .map(|record| (MatchKind::Cidr, record.as_str())),
+            )
+            .chain(
+                self.keywords
+                    .iter()
+                    .map(|record| (MatchKind::Keyword, record.as_str())),
+            )
+            .chain(
+                self.starts
+                    .values()
+                    .flatten()
+                    .map(|record| (MatchKind::Starts, record.as_str())),
+            )
+    }
+
+    fn parse_idnazed(&mut self, idnazed_line: &str) {
+        let _ = self.parse_all(idnazed_line)
+            || self.parse_sub(idnazed_line)
+            || self.parse_apex(idnazed_line)
+            || self.parse_regex(idnazed_line)
+            || self.parse_root_zone_db(idnazed_line)
+            || self.parse_exception(idnazed_line)
+            || self.parse_cidr(idnazed_line)
+            || self.parse_keyword(idnazed_line)
+            || self.parse_starts(idnazed_line)
+            || self.parse_extension(idnazed_line)
+            || self.parse_url_prefix(idnazed_line)
+            || self.parse_wildcard(idnazed_line)
+            || self.parse_plain(idnazed_line);
+    }
+
+    /// Parses the given Vector of Strings into the ruler.
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - The lines to parse.
+    ///
+    /// # Returns
+    ///
+    /// Nothing.
+    pub fn parse_vec<S: AsRef<str>>(&mut self, lines: &[S]) {
+        self.parse_iter(lines);
+    }
+
+    /// Parses the lines of the given iterator into the ruler - e.g. streamed
+    /// from a generator, a database cursor or a channel receiver.
+    ///
+    /// The regular expressions are compiled once - after the last line. The
+    /// invalid rules are ignored - like [`Ruler::parse`] does.
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - The lines to parse.
+    ///
+    /// # Returns
+    ///
+    /// Nothing.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::sync::mpsc;
+    /// use tivilsta::Ruler;
+    ///
+    /// let (sender, receiver) = mpsc::channel();
+    ///
+    /// sender.send(String::from("example.org")).unwrap();
+    /// sender.send(String::from("ALL .example.net")).unwrap();
+    /// drop(sender);
+    ///
+    /// let mut ruler = Ruler::new(false);
+    /// ruler.parse_iter(receiver);
+    ///
+    /// assert_eq!(ruler.is_whitelisted("api.example.net"), true);
+    /// ```
+    pub fn parse_iter(&mut self, lines: impl IntoIterator<Item = impl AsRef<str>>) {
+        self.defer_regex = true;
+
+        for line in lines {
+            self.parse(line.as_ref());
+        }
+
+        self.group = None;
+
+        // Every regular expression was validated while being parsed.
+        let _ = self.compile_deferred_regex();
+    }
+
+    /// Parses the content of the given file into the ruler.
+    ///
+    /// The invalid rules are ignored - like [`Ruler::parse`] does. See
+    /// [`Ruler::try_parse_file`] to report them.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file to parse.
+    ///
+    /// # Returns
+    ///
+    /// Nothing.
+    ///
+    /// # Panics
+    ///
+    /// If the file can't be opened or read.
+    pub fn parse_file(&mut self, path: &str) {
+        self.parse_file_from(path, path);
+    }
+
+    /// Parses the content of the file at the given `path` - remembering its
+    /// rules as coming from the given `origin`.
+    fn parse_file_from(&mut self, path: &str, origin: &str) {
+        if let Err(error) = self.try_parse_file_from(path, origin, true) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Parses the content of the given URL (after downloading it) into the ruler.
+    ///
+    /// The downloaded file is removed when we are dropped - see
+    /// [`Ruler::set_keep_downloads`].
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to download and parse.
+    ///
+    /// # Returns
+    ///
+    /// Nothing.
+    ///
+    /// # Panics
+    ///
+    /// If the URL can't be downloaded or if the file can't be read.
+    pub fn parse_link(&mut self, url: &str) {
+        let (real_path, downloaded) = utils::download_file(&url.to_string());
+
+        if downloaded {
+            self.tmps.downloaded_files.push(real_path.clone());
+        }
+
+        self.parse_file_from(real_path.as_str(), url);
+    }
+
+    /// Parses the content of the given file into the ruler - without
+    /// panicking.
+    ///
+    /// **Note:** The rules preceding an invalid one are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to parse.
+    ///
+    /// # Returns
+    ///
+    /// An error if the file can't be read or if one of its rules is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{Ruler, TivilstaError};
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// assert!(matches!(
+    ///     ruler.try_parse_file("/non/existent/whitelist.list"),
+    ///     Err(TivilstaError::Io { .. })
+    /// ));
+    /// ```
+    pub fn try_parse_file(&mut self, path: &str) -> Result<(), TivilstaError> {
+        self.try_parse_file_from(path, path, false)
+    }
+
+    /// Parses the content of the given URL (after downloading it) into the
+    /// ruler - without panicking.
+    ///
+    /// The downloaded file is removed when we are dropped - see
+    /// [`Ruler::set_keep_downloads`].
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to download and parse.
+    ///
+    /// # Returns
+    ///
+    /// An error if the URL can't be downloaded or if one of its rules is
+    /// invalid.
+    pub fn try_parse_link(&mut self, url: &str) -> Result<(), TivilstaError> {
+        let real_path = self.try_download(url)?;
+
+        self.try_parse_file_from(&real_path, url, false)
+    }
+
+    /// Parses the content of the file at the given `path` - reporting the
+    /// invalid rules as coming from the given `origin`. See
+    /// `try_parse_reader_from`.
+    fn try_parse_file_from(
+        &mut self,
+        path: &str,
+        origin: &str,
+        lenient: bool,
+    ) -> Result<(), TivilstaError> {
+        let file = File::open(path).map_err(|source| TivilstaError::io(origin, source))?;
+
+        self.try_parse_reader_from(BufReader::new(file), origin, lenient)
+    }
+
+    /// Parses the lines of the given `reader` into the ruler - e.g. from a
+    /// socket, a decompressor or an in-memory buffer.
+    ///
+    /// **Note:** The rules preceding an invalid one are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader to parse.
+    ///
+    /// # Returns
+    ///
+    /// An error if the reader fails or if one of its rules is invalid. The
+    /// errors are reported as coming from `<reader>`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler
+    ///     .parse_reader("example.org\nALL .example.net\n".as_bytes())
+    ///     .unwrap();
+    ///
+    /// assert_eq!(ruler.is_whitelisted("api.example.net"), true);
+    /// ```
+    pub fn parse_reader<R: BufRead>(&mut self, reader: R) -> Result<(), TivilstaError> {
+        self.try_parse_reader_from(reader, READER_ORIGIN, false)
+    }
+
+    /// Parses the lines of the given `reader` - reporting the invalid rules as
+    /// coming from the given `origin`. When `lenient`, the invalid rules are
+    /// ignored - like [`Ruler::parse`] does - and only the errors of the
+    /// `reader` are reported.
+    fn try_parse_reader_from<R: BufRead>(
+        &mut self,
+        reader: R,
+        origin: &str,
+        lenient: bool,
+    ) -> Result<(), TivilstaError> {
+        // The regular expressions are validated one by one - but compiled at
+        // once.
+        self.defer_regex = true;
+
+        let path: Arc<str> = Arc::from(origin);
+
+        let result = reader.lines().enumerate().try_for_each(|(index, line)| {
+            let line = line.map_err(|source| TivilstaError::io(origin, source))?;
+
+            self.origin = Some(RuleOrigin {
+                path: path.clone(),
+                line: index + 1,
+            });
+
+            if lenient {
+                self.parse(&line);
+                return Ok(());
+            }
+
+            self.try_parse(&line).map_err(|source| TivilstaError::Rule {
+                origin: origin.to_string(),
+                line: index + 1,
+                source: Box::new(source),
+            })
+        });
+
+        self.origin = None;
+        self.group = None;
+
+        let compiled = self.compile_deferred_regex();
+
+        result.and(compiled)
+    }
+
+    /// Downloads the given URL - keeping track of the downloaded file.
+    ///
+    /// # Returns
+    ///
+    /// The path of the file to read.
+    fn try_download(&mut self, url: &str) -> Result<String, TivilstaError> {
+        let (real_path, downloaded) =
+            utils::try_download_file(url).map_err(|source| TivilstaError::Download {
+                url: url.to_string(),
+                source: Box::new(source),
+            })?;
+
+        if downloaded {
+            self.tmps.downloaded_files.push(real_path.clone());
+        }
+
+        Ok(real_path)
+    }
+
+    /// Unparses the given String into the ruler.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to parse.
+    ///
+    /// # Returns
+    ///
+    /// Nothing.
+    pub fn unparse(&mut self, line: &str) {
+        for rule in self.settings.rule_format.convert(line) {
+            self.unparse_rule(&rule);
+        }
+    }
+
+    /// Unparses the given rule - of our own format - from the ruler.
+    fn unparse_rule(&mut self, line: &str) {
+        if line.is_empty() || line.starts_with('#') {
+            return;
+        }
+
+        let (line, _) = Ruler::split_expiry(line);
+
+        if self
+            .disabled_groups
+            .values()
+            .any(|records| !records.is_empty())
+        {
+            let mut single = self.empty_like();
+
+            single.settings.extensions = std::mem::take(&mut self.settings.extensions);
+            // An invalid rule holds no record.
+            let _ = single.try_parse_rule(line);
+            self.settings.extensions = std::mem::take(&mut single.settings.extensions);
+
+            for records in self.disabled_groups.values_mut() {
+                records.retain(|stashed| !single.holds(stashed.kind, &stashed.record));
+            }
+        }
+
+        let _ = self.unparse_all(line)
+            || self.unparse_sub(line)
+            || self.unparse_apex(line)
+            || self.unparse_regex(line)
+            || self.unparse_root_zone_db(line)
+            || self.unparse_exception(line)
+            || self.unparse_cidr(line)
+            || self.unparse_keyword(line)
+            || self.unparse_starts(line)
+            || self.unparse_extension(line)
+            || self.unparse_url_prefix(line)
+            || self.unparse_wildcard(line)
+            || self.unparse_plain(line);
+    }
+
+    /// Unparses the given Vector of Strings into the ruler.
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - The lines to parse.
+    ///
+    /// # Returns
+    ///
+    /// Nothing.
+    pub fn unparse_vec<S: AsRef<str>>(&mut self, lines: &[S]) {
+        self.unparse_iter(lines);
+    }
+
+    /// Unparses the lines of the given iterator from the ruler - see
+    /// [`Ruler::parse_iter`].
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - The lines to unparse.
+    ///
+    /// # Returns
+    ///
+    /// Nothing.
+    pub fn unparse_iter(&mut self, lines: impl IntoIterator<Item = impl AsRef<str>>) {
+        for line in lines {
+            self.unparse(line.as_ref());
+        }
+    }
+
+    /// Unparses the content of the given file into the ruler.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file to parse.
+    ///
+    /// # Returns
+    ///
+    /// Nothing.
+    ///
+    /// # Panics
+    ///
+    /// If the file can't be opened or read. See [`Ruler::try_unparse_file`].
+    pub fn unparse_file(&mut self, path: &str) {
+        if let Err(error) = self.try_unparse_file(path) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Unparses the content of the given URL (after downloading it) into the ruler.
+    ///
+    /// The downloaded file is removed when we are dropped - see
+    /// [`Ruler::set_keep_downloads`].
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to download and parse.
+    ///
+    /// # Returns
+    ///
+    /// Nothing.
+    ///
+    /// # Panics
+    ///
+    /// If the URL can't be downloaded or if the file can't be read.
+    pub fn unparse_link(&mut self, url: &str) {
+        let (real_path, downloaded) = utils::download_file(&url.to_string());
+
+        if downloaded {
+            self.tmps.downloaded_files.push(real_path.clone());
+        }
+
+        self.unparse_file(real_path.as_str());
+    }
+
+    /// Unparses the content of the given file into the ruler - without
+    /// panicking.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to unparse.
+    ///
+    /// # Returns
+    ///
+    /// An error if the file can't be read.
+    pub fn try_unparse_file(&mut self, path: &str) -> Result<(), TivilstaError> {
+        self.try_unparse_file_from(path, path)
+    }
+
+    /// Unparses the content of the given URL (after downloading it) into the
+    /// ruler - without panicking.
+    ///
+    /// The downloaded file is removed when we are dropped - see
+    /// [`Ruler::set_keep_downloads`].
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to download and unparse.
+    ///
+    /// # Returns
+    ///
+    /// An error if the URL can't be downloaded or read.
+    pub fn try_unparse_link(&mut self, url: &str) -> Result<(), TivilstaError> {
+        let real_path = self.try_download(url)?;
+
+        self.try_unparse_file_from(&real_path, url)
+    }
+
+    /// Unparses the content of the file at the given `path` - reporting the
+    /// errors as coming from the given `origin`.
+    fn try_unparse_file_from(&mut self, path: &str, origin: &str) -> Result<(), TivilstaError> {
+        let file = File::open(path).map_err(|source| TivilstaError::io(origin, source))?;
+
+        self.try_unparse_reader_from(BufReader::new(file), origin)
+    }
+
+    /// Unparses the lines of the given `reader` from the ruler - see
+    /// [`Ruler::parse_reader`].
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader to unparse.
+    ///
+    /// # Returns
+    ///
+    /// An error if the reader fails. The errors are reported as coming from
+    /// `<reader>`.
+    pub fn unparse_reader<R: BufRead>(&mut self, reader: R) -> Result<(), TivilstaError> {
+        self.try_unparse_reader_from(reader, READER_ORIGIN)
+    }
+
+    /// Unparses the lines of the given `reader` - reporting the errors as
+    /// coming from the given `origin`.
+    fn try_unparse_reader_from<R: BufRead>(
+        &mut self,
+        reader: R,
+        origin: &str,
+    ) -> Result<(), TivilstaError> {
+        for line in reader.lines() {
+            self.unparse(&line.map_err(|source| TivilstaError::io(origin, source))?);
+        }
+
+        Ok(())
+    }
+
+    /// Merges the rules of the given ruler into this one.
+    ///
+    /// **Note:** The settings of the given ruler are ignored. Its rules are
+    /// merged as they have been parsed.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The ruler to merge.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    /// let mut other = Ruler::new(false);
+    ///
+    /// ruler.parse(&String::from("example.org"));
+    /// other.parse(&String::from("ALL .example.net"));
+    ///
+    /// ruler.merge(other);
+    ///
+    /// assert_eq!(ruler.is_whitelisted(&String::from("example.org")), true);
+    /// assert_eq!(ruler.is_whitelisted(&String::from("api.example.net")), true);
+    /// ```
+    pub fn merge(&mut self, mut other: Ruler) {
+        // The records of the groups we disabled are set aside.
+        for group in self.disabled_groups.keys() {
+            other.set_group_enabled(group, false);
+        }
+
+        // Like while parsing, a record held by one of us without expiry date
+        // stays permanent.
+        if !self.expiries.is_empty() || !other.expiries.is_empty() {
+            for (kind, record) in other.records() {
+                let expiry = other
+                    .expiries
+                    .get(&kind)
+                    .and_then(|records| records.get(record))
+                    .copied();
+
+                self.push_expiry(kind, record, expiry);
+            }
+        }
+
+        self.strict.extend(std::mem::take(&mut other.strict));
+        self.present.extend(std::mem::take(&mut other.present));
+
+        for (search_key, records) in std::mem::take(&mut other.ends) {
+            self.ends.entry(search_key).or_default().extend(records);
+        }
+
+        self.caches.ends.take();
+
+        for (search_key, records) in std::mem::take(&mut other.starts) {
+            self.starts.entry(search_key).or_default().extend(records);
+        }
+
+        for (host, records) in std::mem::take(&mut other.url_prefixes) {
+            self.url_prefixes.entry(host).or_default().extend(records);
+        }
+
+        // Their patterns were validated while being parsed. Like the other
+        // datasets, a pattern we already hold is not added twice.
+        if !other.regexes.is_empty() {
+            let known: HashSet<&String> = self.regexes.iter().collect();
+            let unknown: Vec<String> = std::mem::take(&mut other.regexes)
+                .into_iter()
+                .filter(|record| !known.contains(record))
+                .collect();
+
+            self.regexes.extend(unknown);
+            self.caches.regexes.take();
+        }
+
+        self.exceptions
+            .extend(std::mem::take(&mut other.exceptions));
+
+        if !other.cidrs.is_empty() {
+            self.cidrs.extend(std::mem::take(&mut other.cidrs));
+            self.caches.cidrs.take();
+        }
+
+        if !other.keywords.is_empty() {
+            self.keywords.extend(std::mem::take(&mut other.keywords));
+            self.caches.keywords.take();
+        }
+
+        for (kind, records) in std::mem::take(&mut other.origins) {
+            let known = self.origins.entry(kind).or_default();
+
+            for (record, origin) in records {
+                known.entry(record).or_insert(origin);
+            }
+        }
+
+        for (kind, records) in std::mem::take(&mut other.groups) {
+            self.groups.entry(kind).or_default().extend(records);
+        }
+
+        // The records of a group we didn't disable are ours to use.
+        for (group, records) in std::mem::take(&mut other.disabled_groups) {
+            match self.disabled_groups.get_mut(&group) {
+                Some(known) => known.extend(records),
+                None => self.restore_group(&group, records),
+            }
+        }
+
+        if self.settings.extensions.is_empty() {
+            self.settings.extensions = std::mem::take(&mut other.settings.extensions);
+        }
+
+        // The downloaded files are now ours to cleanup.
+        self.tmps
+            .downloaded_files
+            .append(&mut other.tmps.downloaded_files);
+    }
+
+    /// Provides the records that didn't match any subject since the usage
+    /// started to be tracked - sorted by kind and record. Useful to prune the
+    /// stale entries of a whitelisting schema.
+    ///
+    /// **Note:** Nothing is provided unless [`Ruler::set_track_usage`] was
+    /// called. The records of a rule are reported one by one - e.g. its
+    /// complements.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false).with_track_usage(true);
+    ///
+    /// ruler.parse_vec(&["example.org", "SUB example.net"]);
+    ///
+    /// assert!(ruler.is_whitelisted("api.example.net"));
+    ///
+    /// let unused = ruler.unused_rules();
+    ///
+    /// assert_eq!(unused.len(), 1);
+    /// assert_eq!(unused[0].rule, "example.org");
+    /// ```
+    pub fn unused_rules(&self) -> Vec<Match<'_>> {
+        let Some(usage) = &self.usage else {
+            return vec![];
+        };
+        let usage = usage.lock().unwrap();
+
+        let mut result: Vec<Match<'_>> = self
+            .records()
+            .filter(|(kind, record)| {
+                !usage
+                    .get(kind)
+                    .is_some_and(|records| records.contains_key(*record))
+            })
+            .map(|(kind, record)| Match::new(kind, record).with_origin(&self.origins))
+            .collect();
+
+        result.sort_unstable_by(|a, b| (a.kind, a.rule).cmp(&(b.kind, b.rule)));
+        result.dedup();
+        result
+    }
+
+    /// Provides the records that matched at least one subject since the usage
+    /// started to be tracked - with their number of matched subjects, the
+    /// records doing the heavy lifting first.
+    ///
+    /// **Note:** Nothing is provided unless [`Ruler::set_track_usage`] was
+    /// called. A subject is only counted for the first record that matched it
+    /// - see [`Ruler::check`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false).with_track_usage(true);
+    ///
+    /// ruler.parse_vec(&["example.org", "SUB example.net"]);
+    ///
+    /// assert!(ruler.is_whitelisted("api.example.net"));
+    /// assert!(ruler.is_whitelisted("cdn.example.net"));
+    /// assert!(ruler.is_whitelisted("example.org"));
+    ///
+    /// let hits = ruler.hit_counts();
+    ///
+    /// assert_eq!(hits[0].0.rule, ".example.net");
+    /// assert_eq!(hits[0].1, 2);
+    /// assert_eq!(hits[1].0.rule, "example.org");
+    /// assert_eq!(hits[1].1, 1);
+    /// ```
+    pub fn hit_counts(&self) -> Vec<(Match<'_>, usize)> {
+        let Some(usage) = &self.usage else {
+            return vec![];
+        };
+        let usage = usage.lock().unwrap();
+
+        let mut result: Vec<(Match<'_>, usize)> = self
+            .records()
+            .filter_map(|(kind, record)| {
+                let hits = *usage.get(&kind)?.get(record)?;
+
+                Some((Match::new(kind, record).with_origin(&self.origins), hits))
+            })
+            .collect();
+
+        result.sort_unstable_by(|(a, a_hits), (b, b_hits)| {
+            b_hits
+                .cmp(a_hits)
+                .then((a.kind, a.rule).cmp(&(b.kind, b.rule)))
+        });
+        result.dedup();
+        result
+    }
+
+    /// Provides the records that expire within the given number of days -
+    /// sorted by expiry date. Useful to review the temporary rules before they
+    /// silently stop whitelisting.
+    ///
+    /// **Note:** A rule is given an expiry date through a trailing
+    /// `@expires=YYYY-MM-DD` - e.g. `example.org @expires=2025-12-31`. It is
+    /// ignored if that date is over when it is parsed - and stops whitelisting
+    /// once it is over.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{ExpiryDate, Ruler};
+    ///
+    /// let mut ruler = Ruler::new(false);
+    /// let soon = ExpiryDate::today().add_days(7);
+    ///
+    /// ruler.parse(&format!("example.org @expires={}", soon));
+    /// ruler.parse("example.net @expires=2999-12-31");
+    /// ruler.parse("example.com @expires=2000-01-01");
+    ///
+    /// assert!(ruler.is_whitelisted("example.org"));
+    /// assert!(!ruler.is_whitelisted("example.com"));
+    ///
+    /// let expiring = ruler.expiring_rules(30);
+    ///
+    /// assert_eq!(expiring.len(), 1);
+    /// assert_eq!(expiring[0].0.rule, "example.org");
+    /// assert_eq!(expiring[0].1, soon);
+    /// ```
+    pub fn expiring_rules(&self, within_days: u64) -> Vec<(Match<'_>, ExpiryDate)> {
+        let limit = ExpiryDate::today().add_days(within_days);
+
+        let mut result: Vec<(Match<'_>, ExpiryDate)> = self
+            .records()
+            .filter_map(|(kind, record)| {
+                let expiry = *self.expiries.get(&kind)?.get(record)?;
+
+                (expiry <= limit)
+                    .then(|| (Match::new(kind, record).with_origin(&self.origins), expiry))
+            })
+            .collect();
+
+        result.sort_unstable_by(|(a, a_expiry), (b, b_expiry)| {
+            (a_expiry, a.kind, a.rule).cmp(&(b_expiry, b.kind, b.rule))
+        });
+        result.dedup();
+        result
+    }
+
+    /// Drops the records whose expiry date is over - e.g. from a long-lived
+    /// ruler. See [`Ruler::expiring_rules`].
+    ///
+    /// **Note:** The lookups - e.g. [`Ruler::check`] - already ignore the
+    /// expired records. This method frees the memory they still hold.
+    ///
+    /// # Returns
+    ///
+    /// The number of dropped records.
+    pub fn remove_expired(&mut self) -> usize {
+        let expired: Vec<(MatchKind, String)> = self
+            .expiries
+            .iter()
+            .flat_map(|(kind, records)| {
+                records
+                    .iter()
+                    .filter(|(_, expiry)| expiry.is_past())
+                    .map(|(record, _)| (*kind, record.clone()))
+            })
+            .collect();
+
+        for (kind, record) in &expired {
+            self.pull_record(*kind, record);
+        }
+
+        expired.len()
+    }
+
+    /// Drops the given (normalized) record from the dataset of the given kind.
+    fn pull_record(&mut self, kind: MatchKind, record: &str) {
+        match kind {
+            MatchKind::Strict => self.pull_strict(record),
+            MatchKind::Present => self.pull_present(record),
+            MatchKind::Ends => self.pull_ends(record),
+            MatchKind::Starts => self.pull_starts(record),
+            MatchKind::UrlPrefix => {
+                self.unparse_url_prefix(record);
+            }
+            MatchKind::Regex => self.pull_regex(record),
+            MatchKind::Exception => self.pull_exception(record),
+            MatchKind::Cidr => {
+                if let Ok(network) = Network::parse(record) {
+                    self.pull_cidr(&network);
+                }
+            }
+            MatchKind::Keyword => self.pull_keyword(record),
+        }
+    }
+
+    /// Enables - or disables - the given group of rules. See
+    /// [`Ruler::set_group_enabled`].
+    pub fn with_group_enabled(mut self, group: &str, enabled: bool) -> Ruler {
+        self.set_group_enabled(group, enabled);
+        self
+    }
+
+    /// Whether the given group of rules is enabled - which they all are by
+    /// default.
+    pub fn is_group_enabled(&self, group: &str) -> bool {
+        !self.disabled_groups.contains_key(group)
+    }
+
+    /// Enables - or disables - the given group of rules. The rules following
+    /// a `# @group <name>` comment belong to that group - until the next
+    /// `# @group` comment or the end of the file.
+    ///
+    /// The rules of a disabled group are set aside - even those parsed
+    /// afterwards - until the group is enabled again. This allows a single
+    /// whitelisting schema to serve several deployment profiles.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler.parse_vec(&["example.org", "# @group corp", "example.net", "# @group", "example.com"]);
+    /// ruler.set_group_enabled("corp", false);
+    ///
+    /// assert_eq!(ruler.is_whitelisted("example.org"), true);
+    /// assert_eq!(ruler.is_whitelisted("example.net"), false);
+    /// assert_eq!(ruler.is_whitelisted("example.com"), true);
+    ///
+    /// ruler.set_group_enabled("corp", true);
+    ///
+    /// assert_eq!(ruler.is_whitelisted("example.net"), true);
+    /// ```
+    pub fn set_group_enabled(&mut self, group: &str, enabled: bool) {
+        if enabled {
+            if let Some(records) = self.disabled_groups.remove(group) {
+                self.restore_group(group, records);
+            }
+
+            return;
+        }
+
+        if self.disabled_groups.contains_key(group) {
+            return;
+        }
+
+        let records: Vec<(MatchKind, String)> = self
+            .groups
+            .iter()
+            .flat_map(|(kind, records)| {
+                records
+                    .iter()
+                    .filter(|(_, known)| &***known == group)
+                    .map(|(record, _)| (*kind, record.clone()))
+            })
+            .collect();
+        let mut stashed = Vec::with_capacity(records.len());
+
+        for (kind, record) in records {
+            let origin = self
+                .origins
+                .get(&kind)
+                .and_then(|records| records.get(&record))
+                .cloned();
+            let expiry = self
+                .expiries
+                .get(&kind)
+                .and_then(|records| records.get(&record))
+                .copied();
+
+            self.pull_record(kind, &record);
+            stashed.push(StashedRecord {
+                kind,
+                record,
+                origin,
+                expiry,
+            });
+        }
+
+        self.disabled_groups.insert(group.to_string(), stashed);
+    }
+
+    /// Puts the given records of the given group back into our datasets - but
+    /// the expired ones.
+    fn restore_group(&mut self, group: &str, records: Vec<StashedRecord>) {
+        let parsing = (
+            self.origin.take(),
+            self.expiry.take(),
+            self.group.replace(Arc::from(group)),
+        );
+
+        for stashed in records {
+            if stashed.expiry.is_some_and(|expiry| expiry.is_past()) {
+                continue;
+            }
+
+            self.origin = stashed.origin;
+            self.expiry = stashed.expiry;
+            self.push_record(stashed.kind, &stashed.record);
+        }
+
+        (self.origin, self.expiry, self.group) = parsing;
+    }
+
+    /// Adds the given (normalized) record to the dataset of the given kind.
+    fn push_record(&mut self, kind: MatchKind, record: &str) {
+        match kind {
+            MatchKind::Strict => self.push_strict(record),
+            MatchKind::Present => self.push_present(record),
+            MatchKind::Ends => self.push_ends(record),
+            MatchKind::Starts => self.push_starts(record),
+            MatchKind::UrlPrefix => {
+                self.parse_url_prefix(record);
+            }
+            MatchKind::Regex => self.push_regex(record),
+            MatchKind::Exception => self.push_exception(record),
+            MatchKind::Cidr => {
+                if let Ok(network) = Network::parse(record) {
+                    self.push_cidr(&network);
+                }
+            }
+            MatchKind::Keyword => self.push_keyword(record),
+        }
+    }
+
+    /// Remembers that the given rule matched a subject - when tracked.
+    pub(crate) fn record_usage(&self, kind: MatchKind, rule: &str) {
+        let Some(usage) = &self.usage else {
+            return;
+        };
+        let mut usage = usage.lock().unwrap();
+        let records = usage.entry(kind).or_default();
+
+        match records.get_mut(rule) {
+            Some(hits) => *hits += 1,
+            None => {
+                records.insert(rule.to_string(), 1);
+            }
+        }
+    }
+
+    /// Checks whether the given rule is currently active - that is, whether
+    /// every record it produces is held by us.
+    ///
+    /// **Note:** Comments, empty lines and invalid rules are never active.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The rule to check - in any flag form.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the rule is active, `false` otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(true);
+    ///
+    /// ruler.parse("ALL .example.org");
+    ///
+    /// assert!(ruler.contains_rule("ALL .example.org"));
+    /// assert!(ruler.contains_rule("example.org"));
+    /// assert!(!ruler.contains_rule("ALL .example.net"));
+    ///
+    /// ruler.unparse("ALL .example.org");
+    ///
+    /// assert!(!ruler.contains_rule("ALL .example.org"));
+    /// ```
+    pub fn contains_rule(&self, line: &str) -> bool {
+        let mut single = self.empty_like();
+
+        for rule in self.settings.rule_format.convert(line) {
+            if Ruler::needs_extensions(&rule) {
+                // Without extensions, we can't hold any of its records - and a
+                // lookup never fetches them.
+                if self.settings.extensions.is_empty() {
+                    return false;
+                }
+
+                if single.settings.extensions.is_empty() {
+                    single.settings.extensions = self.settings.extensions.clone();
+                }
+            }
+
+            if single.try_parse_rule(&rule).is_err() {
+                return false;
+            }
+        }
+
+        let mut records = single.records().peekable();
+
+        records.peek().is_some() && records.all(|(kind, record)| self.holds(kind, record))
+    }
+
+    /// Checks whether we hold the given (normalized) record in the dataset of
+    /// the given kind.
+    fn holds(&self, kind: MatchKind, record: &str) -> bool {
+        match kind {
+            MatchKind::Strict => self.strict.contains(record),
+            MatchKind::Present => self.present.contains(record),
+            MatchKind::Ends => self
+                .ends
+                .get(self.ends_search_key(record))
+                .is_some_and(|records| records.contains(record)),
+            MatchKind::UrlPrefix => self
+                .url_prefixes
+                .values()
+                .any(|records| records.contains(record)),
+            MatchKind::Regex => self.regexes.iter().any(|pattern| pattern == record),
+            MatchKind::Exception => self.exceptions.contains(record),
+            MatchKind::Cidr => self.cidrs.contains(record),
+            MatchKind::Keyword => self.keywords.contains(record),
+            MatchKind::Starts => self
+                .starts
+                .get(self.starts_search_key(record))
+                .is_some_and(|records| records.contains(record)),
+        }
+    }
+
+    /// Provides the number of records we hold - per dataset.
+    ///
+    /// **Note:** A rule may produce several records - e.g. its complements -
+    /// or none at all - e.g. a comment.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(true);
+    ///
+    /// ruler.parse_vec(&["example.org", "ALL .example.net", "REG ^ads\\."]);
+    ///
+    /// let counts = ruler.counts();
+    ///
+    /// // `example.org`, `www.example.org`, `example.net` and `www.example.net`.
+    /// assert_eq!(counts.strict, 4);
+    /// assert_eq!(counts.ends, 1);
+    /// assert_eq!(counts.regexes, 1);
+    /// assert_eq!(ruler.len(), 6);
+    /// ```
+    pub fn counts(&self) -> RuleCounts {
+        RuleCounts {
+            strict: self.strict.len(),
+            present: self.present.len(),
+            ends: self.ends.values().map(HashSet::len).sum(),
+            url_prefixes: self.url_prefixes.values().map(HashSet::len).sum(),
+            regexes: self.regexes.len(),
+            exceptions: self.exceptions.len(),
+            cidrs: self.cidrs.len(),
+            keywords: self.keywords.len(),
+            starts: self.starts.values().map(HashSet::len).sum(),
+        }
+    }
+
+    /// Provides the number of records we hold - whatever their dataset. See
+    /// [`Ruler::counts`].
+    pub fn len(&self) -> usize {
+        self.counts().total()
+    }
+
+    /// Whether we hold no record at all - e.g. because a whitelisting schema
+    /// only holds comments.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops every rule - while keeping our settings.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(true);
+    ///
+    /// ruler.parse_vec(&["example.org", "ALL .example.net", "REG ^ads\\."]);
+    /// ruler.clear();
+    ///
+    /// assert_eq!(ruler.is_whitelisted("www.example.org"), false);
+    /// assert_eq!(ruler.handle_complement(), true);
+    /// ```
+    pub fn clear(&mut self) {
+        self.strict.clear();
+        self.present.clear();
+        self.ends.clear();
+        self.url_prefixes.clear();
+        self.regexes.clear();
+        self.exceptions.clear();
+        self.cidrs.clear();
+        self.keywords.clear();
+        self.starts.clear();
+        self.origins.clear();
+        self.expiries.clear();
+        self.groups.clear();
+
+        for records in self.disabled_groups.values_mut() {
+            records.clear();
+        }
+
+        self.caches.ends.take();
+        self.caches.regexes.take();
+        self.caches.cidrs.take();
+        self.caches.keywords.take();
+    }
+
+    /// Drops the rules of the `REG ` flag.
+    pub fn clear_regex(&mut self) {
+        self.regexes.clear();
+        self.origins.remove(&MatchKind::Regex);
+        self.expiries.remove(&MatchKind::Regex);
+        self.groups.remove(&MatchKind::Regex);
+        self.caches.regexes.take();
+    }
+
+    /// Drops the rules of the `ALL ` and `SUB ` flags - which share the same
+    /// dataset.
+    ///
+    /// **Note:** Like [`Ruler::unparse`] does for an `ALL ` rule, the apex -
+    /// and its complements - of each rule is dropped too. Even when it was
+    /// given through another rule.
+    pub fn clear_all_rules(&mut self) {
+        let records: Vec<String> = self.ends.values().flatten().cloned().collect();
+
+        for record in records {
+            self.unparse_all(&format!("ALL {}", record));
+        }
+
+        self.ends.clear();
+        self.origins.remove(&MatchKind::Ends);
+        self.expiries.remove(&MatchKind::Ends);
+        self.groups.remove(&MatchKind::Ends);
+        self.caches.ends.take();
+    }
+
+    /// Drops the rules of the `RZD ` flag.
+    pub fn clear_rzd(&mut self) {
+        self.present.clear();
+        self.origins.remove(&MatchKind::Present);
+        self.expiries.remove(&MatchKind::Present);
+        self.groups.remove(&MatchKind::Present);
+    }
+
+    /// IDNAze the given `subject`.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - The subject to IDNAze.
+    ///
+    /// # Returns
+    ///
+    /// The IDNAzed subject.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// let subject = String::from("www.äxample.org");
+    ///
+    /// assert_eq!(ruler.idnaze_subject(&subject), "www.xn--xample-9ta.org");
+    /// ```
+    pub fn idnaze_subject(&mut self, subject: &str) -> String {
+        Ruler::idnaze_subject_with_cache(subject, &mut self.caches.idna)
+    }
+
+    fn idnaze_subject_with_cache(
+        subject: &str,
+        cache: &mut Option<LruCache<String, String>>,
+    ) -> String {
+        if let Some(cache) = cache.as_mut() {
+            if let Some(result) = cache.get(subject) {
+                return result.clone();
+            }
+        }
+
+        let result = match idna::domain_to_ascii(subject) {
+            Ok(result) => result,
+            // We still want a case folded subject - even if it's not a valid IDN.
+            Err(_) if !subject.is_ascii() => idna::domain_to_unicode(subject).0,
+            Err(_) => subject.to_string(),
+        };
+
+        if let Some(cache) = cache.as_mut() {
+            cache.put(subject.to_string(), result.clone());
+        }
+
+        result
+    }
+
+    /// IDNAze the given `line`.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to IDNAze.
+    ///
+    /// # Returns
+    ///
+    /// The IDNAzed line.
+    ///
+    /// # Example
+    ///
+    /// This example shows how to IDNAze a line, where the line contains a comment.
+    /// In such cases, the comment will be kept as is.
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// let line = String::from("www.äxample.org # äxample.org");
+    ///
+    /// assert_eq!(ruler.idnaze_line(&line), "www.xn--xample-9ta.org # äxample.org");
+    /// ```
+    pub fn idnaze_line(&mut self, line: &str) -> String {
+        Ruler::idnaze_line_with_cache(line, &mut self.caches.idna)
+    }
+
+    /// IDNAze the given `line` - unless disabled through `set_idna`.
+    fn prepare_line(&mut self, line: &str) -> String {
+        if !self.settings.idna {
+            return line.to_string();
+        }
+
+        self.idnaze_line(line)
+    }
+
+    /// Same as `prepare_line` - with the given IDNA cache.
+    fn prepare_line_with_cache(
+        &self,
+        line: &str,
+        cache: &mut Option<LruCache<String, String>>,
+    ) -> String {
+        if !self.settings.idna {
+            return line.to_string();
+        }
+
+        Ruler::idnaze_line_with_cache(line, cache)
+    }
+
+    /// Provides a new - empty - IDNA cache with the same capacity as ours.
+    ///
+    /// It allows the threads sharing a read-only ruler to keep their own cache.
+    fn new_idna_cache(&self) -> Option<LruCache<String, String>> {
+        NonZeroUsize::new(self.idna_cache_capacity()).map(LruCache::new)
+    }
+
+    fn idnaze_line_with_cache(line: &str, cache: &mut Option<LruCache<String, String>>) -> String {
+        let tab = "\t";
+        let space = " ";
+
+        let separator;
+
+        static REGEX_IGNORE: OnceLock<Regex> = OnceLock::new();
+
+        let regex_ignore = REGEX_IGNORE.get_or_init(|| Regex::new(r"localhost$|localdomain$|local$|broadcasthost$|0\.0\.0\.0$|allhosts$|allnodes$|allrouters$|localnet$|loopback$|mcastprefix$").unwrap());
+
+        if line.is_empty() || line.starts_with('#') || regex_ignore.is_match(line).unwrap() {
+            return line.to_string();
+        }
+
+        if line.contains(tab) {
+            separator = tab
+        } else if line.contains(space) {
+            separator = space
+        } else {
+            separator = ""
+        }
+
+        if !separator.is_empty() {
+            let mut idnazed_data: Vec<String> = Vec::new();
+
+            let subjects: &str;
+            let mut comment = "";
+
+            if line.contains('#') {
+                (subjects, comment) = line.split_once('#').unwrap();
+            } else {
+                subjects = line;
+            }
+
+            let mut splitted_subject: Vec<&str> = subjects.split(separator).collect();
+
+            for data in splitted_subject.iter_mut() {
+                if data.is_empty() || regex_ignore.is_match(data).unwrap() {
+                    idnazed_data.push(data.to_string());
+                    continue;
+                }
+
+                let idnazed = if data.contains('#') {
+                    let (element, comment) = data.split_once('#').unwrap();
+                    let idnazed_line = format!(
+                        "{} #{}",
+                        Ruler::idnaze_subject_with_cache(&element.to_string(), cache),
+                        comment
+                    );
+
+                    idnazed_line
+                } else {
+                    Ruler::idnaze_subject_with_cache(&data.to_string(), cache)
+                };
+
+                idnazed_data.push(idnazed);
+            }
+
+            if !comment.is_empty() {
+                return idnazed_data.join(separator) + "#" + comment;
+            }
+
+            return idnazed_data.join(separator);
+        }
+
+        Ruler::idnaze_subject_with_cache(line, cache)
+    }
+
+    /// Checks the given `line` against the rules.
+    ///
+    /// **Note:** The records whose expiry date is over are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to check. **WARNING:** We assume 1 rule per line.
+    ///
+    ///   **Note:** If a URL (e.g `https://example.org/`) is given, the sub-domain
+    ///   will be used to determine if the line has been whitelisted.
+    ///
+    /// # Returns
+    ///
+    /// A `bool` indicating whether the line matches the rules.
+    /// Any `true` value should be considered positive.
+    /// Meaning that the line matches one of the rule.
+    ///
+    /// # Example
+    ///
+    /// Checking is read-only, so a ruler can be shared across threads.
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use std::thread;
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler.parse("ALL .example.org");
+    ///
+    /// let ruler = Arc::new(ruler);
+    /// let shared = Arc::clone(&ruler);
+    ///
+    /// let checker = thread::spawn(move || shared.is_whitelisted("api.example.org"));
+    ///
+    /// assert_eq!(checker.join().unwrap(), true);
+    /// assert_eq!(ruler.is_whitelisted(&String::from("example.com")), false);
+    /// ```
+    pub fn is_whitelisted(&self, line: &str) -> bool {
+        self.matches_line(line)
+    }
+
+    /// Checks the given `domain` against the rules.
+    ///
+    /// Contrary to [`Ruler::is_whitelisted`], this method doesn't try to extract
+    /// the network location of the given input. Use it when you already have a
+    /// clean hostname - e.g. in a hot loop - to save that overhead.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - The domain to check. **WARNING:** We assume a bare domain.
+    ///
+    /// # Returns
+    ///
+    /// A `bool` indicating whether the domain matches the rules.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler.parse(&String::from("ALL .example.org"));
+    ///
+    /// assert_eq!(ruler.is_whitelisted_domain(&String::from("api.example.org")), true);
+    /// assert_eq!(ruler.is_whitelisted_domain(&String::from("example.com")), false);
+    /// ```
+    pub fn is_whitelisted_domain(&self, domain: &str) -> bool {
+        self.matches(domain)
+    }
+
+    /// Checks the given `subject` against the rules - like
+    /// [`Ruler::is_whitelisted`] - and explains the decision.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - The line to check. **WARNING:** We assume 1 subject per line.
+    ///
+    /// # Returns
+    ///
+    /// The rule that matched - and its kind - or `None` if the subject is not
+    /// whitelisted.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{MatchKind, Ruler};
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler.parse(&String::from("ALL .example.org"));
+    ///
+    /// let found = ruler.check(&String::from("api.example.org")).unwrap();
+    ///
+    /// assert_eq!(found.kind, MatchKind::Ends);
+    /// assert_eq!(found.rule, ".example.org");
+    /// assert_eq!(ruler.check(&String::from("example.com")), None);
+    /// ```
+    pub fn check(&self, subject: &str) -> Option<Match<'_>> {
+        self.find_line_match(subject)
+            .map(|found| found.with_origin(&self.origins))
+    }
+
+    /// Checks the given `url` against the rules and reports which of its
+    /// components was whitelisted.
+    ///
+    /// The host of the URL - without its port - is checked first. When no rule
+    /// matches it, the URL-prefix rules and then the regular expressions are
+    /// checked against the whole URL - which allows path-level exceptions.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to check.
+    ///
+    /// # Returns
+    ///
+    /// The component that was whitelisted and the rule that matched - if any.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{MatchKind, Ruler, UrlComponent};
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler.parse(&String::from("ALL .example.org"));
+    /// ruler.parse(&String::from("REG ^https://example\\.com/api/"));
+    ///
+    /// let found = ruler
+    ///     .is_whitelisted_url(&String::from("https://api.example.org/hello"))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(found.component, UrlComponent::Host);
+    /// assert_eq!(found.kind, MatchKind::Ends);
+    /// assert_eq!(found.rule, ".example.org");
+    ///
+    /// let found = ruler
+    ///     .is_whitelisted_url(&String::from("https://example.com/api/v1"))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(found.component, UrlComponent::Url);
+    /// assert_eq!(found.kind, MatchKind::Regex);
+    ///
+    /// assert_eq!(ruler.is_whitelisted_url(&String::from("https://example.com/")), None);
+    /// ```
+    pub fn is_whitelisted_url(&self, url: &str) -> Option<UrlMatch> {
+        if url.is_empty() || url.starts_with('#') {
+            return None;
+        }
+
+        let host = self.apply_reduction(Reduction::StripPort, utils::extract_netloc(url));
+
+        if self.is_excepted(&host) {
+            return None;
+        }
+
+        let (component, found) = if let Some(found) = self.find_match(&host) {
+            (UrlComponent::Host, found)
+        } else if let Some(found) = self.find_url_prefix_match(url) {
+            (UrlComponent::UrlPrefix, found)
+        } else {
+            let rule = self.find_regex(url)?;
+
+            (UrlComponent::Url, Match::new(MatchKind::Regex, rule))
+        };
+
+        self.record_usage(found.kind, found.rule);
+
+        Some(UrlMatch {
+            component,
+            kind: found.kind,
+            rule: found.rule.to_string(),
+            origin: found.with_origin(&self.origins).origin.cloned(),
+        })
+    }
+
+    /// Checks the given `subjects` against the rules - one after the other, on
+    /// the current thread.
+    ///
+    /// Prefer it over calling [`Ruler::is_whitelisted`] in a loop: the state
+    /// shared by every subject is only looked at once per batch. See
+    /// [`Ruler::is_whitelisted_many`] to spread the batch across threads.
+    ///
+    /// # Arguments
+    ///
+    /// * `subjects` - The lines to check. **WARNING:** We assume 1 subject per line.
+    ///
+    /// # Returns
+    ///
+    /// A vector of `bool` - in the same order as the given `subjects` -
+    /// indicating whether each subject matches the rules.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler.parse(&String::from("ALL .example.org"));
+    ///
+    /// let subjects = vec![String::from("api.example.org"), String::from("example.com")];
+    ///
+    /// assert_eq!(ruler.are_whitelisted(&subjects), vec![true, false]);
+    /// ```
+    pub fn are_whitelisted<S: AsRef<str>>(&self, subjects: &[S]) -> Vec<bool> {
+        self.are_whitelisted_iter(subjects).collect()
+    }
+
+    /// Same as [`Ruler::are_whitelisted`] - lazily, for the subjects of the
+    /// given iterator. E.g. the lines of a file that doesn't fit in memory.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler.parse(&String::from("example.org"));
+    ///
+    /// let subjects = vec![String::from("example.org"), String::from("example.com")];
+    /// let kept: Vec<&String> = subjects
+    ///     .iter()
+    ///     .zip(ruler.are_whitelisted_iter(&subjects))
+    ///     .filter(|(_, whitelisted)| !whitelisted)
+    ///     .map(|(subject, _)| subject)
+    ///     .collect();
+    ///
+    /// assert_eq!(kept, vec!["example.com"]);
+    /// ```
+    pub fn are_whitelisted_iter<'a, I>(&'a self, subjects: I) -> impl Iterator<Item = bool> + 'a
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        I::IntoIter: 'a,
+    {
+        // Nothing can match - spare the extraction of each network location.
+        let holds_rules = !(self.strict.is_empty()
+            && self.present.is_empty()
+            && self.ends.is_empty()
+            && self.starts.is_empty()
+            && self.url_prefixes.is_empty()
+            && self.regexes.is_empty()
+            && self.cidrs.is_empty()
+            && self.keywords.is_empty());
+
+        subjects
+            .into_iter()
+            .map(move |subject| holds_rules && self.matches_line(subject.as_ref()))
+    }
+
+    /// Checks the given `lines` against the rules - in parallel.
+    ///
+    /// The lines are split across a pool of threads sharing the same read-only
+    /// ruler.
+    ///
+    /// **Note:** Only available with the `parallel` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - The lines to check. **WARNING:** We assume 1 subject per line.
+    ///
+    /// # Returns
+    ///
+    /// A vector of `bool` - in the same order as the given `lines` - indicating
+    /// whether each line matches the rules.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler.parse(&String::from("ALL .example.org"));
+    ///
+    /// let subjects = vec![
+    ///     String::from("api.example.org"),
+    ///     String::from("example.com"),
+    ///     String::from("https://example.org/hello"),
+    /// ];
+    ///
+    /// assert_eq!(ruler.is_whitelisted_many(&subjects), vec![true, false, true]);
+    /// ```
+    #[cfg(feature = "parallel")]
+    pub fn is_whitelisted_many<S: AsRef<str> + Sync>(&self, lines: &[S]) -> Vec<bool> {
+        lines
+            .par_iter()
+            .map(|line| self.matches_line(line.as_ref()))
+            .collect()
+    }
+
+    /// Provides the given `subjects` that match the rules - checked in
+    /// parallel, like [`Ruler::is_whitelisted_many`].
+    ///
+    /// **Note:** Only available with the `parallel` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `subjects` - The lines to check. **WARNING:** We assume 1 subject per line.
+    ///
+    /// # Returns
+    ///
+    /// The whitelisted subjects - in the same order as the given `subjects`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler.parse(&String::from("ALL .example.org"));
+    ///
+    /// let subjects = vec![
+    ///     String::from("api.example.org"),
+    ///     String::from("example.com"),
+    ///     String::from("https://example.org/hello"),
+    /// ];
+    ///
+    /// assert_eq!(
+    ///     ruler.par_filter_whitelisted(&subjects),
+    ///     vec!["api.example.org", "https://example.org/hello"]
+    /// );
+    /// ```
+    #[cfg(feature = "parallel")]
+    pub fn par_filter_whitelisted<'a, S: AsRef<str> + Sync>(
+        &self,
+        subjects: &'a [S],
+    ) -> Vec<&'a S> {
+        subjects
+            .par_iter()
+            .filter(|subject| self.matches_line(subject.as_ref()))
+            .collect()
+    }
+
+    fn matches_line(&self, line: &str) -> bool {
+        self.find_line_match(line).is_some()
+    }
+
+    fn find_line_match(&self, line: &str) -> Option<Match<'_>> {
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let netloc = utils::extract_netloc(line);
+
+        if self.is_excepted(&netloc) {
+            return None;
+        }
+
+        self.find_match(&netloc)
+            .or_else(|| self.find_url_prefix_match(line))
+            .inspect(|found| self.record_usage(found.kind, found.rule))
+    }
+
+    /// Whether the given (normalized) record - of the given kind - can still
+    /// match: it has no expiry date or its date is not over yet.
+    fn is_live(&self, kind: MatchKind, record: &str) -> bool {
+        !is_expired(&self.expiries, kind, record)
+    }
+
+    /// Provides the rule of the `EXC ` flag exempting the given `domain` - if
+    /// any.
+    pub(crate) fn find_exception(&self, domain: &str) -> Option<&String> {
+        if self.exceptions.is_empty() {
+            return None;
+        }
+
+        self.exceptions
+            .get(&self.normalize(domain))
+            .filter(|record| self.is_live(MatchKind::Exception, record))
+    }
+
+    /// Whether the given `domain` is exempted from every other rule.
+    fn is_excepted(&self, domain: &str) -> bool {
+        self.find_exception(domain)
+            .inspect(|record| self.record_usage(MatchKind::Exception, record))
+            .is_some()
+    }
+
+    /// Provides the most specific rule of the `CIDR ` flag holding the given
+    /// (normalized) subject - if it is an IP address.
+    fn find_cidr(&self, fline: &str) -> Option<&String> {
+        if self.cidrs.is_empty() {
+            return None;
+        }
+
+        let address = cidr::parse_ip(fline)?;
+
+        self.caches
+            .cidrs
+            .get_or_init(|| CidrTrie::new(self.cidrs.iter()))
+            .find(address, |record| self.is_live(MatchKind::Cidr, record))
+    }
+
+    /// Provides the first rule of the `START ` flag that matches the given
+    /// (normalized) subject.
+    fn find_starts(&self, fline: &str) -> Option<&String> {
+        let candidates = self.starts.get(self.starts_search_key(fline))?;
+
+        // The records always end with a dot.
+        fline.match_indices('.').find_map(|(index, _)| {
+            candidates
+                .get(&fline[..=index])
+                .filter(|record| self.is_live(MatchKind::Starts, record))
+        })
+    }
+
+    /// Provides the first rule of the `KEY ` flag found in the given
+    /// (normalized) subject.
+    fn find_keyword(&self, fline: &str) -> Option<&String> {
+        if self.keywords.is_empty() {
+            return None;
+        }
+
+        self.caches
+            .keywords
+            .get_or_init(|| KeywordAutomaton::new(self.keywords.iter()))
+            .find(fline, |record| self.is_live(MatchKind::Keyword, record))
+    }
+
+    /// Provides the first URL-prefix rule that matches the given `url`.
+    fn find_url_prefix_match(&self, url: &str) -> Option<Match<'_>> {
+        if self.url_prefixes.is_empty() {
+            return None;
+        }
+
+        let (host, url) = self.normalize_url(url)?;
+
+        self.url_prefixes
+            .get(&host)?
+            .iter()
+            .find(|prefix| {
+                url.starts_with(prefix.as_str()) && self.is_live(MatchKind::UrlPrefix, prefix)
+            })
+            .map(|prefix| Match::new(MatchKind::UrlPrefix, prefix))
+    }
+
+    /// Provides the first rule of the `ALL ` and `SUB ` flags that matches the
+    /// given (normalized) subject.
+    fn find_ends(&self, fline: &str) -> Option<&String> {
+        if self.settings.ends_strategy == EndsStrategy::Automaton {
+            if self.ends.is_empty() {
+                return None;
+            }
+
+            return self
+                .caches
+                .ends
+                .get_or_init(|| EndsAutomaton::new(self.ends.values().flatten()))
+                .find(fline, |record| self.is_live(MatchKind::Ends, record));
+        }
+
+        let candidates = self.ends.get(self.ends_search_key(fline))?;
+
+        // The records always start with a dot.
+        fline.match_indices('.').find_map(|(index, _)| {
+            candidates
+                .get(&fline[index..])
+                .filter(|record| self.is_live(MatchKind::Ends, record))
+        })
+    }
+
+    fn matches(&self, domain: &str) -> bool {
+        if self.is_excepted(domain) {
+            return false;
+        }
+
+        self.find_match(domain)
+            .inspect(|found| self.record_usage(found.kind, found.rule))
+            .is_some()
+    }
+
+    /// Provides the first rule that matches the given `domain`.
+    fn find_match(&self, domain: &str) -> Option<Match<'_>> {
+        if domain.is_empty() || domain.starts_with('#') {
+            return None;
+        }
+
+        let fline = self.normalize(domain);
+
+        let strict = self
+            .strict
+            .get(&fline)
+            .filter(|record| self.is_live(MatchKind::Strict, record));
+
+        if let Some(rule) = strict {
+            return Some(Match::new(MatchKind::Strict, rule));
+        }
+
+        let present = self
+            .present
+            .get(&fline)
+            .filter(|record| self.is_live(MatchKind::Present, record));
+
+        if let Some(rule) = present {
+            return Some(Match::new(MatchKind::Present, rule));
+        }
+
+        if let Some(rule) = self.find_ends(&fline) {
+            return Some(Match::new(MatchKind::Ends, rule));
+        }
+
+        if let Some(rule) = self.find_starts(&fline) {
+            return Some(Match::new(MatchKind::Starts, rule));
+        }
+
+        if let Some(rule) = self.find_cidr(&fline) {
+            return Some(Match::new(MatchKind::Cidr, rule));
+        }
+
+        if let Some(rule) = self.find_keyword(&fline) {
+            return Some(Match::new(MatchKind::Keyword, rule));
+        }
+
+        if let Some(rule) = self.find_regex(&fline) {
+            return Some(Match::new(MatchKind::Regex, rule));
+        }
+
+        None
+    }
+}
+
+impl Drop for Ruler {
+    /// Ensures that all downloaded files are cleaned up - unless they should
+    /// be kept. See [`Ruler::set_keep_downloads`].
+    fn drop(&mut self) {
+        if self.tmps.keep_downloads {
+            return;
+        }
+
+        for file in &self.tmps.downloaded_files {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_ruler_gen_complement_true() {
+        let ruler = Ruler::new(true);
+
+        assert_eq!(ruler.settings.handle_complement, true)
+    }
+
+    #[test]
+    fn test_new_ruler_gen_complement_false() {
+        let ruler = Ruler::new(false);
+
+        assert_eq!(ruler.settings.handle_complement, false)
+    }
+
+    #[test]
+    fn test_reduce() {
+        let ruler = Ruler::new(false);
+
+        assert_eq!(
+            ruler.reduce(&"www.example.org".to_string()),
+            "example.org".to_string()
+        )
+    }
+
+    #[test]
+    fn test_reduce_no_www() {
+        let ruler = Ruler::new(false);
+
+        assert_eq!(
+            ruler.reduce(&"example.org".to_string()),
+            "example.org".to_string()
+        )
+    }
+
+    #[test]
+    fn test_reduce_multiple_www() {
+        let ruler = Ruler::new(false);
+
+        assert_eq!(
+            ruler.reduce(&"www.www.example.org".to_string()),
+            "www.example.org".to_string()
+        )
+    }
+
+    #[test]
+    fn test_ends_search_key() {
+        let ruler = Ruler::new(false);
+
+        assert_eq!(ruler.ends_search_key("example.org"), "org".to_string())
+    }
+
+    #[test]
+    fn test_ends_search_key_long_extension() {
+        let ruler = Ruler::new(false);
+
+        assert_eq!(
+            ruler.ends_search_key("example.example"),
+            "example".to_string()
+        )
+    }
+
+    #[test]
+    fn test_idnaze_subject() {
+        let mut ruler = Ruler::new(false);
+
+        assert_eq!(
+            ruler.idnaze_subject(&"www.äxample.org".to_string()),
+            "www.xn--xample-9ta.org".to_string()
+        );
+
+        assert_eq!(
+            ruler.idnaze_subject(&"www.example.org".to_string()),
+            "www.example.org".to_string()
+        );
+    }
+
+    #[test]
+    fn test_idnaze_line() {
+        let mut ruler = Ruler::new(false);
+
+        assert_eq!(
+            ruler.idnaze_line(&"www.äxample.org".to_string()),
+            "www.xn--xample-9ta.org".to_string()
+        );
+
+        assert_eq!(
+            ruler.idnaze_line(&"www.example.org".to_string()),
+            "www.example.org".to_string()
+        );
+
+        assert_eq!(
+            ruler.idnaze_line(&"www.example.org # example.org".to_string()),
+            "www.example.org # example.org".to_string()
+        );
+
+        assert_eq!(
+            ruler.idnaze_line(&"www.example.org # äxample.org".to_string()),
+            "www.example.org # äxample.org".to_string()
+        );
+
+        assert_eq!(
+            ruler.idnaze_line(&"www.example.org # äxample.org # example.org".to_string()),
+            "www.example.org # äxample.org # example.org".to_string()
+        );
+
+        assert_eq!(
+            ruler.idnaze_line(&"www.example.org       # äxample.org # example.org".to_string()),
+            "www.example.org       # äxample.org # example.org".to_string()
+        );
+
+        assert_eq!(
+            ruler.idnaze_line(&"www.äxample.org # äxample.org # example.org #".to_string()),
+            "www.xn--xample-9ta.org # äxample.org # example.org #".to_string()
+        );
+    }
+
+    #[test]
+    fn test_push_strict() {
+        let mut ruler = Ruler::new(false);
+
+        // Ensure that it's really empty :)
+        assert_eq!(ruler.strict.is_empty(), true);
+
+        ruler.push_strict(&"www.example.org".to_string());
+
+        let mut expected = HashSet::new();
+        expected.insert("www.example.org".to_string());
+
+        assert_eq!(ruler.strict, expected);
+
+        // Let's add another one.
+
+        ruler.push_strict(&"example.net".to_string());
+        expected.insert("example.net".to_string());
+
+        assert_eq!(ruler.strict, expected);
+    }
+
+    #[test]
+    fn test_pull_strict() {
+        let mut ruler = Ruler::new(false);
+
+        // Ensure that it's really empty :)
+        assert_eq!(ruler.strict.is_empty(), true);
+
+        // Add some data into it :)
+        ruler.push_strict(&"www.example.org".to_string());
+        ruler.push_strict(&"example.net".to_string());
+
+        ruler.pull_strict(&"www.example.org".to_string());
+
+        let mut expected = HashSet::new();
+        expected.insert("example.net".to_string());
+
+        assert_eq!(ruler.strict, expected);
+
+        // Let's remove another one.
+        ruler.pull_strict(&"example.net".to_string());
+        expected.remove("example.net");
+
+        assert_eq!(ruler.strict, expected);
+    }
+
+    #[test]
+    fn test_push_present() {
+        let mut ruler = Ruler::new(false);
+
+        // Ensure that it's really empty :)
+        assert_eq!(ruler.present.is_empty(), true);
+
+        ruler.push_present(&"www.example.net".to_string());
+
+        let mut expected = HashSet::new();
+        expected.insert("www.example.net".to_string());
+
+        assert_eq!(ruler.present, expected);
+
+        // Let's add another one.
+
+        ruler.push_present(&"example.com".to_string());
+        expected.insert("example.com".to_string());
+
+        assert_eq!(ruler.present, expected);
+    }
+
+    #[test]
+    fn test_pull_present() {
+        let mut ruler = Ruler::new(false);
+
+        // Ensure that it's really empty :)
+        assert_eq!(ruler.present.is_empty(), true);
+
+        // Add some data into it :)
+        ruler.push_present(&"www.example.net".to_string());
+        ruler.push_present(&"example.org".to_string());
+
+        ruler.pull_present(&"www.example.net".to_string());
+
+        let mut expected = HashSet::new();
+        expected.insert("example.org".to_string());
+
+        assert_eq!(ruler.present, expected);
+
+        // Let's remove another one.
+        ruler.pull_present(&"example.org".to_string());
+        expected.remove("example.org");
+
+        assert_eq!(ruler.present, expected);
+    }
+
+    #[test]
+    fn test_push_ends() {
+        let mut ruler = Ruler::new(false);
+
+        // Ensure that it's really empty :)
+        assert_eq!(ruler.ends.get_key_value("example"), None);
+
+        ruler.push_ends(&"www.example.example".to_string());
+
+        let mut expected = HashSet::new();
+        expected.insert("www.example.example".to_string());
+
+        assert_eq!(
+            ruler.ends.get_key_value("example"),
+            Some((&"example".to_string(), &expected))
+        );
+
+        // Let's add another one.
+
+        ruler.push_ends(&"example.com".to_string());
+
+        let mut expected = HashSet::new();
+        expected.insert("example.com".to_string());
+
+        assert_eq!(
+            ruler.ends.get_key_value("com"),
+            Some((&"com".to_string(), &expected))
+        );
+
+        // Let's add another one.
+
+        ruler.push_ends(&"example.co".to_string());
+
+        let mut expected = HashSet::new();
+        expected.insert("example.co".to_string());
+
+        assert_eq!(
+            ruler.ends.get_key_value("co"),
+            Some((&"co".to_string(), &expected))
+        );
+
+        assert_eq!(ruler.ends.contains_key("com"), true);
+        assert_eq!(ruler.ends.contains_key("example"), true);
+        assert_eq!(ruler.ends.contains_key("co"), true);
+    }
+
+    #[test]
+    fn test_pull_ends() {
+        let mut ruler = Ruler::new(false);
+
+        // Ensure that it's really empty :)
+        assert_eq!(ruler.ends.get_key_value("example"), None);
+
+        // Add some data into it :)
+        ruler.push_ends(&"www.example.example".to_string());
+        ruler.push_ends(&"example.com".to_string());
+        ruler.push_ends(&"example.co".to_string());
+
+        assert_eq!(ruler.ends.contains_key("com"), true);
+        assert_eq!(ruler.ends.contains_key("example"), true);
+        assert_eq!(ruler.ends.contains_key("co"), true);
+
+        ruler.pull_ends(&"www.example.example".to_string());
+
+        let expected = HashSet::new();
+
+        assert_eq!(
+            ruler.ends.get_key_value("example"),
+            Some((&"example".to_string(), &expected))
+        );
+
+        let mut expected = HashSet::new();
+        expected.insert("example.com".to_string());
+
+        assert_eq!(
+            ruler.ends.get_key_value("com"),
+            Some((&"com".to_string(), &expected))
+        );
+
+        let mut expected = HashSet::new();
+        expected.insert("example.co".to_string());
+
+        assert_eq!(
+            ruler.ends.get_key_value("co"),
+            Some((&"co".to_string(), &expected))
+        );
+
+        // Let's remove another one.
+        ruler.pull_ends(&"example.com".to_string());
+
+        let expected = HashSet::new();
+
+        assert_eq!(
+            ruler.ends.get_key_value("com"),
+            Some((&"com".to_string(), &expected))
+        );
+
+        assert_eq!(ruler.ends.contains_key("com"), true);
+        assert_eq!(ruler.ends.contains_key("example"), true);
+        assert_eq!(ruler.ends.contains_key("co"), true);
+    }
+
+    #[test]
+    fn test_push_regex() {
+        let mut ruler = Ruler::new(false);
+
+        // Ensure that it's really empty :)
+        assert!(ruler.regexes.is_empty());
+        assert_eq!(ruler.find_regex("www.example.com"), None);
+
+        ruler.push_regex(&"^(www.)?example.com$".to_string());
+
+        let expected = vec!["^(www.)?example.com$".to_string()];
+
+        assert_eq!(ruler.regexes, expected);
+        assert_eq!(ruler.find_regex("www.example.com"), Some(&expected[0]));
+
+        // Let's add another one.
+        ruler.push_regex(&"^(api.)?example.org$".to_string());
+
+        let expected = vec![
+            "^(www.)?example.com$".to_string(),
+            "^(api.)?example.org$".to_string(),
+        ];
+
+        assert_eq!(ruler.regexes, expected);
+        assert_eq!(ruler.find_regex("www.example.com"), Some(&expected[0]));
+        assert_eq!(ruler.find_regex("api.example.org"), Some(&expected[1]));
+    }
+
+    #[test]
+    fn test_pull_regex() {
+        let mut ruler = Ruler::new(false);
+
+        // Ensure that it's really empty :)
+        assert!(ruler.regexes.is_empty());
+
+        // Add some data into it :)
+        ruler.push_regex(&"^(www.)?example.com$".to_string());
+        ruler.push_regex(&"^(api.)?example.org$".to_string());
+
+        assert!(ruler.find_regex("www.example.com").is_some());
+
+        ruler.pull_regex(&"^(www.)?example.com$".to_string());
+
+        let expected = vec!["^(api.)?example.org$".to_string()];
+
+        assert_eq!(ruler.regexes, expected);
+        assert_eq!(ruler.find_regex("www.example.com"), None);
+        assert_eq!(ruler.find_regex("api.example.org"), Some(&expected[0]));
+
+        // Let's remove another one.
+        ruler.pull_regex(&"^(api.)?example.org$".to_string());
+
+        assert!(ruler.regexes.is_empty());
+        assert_eq!(ruler.find_regex("api.example.org"), None);
+    }
+
+    #[test]
+    fn test_pull_regex_exact() {
+        let mut ruler = Ruler::new(false);
+
+        // Each rule is a substring of - or shares a prefix with - another one.
+        ruler.parse_vec(&[
+            "REG ^ads\\.".to_string(),
+            "REG ^ads\\.example\\.org$".to_string(),
+            "REG example".to_string(),
+            "REG ^ads\\.".to_string(),
+            "REG example|^cdn\\.".to_string(),
+        ]);
+
+        ruler.unparse(&"REG ^ads\\.".to_string());
+
+        assert_eq!(
+            ruler.regexes,
+            vec!["^ads\\.example\\.org$", "example", "example|^cdn\\."]
+        );
+        assert_eq!(ruler.is_whitelisted(&"ads.example.org".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"ads.test.org".to_string()), false);
+
+        ruler.unparse(&"REG example".to_string());
+
+        assert_eq!(
+            ruler.regexes,
+            vec!["^ads\\.example\\.org$", "example|^cdn\\."]
+        );
+        assert_eq!(ruler.is_whitelisted(&"cdn.test.org".to_string()), true);
+
+        // An unknown rule leaves the others untouched.
+        ruler.unparse(&"REG ^cdn\\.".to_string());
+
+        assert_eq!(ruler.regexes.len(), 2);
+        assert_eq!(ruler.is_whitelisted(&"cdn.test.org".to_string()), true);
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let mut ruler = Ruler::new(false);
+
+        let given = &"example.org".to_string();
+        let mut expected_res = false;
+
+        let mut expected_ends: HashMap<String, HashSet<String>> = HashMap::new();
+        let mut expected_strict: HashSet<String> = HashSet::new();
+        let expected_present: HashSet<String> = HashSet::new();
+        let expected_regex: Vec<String> = vec![];
+
+        assert_eq!(ruler.parse_all(given), expected_res);
+        assert_eq!(ruler.ends, expected_ends);
+        assert_eq!(ruler.strict, expected_strict);
+        assert_eq!(ruler.present, expected_present);
+        assert_eq!(ruler.regexes, expected_regex);
+
+        // Let's add a new one.
+        let given = &"ALL example.org".to_string();
+        expected_res = true;
+
+        let mut ends_set = HashSet::new();
+        ends_set.insert(".example.org".to_string());
+        expected_ends.insert("org".to_string(), ends_set);
+
+        expected_strict.insert("example.org".to_string());
+
+        assert_eq!(ruler.parse_all(given), expected_res);
+        assert_eq!(ruler.ends, expected_ends);
+        assert_eq!(ruler.strict, expected_strict);
+        assert_eq!(ruler.present, expected_present);
+        assert_eq!(ruler.regexes, expected_regex);
+
+        // Let's add another one but the marker is in lowercase.
+        let given = &"all .example.net".to_string();
+        expected_res = true;
+
+        let mut new_set = HashSet::new();
+        new_set.insert(".example.net".to_string());
+        expected_ends.insert("net".to_string(), new_set);
+
+        expected_strict.insert("example.net".to_string());
+
+        assert_eq!(ruler.parse_all(given), expected_res);
+        assert_eq!(ruler.ends, expected_ends);
+        assert_eq!(ruler.strict, expected_strict);
+        assert_eq!(ruler.present, expected_present);
+        assert_eq!(ruler.regexes, expected_regex);
+
+        // Let's add another one but this time with the complement generation.
+        ruler.settings.handle_complement = true;
+
+        let given = &"ALL .example.de".to_string();
+        expected_res = true;
+
+        let mut new_set = HashSet::new();
+        new_set.insert(".example.de".to_string());
+        expected_ends.insert("de".to_string(), new_set);
+
+        expected_strict.insert("example.de".to_string());
+        expected_strict.insert("www.example.de".to_string());
+
+        assert_eq!(ruler.parse_all(given), expected_res);
+        assert_eq!(ruler.ends, expected_ends);
+        assert_eq!(ruler.strict, expected_strict);
+        assert_eq!(ruler.present, expected_present);
+        assert_eq!(ruler.regexes, expected_regex);
+    }
+
+    #[test]
+    fn test_unparse_all() {
+        let mut ruler = Ruler::new(false);
+
+        let given = &"ALL example.com".to_string();
+        let mut expected_ends: HashMap<String, HashSet<String>> = HashMap::new();
+        let mut expected_strict: HashSet<String> = HashSet::new();
+        let expected_present: HashSet<String> = HashSet::new();
+        let expected_regex: Vec<String> = vec![];
+
+        // Fill ruler with some data
+        ruler.parse_all(&"ALL .hello.com".to_string());
+        ruler.parse_all(&"ALL .github.com".to_string());
+        ruler.parse_all(&"ALL .example.com".to_string());
+
+        let mut ends_set = HashSet::new();
+        ends_set.insert(".github.com".to_string());
+        ends_set.insert(".hello.com".to_string());
+        expected_ends.insert("com".to_string(), ends_set);
+
+        expected_strict.insert("hello.com".to_string());
+        expected_strict.insert("github.com".to_string());
+
+        assert_eq!(ruler.unparse_all(given), true);
+        assert_eq!(ruler.ends, expected_ends);
+        assert_eq!(ruler.strict, expected_strict);
+        assert_eq!(ruler.present, expected_present);
+        assert_eq!(ruler.regexes, expected_regex);
+
+        // Let's remove another one but this time with the complement generation.
+        ruler.settings.handle_complement = true;
+
+        ruler.parse_all(&"ALL .hello.com".to_string());
+
+        expected_strict.insert("www.hello.com".to_string());
+
+        let given = &"ALL .hello.world".to_string();
+
+        assert_eq!(ruler.strict, expected_strict);
+
+        assert_eq!(ruler.unparse_all(given), true);
+        assert_eq!(ruler.ends, expected_ends);
+        assert_eq!(ruler.strict, expected_strict);
+        assert_eq!(ruler.present, expected_present);
+        assert_eq!(ruler.regexes, expected_regex);
+    }
+
+    #[test]
+    fn test_parse_sub() {
+        let mut ruler = Ruler::new(false);
+
+        let given = &"example.org".to_string();
+        let mut expected_res = false;
+
+        let mut expected_ends: HashMap<String, HashSet<String>> = HashMap::new();
+        let expected_strict: HashSet<String> = HashSet::new();
+        let expected_present: HashSet<String> = HashSet::new();
+        let expected_regex: Vec<String> = vec![];
+
+        assert_eq!(ruler.parse_sub(given), expected_res);
+        assert_eq!(ruler.ends, expected_ends);
+        assert_eq!(ruler.strict, expected_strict);
+        assert_eq!(ruler.present, expected_present);
+        assert_eq!(ruler.regexes, expected_regex);
+
+        // Let's add a new one. The apex should never land into the strict dataset.
+        let given = &"SUB example.org".to_string();
+        expected_res = true;
+
+        let mut ends_set = HashSet::new();
+        ends_set.insert(".example.org".to_string());
+        expected_ends.insert("org".to_string(), ends_set);
+
+        assert_eq!(ruler.parse_sub(given), expected_res);
+        assert_eq!(ruler.ends, expected_ends);
+        assert_eq!(ruler.strict, expected_strict);
+        assert_eq!(ruler.present, expected_present);
+        assert_eq!(ruler.regexes, expected_regex);
+
+        // Let's add another one but the marker is in lowercase.
+        let given = &"sub .example.net".to_string();
+
+        let mut ends_set = HashSet::new();
+        ends_set.insert(".example.net".to_string());
+        expected_ends.insert("net".to_string(), ends_set);
+
+        assert_eq!(ruler.parse_sub(given), expected_res);
+        assert_eq!(ruler.ends, expected_ends);
+        assert_eq!(ruler.strict, expected_strict);
+
+        // Let's add another one but this time with the complement generation.
+        ruler.settings.handle_complement = true;
+
+        let given = &"SUB www.example.de".to_string();
+
+        let mut ends_set = HashSet::new();
+        ends_set.insert(".example.de".to_string());
+        expected_ends.insert("de".to_string(), ends_set);
+
+        assert_eq!(ruler.parse_sub(given), expected_res);
+        assert_eq!(ruler.ends, expected_ends);
+        assert_eq!(ruler.strict, expected_strict);
+    }
+
+    #[test]
+    fn test_unparse_sub() {
+        let mut ruler = Ruler::new(false);
+
+        ruler.parse_sub(&"SUB example.com".to_string());
+        ruler.parse_sub(&"SUB .github.com".to_string());
+
+        let mut expected_ends: HashMap<String, HashSet<String>> = HashMap::new();
+
+        let mut ends_set = HashSet::new();
+        ends_set.insert(".github.com".to_string());
+        expected_ends.insert("com".to_string(), ends_set);
+
+        assert_eq!(ruler.unparse_sub(&"SUB .example.com".to_string()), true);
+        assert_eq!(ruler.ends, expected_ends);
+
+        assert_eq!(ruler.unparse_sub(&"github.com".to_string()), false);
+        assert_eq!(ruler.ends, expected_ends);
+    }
+
+    #[test]
+    fn test_is_whitelisted_sub() {
+        let mut ruler = Ruler::new(false);
+
+        ruler.parse(&"SUB example.org".to_string());
+
+        assert_eq!(ruler.is_whitelisted(&"example.org".to_string()), false);
+        assert_eq!(ruler.is_whitelisted(&"api.example.org".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"a.b.example.org".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"myexample.org".to_string()), false);
+    }
+
+    #[test]
+    fn test_parse_apex() {
+        let mut ruler = Ruler::new(false);
+
+        let expected_ends: HashMap<String, HashSet<String>> = HashMap::new();
+        let mut expected_strict: HashSet<String> = HashSet::new();
+
+        assert_eq!(ruler.parse_apex(&"example.org".to_string()), false);
+        assert_eq!(ruler.strict, expected_strict);
+
+        // The complement should be generated even if the ruler doesn't handle them.
+        assert_eq!(ruler.parse_apex(&"APEX example.org".to_string()), true);
+
+        expected_strict.insert("example.org".to_string());
+        expected_strict.insert("www.example.org".to_string());
+
+        assert_eq!(ruler.ends, expected_ends);
+        assert_eq!(ruler.strict, expected_strict);
+
+        // Let's add another one but the marker is in lowercase.
+        assert_eq!(ruler.parse_apex(&"apex www.example.net".to_string()), true);
+
+        expected_strict.insert("example.net".to_string());
+        expected_strict.insert("www.example.net".to_string());
+
+        assert_eq!(ruler.ends, expected_ends);
+        assert_eq!(ruler.strict, expected_strict);
+    }
+
+    #[test]
+    fn test_unparse_apex() {
+        let mut ruler = Ruler::new(false);
+
+        ruler.parse_apex(&"APEX example.org".to_string());
+        ruler.parse_apex(&"APEX example.net".to_string());
+
+        let mut expected_strict: HashSet<String> = HashSet::new();
+
+        expected_strict.insert("example.net".to_string());
+        expected_strict.insert("www.example.net".to_string());
+
+        assert_eq!(
+            ruler.unparse_apex(&"APEX www.example.org".to_string()),
+            true
+        );
+        assert_eq!(ruler.strict, expected_strict);
+    }
+
+    #[test]
+    fn test_is_whitelisted_apex() {
+        let mut ruler = Ruler::new(false);
+
+        ruler.parse(&"APEX example.org".to_string());
+
+        assert_eq!(ruler.is_whitelisted(&"example.org".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"www.example.org".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"ads.example.org".to_string()), false);
+        assert_eq!(
+            ruler.is_whitelisted(&"www.ads.example.org".to_string()),
+            false
+        );
+    }
+
+    #[test]
+    fn test_with_complement_prefixes() {
+        let ruler = Ruler::new(true).with_complement_prefixes(&[
+            "www.".to_string(),
+            "m".to_string(),
+            " ".to_string(),
+        ]);
+
+        assert_eq!(
+            ruler.settings.complement_prefixes,
+            vec!["www.".to_string(), "m.".to_string()]
+        )
+    }
+
+    #[test]
+    fn test_reduce_custom_prefixes() {
+        let ruler =
+            Ruler::new(false).with_complement_prefixes(&["m.".to_string(), "mail.".to_string()]);
+
+        assert_eq!(
+            ruler.reduce(&"mail.example.org".to_string()),
+            "example.org".to_string()
+        );
+        assert_eq!(
+            ruler.reduce(&"m.example.org".to_string()),
+            "example.org".to_string()
+        );
+        assert_eq!(
+            ruler.reduce(&"www.example.org".to_string()),
+            "www.example.org".to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_plain_custom_prefixes() {
+        let mut ruler =
+            Ruler::new(true).with_complement_prefixes(&["www.".to_string(), "m.".to_string()]);
+
+        ruler.parse(&"m.example.org".to_string());
+
+        let mut strict_set = HashSet::new();
+        strict_set.insert("example.org".to_string());
+        strict_set.insert("www.example.org".to_string());
+        strict_set.insert("m.example.org".to_string());
+
+        assert_eq!(ruler.strict, strict_set);
+
+        ruler.unparse(&"www.example.org".to_string());
+
+        assert_eq!(ruler.strict, HashSet::new());
+    }
+
+    #[test]
+    fn test_reduce_chain() {
+        let ruler = Ruler::new(false).with_reductions(&[
+            Reduction::StripPrefixes,
+            Reduction::Lowercase,
+            Reduction::StripTrailingDot,
+            Reduction::StripPort,
+        ]);
+
+        assert_eq!(
+            ruler.reduce(&"www.Example.ORG.".to_string()),
+            "example.org".to_string()
+        );
+        assert_eq!(
+            ruler.reduce(&"example.org:8080".to_string()),
+            "example.org".to_string()
+        );
+        assert_eq!(ruler.reduce(&"[::1]:8080".to_string()), "[::1]".to_string());
+        assert_eq!(ruler.reduce(&"::1".to_string()), "::1".to_string());
+    }
+
+    #[test]
+    fn test_reduce_trim_strip_scheme() {
+        let ruler = Ruler::new(false).with_reductions(&[Reduction::Trim, Reduction::StripScheme]);
+
+        assert_eq!(
+            ruler.reduce(&" https://example.org ".to_string()),
+            "example.org".to_string()
+        );
+        assert_eq!(
+            ruler.reduce(&"example.org".to_string()),
+            "example.org".to_string()
+        );
+    }
+
+    #[test]
+    fn test_idna_disabled() {
+        let mut ruler = Ruler::new(false).with_idna(false);
+
+        ruler.parse(&"äxample.org".to_string());
+
+        assert_eq!(ruler.idna(), false);
+        assert_eq!(ruler.strict, HashSet::from(["äxample.org".to_string()]));
+        assert_eq!(ruler.empty_like().idna(), false);
+        assert_eq!(
+            ruler.prepare_line_with_cache(&"ÄXAMPLE.org".to_string(), &mut None),
+            "ÄXAMPLE.org".to_string()
+        );
+    }
+
+    #[test]
+    fn test_reduce_no_reduction() {
+        let ruler = Ruler::new(false).with_reductions(&[]);
+
+        assert_eq!(
+            ruler.reduce(&"www.example.org".to_string()),
+            "www.example.org".to_string()
+        )
+    }
+
+    #[test]
+    fn test_normalize() {
+        let ruler = Ruler::new(false).with_reductions(&[
+            Reduction::StripPrefixes,
+            Reduction::Lowercase,
+            Reduction::StripTrailingDot,
+        ]);
+
+        assert_eq!(
+            ruler.normalize(&"WWW.Example.org.".to_string()),
+            "www.example.org".to_string()
+        )
+    }
+
+    #[test]
+    fn test_is_whitelisted_url() {
+        let mut ruler = Ruler::new(false);
+
+        ruler.parse(&"example.org".to_string());
+        ruler.parse(&"REG /ads/".to_string());
+
+        assert_eq!(
+            ruler.is_whitelisted_url(&"http://example.org:80/ads/".to_string()),
+            Some(UrlMatch {
+                component: UrlComponent::Host,
+                kind: MatchKind::Strict,
+                rule: "example.org".to_string(),
+                origin: None,
+            })
+        );
+        assert_eq!(
+            ruler.is_whitelisted_url(&"https://example.com/ads/banner.png".to_string()),
+            Some(UrlMatch {
+                component: UrlComponent::Url,
+                kind: MatchKind::Regex,
+                rule: "/ads/".to_string(),
+                origin: None,
+            })
+        );
+        assert_eq!(
+            ruler.is_whitelisted_url(&"https://example.com/".to_string()),
+            None
+        );
+        assert_eq!(ruler.is_whitelisted_url(&"".to_string()), None);
+    }
+
+    #[test]
+    fn test_url_prefix() {
+        let mut ruler = Ruler::new(false);
+
+        ruler.parse(&"https://example.com/api/".to_string());
+        ruler.parse(&"http://example.net:8080/".to_string());
+
+        assert_eq!(ruler.strict.is_empty(), true);
+        assert_eq!(
+            ruler.is_whitelisted(&"https://example.com/api/v1".to_string()),
+            true
+        );
+        assert_eq!(
+            ruler.is_whitelisted(&"HTTPS://EXAMPLE.com/api/".to_string()),
+            true
+        );
+        assert_eq!(
+            ruler.is_whitelisted(&"https://example.com/apis".to_string()),
+            false
+        );
+        assert_eq!(
+            ruler.is_whitelisted(&"http://example.com/api/".to_string()),
+            false
+        );
+        assert_eq!(ruler.is_whitelisted(&"example.com".to_string()), false);
+        assert_eq!(
+            ruler.is_whitelisted(&"http://example.net:8080/x".to_string()),
+            true
+        );
+        assert_eq!(
+            ruler.is_whitelisted(&"http://example.net/x".to_string()),
+            false
+        );
+        assert_eq!(
+            ruler.is_whitelisted_url(&"https://example.com/api/v1".to_string()),
+            Some(UrlMatch {
+                component: UrlComponent::UrlPrefix,
+                kind: MatchKind::UrlPrefix,
+                rule: "https://example.com/api/".to_string(),
+                origin: None,
+            })
+        );
+
+        ruler.unparse(&"https://example.com/api/".to_string());
+
+        assert_eq!(
+            ruler.is_whitelisted(&"https://example.com/api/v1".to_string()),
+            false
+        );
+    }
+
+    #[test]
+    fn test_is_whitelisted_reductions() {
+        let mut ruler =
+            Ruler::new(false).with_reductions(&[Reduction::Lowercase, Reduction::StripPort]);
+
+        ruler.parse(&"Example.ORG".to_string());
+        ruler.parse(&"ALL .Example.NET".to_string());
+
+        assert_eq!(ruler.is_whitelisted(&"example.org".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"EXAMPLE.org:80".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"api.example.net".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"www.example.org".to_string()), false);
+    }
+
+    #[test]
+    fn test_is_whitelisted_ends_multiple_candidates() {
+        let mut ruler = Ruler::new(false);
+
+        // All of them share the same search key.
+        ruler.parse(&"ALL .hello.com".to_string());
+        ruler.parse(&"ALL .github.com".to_string());
+        ruler.parse(&"ALL .example.com".to_string());
+        ruler.parse(&"ALL .world.com".to_string());
 
-                    idnazed_line
-                } else {
-                    self.idnaze_subject(&data.to_string())
-                };
+        assert_eq!(ruler.ends.get("com").unwrap().len(), 4);
 
-                idnazed_data.push(idnazed);
-            }
+        assert_eq!(ruler.is_whitelisted(&"api.hello.com".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"api.github.com".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"api.example.com".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"api.world.com".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"api.unknown.com".to_string()), false);
+    }
 
-            if !comment.is_empty() {
-                return idnazed_data.join(separator) + "#" + comment;
-            }
+    #[test]
+    fn test_is_whitelisted_ends_after_pull() {
+        let mut ruler = Ruler::new(false);
 
-            return idnazed_data.join(separator);
-        }
+        ruler.parse(&"ALL .hello.com".to_string());
+        ruler.parse(&"ALL .github.com".to_string());
+        ruler.unparse(&"ALL .hello.com".to_string());
 
-        self.idnaze_subject(line)
+        assert_eq!(ruler.is_whitelisted(&"api.hello.com".to_string()), false);
+        assert_eq!(ruler.is_whitelisted(&"api.github.com".to_string()), true);
     }
 
-    /// Checks the given `line` against the rules.
-    ///
-    /// # Arguments
-    ///
-    /// * `line` - The line to check. **WARNING:** We assume 1 rule per line.
-    ///
-    ///   **Note:** If a URL (e.g `https://example.org/`) is given, the sub-domain
-    ///   will be used to determine if the line has been whitelisted.
-    ///
-    /// # Returns
-    ///
-    /// A `bool` indicating whether the line matches the rules.
-    /// Any `true` value should be considered positive.
-    /// Meaning that the line matches one of the rule.
-    pub fn is_whitelisted(&mut self, line: &String) -> bool {
-        if line.is_empty() || line.starts_with('#') {
-            return false;
-        }
+    #[test]
+    fn test_is_whitelisted_domain() {
+        let mut ruler = Ruler::new(true);
 
-        let fline = utils::extract_netloc(line);
+        ruler.parse(&"example.org".to_string());
+        ruler.parse(&"ALL .example.net".to_string());
+        ruler.parse(&"REG ^ads\\.example\\.com$".to_string());
 
-        let (common_skey, ends_skey) = self.search_keys(&self.reduce(&fline));
+        assert_eq!(
+            ruler.is_whitelisted_domain(&"example.org".to_string()),
+            true
+        );
+        assert_eq!(
+            ruler.is_whitelisted_domain(&"www.example.org".to_string()),
+            true
+        );
+        assert_eq!(
+            ruler.is_whitelisted_domain(&"api.example.net".to_string()),
+            true
+        );
+        assert_eq!(
+            ruler.is_whitelisted_domain(&"ads.example.com".to_string()),
+            true
+        );
+        assert_eq!(
+            ruler.is_whitelisted_domain(&"example.com".to_string()),
+            false
+        );
+        assert_eq!(ruler.is_whitelisted_domain(&"".to_string()), false);
 
-        let mut matching_state;
+        // No netloc extraction is done.
+        assert_eq!(
+            ruler.is_whitelisted_domain(&"https://example.org/".to_string()),
+            false
+        );
+        assert_eq!(
+            ruler.is_whitelisted(&"https://example.org/".to_string()),
+            true
+        );
+    }
 
-        match self.strict.entry(common_skey.to_string()) {
-            Entry::Occupied(entry) => matching_state = entry.get().contains(&fline),
-            Entry::Vacant(_) => matching_state = false,
-        }
+    #[test]
+    fn test_check() {
+        let mut ruler = Ruler::new(true);
+
+        ruler.parse(&"example.org".to_string());
+        ruler.parse(&"ALL .example.net".to_string());
+        ruler.parse(&"REG ^ads\\.".to_string());
+        ruler.parse(&"https://example.com/api/".to_string());
+
+        let check = |subject: &str| {
+            ruler
+                .check(&subject.to_string())
+                .map(|found| (found.kind, found.rule.to_string()))
+        };
 
-        if matching_state {
-            return true;
-        }
+        assert_eq!(
+            check("www.example.org"),
+            Some((MatchKind::Strict, "www.example.org".to_string()))
+        );
+        assert_eq!(
+            check("https://api.example.net/hello"),
+            Some((MatchKind::Ends, ".example.net".to_string()))
+        );
+        assert_eq!(
+            check("ads.example.de"),
+            Some((MatchKind::Regex, "^ads\\.".to_string()))
+        );
+        assert_eq!(
+            check("https://example.com/api/v1"),
+            Some((MatchKind::UrlPrefix, "https://example.com/api/".to_string()))
+        );
+        assert_eq!(check("example.com"), None);
+        assert_eq!(check("# example.org"), None);
+    }
 
-        match self.present.entry(common_skey) {
-            Entry::Occupied(entry) => matching_state = entry.get().contains(&fline),
-            Entry::Vacant(_) => matching_state = false,
-        }
+    #[test]
+    fn test_idnaze_subject_cache() {
+        let mut ruler = Ruler::new(false).with_idna_cache_capacity(2);
 
-        if matching_state {
-            return true;
-        }
+        ruler.idnaze_subject(&"www.äxample.org".to_string());
+        ruler.idnaze_subject(&"www.example.org".to_string());
 
-        match self.ends.entry(ends_skey) {
-            Entry::Occupied(entry) => {
-                let mut matching = entry.get().iter().map(|x| fline.ends_with(x)).peekable();
-                matching_state = *matching.peek().unwrap_or(&false);
-            }
-            Entry::Vacant(_) => matching_state = false,
-        }
+        let cache = ruler.caches.idna.as_ref().unwrap();
 
-        if matching_state {
-            return true;
-        }
+        assert_eq!(
+            cache.peek("www.äxample.org"),
+            Some(&"www.xn--xample-9ta.org".to_string())
+        );
+        assert_eq!(
+            cache.peek("www.example.org"),
+            Some(&"www.example.org".to_string())
+        );
+
+        // The least recently used one should be evicted.
+        ruler.idnaze_subject(&"www.äxample.net".to_string());
 
-        !self.regex.is_empty() && self.compiled_regex.is_match(&fline[..]).unwrap()
+        let cache = ruler.caches.idna.as_ref().unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.peek("www.äxample.org"), None);
+        assert_eq!(
+            ruler.idnaze_subject(&"www.äxample.net".to_string()),
+            "www.xn--xample-9ta.net".to_string()
+        );
     }
-}
 
-impl Drop for Ruler {
-    fn drop(&mut self) {
-        for file in &self.tmps.downloaded_files {
-            let _ = fs::remove_file(file);
-        }
+    #[test]
+    fn test_idnaze_subject_no_cache() {
+        let mut ruler = Ruler::new(false).with_idna_cache_capacity(0);
+
+        assert_eq!(
+            ruler.idnaze_subject(&"www.äxample.org".to_string()),
+            "www.xn--xample-9ta.org".to_string()
+        );
+        assert_eq!(ruler.caches.idna.is_none(), true);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_case_fold() {
+        assert_eq!(
+            Ruler::case_fold("ÄXAMPLE.ORG"),
+            "xn--xample-9ta.org".to_string()
+        );
+        assert_eq!(
+            Ruler::case_fold("WWW.Example.org"),
+            "www.example.org".to_string()
+        );
+        assert_eq!(
+            Ruler::case_fold("xn--xample-9ta.org"),
+            "xn--xample-9ta.org".to_string()
+        );
+    }
 
     #[test]
-    fn test_new_ruler_gen_complement_true() {
-        let ruler = Ruler::new(true);
+    fn test_is_whitelisted_case_fold() {
+        let mut ruler =
+            Ruler::new(false).with_reductions(&[Reduction::StripPrefixes, Reduction::CaseFold]);
 
-        assert_eq!(ruler.settings.handle_complement, true)
+        ruler.parse(&"äxample.org".to_string());
+        ruler.parse(&"ALL .bücher.de".to_string());
+
+        assert_eq!(ruler.is_whitelisted(&"ÄXAMPLE.ORG".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"Äxample.org".to_string()), true);
+        assert_eq!(
+            ruler.is_whitelisted(&"xn--xample-9ta.org".to_string()),
+            true
+        );
+        assert_eq!(ruler.is_whitelisted(&"shop.BÜCHER.de".to_string()), true);
+        assert_eq!(ruler.is_whitelisted(&"example.org".to_string()), false);
     }
 
     #[test]
-    fn test_new_ruler_gen_complement_false() {
-        let ruler = Ruler::new(false);
-
-        assert_eq!(ruler.settings.handle_complement, false)
+    #[cfg(feature = "parallel")]
+    fn test_is_whitelisted_many() {
+        let mut ruler = Ruler::new(true);
+
+        ruler.parse(&"example.org".to_string());
+        ruler.parse(&"ALL .example.net".to_string());
+
+        let subjects: Vec<String> = (0..1000)
+            .map(|index| match index % 4 {
+                0 => "www.example.org".to_string(),
+                1 => format!("api{}.example.net", index),
+                2 => format!("example{}.com", index),
+                _ => "# example.org".to_string(),
+            })
+            .collect();
+
+        let expected: Vec<bool> = (0..1000).map(|index| index % 4 < 2).collect();
+
+        assert_eq!(ruler.is_whitelisted_many(&subjects), expected);
+        assert_eq!(ruler.is_whitelisted_many::<String>(&[]), Vec::<bool>::new());
+
+        let whitelisted = ruler.par_filter_whitelisted(&subjects);
+
+        assert_eq!(whitelisted.len(), 500);
+        assert!(whitelisted
+            .iter()
+            .all(|subject| ruler.is_whitelisted(subject)));
+        assert_eq!(whitelisted[0], "www.example.org");
+        assert_eq!(whitelisted[1], "api1.example.net");
     }
 
     #[test]
-    fn test_reduce() {
-        let ruler = Ruler::new(false);
+    fn test_are_whitelisted() {
+        let mut ruler = Ruler::new(true);
+        let subjects: Vec<String> = (0..1000)
+            .map(|index| match index % 4 {
+                0 => "www.example.org".to_string(),
+                1 => format!("https://api{}.example.net/", index),
+                2 => format!("example{}.com", index),
+                _ => "# example.org".to_string(),
+            })
+            .collect();
+
+        assert_eq!(ruler.are_whitelisted(&subjects), vec![false; 1000]);
+
+        ruler.parse(&"example.org".to_string());
+        ruler.parse(&"ALL .example.net".to_string());
 
+        let expected: Vec<bool> = (0..1000).map(|index| index % 4 < 2).collect();
+
+        assert_eq!(ruler.are_whitelisted(&subjects), expected);
         assert_eq!(
-            ruler.reduce(&"www.example.org".to_string()),
-            "example.org".to_string()
-        )
+            ruler.are_whitelisted_iter(&subjects).filter(|x| *x).count(),
+            500
+        );
+        assert_eq!(ruler.are_whitelisted::<String>(&[]), Vec::<bool>::new());
     }
 
     #[test]
-    fn test_reduce_no_www() {
-        let ruler = Ruler::new(false);
+    fn test_parse_reader() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
 
-        assert_eq!(
-            ruler.reduce(&"example.org".to_string()),
-            "example.org".to_string()
-        )
+        let mut ruler = Ruler::new(false);
+
+        assert!(ruler
+            .parse_reader("example.org\n# A comment.\nREG ^ads\\.\n".as_bytes())
+            .is_ok());
+
+        // E.g. a compressed schema - without any temporary file.
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder
+            .write_all(b"ALL .example.net\r\nREG ^cdn\\.\n")
+            .unwrap();
+
+        let compressed = encoder.finish().unwrap();
+
+        assert!(ruler
+            .parse_reader(BufReader::new(flate2::read::GzDecoder::new(
+                compressed.as_slice()
+            )))
+            .is_ok());
+
+        assert_eq!(ruler.is_whitelisted("example.org"), true);
+        assert_eq!(ruler.is_whitelisted("api.example.net"), true);
+        assert_eq!(ruler.is_whitelisted("ads.example.com"), true);
+        assert_eq!(ruler.is_whitelisted("cdn.example.com"), true);
+        assert!(!ruler.defer_regex);
+
+        let error = ruler
+            .parse_reader("example.com\nREG ^(api\nexample.de\n".as_bytes())
+            .unwrap_err();
+
+        assert!(matches!(error, TivilstaError::Rule { line: 2, .. }));
+        assert_eq!(error.to_string().starts_with("<reader>:2: "), true);
+        assert_eq!(ruler.is_whitelisted("example.com"), true);
+        assert_eq!(ruler.is_whitelisted("example.de"), false);
+
+        assert!(matches!(
+            ruler.parse_reader(&[0xff, 0xfe, b'\n'][..]),
+            Err(TivilstaError::Io { .. })
+        ));
+
+        assert!(ruler
+            .unparse_reader("example.org\nREG ^ads\\.\n".as_bytes())
+            .is_ok());
+
+        assert_eq!(ruler.is_whitelisted("example.org"), false);
+        assert_eq!(ruler.is_whitelisted("ads.example.com"), false);
+        assert_eq!(ruler.is_whitelisted("cdn.example.com"), true);
     }
 
     #[test]
-    fn test_reduce_multiple_www() {
-        let ruler = Ruler::new(false);
+    fn test_parse_iter() {
+        let mut ruler = Ruler::new(true);
 
-        assert_eq!(
-            ruler.reduce(&"www.www.example.org".to_string()),
-            "www.example.org".to_string()
-        )
+        ruler.parse_iter((0..100).map(|index| format!("example{}.org", index)));
+        ruler.parse_iter(["ALL .example.net", "REG ^ads\\.", "REG ^cdn\\."]);
+
+        assert_eq!(ruler.is_whitelisted("www.example42.org"), true);
+        assert_eq!(ruler.is_whitelisted("example100.org"), false);
+        assert_eq!(ruler.is_whitelisted("api.example.net"), true);
+        assert_eq!(ruler.is_whitelisted("cdn.example.com"), true);
+        assert_eq!(ruler.regexes.len(), 2);
+        assert!(!ruler.defer_regex);
+
+        ruler.unparse_iter(vec![
+            String::from("REG ^ads\\."),
+            String::from("example1.org"),
+        ]);
+
+        assert_eq!(ruler.is_whitelisted("ads.example.com"), false);
+        assert_eq!(ruler.is_whitelisted("example1.org"), false);
+        assert_eq!(ruler.is_whitelisted("example2.org"), true);
+
+        // An invalid rule is ignored - the following ones are kept.
+        ruler.parse_iter(["REG ^(bad", "REG ^api\\."]);
+
+        assert_eq!(ruler.is_whitelisted("api.example.com"), true);
+        assert_eq!(ruler.regexes.len(), 2);
     }
 
     #[test]
-    fn test_search_keys() {
+    fn test_str_arguments() {
         let mut ruler = Ruler::new(false);
 
+        ruler.parse_vec(&["example.org", "ALL .example.net"]);
+        ruler.parse("REG ^ads\\.");
+
+        assert_eq!(ruler.is_whitelisted("example.org"), true);
+        assert_eq!(ruler.is_whitelisted_domain("api.example.net"), true);
+        assert_eq!(ruler.check("ads.example.com").unwrap().rule, "^ads\\.");
         assert_eq!(
-            ruler.search_keys(&"example.org".to_string()),
-            ("exam".to_string(), "org".to_string())
-        )
+            ruler.are_whitelisted(&["example.org", "example.com"]),
+            vec![true, false]
+        );
+        assert_eq!(
+            ruler
+                .are_whitelisted_iter(["example.org", "example.com"])
+                .collect::<Vec<_>>(),
+            vec![true, false]
+        );
+
+        ruler.unparse_vec(&["example.org"]);
+
+        assert_eq!(ruler.is_whitelisted("example.org"), false);
     }
 
     #[test]
-    fn test_search_keys_long_extension() {
+    fn test_settings_getters_setters() {
         let mut ruler = Ruler::new(false);
 
+        assert_eq!(ruler.handle_complement(), false);
+        assert_eq!(ruler.complement_prefixes(), &["www.".to_string()]);
+        assert_eq!(ruler.reductions(), &[Reduction::StripPrefixes]);
+        assert_eq!(ruler.idna_cache_capacity(), DEFAULT_IDNA_CACHE_CAPACITY);
+
+        ruler.set_handle_complement(true);
+        ruler.set_complement_prefixes(&["m".to_string()]);
+        ruler.set_reductions(&[Reduction::Lowercase]);
+        ruler.set_idna_cache_capacity(0);
+
+        assert_eq!(ruler.handle_complement(), true);
+        assert_eq!(ruler.complement_prefixes(), &["m.".to_string()]);
+        assert_eq!(ruler.reductions(), &[Reduction::Lowercase]);
+        assert_eq!(ruler.idna_cache_capacity(), 0);
+    }
+
+    #[test]
+    fn test_ends_every_candidate() {
+        // Most of the rules share the same search key.
+        let mut rules: Vec<String> = (0..64).map(|x| format!("ALL .rule{}.org", x)).collect();
+
+        rules.push(String::from("ALL .example.org"));
+        rules.push(String::from("ALL .xorg"));
+        rules.push(String::from("ALL .io"));
+        rules.push(String::from("ALL .a"));
+
+        let subjects = vec![
+            String::from("api.example.org"),
+            String::from("api.rule42.org"),
+            String::from("api.xorg"),
+            String::from("api.other.org"),
+            String::from("www.io"),
+            String::from("example.io"),
+            String::from("example.a"),
+            String::from("example.ba"),
+        ];
+
+        for strategy in [EndsStrategy::Buckets, EndsStrategy::Automaton] {
+            let mut ruler = Ruler::new(true).with_ends_strategy(strategy);
+
+            ruler.parse_vec(&rules);
+
+            assert_eq!(
+                ruler.are_whitelisted(&subjects),
+                vec![true, true, true, false, true, true, true, false],
+                "{:?}",
+                strategy
+            );
+        }
+    }
+
+    #[test]
+    fn test_ends_strategy_automaton() {
+        let rules = vec![
+            String::from("ALL .example.org"),
+            String::from("ALL .gov.uk"),
+            String::from("SUB a.b.example.net"),
+            String::from("ALL ample.com"),
+        ];
+        let subjects = vec![
+            String::from("example.org"),
+            String::from("api.example.org"),
+            String::from("apiexample.org"),
+            String::from("www.gov.uk"),
+            String::from("a.b.example.net"),
+            String::from("c.a.b.example.net"),
+            String::from("www.ample.com"),
+        ];
+
+        let mut buckets = Ruler::new(false);
+        let mut automaton = Ruler::new(false).with_ends_strategy(EndsStrategy::Automaton);
+
+        buckets.parse_vec(&rules);
+        automaton.parse_vec(&rules);
+
         assert_eq!(
-            ruler.search_keys(&"example.example".to_string()),
-            ("exam".to_string(), "ple".to_string())
-        )
+            automaton.are_whitelisted(&subjects),
+            buckets.are_whitelisted(&subjects)
+        );
+        assert_eq!(
+            automaton
+                .check(&String::from("api.example.org"))
+                .unwrap()
+                .rule,
+            ".example.org"
+        );
+
+        // The automaton follows the changes of the rules.
+        automaton.unparse(&String::from("ALL .example.org"));
+        automaton.parse(&String::from("ALL .example.de"));
+
+        assert_eq!(
+            automaton.is_whitelisted(&String::from("api.example.org")),
+            false
+        );
+        assert_eq!(
+            automaton.is_whitelisted(&String::from("api.example.de")),
+            true
+        );
     }
 
     #[test]
-    fn test_idnaze_subject() {
+    fn test_try_parse() {
         let mut ruler = Ruler::new(false);
 
+        assert!(ruler.try_parse(&String::from("example.org")).is_ok());
+        assert!(ruler.try_parse(&String::from("REG ^api\\.")).is_ok());
+        assert!(ruler.try_parse(&String::from("# REG ^(api")).is_ok());
+        assert!(ruler.try_parse(&String::from("REG ^(api")).is_err());
+
+        assert_eq!(ruler.is_whitelisted(&String::from("example.org")), true);
+        assert_eq!(ruler.is_whitelisted(&String::from("api.example.net")), true);
+        assert_eq!(ruler.regexes, vec!["^api\\."]);
+    }
+
+    #[test]
+    fn test_bulk_parse() {
+        let mut ruler = Ruler::new(true);
+
+        assert!(ruler
+            .bulk_parse(
+                "example.org\n\n# A comment.\n^ads\\.\n^api\\.\n",
+                "REG ",
+                READER_ORIGIN
+            )
+            .is_ok());
+        assert!(ruler
+            .bulk_parse("example.net\n.example.com\n", "", READER_ORIGIN)
+            .is_ok());
+
+        assert_eq!(ruler.regexes, vec!["example.org", "^ads\\.", "^api\\."]);
         assert_eq!(
-            ruler.idnaze_subject(&"www.äxample.org".to_string()),
-            "www.xn--xample-9ta.org".to_string()
+            ruler.is_whitelisted(&String::from("api.example.info")),
+            true
         );
+        assert_eq!(ruler.is_whitelisted(&String::from("www.example.net")), true);
+        assert_eq!(ruler.is_whitelisted(&String::from("example.info")), false);
+
+        let error = ruler.bulk_parse("^ads\\.\n^(api\n^cdn\\.\n", "REG ", READER_ORIGIN);
+
+        assert!(matches!(
+            error,
+            Err(TivilstaError::Rule { line: 2, source, .. })
+                if matches!(*source, TivilstaError::Regex { .. })
+        ));
+        assert!(!ruler.defer_regex);
+    }
 
+    #[test]
+    fn test_stream_parse() {
+        let mut ruler = Ruler::new(true);
+
+        assert!(ruler
+            .stream_parse(
+                "example.org\r\n\n# A comment.\n^ads\\.\n^api\\.".as_bytes(),
+                "REG ",
+                READER_ORIGIN,
+            )
+            .is_ok());
+
+        assert_eq!(ruler.regexes, vec!["example.org", "^ads\\.", "^api\\."]);
         assert_eq!(
-            ruler.idnaze_subject(&"www.example.org".to_string()),
-            "www.example.org".to_string()
+            ruler.is_whitelisted(&String::from("api.example.info")),
+            true
         );
+
+        let error = ruler.stream_parse("^ads\\.\n^(api\n".as_bytes(), "REG ", READER_ORIGIN);
+
+        assert!(matches!(
+            error,
+            Err(TivilstaError::Rule { line: 2, source, .. })
+                if matches!(*source, TivilstaError::Regex { .. })
+        ));
+        assert!(!ruler.defer_regex);
     }
 
     #[test]
-    fn test_idnaze_line() {
+    fn test_merge() {
         let mut ruler = Ruler::new(false);
+        let mut other = Ruler::new(false);
+
+        ruler.parse(&"example.org".to_string());
+        ruler.parse(&"ALL .example.com".to_string());
+        ruler.parse(&"REG ^ads\\.example\\.de$".to_string());
+
+        other.parse(&"example.net".to_string());
+        other.parse(&"ALL .github.com".to_string());
+        other.parse(&"REG ^ads\\.example\\.fr$".to_string());
+        other.parse(&"REG ^ads\\.example\\.de$".to_string());
+        other.tmps.downloaded_files.push("/tmp/hello".to_string());
+
+        ruler.merge(other);
+
+        let mut expected_strict = HashSet::new();
+        expected_strict.insert("example.org".to_string());
+        expected_strict.insert("example.com".to_string());
+        expected_strict.insert("example.net".to_string());
+        expected_strict.insert("github.com".to_string());
+
+        let mut expected_ends = HashSet::new();
+        expected_ends.insert(".example.com".to_string());
+        expected_ends.insert(".github.com".to_string());
 
+        assert_eq!(ruler.strict, expected_strict);
+        assert_eq!(ruler.ends.get("com"), Some(&expected_ends));
         assert_eq!(
-            ruler.idnaze_line(&"www.äxample.org".to_string()),
-            "www.xn--xample-9ta.org".to_string()
+            ruler.regexes,
+            vec!["^ads\\.example\\.de$", "^ads\\.example\\.fr$"]
         );
+        assert_eq!(ruler.tmps.downloaded_files, vec!["/tmp/hello".to_string()]);
+
+        assert_eq!(ruler.is_whitelisted(&"ads.example.fr".to_string()), true);
+
+        // We don't want to remove anything that is not ours.
+        ruler.tmps.downloaded_files.clear();
+    }
+
+    #[test]
+    fn test_origins() {
+        let mut ruler = Ruler::new(true);
 
+        ruler
+            .parse_reader("# A comment.\nexample.org\nALL .example.net\nREG ^ads\\.\n".as_bytes())
+            .unwrap();
+        ruler.parse("example.com");
+        ruler.parse("example.org");
+
+        let found = ruler.check("www.example.org").unwrap();
+
+        assert_eq!(found.origin.map(RuleOrigin::path), Some(READER_ORIGIN));
+        assert_eq!(found.origin.map(RuleOrigin::line), Some(2));
+
+        let found = ruler.check("api.example.net").unwrap();
+
+        assert_eq!(found.origin.unwrap().to_string(), "<reader>:3");
         assert_eq!(
-            ruler.idnaze_line(&"www.example.org".to_string()),
-            "www.example.org".to_string()
+            ruler
+                .check("ads.example.info")
+                .unwrap()
+                .origin
+                .unwrap()
+                .line(),
+            4
+        );
+        assert_eq!(ruler.check("example.com").unwrap().origin, None);
+        assert_eq!(
+            ruler
+                .is_whitelisted_url("https://api.example.net/")
+                .unwrap()
+                .origin
+                .map(|origin| origin.line()),
+            Some(3)
         );
 
+        ruler.unparse("example.org");
+        ruler.parse("example.org");
+
+        assert_eq!(ruler.check("example.org").unwrap().origin, None);
+
+        ruler
+            .try_parse_with_origin("ALL .example.info", RuleOrigin::new("command line", 2))
+            .unwrap();
+        ruler.parse("example.biz");
+
+        let found = ruler.check("www.example.info").unwrap();
+
+        assert_eq!(found.origin.unwrap().to_string(), "command line:2");
+        assert_eq!(ruler.check("example.biz").unwrap().origin, None);
+
+        let mut other = Ruler::new(true);
+
+        other.parse_reader("example.com\n".as_bytes()).unwrap();
+        ruler.merge(other);
+
         assert_eq!(
-            ruler.idnaze_line(&"www.example.org # example.org".to_string()),
-            "www.example.org # example.org".to_string()
+            ruler
+                .check("www.example.com")
+                .unwrap()
+                .origin
+                .unwrap()
+                .line(),
+            1
         );
 
+        let frozen = ruler.freeze();
+
         assert_eq!(
-            ruler.idnaze_line(&"www.example.org # äxample.org".to_string()),
-            "www.example.org # äxample.org".to_string()
+            frozen
+                .check("api.example.net")
+                .unwrap()
+                .origin
+                .unwrap()
+                .line(),
+            3
         );
+    }
+
+    #[test]
+    fn test_unused_rules() {
+        let mut ruler = Ruler::new(true);
+
+        ruler
+            .parse_reader("example.org\nALL .example.net\nREG ^ads\\.\n".as_bytes())
+            .unwrap();
+        ruler.parse("https://example.com/api/");
+
+        assert!(!ruler.track_usage());
+        assert!(ruler.is_whitelisted("api.example.net"));
+        assert!(ruler.unused_rules().is_empty());
+
+        ruler.set_track_usage(true);
+
+        assert!(ruler.is_whitelisted("api.example.net"));
+        assert!(ruler.is_whitelisted_domain("www.example.org"));
+        assert!(ruler.check("https://example.com/api/v1").is_some());
+        assert!(!ruler.is_whitelisted("example.info"));
+
+        let unused = ruler.unused_rules();
 
         assert_eq!(
-            ruler.idnaze_line(&"www.example.org # äxample.org # example.org".to_string()),
-            "www.example.org # äxample.org # example.org".to_string()
+            unused
+                .iter()
+                .map(|found| (found.kind, found.rule))
+                .collect::<Vec<_>>(),
+            vec![
+                (MatchKind::Strict, "example.net"),
+                (MatchKind::Strict, "example.org"),
+                (MatchKind::Strict, "www.example.net"),
+                (MatchKind::Regex, "^ads\\."),
+            ]
         );
+        assert_eq!(unused[0].origin.map(RuleOrigin::line), Some(2));
+        assert_eq!(unused[1].origin.map(RuleOrigin::line), Some(1));
+
+        assert!(ruler
+            .is_whitelisted_url("https://ads.example.info/")
+            .is_some());
+
+        assert_eq!(ruler.unused_rules().len(), 3);
+
+        ruler.set_track_usage(false);
+
+        assert!(ruler.unused_rules().is_empty());
+    }
+
+    #[test]
+    fn test_hit_counts() {
+        let mut ruler = Ruler::new(false);
+
+        ruler.parse_vec(&["example.org", "SUB example.net", "REG ^ads\\."]);
+
+        assert!(ruler.is_whitelisted("example.org"));
+        assert!(ruler.hit_counts().is_empty());
+
+        ruler.set_track_usage(true);
+
+        for subject in [
+            "api.example.net",
+            "cdn.example.net",
+            "ads.example.net",
+            "ads.example.info",
+            "example.org",
+            "example.com",
+        ] {
+            ruler.is_whitelisted(subject);
+        }
 
         assert_eq!(
-            ruler.idnaze_line(&"www.example.org       # äxample.org # example.org".to_string()),
-            "www.example.org       # äxample.org # example.org".to_string()
+            ruler
+                .hit_counts()
+                .iter()
+                .map(|(found, hits)| (found.kind, found.rule, *hits))
+                .collect::<Vec<_>>(),
+            vec![
+                (MatchKind::Ends, ".example.net", 3),
+                (MatchKind::Strict, "example.org", 1),
+                (MatchKind::Regex, "^ads\\.", 1),
+            ]
         );
 
+        // The records that are gone are not reported anymore.
+        ruler.unparse("SUB example.net");
+
+        assert_eq!(ruler.hit_counts().len(), 2);
+
+        ruler.set_track_usage(true);
+
+        assert!(ruler.hit_counts().is_empty());
+    }
+
+    #[test]
+    fn test_exception() {
+        let mut ruler = Ruler::new(true);
+
+        ruler.parse_vec(&[
+            "ALL .example.com",
+            "EXC ads.example.com",
+            "not tracker.example.com",
+            "example.org",
+            "EXC example.org",
+            "REG ^cdn\\.",
+            "NOT cdn.example.net",
+            "https://api.example.info/v1/",
+            "EXC api.example.info",
+        ]);
+
+        assert!(ruler.is_whitelisted("example.com"));
+        assert!(ruler.is_whitelisted("api.example.com"));
+        assert!(!ruler.is_whitelisted("ads.example.com"));
+        assert!(!ruler.is_whitelisted("www.ads.example.com"));
+        assert!(ruler.is_whitelisted("cdn.ads.example.com"));
+        assert!(!ruler.is_whitelisted_domain("tracker.example.com"));
+        assert!(!ruler.is_whitelisted("example.org"));
+        assert!(!ruler.is_whitelisted("www.example.org"));
+        assert!(ruler.is_whitelisted("cdn.example.org"));
+        assert!(!ruler.is_whitelisted("cdn.example.net"));
+        assert!(!ruler.is_whitelisted("https://api.example.info/v1/users"));
+        assert!(ruler
+            .is_whitelisted_url("https://ads.example.com/banner.png")
+            .is_none());
+        assert_eq!(ruler.check("ads.example.com"), None);
+        assert!(ruler.contains_rule("EXC ads.example.com"));
+        assert!(ruler.contains_rule("NOT www.example.org"));
+        assert_eq!(ruler.counts().exceptions, 10);
+
+        ruler.unparse("EXC ads.example.com");
+
+        assert!(ruler.is_whitelisted("ads.example.com"));
+        assert!(!ruler.contains_rule("EXC ads.example.com"));
+
+        let frozen = ruler.freeze();
+
+        assert!(frozen.is_whitelisted("ads.example.com"));
+        assert!(!frozen.is_whitelisted("tracker.example.com"));
+        assert!(!frozen.is_whitelisted_domain("www.example.org"));
+        assert!(!frozen.is_whitelisted("https://api.example.info/v1/users"));
+    }
+
+    #[test]
+    fn test_wildcard() {
         assert_eq!(
-            ruler.idnaze_line(&"www.äxample.org # äxample.org # example.org #".to_string()),
-            "www.xn--xample-9ta.org # äxample.org # example.org #".to_string()
+            Ruler::translate_wildcard("*.example.org"),
+            Some(String::from("SUB example.org"))
         );
+        assert_eq!(
+            Ruler::translate_wildcard("*.cdn.*.net"),
+            Some(String::from("REG ^.*\\.cdn\\..*\\.net$"))
+        );
+        assert_eq!(
+            Ruler::translate_wildcard("ads*.example.org"),
+            Some(String::from("REG ^ads.*\\.example\\.org$"))
+        );
+        assert_eq!(Ruler::translate_wildcard("example.org"), None);
+
+        let mut ruler = Ruler::new(false);
+
+        ruler.parse_vec(&["*.example.org", "*.cdn.*.net", "*.exämple.com"]);
+
+        assert!(ruler.is_whitelisted("api.example.org"));
+        assert!(ruler.is_whitelisted("a.b.example.org"));
+        assert!(!ruler.is_whitelisted("example.org"));
+        assert!(ruler.is_whitelisted("img.cdn.example.net"));
+        assert!(!ruler.is_whitelisted("img.cdn.net"));
+        assert!(!ruler.is_whitelisted("img.cdnxexample.net"));
+        assert!(ruler.is_whitelisted("www.xn--exmple-cua.com"));
+        assert!(ruler.contains_rule("*.example.org"));
+        assert!(ruler.contains_rule("SUB example.org"));
+
+        ruler.unparse_vec(&["*.example.org", "*.cdn.*.net"]);
+
+        assert!(!ruler.is_whitelisted("api.example.org"));
+        assert!(!ruler.is_whitelisted("img.cdn.example.net"));
+        assert!(ruler.regexes.is_empty());
     }
 
     #[test]
-    fn test_push_strict() {
-        let mut ruler = Ruler::new(false);
+    fn test_cidr() {
+        let mut ruler = Ruler::new(true);
 
-        // Ensure that it's really empty :)
-        assert_eq!(ruler.strict.get_key_value("exam"), None);
+        ruler.parse_vec(&[
+            "CIDR 10.0.0.0/8",
+            "cidr 10.1.2.3/16",
+            "CIDR 192.168.1.1",
+            "CIDR 2001:DB8::/32",
+        ]);
 
-        ruler.push_strict(&"www.example.org".to_string());
+        assert_eq!(ruler.counts().cidrs, 4);
+        assert!(ruler.is_whitelisted("10.20.30.40"));
+        assert!(ruler.is_whitelisted("http://10.20.30.40/ads"));
+        assert!(ruler.is_whitelisted("192.168.1.1"));
+        assert!(!ruler.is_whitelisted("192.168.1.2"));
+        assert!(!ruler.is_whitelisted("11.0.0.1"));
+        assert!(ruler.is_whitelisted("2001:db8::1"));
+        assert!(ruler.is_whitelisted("http://[2001:db8::1]/ads"));
+        assert!(!ruler.is_whitelisted("2001:db9::1"));
+        assert!(!ruler.is_whitelisted("example.org"));
 
-        let mut expected = HashSet::new();
-        expected.insert("www.example.org".to_string());
+        // The most specific network is reported.
+        let found = ruler.check("10.1.0.1").unwrap();
 
-        assert_eq!(
-            ruler.strict.get_key_value("exam"),
-            Some((&"exam".to_string(), &expected))
-        );
+        assert_eq!(found.kind, MatchKind::Cidr);
+        assert_eq!(found.rule, "10.1.0.0/16");
+        assert_eq!(ruler.check("10.2.0.1").unwrap().rule, "10.0.0.0/8");
 
-        // Let's add another one.
+        assert!(ruler.contains_rule("CIDR 10.1.0.0/16"));
+        assert!(!ruler.contains_rule("CIDR 10.1.0.0/24"));
 
-        ruler.push_strict(&"example.net".to_string());
-        expected.insert("example.net".to_string());
+        assert!(matches!(
+            ruler.try_parse("CIDR 10.0.0.0/33"),
+            Err(TivilstaError::Cidr { .. })
+        ));
+        assert!(matches!(
+            ruler.try_parse("CIDR example.org"),
+            Err(TivilstaError::Cidr { .. })
+        ));
 
-        assert_eq!(
-            ruler.strict.get_key_value("exam"),
-            Some((&"exam".to_string(), &expected))
-        );
-    }
+        ruler.parse("EXC 10.9.9.9");
 
-    #[test]
-    fn test_pull_strict() {
-        let mut ruler = Ruler::new(false);
+        assert!(!ruler.is_whitelisted("10.9.9.9"));
 
-        // Ensure that it's really empty :)
-        assert_eq!(ruler.strict.get_key_value("exam"), None);
+        ruler.unparse_vec(&["CIDR 10.1.0.0/16", "CIDR 10.0.0.0/8"]);
 
-        // Add some data into it :)
-        ruler.push_strict(&"www.example.org".to_string());
-        ruler.push_strict(&"example.net".to_string());
+        assert!(!ruler.is_whitelisted("10.20.30.40"));
+        assert!(ruler.is_whitelisted("192.168.1.1"));
 
-        ruler.pull_strict(&"www.example.org".to_string());
+        let frozen = ruler.duplicate().freeze();
 
-        let mut expected = HashSet::new();
-        expected.insert("example.net".to_string());
+        assert!(frozen.is_whitelisted("192.168.1.1"));
+        assert!(frozen.is_whitelisted("2001:db8::abcd"));
+        assert!(!frozen.is_whitelisted("10.20.30.40"));
 
-        assert_eq!(
-            ruler.strict.get_key_value("exam"),
-            Some((&"exam".to_string(), &expected))
-        );
+        ruler.clear();
 
-        // Let's remove another one.
-        ruler.pull_strict(&"example.net".to_string());
-        expected.remove("example.net");
+        assert!(!ruler.is_whitelisted("192.168.1.1"));
+    }
+
+    #[test]
+    fn test_expiry() {
+        let tomorrow = ExpiryDate::today().add_days(1);
+        let mut ruler = Ruler::new(true);
+
+        ruler.parse_vec(&[
+            format!("example.org @expires={}", tomorrow),
+            String::from("ALL .example.net\t@expires=2999-12-31"),
+            String::from("example.com @expires=2000-01-01"),
+            String::from("example.info @expires=tomorrow"),
+            String::from("example.de"),
+        ]);
+
+        assert!(ruler.is_whitelisted("www.example.org"));
+        assert!(ruler.is_whitelisted("api.example.net"));
+        assert!(!ruler.is_whitelisted("example.com"));
+        assert!(ruler.is_whitelisted("example.info"));
+        assert!(ruler.is_whitelisted("example.de"));
+
+        assert!(matches!(
+            ruler.try_parse("example.info @expires=2025-02-30"),
+            Err(TivilstaError::Expiry { .. })
+        ));
+        assert!(ruler.try_parse("example.com @expires=2000-01-01").is_ok());
+        assert!(!ruler.is_whitelisted("example.com"));
+
+        let expiring: Vec<(&str, ExpiryDate)> = ruler
+            .expiring_rules(2)
+            .into_iter()
+            .map(|(found, expiry)| (found.rule, expiry))
+            .collect();
 
         assert_eq!(
-            ruler.strict.get_key_value("exam"),
-            Some((&"exam".to_string(), &expected))
+            expiring,
+            vec![("example.org", tomorrow), ("www.example.org", tomorrow)]
         );
-    }
+        assert_eq!(ruler.expiring_rules(365_000).len(), 5);
 
-    #[test]
-    fn test_push_present() {
-        let mut ruler = Ruler::new(false);
+        // A rule given again without expiry keeps its records forever.
+        ruler.parse("example.org");
 
-        // Ensure that it's really empty :)
-        assert_eq!(ruler.present.get_key_value("exam"), None);
+        assert_eq!(ruler.expiring_rules(2), vec![]);
 
-        ruler.push_present(&"www.example.net".to_string());
+        ruler.unparse("ALL .example.net @expires=2999-12-31");
 
-        let mut expected = HashSet::new();
-        expected.insert("www.example.net".to_string());
+        assert!(!ruler.is_whitelisted("api.example.net"));
+        assert_eq!(ruler.expiring_rules(365_000), vec![]);
+
+        // A record stays permanent as long as one of its rules is - or
+        // expires with the last of its rules.
+        ruler.parse_vec(&[
+            "example.be",
+            "example.be @expires=2999-12-31",
+            "example.nl @expires=2999-12-31",
+            "example.nl @expires=2998-12-31",
+        ]);
+
+        let expiring: Vec<(&str, String)> = ruler
+            .expiring_rules(365_000)
+            .into_iter()
+            .map(|(found, expiry)| (found.rule, expiry.to_string()))
+            .collect();
 
         assert_eq!(
-            ruler.present.get_key_value("exam"),
-            Some((&"exam".to_string(), &expected))
+            expiring,
+            vec![
+                ("example.nl", String::from("2999-12-31")),
+                ("www.example.nl", String::from("2999-12-31"))
+            ]
         );
 
-        // Let's add another one.
+        let mut other = Ruler::new(true);
 
-        ruler.push_present(&"example.com".to_string());
-        expected.insert("example.com".to_string());
+        other.parse_vec(&["example.be @expires=2999-12-31", "example.nl"]);
+        ruler.merge(other);
 
-        assert_eq!(
-            ruler.present.get_key_value("exam"),
-            Some((&"exam".to_string(), &expected))
+        assert_eq!(ruler.expiring_rules(365_000), vec![]);
+        ruler.unparse_vec(&["example.be", "example.nl"]);
+
+        ruler.parse("example.fr @expires=2999-12-31");
+        ruler.expiries.get_mut(&MatchKind::Strict).unwrap().insert(
+            String::from("example.fr"),
+            ExpiryDate::new(2000, 1, 1).unwrap(),
         );
-    }
 
-    #[test]
-    fn test_pull_present() {
-        let mut ruler = Ruler::new(false);
+        // The lookups ignore the records that expired since being parsed.
+        assert!(!ruler.is_whitelisted("example.fr"));
+        assert!(ruler.is_whitelisted("www.example.fr"));
+        assert!(ruler.check("example.fr").is_none());
+        assert_eq!(ruler.remove_expired(), 1);
+        assert!(!ruler.is_whitelisted("example.fr"));
+        assert!(ruler.is_whitelisted("www.example.fr"));
+
+        ruler.parse("example.eu @expires=2999-12-31");
+        ruler.expiries.get_mut(&MatchKind::Strict).unwrap().insert(
+            String::from("example.eu"),
+            ExpiryDate::new(2000, 1, 1).unwrap(),
+        );
 
-        // Ensure that it's really empty :)
-        assert_eq!(ruler.present.get_key_value("exam"), None);
+        let frozen = ruler.freeze();
 
-        // Add some data into it :)
-        ruler.push_present(&"www.example.net".to_string());
-        ruler.push_present(&"example.org".to_string());
+        assert!(!frozen.is_whitelisted("example.eu"));
+        assert!(frozen.is_whitelisted("www.example.eu"));
 
-        ruler.pull_present(&"www.example.net".to_string());
+        // Every kind of record stops matching once its date is over.
+        let mut ruler = Ruler::new(false).with_ends_strategy(EndsStrategy::Automaton);
 
-        let mut expected = HashSet::new();
-        expected.insert("example.org".to_string());
+        ruler.parse_vec(&[
+            "ALL .example.net @expires=2999-12-31",
+            "ALL .net",
+            "KEY tracker @expires=2999-12-31",
+            "REG ^ads\\. @expires=2999-12-31",
+            "CIDR 10.0.0.0/8 @expires=2999-12-31",
+            "START mail. @expires=2999-12-31",
+            "EXC api.example.org @expires=2999-12-31",
+            "ALL .example.org",
+            "https://example.de/api/ @expires=2999-12-31",
+        ]);
 
-        assert_eq!(
-            ruler.present.get_key_value("exam"),
-            Some((&"exam".to_string(), &expected))
-        );
+        assert!(!ruler.is_whitelisted("api.example.org"));
 
-        // Let's remove another one.
-        ruler.pull_present(&"example.org".to_string());
-        expected.remove("example.org");
+        for records in ruler.expiries.values_mut() {
+            for expiry in records.values_mut() {
+                *expiry = ExpiryDate::new(2000, 1, 1).unwrap();
+            }
+        }
 
         assert_eq!(
-            ruler.present.get_key_value("exam"),
-            Some((&"exam".to_string(), &expected))
+            ruler.check("api.example.net").map(|found| found.rule),
+            Some(".net")
         );
+        assert!(ruler.is_whitelisted("api.example.org"));
+
+        for subject in [
+            "tracker.example.fr",
+            "ads.example.fr",
+            "10.1.2.3",
+            "mail.example.fr",
+            "https://example.de/api/v1",
+        ] {
+            assert!(!ruler.is_whitelisted(subject), "{subject}");
+        }
     }
 
     #[test]
-    fn test_push_ends() {
+    fn test_groups() {
         let mut ruler = Ruler::new(false);
 
-        // Ensure that it's really empty :)
-        assert_eq!(ruler.ends.get_key_value("ple"), None);
+        assert_eq!(Ruler::group_directive("# @group corp"), Some(Some("corp")));
+        assert_eq!(Ruler::group_directive("#@group\tcorp "), Some(Some("corp")));
+        assert_eq!(Ruler::group_directive("# @group"), Some(None));
+        assert_eq!(Ruler::group_directive("# @groups corp"), None);
+        assert_eq!(Ruler::group_directive("# group corp"), None);
 
-        ruler.push_ends(&"www.example.example".to_string());
+        ruler.parse_vec(&[
+            "example.org",
+            "# @group corp",
+            "ALL .example.net",
+            "REG ^ads\\.",
+            "# @group lab",
+            "example.com",
+            "# @group",
+            "example.de",
+        ]);
+        ruler.set_group_enabled("corp", false);
 
-        let mut expected = HashSet::new();
-        expected.insert("www.example.example".to_string());
+        assert!(!ruler.is_group_enabled("corp"));
+        assert!(ruler.is_group_enabled("lab"));
+        assert!(ruler.is_whitelisted("example.org"));
+        assert!(!ruler.is_whitelisted("api.example.net"));
+        assert!(!ruler.is_whitelisted("ads.example.org"));
+        assert!(ruler.is_whitelisted("example.com"));
+        assert!(ruler.is_whitelisted("example.de"));
 
-        assert_eq!(
-            ruler.ends.get_key_value("ple"),
-            Some((&"ple".to_string(), &expected))
-        );
+        // The rules of a disabled group are set aside even when parsed
+        // afterwards.
+        ruler.parse_vec(&["# @group corp", "example.info"]);
 
-        // Let's add another one.
+        assert!(!ruler.is_whitelisted("example.info"));
 
-        ruler.push_ends(&"example.com".to_string());
+        // Unparsing a rule also forgets it while its group is disabled.
+        ruler.unparse("example.info");
+        ruler.set_group_enabled("corp", true);
 
-        let mut expected = HashSet::new();
-        expected.insert("example.com".to_string());
+        assert!(ruler.is_whitelisted("api.example.net"));
+        assert!(ruler.is_whitelisted("ads.example.org"));
+        assert!(!ruler.is_whitelisted("example.info"));
 
-        assert_eq!(
-            ruler.ends.get_key_value("com"),
-            Some((&"com".to_string(), &expected))
-        );
+        // The group of a rule given again is the last one.
+        ruler.parse_vec(&["# @group lab", "example.org"]);
+        ruler.set_group_enabled("lab", false);
 
-        // Let's add another one.
+        assert!(!ruler.is_whitelisted("example.org"));
+        assert!(!ruler.is_whitelisted("example.com"));
+        assert!(ruler.is_whitelisted("example.de"));
 
-        ruler.push_ends(&"example.co".to_string());
+        let mut other = Ruler::new(false).with_group_enabled("corp", false);
 
-        let mut expected = HashSet::new();
-        expected.insert("example.co".to_string());
+        other.parse_vec(&["# @group corp", "example.fr", "# @group lab", "example.be"]);
 
-        assert_eq!(
-            ruler.ends.get_key_value(".co"),
-            Some((&".co".to_string(), &expected))
-        );
+        assert!(!other.is_whitelisted("example.fr"));
 
-        assert_eq!(ruler.ends.contains_key("com"), true);
-        assert_eq!(ruler.ends.contains_key("ple"), true);
-        assert_eq!(ruler.ends.contains_key(".co"), true);
-    }
+        ruler.merge(other);
 
-    #[test]
-    fn test_pull_ends() {
-        let mut ruler = Ruler::new(false);
+        // Our groups prevail.
+        assert!(ruler.is_whitelisted("example.fr"));
+        assert!(!ruler.is_whitelisted("example.be"));
 
-        // Ensure that it's really empty :)
-        assert_eq!(ruler.ends.get_key_value("ple"), None);
+        ruler.set_group_enabled("lab", true);
 
-        // Add some data into it :)
-        ruler.push_ends(&"www.example.example".to_string());
-        ruler.push_ends(&"example.com".to_string());
-        ruler.push_ends(&"example.co".to_string());
+        assert!(ruler.is_whitelisted("example.be"));
+        assert!(ruler.is_whitelisted("example.org"));
 
-        assert_eq!(ruler.ends.contains_key("com"), true);
-        assert_eq!(ruler.ends.contains_key("ple"), true);
-        assert_eq!(ruler.ends.contains_key(".co"), true);
+        // An invalid rule never reaches a disabled group.
+        let mut ruler = Ruler::new(false).with_group_enabled("corp", false);
 
-        ruler.pull_ends(&"www.example.example".to_string());
+        ruler.parse_vec(&["# @group corp", "REG ^(bad", "example.org"]);
+        ruler.unparse("REG ^(bad");
+        ruler.set_group_enabled("corp", true);
 
-        let expected = HashSet::new();
+        assert!(ruler.is_whitelisted("example.org"));
+        assert_eq!(ruler.len(), 1);
+    }
 
-        assert_eq!(
-            ruler.ends.get_key_value("ple"),
-            Some((&"ple".to_string(), &expected))
-        );
+    #[test]
+    fn test_rule_format() {
+        let mut ruler = Ruler::new(false).with_rule_format(RuleFormat::Adblock);
+
+        ruler.parse_vec(&[
+            "[Adblock Plus 2.0]",
+            "! Title: My whitelist",
+            "@@||example.org^$document",
+            "||example.net^",
+            "@@|https://example.com/api/",
+            "example.info##.banner",
+            "@@||example.de^$script",
+            "! @group corp",
+            "@@||example.fr^",
+        ]);
+
+        assert!(ruler.is_whitelisted("example.org"));
+        assert!(ruler.is_whitelisted("api.example.org"));
+        assert!(ruler.is_whitelisted("api.example.net"));
+        assert!(ruler.is_whitelisted("https://example.com/api/v1"));
+        assert!(!ruler.is_whitelisted("example.info"));
+        assert!(!ruler.is_whitelisted("example.de"));
+        assert!(ruler.contains_rule("@@||example.net^"));
+        assert_eq!(ruler.len(), 7);
+
+        ruler.set_group_enabled("corp", false);
+
+        assert!(!ruler.is_whitelisted("example.fr"));
+
+        ruler.unparse("@@||example.org^");
+
+        assert!(!ruler.is_whitelisted("api.example.org"));
+
+        // Our own rules are ignored.
+        ruler.parse("ALL .example.be");
+
+        assert!(!ruler.is_whitelisted("example.be"));
+        assert_eq!(ruler.rule_format(), RuleFormat::Adblock);
+    }
 
-        let mut expected = HashSet::new();
-        expected.insert("example.com".to_string());
+    #[test]
+    fn test_rule_format_adguard() {
+        let mut ruler = Ruler::new(false).with_rule_format(RuleFormat::Adguard);
+
+        ruler.parse_vec(&[
+            "! Title: My exceptions",
+            "@@||example.org^$important",
+            "0.0.0.0 example.net example.com",
+            "@@/^ads[0-9]+\\.example\\.de$/",
+            "example.info#$?#.banner { display: none; }",
+        ]);
+
+        assert!(ruler.is_whitelisted("api.example.org"));
+        assert!(ruler.is_whitelisted("example.net"));
+        assert!(!ruler.is_whitelisted("api.example.net"));
+        assert!(ruler.is_whitelisted("example.com"));
+        assert!(ruler.is_whitelisted("ads1.example.de"));
+        assert!(!ruler.is_whitelisted("example.info"));
+
+        ruler.unparse("127.0.0.1 example.net example.com");
+
+        assert!(!ruler.is_whitelisted("example.net"));
+        assert!(!ruler.is_whitelisted("example.com"));
+        assert!(matches!(
+            ruler.try_parse("@@/^(ads/"),
+            Err(TivilstaError::Regex { .. })
+        ));
+    }
 
-        assert_eq!(
-            ruler.ends.get_key_value("com"),
-            Some((&"com".to_string(), &expected))
-        );
+    #[test]
+    fn test_rule_format_hosts() {
+        let mut ruler = Ruler::new(false).with_rule_format(RuleFormat::Hosts);
+
+        ruler.parse_vec(&[
+            "# My whitelist",
+            "0.0.0.0 example.org",
+            "127.0.0.1 example.net example.com # Our websites",
+            "ALL .example.de",
+        ]);
+
+        assert!(ruler.is_whitelisted("example.org"));
+        assert!(ruler.is_whitelisted("example.net"));
+        assert!(ruler.is_whitelisted("example.com"));
+        assert!(!ruler.is_whitelisted("api.example.com"));
+        assert!(ruler.is_whitelisted("api.example.de"));
+        assert!(ruler.contains_rule("0.0.0.0 example.com"));
+
+        ruler.unparse("0.0.0.0 example.org example.net");
+
+        assert!(!ruler.is_whitelisted("example.org"));
+        assert!(!ruler.is_whitelisted("example.net"));
+    }
 
-        let mut expected = HashSet::new();
-        expected.insert("example.co".to_string());
+    #[test]
+    fn test_extension() {
+        let mut ruler = Ruler::new(true);
+
+        ruler.settings.extensions = vec![
+            String::from("dev"),
+            String::from("org"),
+            String::from("co.uk"),
+        ];
+        ruler.parse_vec(&["EXT dev", "ext .co.uk", "EXT example"]);
+
+        assert_eq!(ruler.counts().ends, 2);
+        assert!(ruler.is_whitelisted("example.dev"));
+        assert!(ruler.is_whitelisted("api.example.dev"));
+        assert!(ruler.is_whitelisted("example.co.uk"));
+        assert!(!ruler.is_whitelisted("example.uk"));
+        assert!(!ruler.is_whitelisted("example.org"));
+        assert!(!ruler.is_whitelisted("www.example"));
+        assert_eq!(ruler.check("example.dev").unwrap().rule, ".dev");
+        assert!(ruler.contains_rule("EXT dev"));
+        assert!(ruler.contains_rule("ALL .dev"));
+
+        assert!(ruler.try_parse("EXT org").is_ok());
+        assert!(matches!(
+            ruler.try_parse("EXT example"),
+            Err(TivilstaError::UnknownExtension { .. })
+        ));
+
+        ruler.unparse("EXT dev");
+
+        assert!(!ruler.is_whitelisted("example.dev"));
+        assert!(ruler.is_whitelisted("example.org"));
+    }
 
-        assert_eq!(
-            ruler.ends.get_key_value(".co"),
-            Some((&".co".to_string(), &expected))
-        );
+    #[test]
+    fn test_starts() {
+        let mut ruler = Ruler::new(true);
+
+        ruler.parse_vec(&["START mail.", "start smtp", "START api.v2.", "START ."]);
+
+        assert_eq!(ruler.counts().starts, 3);
+        assert!(ruler.is_whitelisted("mail.example.org"));
+        assert!(ruler.is_whitelisted("smtp.example.org"));
+        assert!(ruler.is_whitelisted("http://mail.example.org/inbox"));
+        assert!(ruler.is_whitelisted("api.v2.example.org"));
+        assert!(!ruler.is_whitelisted("api.v3.example.org"));
+        assert!(!ruler.is_whitelisted("mailer.example.org"));
+        assert!(!ruler.is_whitelisted("webmail.example.org"));
+        assert!(!ruler.is_whitelisted("mail"));
+        assert!(ruler.are_whitelisted_iter(["mail.example.org"]).all(|x| x));
+
+        let found = ruler.check("smtp.example.org").unwrap();
+
+        assert_eq!(found.kind, MatchKind::Starts);
+        assert_eq!(found.rule, "smtp.");
+        assert!(ruler.contains_rule("START smtp."));
+        assert!(ruler
+            .duplicate()
+            .freeze()
+            .is_whitelisted("api.v2.example.net"));
+
+        ruler.unparse_vec(&["START mail", "START api.v2."]);
+
+        assert!(!ruler.is_whitelisted("mail.example.org"));
+        assert!(!ruler.is_whitelisted("api.v2.example.org"));
+        assert!(ruler.is_whitelisted("smtp.example.org"));
+    }
 
-        // Let's remove another one.
-        ruler.pull_ends(&"example.com".to_string());
+    #[test]
+    fn test_keyword() {
+        let mut ruler = Ruler::new(true).with_reductions(&[Reduction::Lowercase]);
+
+        ruler.parse_vec(&[
+            "KEY tracker",
+            "key Telemetry",
+            "KEY ",
+            "EXC tracker.example.org",
+        ]);
+
+        assert_eq!(ruler.counts().keywords, 2);
+        assert_eq!(ruler.len(), 4);
+        assert!(ruler.is_whitelisted("tracker.example.net"));
+        assert!(ruler.is_whitelisted("mytracker.example.net"));
+        assert!(ruler
+            .are_whitelisted_iter(["tracker.example.net"])
+            .all(|x| x));
+        assert!(ruler.is_whitelisted("https://example.tracker/ads"));
+        assert!(ruler.is_whitelisted("TELEMETRY.example.net"));
+        assert!(!ruler.is_whitelisted("example.net"));
+        assert!(!ruler.is_whitelisted("tracker.example.org"));
+
+        let found = ruler.check("api.telemetry.example.net").unwrap();
+
+        assert_eq!(found.kind, MatchKind::Keyword);
+        assert_eq!(found.rule, "telemetry");
+        assert!(ruler.contains_rule("KEY telemetry"));
+        assert!(ruler
+            .duplicate()
+            .freeze()
+            .is_whitelisted("telemetry.example.com"));
+
+        ruler.unparse("KEY tracker");
+
+        assert!(!ruler.is_whitelisted("tracker.example.net"));
+        assert!(ruler.is_whitelisted("telemetry.example.net"));
+    }
 
-        let expected = HashSet::new();
+    #[test]
+    fn test_ip() {
+        let mut ruler = Ruler::new(true);
 
-        assert_eq!(
-            ruler.ends.get_key_value("com"),
-            Some((&"com".to_string(), &expected))
-        );
+        ruler.parse_vec(&["0:0:0:0:0:0:0:1", "010.000.000.001", "EXC 2001:DB8::1"]);
 
-        assert_eq!(ruler.ends.contains_key("com"), true);
-        assert_eq!(ruler.ends.contains_key("ple"), true);
-        assert_eq!(ruler.ends.contains_key(".co"), true);
+        assert_eq!(ruler.counts().strict, 2);
+        assert!(ruler.is_whitelisted("::1"));
+        assert!(ruler.is_whitelisted("[::1]"));
+        assert!(ruler.is_whitelisted("0000::0001"));
+        assert!(ruler.is_whitelisted("10.0.0.1"));
+        assert!(ruler.is_whitelisted("::ffff:10.0.0.1"));
+        assert!(ruler.is_whitelisted("http://010.0.0.1/ads"));
+        assert!(!ruler.is_whitelisted("10.0.0.10"));
+        assert!(!ruler.is_whitelisted("www.10.0.0.1"));
+        assert_eq!(ruler.check("10.00.0.1").unwrap().rule, "10.0.0.1");
+
+        ruler.parse("CIDR 2001:db8::/32");
+
+        assert!(!ruler.is_whitelisted("2001:0db8:0:0:0:0:0:1"));
+        assert!(ruler.is_whitelisted("2001:0db8:0:0:0:0:0:2"));
+        assert!(ruler.contains_rule("::1"));
+
+        ruler.unparse("0::1");
+
+        assert!(!ruler.is_whitelisted("::1"));
+        assert!(ruler.duplicate().freeze().is_whitelisted("10.000.0.1"));
     }
 
     #[test]
-    fn test_push_regex() {
-        let mut ruler = Ruler::new(false);
+    fn test_contains_rule() {
+        let mut ruler = Ruler::new(true);
+
+        ruler.settings.extensions = vec![String::from("de"), String::from("fr")];
+        ruler.parse_vec(&[
+            "example.org",
+            "ALL .example.net",
+            "SUB example.info",
+            "APEX example.com",
+            "RZD example",
+            "REG ^ads\\.",
+            "https://example.info/api/",
+        ]);
+
+        for rule in [
+            "example.org",
+            "www.example.org",
+            "EXAMPLE.ORG",
+            "ALL .example.net",
+            "all example.net",
+            "SUB example.info",
+            "SUB example.net",
+            "APEX example.com",
+            "RZD example",
+            "REG ^ads\\.",
+            "https://example.info/api/",
+        ] {
+            assert!(ruler.contains_rule(rule), "{rule}");
+        }
 
-        // Ensure that it's really empty :)
-        assert_eq!(ruler.regex, "");
-        assert_eq!(ruler.compiled_regex.as_str(), "");
+        for rule in [
+            "",
+            "# example.org",
+            "example.de",
+            "SUB example.com",
+            "ALL .example.com",
+            "RZD example.org",
+            "REG ^ads",
+            "REG (",
+            "https://example.info/",
+        ] {
+            assert!(!ruler.contains_rule(rule), "{rule}");
+        }
 
-        ruler.push_regex(&"^(www.)?example.com$".to_string());
+        ruler.unparse("www.example.org");
 
-        let expected = "^(www.)?example.com$".to_string();
+        assert!(!ruler.contains_rule("example.org"));
+        assert!(!ruler.contains_rule("www.example.org"));
 
-        assert_eq!(ruler.regex, expected);
-        assert_eq!(ruler.compiled_regex.as_str(), &expected[..]);
+        ruler.unparse("REG ^ads\\.");
 
-        // Let's add another one.
-        ruler.push_regex(&"^(api.)?example.org$".to_string());
+        assert!(!ruler.contains_rule("REG ^ads\\."));
+
+        // Without extensions, we can't hold the records of such rules - the
+        // datasets are never fetched.
+        let mut ruler = Ruler::new(false);
 
-        let expected = "^(www.)?example.com$|^(api.)?example.org$".to_string();
+        ruler.parse("example.org");
 
-        assert_eq!(ruler.regex, expected);
-        assert_eq!(ruler.compiled_regex.as_str(), &expected[..]);
+        assert!(!ruler.contains_rule("RZD example"));
+        assert!(!ruler.contains_rule("EXT org"));
+        assert!(ruler.contains_rule("example.org"));
     }
 
     #[test]
-    fn test_pull_regex() {
+    fn test_counts() {
         let mut ruler = Ruler::new(false);
 
-        // Ensure that it's really empty :)
-        assert_eq!(ruler.regex, "");
-        assert_eq!(ruler.compiled_regex.as_str(), "");
+        assert_eq!(ruler.counts(), RuleCounts::default());
+        assert_eq!(ruler.len(), 0);
+        assert!(ruler.is_empty());
+
+        ruler.settings.extensions = vec![String::from("de"), String::from("fr")];
+        ruler.parse_vec(&[
+            "# A comment.",
+            "example.org",
+            "APEX example.com",
+            "ALL .example.net",
+            "SUB example.info",
+            "RZD example",
+            "REG ^ads\\.",
+            "https://example.info/api/",
+            "EXC ads.example.net",
+            "CIDR 10.0.0.0/8",
+            "KEY tracker",
+            "START mail.",
+        ]);
 
-        // Add some data into it :)
-        ruler.push_regex(&"^(www.)?example.com$".to_string());
-        ruler.push_regex(&"^(api.)?example.org$".to_string());
+        assert_eq!(
+            ruler.counts(),
+            RuleCounts {
+                strict: 4,
+                present: 2,
+                ends: 2,
+                url_prefixes: 1,
+                regexes: 1,
+                exceptions: 1,
+                cidrs: 1,
+                keywords: 1,
+                starts: 1,
+            }
+        );
+        assert_eq!(ruler.len(), 14);
+        assert!(!ruler.is_empty());
 
-        ruler.pull_regex(&"^(www.)?example.com$".to_string());
+        ruler.unparse("ALL .example.net");
 
-        let expected = "^(api.)?example.org$".to_string();
+        assert_eq!(ruler.counts().ends, 1);
+        assert_eq!(ruler.counts().strict, 3);
+    }
 
-        assert_eq!(ruler.regex, expected);
-        assert_eq!(ruler.compiled_regex.as_str(), &expected[..]);
+    #[test]
+    fn test_clear() {
+        let rules = [
+            "example.org",
+            "ALL .example.net",
+            "SUB example.com",
+            "RZD example",
+            "REG ^ads\\.",
+            "https://example.info/api/",
+        ];
+        let mut ruler = Ruler::new(true).with_ends_strategy(EndsStrategy::Automaton);
 
-        // Let's remove another one.
-        ruler.pull_regex(&"^(api.)?example.org$".to_string());
+        ruler.settings.extensions = vec![String::from("de"), String::from("fr")];
+        ruler.parse_vec(&rules);
 
-        let expected = "".to_string();
+        assert_eq!(ruler.is_whitelisted("ads.example.de"), true);
+        assert_eq!(ruler.is_whitelisted("example.fr"), true);
 
-        assert_eq!(ruler.regex, expected);
-        assert_eq!(ruler.compiled_regex.as_str(), &expected[..]);
-    }
+        ruler.clear_regex();
 
-    #[test]
-    fn test_parse_all() {
-        let mut ruler = Ruler::new(false);
+        assert_eq!(ruler.is_whitelisted("ads.test.de"), false);
+        assert_eq!(ruler.is_whitelisted("www.example.org"), true);
 
-        let given = &"example.org".to_string();
-        let mut expected_res = false;
+        ruler.clear_rzd();
 
-        let mut expected_ends: HashMap<String, HashSet<String>> = HashMap::new();
-        let mut expected_strict: HashMap<String, HashSet<String>> = HashMap::new();
-        let expected_present: HashMap<String, HashSet<String>> = HashMap::new();
-        let expected_regex = "".to_string();
+        assert_eq!(ruler.is_whitelisted("example.fr"), false);
+        assert_eq!(ruler.is_whitelisted("api.example.net"), true);
 
-        assert_eq!(ruler.parse_all(given), expected_res);
-        assert_eq!(ruler.ends, expected_ends);
-        assert_eq!(ruler.strict, expected_strict);
-        assert_eq!(ruler.present, expected_present);
-        assert_eq!(ruler.regex, expected_regex);
+        ruler.clear_all_rules();
 
-        // Let's add a new one.
-        let given = &"ALL example.org".to_string();
-        expected_res = true;
+        assert_eq!(ruler.is_whitelisted("api.example.net"), false);
+        assert_eq!(ruler.is_whitelisted("example.net"), false);
+        assert_eq!(ruler.is_whitelisted("api.example.com"), false);
+        assert_eq!(ruler.is_whitelisted("www.example.org"), true);
+        assert_eq!(ruler.is_whitelisted("https://example.info/api/v1"), true);
 
-        let mut ends_set = HashSet::new();
-        ends_set.insert(".example.org".to_string());
-        expected_ends.insert("org".to_string(), ends_set);
+        ruler.clear();
 
-        let mut strict_set = HashSet::new();
-        strict_set.insert("example.org".to_string());
-        expected_strict.insert("exam".to_string(), strict_set);
+        assert_eq!(ruler.records().count(), 0);
+        assert!(ruler.is_empty());
+        assert_eq!(ruler.ends_strategy(), EndsStrategy::Automaton);
+        assert_eq!(ruler.settings.extensions, vec!["de", "fr"]);
 
-        assert_eq!(ruler.parse_all(given), expected_res);
-        assert_eq!(ruler.ends, expected_ends);
-        assert_eq!(ruler.strict, expected_strict);
-        assert_eq!(ruler.present, expected_present);
-        assert_eq!(ruler.regex, expected_regex);
+        // The ruler can be filled again.
+        ruler.parse_vec(&rules);
 
-        // Let's add another one but the marker is in lowercase.
-        let given = &"all .example.net".to_string();
-        expected_res = true;
+        assert_eq!(ruler.is_whitelisted("ads.test.de"), true);
+        assert_eq!(ruler.is_whitelisted("api.example.net"), true);
+    }
 
-        let mut new_set = HashSet::new();
-        new_set.insert(".example.net".to_string());
-        expected_ends.insert("net".to_string(), new_set);
+    #[test]
+    fn test_try_parse_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("whitelist.list");
+        let path = path.to_string_lossy().to_string();
 
-        let mut new_set = HashSet::new();
-        new_set.insert("example.org".to_string());
-        new_set.insert("example.net".to_string());
-        expected_strict.insert("exam".to_string(), new_set);
+        fs::write(&path, "example.org\n# A comment.\nALL .example.net\n").unwrap();
 
-        assert_eq!(ruler.parse_all(given), expected_res);
-        assert_eq!(ruler.ends, expected_ends);
-        assert_eq!(ruler.strict, expected_strict);
-        assert_eq!(ruler.present, expected_present);
-        assert_eq!(ruler.regex, expected_regex);
+        let mut ruler = Ruler::new(false);
 
-        // Let's add another one but this time with the complement generation.
-        ruler.settings.handle_complement = true;
+        assert!(ruler.try_parse_file(&path).is_ok());
+        assert!(ruler.is_whitelisted(&String::from("api.example.net")));
 
-        let given = &"ALL .example.de".to_string();
-        expected_res = true;
+        assert!(ruler.try_unparse_link(&path).is_ok());
+        assert!(!ruler.is_whitelisted(&String::from("example.org")));
+        assert!(ruler.downloaded_files().is_empty());
 
-        let mut new_set = HashSet::new();
-        new_set.insert(".example.de".to_string());
-        expected_ends.insert(".de".to_string(), new_set);
+        fs::write(&path, "example.org\nREG ^(example\n").unwrap();
 
-        let mut new_set = HashSet::new();
-        new_set.insert("example.org".to_string());
-        new_set.insert("example.net".to_string());
-        new_set.insert("example.de".to_string());
-        new_set.insert("www.example.de".to_string());
+        let mut ruler = Ruler::new(false);
 
-        expected_strict.insert("exam".to_string(), new_set);
+        match ruler.try_parse_link(&path) {
+            Err(TivilstaError::Rule { origin, line, .. }) => {
+                assert_eq!(origin, path);
+                assert_eq!(line, 2);
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
 
-        assert_eq!(ruler.parse_all(given), expected_res);
-        assert_eq!(ruler.ends, expected_ends);
-        assert_eq!(ruler.strict, expected_strict);
-        assert_eq!(ruler.present, expected_present);
-        assert_eq!(ruler.regex, expected_regex);
+        assert!(ruler.is_whitelisted(&String::from("example.org")));
+
+        let mut ruler = Ruler::new(false);
+
+        fs::write(&path, "example.org\nREG ^(bad\nALL .example.net\n").unwrap();
+        ruler.parse_file(&path);
+
+        assert!(ruler.is_whitelisted(&String::from("example.org")));
+        assert!(ruler.is_whitelisted(&String::from("api.example.net")));
+        assert!(!ruler.is_whitelisted(&String::from("bad")));
+
+        ruler.parse("REG ^(bad");
+
+        assert!(ruler.regexes.is_empty());
+
+        let missing = dir.path().join("missing.list");
+        let missing = missing.to_string_lossy().to_string();
+
+        assert!(matches!(
+            ruler.try_parse_file(&missing),
+            Err(TivilstaError::Io { .. })
+        ));
+        assert!(matches!(
+            ruler.try_unparse_file(&missing),
+            Err(TivilstaError::Io { .. })
+        ));
     }
 
     #[test]
-    fn test_unparse_all() {
+    fn test_keep_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let downloaded = dir.path().join("downloaded.list");
+
+        fs::write(&downloaded, "example.org\n").unwrap();
+
         let mut ruler = Ruler::new(false);
 
-        let given = &"ALL example.com".to_string();
-        let mut expected_ends: HashMap<String, HashSet<String>> = HashMap::new();
-        let mut expected_strict: HashMap<String, HashSet<String>> = HashMap::new();
-        let expected_present: HashMap<String, HashSet<String>> = HashMap::new();
-        let expected_regex = "".to_string();
+        assert_eq!(ruler.keep_downloads(), false);
 
-        // Fill ruler with some data
-        ruler.parse_all(&"ALL .hello.com".to_string());
-        ruler.parse_all(&"ALL .github.com".to_string());
-        ruler.parse_all(&"ALL .example.com".to_string());
+        ruler
+            .tmps
+            .downloaded_files
+            .push(downloaded.to_string_lossy().to_string());
+        ruler.set_keep_downloads(true);
 
-        let mut ends_set = HashSet::new();
-        ends_set.insert(".github.com".to_string());
-        ends_set.insert(".hello.com".to_string());
-        expected_ends.insert("com".to_string(), ends_set);
+        assert_eq!(ruler.keep_downloads(), true);
+        assert_eq!(
+            ruler.downloaded_files(),
+            &[downloaded.to_string_lossy().to_string()]
+        );
 
-        let mut strict_set1 = HashSet::new();
-        strict_set1.insert("hello.com".to_string());
-        expected_strict.insert("hell".to_string(), strict_set1);
+        drop(ruler);
 
-        let mut strict_set2 = HashSet::new();
-        strict_set2.insert("github.com".to_string());
-        expected_strict.insert("gith".to_string(), strict_set2);
-        expected_strict.insert("exam".to_string(), HashSet::new());
+        assert!(downloaded.exists());
 
-        assert_eq!(ruler.unparse_all(given), true);
-        assert_eq!(ruler.ends, expected_ends);
-        assert_eq!(ruler.strict, expected_strict);
-        assert_eq!(ruler.present, expected_present);
-        assert_eq!(ruler.regex, expected_regex);
+        let mut ruler = Ruler::new(false);
 
-        // Let's remove another one but this time with the complement generation.
-        ruler.settings.handle_complement = true;
+        ruler
+            .tmps
+            .downloaded_files
+            .push(downloaded.to_string_lossy().to_string());
 
-        ruler.parse_all(&"ALL .hello.com".to_string());
+        assert_eq!(ruler.take_downloaded_files().len(), 1);
+        assert!(ruler.downloaded_files().is_empty());
 
-        let mut strict_set1 = HashSet::new();
-        strict_set1.insert("hello.com".to_string());
-        strict_set1.insert("www.hello.com".to_string());
-        expected_strict.insert("hell".to_string(), strict_set1);
+        drop(ruler);
 
-        let mut strict_set2 = HashSet::new();
-        strict_set2.insert("github.com".to_string());
-        expected_strict.insert("gith".to_string(), strict_set2);
-        expected_strict.insert("exam".to_string(), HashSet::new());
+        assert!(downloaded.exists());
 
-        let given = &"ALL .hello.world".to_string();
+        let mut ruler = Ruler::new(false);
 
-        assert_eq!(ruler.strict, expected_strict);
+        ruler
+            .tmps
+            .downloaded_files
+            .push(downloaded.to_string_lossy().to_string());
 
-        assert_eq!(ruler.unparse_all(given), true);
-        assert_eq!(ruler.ends, expected_ends);
-        assert_eq!(ruler.strict, expected_strict);
-        assert_eq!(ruler.present, expected_present);
-        assert_eq!(ruler.regex, expected_regex);
+        drop(ruler);
+
+        assert!(!downloaded.exists());
     }
 }
@@ -19,34 +19,84 @@
 //      limitations under the License.
 
 mod cli;
-mod data;
-mod utils;
 
 use std::path::PathBuf;
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use tivilsta::data;
 
 use cli::CLIHandler;
 
 #[derive(Parser, Default, Debug)]
-#[clap(author = "Nissar Chababy (@funilrys)", version, about)]
+#[clap(
+    author = "Nissar Chababy (@funilrys)",
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 /// A tool to compute whitelist lists against your lists or hosts files.
 pub struct Arguments {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     #[clap(short, long, parse(from_os_str), required = true)]
     /// The file to cleanup.
-    source: PathBuf,
+    /// Note: Gzip and zstd compressed files are decompressed on the fly.
+    source: Option<PathBuf>,
 
     #[clap(short, long, parse(from_os_str), required = false)]
     /// The output file.
     output: Option<PathBuf>,
 
-    #[clap(short, long, min_values = 1, required = true)]
+    #[clap(long, requires = "output")]
+    /// Exit early - with the exit code 3 - when the source and the
+    /// whitelisting schemas, presets, rules and arguments didn't change since
+    /// the last run. Their digest is stored next to the output file - e.g.
+    /// `output.list.sha256`.
+    skip_unchanged: bool,
+
+    #[clap(long)]
+    /// Don't prepend the generated header - the version, date, sources and
+    /// number of kept lines - to the output file.
+    no_header: bool,
+
+    #[clap(long, parse(from_os_str), required = false)]
+    /// Write one JSON object per line of the source - describing the decision
+    /// that was made, the matching rule and where it comes from - into the
+    /// given file.
+    audit_log: Option<PathBuf>,
+
+    #[clap(long, conflicts_with = "quiet")]
+    /// Echo the kept lines to `stdout` even when an output file is given.
+    tee: bool,
+
+    #[clap(short, long)]
+    /// Never echo the kept lines to `stdout`.
+    quiet: bool,
+
+    #[clap(short, long, min_values = 1, required_unless_present_any = &["rule", "preset"])]
     /// One or multiple space separated whitelisting schema in form of a file path or URL.
     /// Each rule/line will be parsed as-it-is.
     /// Note: When using a URL, the file will be downloaded and stored in a
     /// temporary file that will be deleted when the program exits.
+    /// Note: Archives - `.tar`, `.tar.gz`, `.tgz`, `.tar.zst` or `.zip` - are
+    /// unpacked in memory and each of their files is parsed.
+    /// Note: The lists of the registry can be given by name - e.g.
+    /// `@ultimate-hosts-blacklist/whitelist`.
     whitelist: Vec<String>,
 
+    #[clap(long, default_value = "tivilsta", possible_values = ["tivilsta", "adblock", "adguard", "hosts"])]
+    /// The format of the whitelisting schemas. `adblock` reads the AdBlock
+    /// Plus - and uBlock Origin - filters like `@@||example.org^`, `adguard`
+    /// the AdGuard filters - including the hosts-style entries like
+    /// `0.0.0.0 example.org` - and `hosts` the hosts files.
+    /// Note: The schemas given through `--all`, `--reg` and `--rzd`, the
+    /// presets and the rules given through `--rule` are always read in our
+    /// own format.
+    whitelist_format: String,
+
     #[clap(long, min_values = 1, required = false)]
     /// One or multiple space separated whitelisting schema in form of a file path or URL to read.
     /// Each rule/line will be automatically prefixed with the `ALL ` flag while parsing.
@@ -69,18 +119,357 @@ pub struct Arguments {
     /// temporary file that will be deleted when the program exits.
     rzd: Vec<String>,
 
+    #[clap(long, min_values = 1, required = false, possible_values = data::presets::names())]
+    /// One or multiple space separated built-in presets to load. `core` holds
+    /// the entries that should never be blocked - `localhost`, `broadcasthost`,
+    /// the names reserved by RFC 2606 and RFC 6761, etc. `connectivity` holds
+    /// the connectivity checks and NTP pools, `pki` the OCSP responders and CRL
+    /// distribution points of the common certificate authorities.
+    preset: Vec<String>,
+
+    #[clap(long, multiple_occurrences = true, required = false)]
+    /// A rule to parse as-it-is. This argument can be given multiple times.
+    /// Example: `--rule "ALL .example.com" --rule "example.org"`
+    rule: Vec<String>,
+
     #[clap(long)]
     /// Whether we consider complements while parsing rules.
     /// Note: Complements are `www.example.org` if `example.org` is given - and
     /// vice-versa.
     allow_complements: bool,
+
+    #[clap(long, min_values = 1, required = false)]
+    /// One or multiple space separated prefixes to consider as complements.
+    /// Default: `www.`
+    /// Note: This is only relevant when `--allow-complements` is given.
+    complement_prefixes: Vec<String>,
+
+    #[clap(
+        long,
+        min_values = 1,
+        required = false,
+        possible_values = ["trim", "lowercase", "strip-scheme", "strip-port", "strip-trailing-dot", "idna"]
+    )]
+    /// One or multiple space separated canonicalization stages to apply - in
+    /// the given order - to the rules and the lines of the source before they
+    /// are compared. The `idna` stage is always applied first. The stages that
+    /// are not given are disabled.
+    /// Default: `idna`
+    canonicalize: Vec<String>,
+
+    #[clap(long, min_values = 1, required = false)]
+    /// One or multiple space separated groups of rules to disable. The rules
+    /// following a `# @group <name>` comment belong to that group - until the
+    /// next `# @group` comment or the end of the file.
+    disable_group: Vec<String>,
+
+    #[clap(long, default_value_t = 1)]
+    /// The number of threads used to filter the source.
+    /// Note: The output is written in the same order as the source.
+    threads: usize,
+
+    #[clap(long, default_value_t = tivilsta::pipeline::DEFAULT_CHANNEL_CAPACITY)]
+    /// The number of chunks each queue between the threads can hold. Lower
+    /// values reduce the memory usage when the source is read faster than it
+    /// is filtered.
+    /// Note: This is only relevant when `--threads` is greater than 1.
+    channel_capacity: usize,
+
+    #[clap(long, default_value_t = tivilsta::pipeline::DEFAULT_CHUNK_SIZE)]
+    /// The number of lines sent at once to each thread.
+    /// Note: This is only relevant when `--threads` is greater than 1.
+    chunk_size: usize,
+
+    #[clap(long, default_value = "source", possible_values = ["source", "hosts", "domains", "dnsmasq", "rpz", "adguard-home"])]
+    /// How the lines that are not whitelisted are written. `source` writes
+    /// them as they are read, `hosts` writes them as hosts file entries - see
+    /// `--hosts-ip` -, `domains` writes their bare subjects - one per line -
+    /// `dnsmasq` writes them as dnsmasq entries like `address=/example.org/#`,
+    /// `rpz` writes a DNS Response Policy Zone - see `--rpz-origin` and
+    /// `--rpz-ttl` - and `adguard-home` writes them as AdGuard Home filters
+    /// like `||example.org^` - see `--filter-title`.
+    output_format: String,
+
+    #[clap(long, default_value = "0.0.0.0", parse(try_from_str = parse_ip))]
+    /// The IP of the hosts file entries written by `--output-format hosts`.
+    hosts_ip: String,
+
+    #[clap(long, default_value = "rpz.local")]
+    /// The origin of the zone written by `--output-format rpz`.
+    rpz_origin: String,
+
+    #[clap(long, default_value_t = 3600)]
+    /// The TTL - in seconds - of the zone written by `--output-format rpz`.
+    rpz_ttl: u32,
+
+    #[clap(long)]
+    /// Write a header - with the given title, the version given through
+    /// `--filter-version` and the number of entries - at the top of the
+    /// filters written by `--output-format adguard-home`.
+    filter_title: Option<String>,
+
+    #[clap(long, requires = "filter-title")]
+    /// The version written in the header of the filters - see
+    /// `--filter-title`.
+    filter_version: Option<String>,
+
+    #[clap(long)]
+    /// Report - on `stderr` - the given number of registrable domains with
+    /// the most removed lines. Useful to spot over-broad rules.
+    top_removed: Option<usize>,
+
+    #[clap(long)]
+    /// Report - on `stderr` - the rules that didn't match any line of the
+    /// source - with the file and line they come from. Useful to prune the
+    /// stale entries of the whitelisting schemas.
+    report_unused: bool,
+
+    #[clap(long, possible_values = ["annotate", "retain"])]
+    /// Check whether the subject of every line that is about to be removed
+    /// still resolves. `annotate` removes the non-resolving ones anyway, while
+    /// `retain` keeps them. The rules only matching non-resolving subjects are
+    /// reported on `stderr`.
+    /// Note: The subjects are resolved one after the other - this is slow.
+    verify_dns: Option<String>,
+
+    #[clap(long)]
+    /// Reuse the decision made for a line already seen instead of matching
+    /// it again. The duplicated lines are still written.
+    dedup_input: bool,
+
+    #[clap(long, default_value_t = tivilsta::pipeline::DEFAULT_DEDUP_CAPACITY)]
+    /// The maximum number of distinct lines remembered by `--dedup-input`.
+    /// The least recently seen lines are forgotten first.
+    dedup_capacity: usize,
+
+    #[clap(long, parse(try_from_str = parse_sample_ratio), conflicts_with = "sample-lines")]
+    /// Only check a random sample - e.g. `1%` - of the source and report the
+    /// number of lines the whole source would lose on `stderr`.
+    sample: Option<f64>,
+
+    #[clap(long)]
+    /// Only check the given number of lines - picked at random across the
+    /// source - and report the number of lines the whole source would lose on
+    /// `stderr`.
+    sample_lines: Option<usize>,
+
+    #[clap(long, default_value_t = 0)]
+    /// Ignore the given number of lines at the start of the source - as if
+    /// the source started after them.
+    skip: usize,
+
+    #[clap(long)]
+    /// Only read the given number of lines of the source - after the ones
+    /// ignored by `--skip`.
+    limit: Option<usize>,
+
+    #[clap(long, parse(try_from_str = parse_rate))]
+    /// Cap the speed of each download - in bytes per second. The `K`, `M` and
+    /// `G` suffixes are accepted - e.g. `500K`.
+    max_download_rate: Option<u64>,
+
+    #[clap(long)]
+    /// Cap the memory - in megabytes - used by the buffers of the cleanup. The
+    /// deduplication and the queues between the threads shrink to fit, the
+    /// chunks waiting to be written and the members of the archives are
+    /// spilled into temporary files beyond it. Note: The rules themselves are
+    /// still held in memory.
+    max_memory: Option<usize>,
+
+    #[clap(long, parse(try_from_str = parse_seconds))]
+    /// The minimum delay - in seconds - between two requests sent to the same
+    /// host. Useful to not trip the rate limits of a mirror serving many lists.
+    host_request_spacing: Option<Duration>,
+
+    #[clap(long)]
+    /// A PEM bundle of certificate authorities to trust - in addition to the
+    /// ones of the system - when downloading. Useful for the internal mirrors
+    /// using a private certificate authority.
+    ca_cert: Option<PathBuf>,
+
+    #[clap(long)]
+    /// Don't verify the certificates of the remote servers when downloading.
+    /// Dangerous: anyone between you and the remote servers can tamper with
+    /// the downloaded files. Prefer `--ca-cert`.
+    insecure: bool,
+
+    #[clap(long)]
+    /// The directory to write the downloaded and intermediate files into.
+    /// Default: the temporary directory of the system - which honors `TMPDIR`.
+    temp_dir: Option<PathBuf>,
+
+    #[clap(long)]
+    /// Only check that every given source and whitelisting schema is reachable,
+    /// report the problems and exit - without running the cleanup.
+    validate_sources: bool,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Arguments::parse();
-    let mut handler = CLIHandler::new(args);
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Fetches the IANA and PSL registries - and the registry of named lists -
+    /// and stores them into the cache directory. Subsequent runs will use them
+    /// instead of reaching the network - or the bundled registry of named lists.
+    /// Note: The cache directory can be changed through the `TIVILSTA_CACHE_DIR`
+    /// environment variable.
+    UpdateData {
+        #[clap(long)]
+        /// Only print the location and age of the current snapshots.
+        check: bool,
+    },
+    /// Reports which rules of the given whitelisting schemas whitelisted at
+    /// least one entry of the given corpus - and which never fired.
+    Coverage {
+        #[clap(short, long, min_values = 1, required = true)]
+        /// One or multiple space separated whitelisting schema in form of a file path or URL.
+        /// Each rule/line will be parsed as-it-is.
+        whitelist: Vec<String>,
+
+        #[clap(long, required = true)]
+        /// The file - one subject per line - to check the rules against.
+        corpus: PathBuf,
+
+        #[clap(long)]
+        /// Whether we consider complements while parsing rules.
+        allow_complements: bool,
+    },
+    /// Reports the rules added, removed or changed between two whitelisting
+    /// schemas - after the prefix and complement expansion.
+    Diff {
+        /// The old whitelisting schema in form of a file path or URL.
+        old: String,
+
+        /// The new whitelisting schema in form of a file path or URL.
+        new: String,
+
+        #[clap(long, parse(from_os_str))]
+        /// A file - one subject per line - to report the subjects whose
+        /// decision differs between the two schemas.
+        corpus: Option<PathBuf>,
+
+        #[clap(long)]
+        /// Whether we consider complements while parsing rules.
+        allow_complements: bool,
+    },
+    /// Reports the health of the given whitelisting schemas: rule counts by
+    /// type, duplicate and shadowed rules, regular expression cost, RZD
+    /// expansion size and projected memory footprint.
+    Stats {
+        #[clap(min_values = 1, required = true)]
+        /// One or multiple space separated whitelisting schema in form of a file path or URL.
+        whitelist: Vec<String>,
+
+        #[clap(long)]
+        /// Whether we consider complements while parsing rules.
+        allow_complements: bool,
+    },
+}
+
+/// Parses - and validates - an IP address.
+fn parse_ip(value: &str) -> Result<String, String> {
+    value
+        .parse::<std::net::IpAddr>()
+        .map(|ip| ip.to_string())
+        .map_err(|_| format!("Invalid IP address: {}", value))
+}
+
+/// Parses a sample ratio given as a percentage - e.g. `1%` or `0.5`.
+fn parse_sample_ratio(value: &str) -> Result<f64, String> {
+    let percentage: f64 = value
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("Invalid percentage: {}", value))?;
+
+    if !(percentage > 0.0 && percentage <= 100.0) {
+        return Err(String::from(
+            "The percentage must be greater than 0 and at most 100.",
+        ));
+    }
+
+    Ok(percentage / 100.0)
+}
+
+/// Parses a download rate - e.g. `500K` or `2M` - into bytes per second.
+fn parse_rate(value: &str) -> Result<u64, String> {
+    let (number, multiplier) = match value.chars().last().map(|unit| unit.to_ascii_uppercase()) {
+        Some('K') => (&value[..value.len() - 1], 1024),
+        Some('M') => (&value[..value.len() - 1], 1024 * 1024),
+        Some('G') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+
+    match number.parse::<u64>() {
+        Ok(rate) if rate > 0 => Ok(rate * multiplier),
+        _ => Err(format!("Invalid rate: {}", value)),
+    }
+}
+
+/// Parses a number of seconds - e.g. `0.5` - into a duration.
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("Invalid number of seconds: {}", value))
+}
 
-    handler.cleanup();
+fn run(args: Arguments) -> Result<(), Box<dyn std::error::Error>> {
+    match &args.command {
+        Some(Command::UpdateData { check }) => return cli::update_data(*check),
+        Some(Command::Coverage {
+            whitelist,
+            corpus,
+            allow_complements,
+        }) => return cli::coverage(whitelist, corpus, *allow_complements),
+        Some(Command::Diff {
+            old,
+            new,
+            corpus,
+            allow_complements,
+        }) => return cli::diff(old, new, corpus.as_deref(), *allow_complements),
+        Some(Command::Stats {
+            whitelist,
+            allow_complements,
+        }) => return cli::stats(whitelist, *allow_complements),
+        None => {}
+    }
+
+    if args.validate_sources {
+        return cli::validate_sources(&args);
+    }
+
+    let mut handler = CLIHandler::new(args)?;
+
+    if handler.is_unchanged() {
+        eprintln!("The inputs didn't change since the last run. Nothing to do.");
+
+        // Exiting doesn't run the destructors - which delete the downloaded files.
+        drop(handler);
+        std::process::exit(cli::UNCHANGED_EXIT_CODE);
+    }
+
+    handler.cleanup()
+}
+
+fn main() {
+    // Unexpected failures are reported as concise messages. The full report
+    // is still available through the `RUST_BACKTRACE` environment variable.
+    if std::env::var_os("RUST_BACKTRACE").is_none() {
+        std::panic::set_hook(Box::new(|info| {
+            let message = info
+                .payload()
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| info.payload().downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("Unexpected failure."));
+
+            eprintln!("Error: {}", message);
+        }));
+    }
+
+    let args = Arguments::parse();
 
-    Ok(())
+    if let Err(error) = run(args) {
+        eprintln!("Error: {}", error);
+        std::process::exit(1)
+    }
 }
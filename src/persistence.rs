@@ -0,0 +1,411 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::sync::OnceLock;
+
+use lru::LruCache;
+
+#[cfg(feature = "bincode")]
+use crate::TivilstaError;
+use crate::{Expiries, Groups, Ruler, RulerCaches, RulerSettings, RulerTmps, StashedRecord};
+#[cfg(feature = "bincode")]
+use std::fs::File;
+#[cfg(feature = "bincode")]
+use std::io::{BufReader, BufWriter, Write};
+
+/// What is persisted of a ruler - its rules, their expiry dates and groups and
+/// its settings. The compiled regular expressions, the origins of the rules, the
+/// downloaded files and the caches are not.
+#[derive(Serialize)]
+struct RulerStateRef<'a> {
+    strict: &'a HashSet<String>,
+    present: &'a HashSet<String>,
+    ends: &'a HashMap<String, HashSet<String>>,
+    starts: &'a HashMap<String, HashSet<String>>,
+    url_prefixes: &'a HashMap<String, HashSet<String>>,
+    regexes: &'a [String],
+    exceptions: &'a HashSet<String>,
+    cidrs: &'a HashSet<String>,
+    keywords: &'a HashSet<String>,
+    expiries: &'a Expiries,
+    groups: &'a Groups,
+    disabled_groups: &'a HashMap<String, Vec<StashedRecord>>,
+    settings: &'a RulerSettings,
+    idna_cache_capacity: usize,
+}
+
+/// The owned counterpart of [`RulerStateRef`].
+#[derive(Deserialize)]
+struct RulerState {
+    strict: HashSet<String>,
+    present: HashSet<String>,
+    ends: HashMap<String, HashSet<String>>,
+    starts: HashMap<String, HashSet<String>>,
+    url_prefixes: HashMap<String, HashSet<String>>,
+    regexes: Vec<String>,
+    exceptions: HashSet<String>,
+    cidrs: HashSet<String>,
+    keywords: HashSet<String>,
+    expiries: Expiries,
+    groups: Groups,
+    disabled_groups: HashMap<String, Vec<StashedRecord>>,
+    settings: RulerSettings,
+    idna_cache_capacity: usize,
+}
+
+impl Serialize for Ruler {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RulerStateRef {
+            strict: &self.strict,
+            present: &self.present,
+            ends: &self.ends,
+            starts: &self.starts,
+            url_prefixes: &self.url_prefixes,
+            regexes: &self.regexes,
+            exceptions: &self.exceptions,
+            cidrs: &self.cidrs,
+            keywords: &self.keywords,
+            expiries: &self.expiries,
+            groups: &self.groups,
+            disabled_groups: &self.disabled_groups,
+            settings: &self.settings,
+            idna_cache_capacity: self.idna_cache_capacity(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Ruler {
+    /// Restores a ruler - and compiles its regular expressions within its
+    /// limits.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Ruler, D::Error> {
+        let state = RulerState::deserialize(deserializer)?;
+        let mut ruler = Ruler {
+            strict: state.strict,
+            present: state.present,
+            ends: state.ends,
+            starts: state.starts,
+            url_prefixes: state.url_prefixes,
+            regexes: state.regexes,
+            exceptions: state.exceptions,
+            cidrs: state.cidrs,
+            keywords: state.keywords,
+            defer_regex: false,
+            origins: HashMap::new(),
+            origin: None,
+            expiries: state.expiries,
+            expiry: None,
+            groups: state.groups,
+            group: None,
+            disabled_groups: state.disabled_groups,
+            usage: None,
+            settings: state.settings,
+            tmps: RulerTmps {
+                downloaded_files: vec![],
+                keep_downloads: false,
+            },
+            caches: RulerCaches {
+                idna: NonZeroUsize::new(state.idna_cache_capacity).map(LruCache::new),
+                ends: OnceLock::new(),
+                regexes: OnceLock::new(),
+                cidrs: OnceLock::new(),
+                keywords: OnceLock::new(),
+            },
+        };
+
+        // The rules may have expired since they were persisted.
+        ruler.remove_expired();
+
+        if !ruler.regexes.is_empty() {
+            let matcher = ruler
+                .compile_regex_set()
+                .map_err(|error| D::Error::custom(error.to_string()))?;
+
+            ruler.caches.regexes = OnceLock::from(matcher);
+        }
+
+        Ok(ruler)
+    }
+}
+
+/// A compiled rule set - tagged with the version of tivilsta which wrote it.
+#[cfg(feature = "bincode")]
+#[derive(Serialize)]
+struct CompiledRef<'a> {
+    version: &'a str,
+    ruler: &'a Ruler,
+}
+
+/// The header of a compiled rule set - read before the ruler itself.
+#[cfg(feature = "bincode")]
+#[derive(Deserialize)]
+struct CompiledHeader {
+    version: String,
+}
+
+#[cfg(feature = "bincode")]
+impl Ruler {
+    /// Writes the parsed rules - and the settings - of the ruler into the
+    /// given file, using a compact binary format.
+    ///
+    /// The compiled file can only be loaded back by the same version of
+    /// tivilsta.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file to write.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    /// ruler.try_parse_file("whitelist.list").unwrap();
+    /// ruler.save_compiled("whitelist.bin").unwrap();
+    /// ```
+    pub fn save_compiled(&self, path: &str) -> Result<(), TivilstaError> {
+        let file = File::create(path).map_err(|error| TivilstaError::io(path, error))?;
+        let mut writer = BufWriter::new(file);
+
+        bincode::serialize_into(
+            &mut writer,
+            &CompiledRef {
+                version: env!("CARGO_PKG_VERSION"),
+                ruler: self,
+            },
+        )
+        .map_err(|error| TivilstaError::Compiled {
+            path: path.to_string(),
+            message: error.to_string(),
+        })?;
+
+        writer
+            .flush()
+            .map_err(|error| TivilstaError::io(path, error))
+    }
+
+    /// Restores a ruler from a file written by [`Ruler::save_compiled`] -
+    /// without parsing its rules again.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the compiled file.
+    ///
+    /// # Returns
+    ///
+    /// The restored ruler - or an error when the file was written by another
+    /// version of tivilsta or is corrupted.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use tivilsta::Ruler;
+    ///
+    /// let ruler = Ruler::load_compiled("whitelist.bin").unwrap();
+    /// assert!(ruler.is_whitelisted(&String::from("example.org")));
+    /// ```
+    pub fn load_compiled(path: &str) -> Result<Ruler, TivilstaError> {
+        let file = File::open(path).map_err(|error| TivilstaError::io(path, error))?;
+        let mut reader = BufReader::new(file);
+        let invalid = |message: String| TivilstaError::Compiled {
+            path: path.to_string(),
+            message,
+        };
+
+        let header: CompiledHeader =
+            bincode::deserialize_from(&mut reader).map_err(|error| invalid(error.to_string()))?;
+
+        if header.version != env!("CARGO_PKG_VERSION") {
+            return Err(invalid(format!(
+                "written by tivilsta {} - expected {}.",
+                header.version,
+                env!("CARGO_PKG_VERSION")
+            )));
+        }
+
+        bincode::deserialize_from(&mut reader).map_err(|error| invalid(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EndsStrategy, ExpiryDate, MatchKind, Reduction, Ruler};
+
+    #[test]
+    fn test_round_trip() {
+        let mut ruler = Ruler::new(true)
+            .with_reductions(&[Reduction::StripPrefixes, Reduction::Lowercase])
+            .with_ends_strategy(EndsStrategy::Automaton)
+            .with_idna_cache_capacity(42);
+
+        ruler.parse_vec(&[
+            String::from("example.org"),
+            String::from("ALL .example.net"),
+            String::from("REG ^ads\\."),
+            String::from("https://example.com/api/"),
+        ]);
+
+        let json = serde_json::to_string(&ruler).unwrap();
+        let restored: Ruler = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored.handle_complement(), true);
+        assert_eq!(restored.reductions(), ruler.reductions());
+        assert_eq!(restored.ends_strategy(), EndsStrategy::Automaton);
+        assert_eq!(restored.idna_cache_capacity(), 42);
+
+        for subject in [
+            "WWW.EXAMPLE.ORG",
+            "api.example.net",
+            "ads.example.de",
+            "https://example.com/api/v1",
+            "example.com",
+        ] {
+            let subject = subject.to_string();
+
+            assert_eq!(
+                restored.is_whitelisted(&subject),
+                ruler.is_whitelisted(&subject),
+                "{}",
+                subject
+            );
+        }
+
+        let json = json.replace("^ads\\\\.", "^(ads");
+
+        assert!(serde_json::from_str::<Ruler>(&json).is_err());
+
+        // The former strategy is restored as the one it had become.
+        assert_eq!(
+            serde_json::from_str::<EndsStrategy>("\"LabelSuffixes\"").unwrap(),
+            EndsStrategy::Buckets
+        );
+    }
+
+    #[test]
+    fn test_round_trip_expired() {
+        let mut ruler = Ruler::new(false);
+
+        ruler.parse_vec(&["example.org @expires=2999-12-31", "example.net"]);
+
+        let json = serde_json::to_string(&ruler).unwrap();
+        let restored: Ruler = serde_json::from_str(&json).unwrap();
+
+        assert!(restored.is_whitelisted("example.org"));
+        assert_eq!(restored.expiring_rules(365_000).len(), 1);
+
+        // The rule expired since it was persisted.
+        ruler.expiries.get_mut(&MatchKind::Strict).unwrap().insert(
+            String::from("example.org"),
+            ExpiryDate::new(2000, 1, 1).unwrap(),
+        );
+
+        let json = serde_json::to_string(&ruler).unwrap();
+        let restored: Ruler = serde_json::from_str(&json).unwrap();
+
+        assert!(!restored.is_whitelisted("example.org"));
+        assert!(restored.is_whitelisted("example.net"));
+    }
+
+    #[test]
+    fn test_round_trip_groups() {
+        let mut ruler = Ruler::new(false);
+
+        ruler.parse_vec(&[
+            "# @group corp",
+            "example.org",
+            "# @group lab",
+            "example.net",
+        ]);
+        ruler.set_group_enabled("lab", false);
+
+        let json = serde_json::to_string(&ruler).unwrap();
+        let mut restored: Ruler = serde_json::from_str(&json).unwrap();
+
+        assert!(restored.is_whitelisted("example.org"));
+        assert!(!restored.is_whitelisted("example.net"));
+
+        restored.set_group_enabled("corp", false);
+        restored.set_group_enabled("lab", true);
+
+        assert!(!restored.is_whitelisted("example.org"));
+        assert!(restored.is_whitelisted("example.net"));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_save_and_load_compiled() {
+        use crate::TivilstaError;
+
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("whitelist.bin");
+        let path = path.to_str().unwrap();
+
+        let mut ruler = Ruler::new(false).with_ends_strategy(EndsStrategy::Automaton);
+        ruler.parse_vec(&[
+            String::from("example.org"),
+            String::from("ALL .example.net"),
+            String::from("REG ^ads\\."),
+        ]);
+        ruler.save_compiled(path).unwrap();
+
+        let restored = Ruler::load_compiled(path).unwrap();
+
+        assert_eq!(restored.ends_strategy(), EndsStrategy::Automaton);
+
+        for subject in [
+            "example.org",
+            "api.example.net",
+            "ads.example.de",
+            "example.com",
+        ] {
+            let subject = subject.to_string();
+
+            assert_eq!(
+                restored.is_whitelisted(&subject),
+                ruler.is_whitelisted(&subject),
+                "{}",
+                subject
+            );
+        }
+
+        std::fs::write(path, bincode::serialize("0.0.0").unwrap()).unwrap();
+
+        assert!(matches!(
+            Ruler::load_compiled(path),
+            Err(TivilstaError::Compiled { .. })
+        ));
+
+        std::fs::write(path, b"garbage").unwrap();
+
+        assert!(matches!(
+            Ruler::load_compiled(path),
+            Err(TivilstaError::Compiled { .. })
+        ));
+        assert!(matches!(
+            Ruler::load_compiled(&format!("{}.missing", path)),
+            Err(TivilstaError::Io { .. })
+        ));
+    }
+}
@@ -0,0 +1,2807 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use lru::LruCache;
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::net::{IpAddr, ToSocketAddrs};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::data::presets;
+use crate::data::psl::PublicSuffixes;
+use crate::data::registry;
+use crate::utils;
+use crate::utils::ArchiveMember;
+use crate::{MatchKind, RuleOrigin, Ruler, TivilstaError};
+
+/// The default number of messages each channel of the worker pool can hold.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 64;
+
+/// The default number of lines sent at once to the workers.
+pub const DEFAULT_CHUNK_SIZE: usize = 512;
+
+/// The default number of distinct lines remembered when deduplicating the
+/// source.
+pub const DEFAULT_DEDUP_CAPACITY: usize = 1_000_000;
+
+/// The estimated number of bytes a line of the source - and the decision made
+/// for it - occupies in memory. Used to honor [`Pipeline::max_memory`].
+const ESTIMATED_LINE_SIZE: usize = 256;
+
+/// The origin of the rules given through [`Pipeline::rule`].
+const COMMAND_LINE: &str = "command line";
+
+/// How the source, the output and the audit log are named in the errors -
+/// when they are not files.
+const SOURCE: &str = "<source>";
+const OUTPUT: &str = "<output>";
+const AUDIT_LOG: &str = "<audit log>";
+
+/// A summary of a cleanup.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineReport {
+    /// The number of lines read from the source - within the window set by
+    /// [`Pipeline::skip`] and [`Pipeline::limit`].
+    pub source_lines: usize,
+    /// The number of lines that were checked against the rules. This is less
+    /// than `source_lines` when only a sample of the source is checked.
+    pub read: usize,
+    /// The number of lines that were whitelisted - and therefore removed.
+    pub whitelisted: usize,
+    /// The number of lines written into the output.
+    pub written: usize,
+    /// The number of lines matching a rule whose subject failed the
+    /// verification - see [`Pipeline::verify_removals`].
+    pub unverified: usize,
+}
+
+impl PipelineReport {
+    /// Whether only a sample of the source was checked.
+    pub fn is_sampled(&self) -> bool {
+        self.read < self.source_lines
+    }
+
+    /// Provides the number of lines of the whole source that would have been
+    /// whitelisted - extrapolated from the checked sample.
+    pub fn estimated_whitelisted(&self) -> usize {
+        if self.read == 0 || !self.is_sampled() {
+            return self.whitelisted;
+        }
+
+        (self.whitelisted as f64 * self.source_lines as f64 / self.read as f64).round() as usize
+    }
+
+    /// Provides the number of lines of the whole source that would have been
+    /// written - extrapolated from the checked sample.
+    pub fn estimated_written(&self) -> usize {
+        if !self.is_sampled() {
+            return self.written;
+        }
+
+        self.source_lines - self.estimated_whitelisted()
+    }
+}
+
+/// The part of the source to check - when evaluating rules against a large
+/// source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sample {
+    /// Check each line with the given probability - e.g. `0.01` to check
+    /// about 1% of the source.
+    Ratio(f64),
+    /// Check the given number of lines - picked at random across the whole
+    /// source.
+    Lines(usize),
+}
+
+/// Writes the lines that are not whitelisted - see
+/// [`Pipeline::output_writer`].
+///
+/// The built-in formats are the ones of [`OutputFormat`]. Implement it to
+/// write another one.
+///
+/// # Example
+///
+/// ```rust
+/// use std::io::{self, Write};
+/// use tivilsta::pipeline::OutputWriter;
+/// use tivilsta::{Pipeline, Ruler};
+///
+/// /// Writes Unbound entries.
+/// #[derive(Debug)]
+/// struct Unbound;
+///
+/// impl OutputWriter for Unbound {
+///     fn write(&self, line: &str, output: &mut dyn Write) -> io::Result<bool> {
+///         writeln!(output, "local-zone: \"{}\" always_nxdomain", line)?;
+///
+///         Ok(true)
+///     }
+/// }
+///
+/// let mut pipeline = Pipeline::new(Ruler::new(false))
+///     .rule("example.org")
+///     .output_writer(Unbound);
+/// let mut output: Vec<u8> = vec![];
+///
+/// pipeline
+///     .filter("example.org\nexample.com\n".as_bytes(), &mut output)
+///     .unwrap();
+///
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     "local-zone: \"example.com\" always_nxdomain\n"
+/// );
+/// ```
+pub trait OutputWriter: fmt::Debug {
+    /// Provides what starts a comment line in the output - e.g. `;` in a DNS
+    /// zone.
+    fn comment_marker(&self) -> &'static str {
+        "#"
+    }
+
+    /// Whether the header holds the number of entries - which is only known
+    /// once the whole source is filtered. The output is then held in memory
+    /// until the header is written.
+    fn counts_entries(&self) -> bool {
+        false
+    }
+
+    /// Writes what comes before the given number of `entries` - if anything -
+    /// into `output`. The number of `entries` is only known - otherwise `0` -
+    /// when [`OutputWriter::counts_entries`].
+    fn write_header(&self, entries: usize, output: &mut dyn Write) -> std::io::Result<()> {
+        let _ = (entries, output);
+
+        Ok(())
+    }
+
+    /// Writes the given - not whitelisted - `line` of the source into
+    /// `output`.
+    ///
+    /// # Returns
+    ///
+    /// Whether something was written.
+    fn write(&self, line: &str, output: &mut dyn Write) -> std::io::Result<bool>;
+}
+
+/// How the lines that are not whitelisted are written - by the built-in
+/// [`OutputWriter`]s.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// As they are read from the source.
+    #[default]
+    Source,
+    /// As hosts file entries pointing to the given IP - e.g.
+    /// `0.0.0.0 example.org`. Comments and empty lines are dropped.
+    Hosts(String),
+    /// As bare subjects - one per line. The IP column of hosts file entries,
+    /// comments and empty lines are dropped.
+    Domains,
+    /// As dnsmasq entries blocking the subjects - and their subdomains -
+    /// e.g. `address=/example.org/#`. Comments and empty lines are dropped.
+    Dnsmasq,
+    /// As a DNS Response Policy Zone - with the given origin - e.g.
+    /// `rpz.local` - and TTL. The SOA and NS records are written first, then
+    /// one `example.org CNAME .` record per subject. Comments and empty lines
+    /// are dropped.
+    Rpz { origin: String, ttl: u32 },
+    /// As AdGuard Home filters blocking the subjects - and their subdomains -
+    /// e.g. `||example.org^` - after the given header - if any. Comments and
+    /// empty lines are dropped.
+    AdguardHome(Option<FilterHeader>),
+}
+
+/// The metadata written at the top of a filter list.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FilterHeader {
+    /// The title of the list - e.g. `My blocklist`.
+    pub title: String,
+    /// The version of the list - if any.
+    pub version: Option<String>,
+}
+
+impl OutputWriter for OutputFormat {
+    fn comment_marker(&self) -> &'static str {
+        match self {
+            OutputFormat::Rpz { .. } => ";",
+            OutputFormat::AdguardHome(_) => "!",
+            _ => "#",
+        }
+    }
+
+    fn counts_entries(&self) -> bool {
+        matches!(self, OutputFormat::AdguardHome(Some(_)))
+    }
+
+    fn write_header(&self, entries: usize, output: &mut dyn Write) -> std::io::Result<()> {
+        if let OutputFormat::AdguardHome(Some(header)) = self {
+            writeln!(output, "! Title: {}", header.title)?;
+
+            if let Some(version) = &header.version {
+                writeln!(output, "! Version: {}", version)?;
+            }
+
+            writeln!(output, "! Entries: {}", entries)?;
+            writeln!(output, "!")?;
+        }
+
+        if let OutputFormat::Rpz { origin, ttl } = self {
+            let serial = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs() as u32)
+                .unwrap_or(1);
+
+            writeln!(output, "$TTL {}", ttl)?;
+            writeln!(output, "$ORIGIN {}.", origin.trim_end_matches('.'))?;
+            writeln!(
+                output,
+                "@ IN SOA localhost. hostmaster.localhost. ({} 3600 600 604800 {})",
+                serial, ttl
+            )?;
+            writeln!(output, "@ IN NS localhost.")?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, line: &str, output: &mut dyn Write) -> std::io::Result<bool> {
+        if *self == OutputFormat::Source {
+            output.write_all(line.as_bytes())?;
+            output.write_all(b"\n")?;
+
+            return Ok(true);
+        }
+
+        let subjects = hosts_subjects(line);
+
+        if subjects.is_empty() {
+            return Ok(false);
+        }
+
+        match self {
+            OutputFormat::Hosts(ip) => writeln!(output, "{} {}", ip, subjects.join(" "))?,
+            OutputFormat::Dnsmasq => {
+                for subject in subjects {
+                    writeln!(output, "address=/{}/#", subject)?;
+                }
+            }
+            OutputFormat::Rpz { .. } => {
+                for subject in subjects {
+                    writeln!(output, "{} CNAME .", subject.trim_end_matches('.'))?;
+                }
+            }
+            OutputFormat::AdguardHome(_) => {
+                for subject in subjects {
+                    writeln!(output, "||{}^", subject)?;
+                }
+            }
+            _ => {
+                for subject in subjects {
+                    writeln!(output, "{}", subject)?;
+                }
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Provides the subjects of the given plain or hosts file `line` - without
+/// its IP and comment.
+fn hosts_subjects(line: &str) -> Vec<&str> {
+    let line = line.split_once('#').map_or(line, |(line, _)| line);
+    let mut subjects: Vec<&str> = line.split_whitespace().collect();
+
+    if subjects.len() > 1 && subjects[0].parse::<IpAddr>().is_ok() {
+        subjects.remove(0);
+    }
+
+    subjects
+}
+
+/// A line of the source and the rule it matched - if any.
+#[derive(Clone)]
+struct Decision {
+    line: String,
+    rule: Option<(MatchKind, String)>,
+    /// Where the rule comes from - e.g. `whitelist.list:12` - when known.
+    source: Option<String>,
+}
+
+impl Decision {
+    /// Decides whether the given - already IDNAzed - `line` is whitelisted.
+    fn new(ruler: &Ruler, line: String) -> Decision {
+        let found = ruler.check(&line);
+        let source = found
+            .as_ref()
+            .and_then(|found| found.origin)
+            .map(|origin| origin.to_string());
+        let rule = found.map(|found| (found.kind, found.rule.to_string()));
+
+        Decision { line, rule, source }
+    }
+}
+
+/// A spilled decision - its line, the kind and rule it matched and the source
+/// of the rule.
+type SpilledDecision<S> = (S, Option<(S, S)>, Option<S>);
+
+/// Makes the decisions for the lines of the source - with its own caches.
+struct Decider<'a> {
+    ruler: &'a Ruler,
+    idna_cache: Option<LruCache<String, String>>,
+    decisions: Option<LruCache<String, Decision>>,
+}
+
+impl<'a> Decider<'a> {
+    /// Creates a new Decider that remembers the decisions of at most
+    /// `dedup_capacity` distinct lines. `0` disables the deduplication.
+    fn new(ruler: &'a Ruler, dedup_capacity: usize) -> Decider<'a> {
+        Decider {
+            ruler,
+            idna_cache: ruler.new_idna_cache(),
+            decisions: NonZeroUsize::new(dedup_capacity).map(LruCache::new),
+        }
+    }
+
+    /// Decides whether the given - raw - `line` is whitelisted.
+    fn decide(&mut self, line: String) -> Decision {
+        if let Some(decision) = self.decisions.as_mut().and_then(|cache| cache.get(&line)) {
+            if let Some((kind, rule)) = &decision.rule {
+                self.ruler.record_usage(*kind, rule);
+            }
+
+            return decision.clone();
+        }
+
+        let decision = Decision::new(
+            self.ruler,
+            self.ruler
+                .prepare_line_with_cache(&line, &mut self.idna_cache),
+        );
+
+        if let Some(cache) = self.decisions.as_mut() {
+            cache.put(line, decision.clone());
+        }
+
+        decision
+    }
+}
+
+/// What to do with the lines matching a rule whose subject failed the
+/// verification - see [`Pipeline::verify_removals`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnverifiedAction {
+    /// Remove them anyway - while reporting them.
+    Annotate,
+    /// Keep them in the output.
+    Retain,
+}
+
+/// A function verifying the subject - e.g. `example.org` - of a line that is
+/// about to be removed.
+pub type RemovalVerifier = dyn FnMut(&str) -> bool;
+
+/// Whether the given `subject` currently resolves through the system
+/// resolver.
+///
+/// This is meant to be given to [`Pipeline::verify_removals`].
+pub fn resolves(subject: &str) -> bool {
+    (subject, 0)
+        .to_socket_addrs()
+        .is_ok_and(|mut addresses| addresses.next().is_some())
+}
+
+/// The verification of the lines that are about to be removed.
+struct Verification {
+    verify: Box<RemovalVerifier>,
+    action: UnverifiedAction,
+    /// The number of verified and unverified subjects per rule.
+    rules: HashMap<String, (usize, usize)>,
+}
+
+impl fmt::Debug for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Verification")
+            .field("action", &self.action)
+            .field("rules", &self.rules)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Verification {
+    /// Verifies the subject of the given `line` - matching the given `rule`.
+    fn verify(&mut self, line: &str, rule: &str) -> bool {
+        let verified = (self.verify)(&utils::extract_netloc(line));
+        let counts = self.rules.entry(rule.to_string()).or_default();
+
+        if verified {
+            counts.0 += 1;
+        } else {
+            counts.1 += 1;
+        }
+
+        verified
+    }
+}
+
+/// The number of whitelisted lines per registrable domain.
+#[derive(Debug, Default)]
+struct RemovedDomains {
+    suffixes: Option<PublicSuffixes>,
+    counts: HashMap<String, usize>,
+}
+
+impl RemovedDomains {
+    /// Counts the given whitelisted `line` under its registrable domain.
+    fn count(&mut self, line: &str) {
+        let host = utils::extract_netloc(line);
+        let domain = self
+            .suffixes
+            .as_ref()
+            .and_then(|suffixes| suffixes.registrable_domain(&host))
+            .unwrap_or(&host);
+
+        match self.counts.get_mut(domain) {
+            Some(count) => *count += 1,
+            None => {
+                self.counts.insert(domain.to_string(), 1);
+            }
+        }
+    }
+}
+
+/// A function called - while downloading a remote file - with its URL, the
+/// number of bytes downloaded so far and the total number of bytes - when
+/// announced by the remote server.
+pub type DownloadProgress = dyn FnMut(&str, u64, Option<u64>);
+
+/// The name, reader and flag of a whitelisting schema to parse.
+type Schema = (String, Box<dyn BufRead + Send>, &'static str);
+
+/// The whole cleanup process - download, parse, filter and write - as
+/// driven by the CLI.
+pub struct Pipeline {
+    ruler: Ruler,
+    source: Option<String>,
+    inputs: Vec<(String, &'static str)>,
+    presets: Vec<String>,
+    rules: Vec<String>,
+    threads: usize,
+    channel_capacity: usize,
+    chunk_size: usize,
+    skip: usize,
+    limit: Option<usize>,
+    sample: Option<Sample>,
+    dedup_capacity: usize,
+    max_memory: Option<usize>,
+    temp_dir: PathBuf,
+    ca_cert: Option<PathBuf>,
+    insecure: bool,
+    client: Option<reqwest::blocking::Client>,
+    removed_domains: Option<RemovedDomains>,
+    verification: Option<Verification>,
+    output_writer: Box<dyn OutputWriter>,
+    progress: Option<Box<DownloadProgress>>,
+    max_download_rate: Option<u64>,
+    host_request_spacing: Duration,
+    last_requests: HashMap<String, Instant>,
+    audit_log: Option<Box<dyn Write>>,
+    tmps: HashMap<String, String>,
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("ruler", &self.ruler)
+            .field("source", &self.source)
+            .field("inputs", &self.inputs)
+            .field("presets", &self.presets)
+            .field("rules", &self.rules)
+            .field("threads", &self.threads)
+            .field("channel_capacity", &self.channel_capacity)
+            .field("chunk_size", &self.chunk_size)
+            .field("skip", &self.skip)
+            .field("limit", &self.limit)
+            .field("sample", &self.sample)
+            .field("dedup_capacity", &self.dedup_capacity)
+            .field("max_memory", &self.max_memory)
+            .field("temp_dir", &self.temp_dir)
+            .field("ca_cert", &self.ca_cert)
+            .field("insecure", &self.insecure)
+            .field("removed_domains", &self.removed_domains)
+            .field("verification", &self.verification)
+            .field("output_writer", &self.output_writer)
+            .field("max_download_rate", &self.max_download_rate)
+            .field("host_request_spacing", &self.host_request_spacing)
+            .field("tmps", &self.tmps)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Pipeline {
+    /// Creates a new Pipeline object.
+    ///
+    /// # Arguments
+    ///
+    /// * `ruler` - The ruler to load the whitelisting schemas into.
+    /// Its settings are used to parse every schema.
+    ///
+    /// # Returns
+    ///
+    /// A new Pipeline object.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{Pipeline, Ruler};
+    ///
+    /// let mut pipeline = Pipeline::new(Ruler::new(false))
+    ///     .rule("ALL .com")
+    ///     .rule("api.example.org");
+    ///
+    /// let source = "example.com\nexample.org\napi.example.org\n";
+    /// let mut output: Vec<u8> = vec![];
+    ///
+    /// let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+    ///
+    /// assert_eq!(String::from_utf8(output).unwrap(), "example.org\n");
+    /// assert_eq!(report.whitelisted, 2);
+    /// ```
+    pub fn new(ruler: Ruler) -> Pipeline {
+        Pipeline {
+            ruler,
+            source: None,
+            inputs: vec![],
+            presets: vec![],
+            rules: vec![],
+            threads: 1,
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            skip: 0,
+            limit: None,
+            sample: None,
+            dedup_capacity: 0,
+            max_memory: None,
+            temp_dir: env::temp_dir(),
+            ca_cert: None,
+            insecure: false,
+            client: None,
+            removed_domains: None,
+            verification: None,
+            output_writer: Box::new(OutputFormat::Source),
+            progress: None,
+            max_download_rate: None,
+            host_request_spacing: Duration::ZERO,
+            last_requests: HashMap::new(),
+            audit_log: None,
+            tmps: HashMap::new(),
+        }
+    }
+
+    /// Sets the source - a file path or URL - to cleanup.
+    pub fn source(mut self, source: &str) -> Pipeline {
+        self.source = Some(source.to_string());
+
+        self
+    }
+
+    /// Adds a whitelisting schema - a file path or URL - whose lines are
+    /// parsed as-it-is.
+    pub fn whitelist(mut self, input: &str) -> Pipeline {
+        self.inputs.push((input.to_string(), ""));
+
+        self
+    }
+
+    /// Adds a whitelisting schema - a file path or URL - whose lines are
+    /// prefixed with the `ALL ` flag.
+    pub fn all(mut self, input: &str) -> Pipeline {
+        self.inputs.push((input.to_string(), "ALL "));
+
+        self
+    }
+
+    /// Adds a whitelisting schema - a file path or URL - whose lines are
+    /// prefixed with the `REG ` flag.
+    pub fn reg(mut self, input: &str) -> Pipeline {
+        self.inputs.push((input.to_string(), "REG "));
+
+        self
+    }
+
+    /// Adds a whitelisting schema - a file path or URL - whose lines are
+    /// prefixed with the `RZD ` flag.
+    pub fn rzd(mut self, input: &str) -> Pipeline {
+        self.inputs.push((input.to_string(), "RZD "));
+
+        self
+    }
+
+    /// Adds a built-in preset - e.g. `core` - whose rules are parsed
+    /// as-it-is. An unknown preset is reported by [`Pipeline::load`].
+    pub fn preset(mut self, name: &str) -> Pipeline {
+        self.presets.push(name.to_string());
+
+        self
+    }
+
+    /// Adds a single rule that is parsed as-it-is. Its origin is
+    /// `command line:<n>` - for the n-th rule given.
+    pub fn rule(mut self, rule: &str) -> Pipeline {
+        self.rules.push(rule.to_string());
+
+        self
+    }
+
+    /// Sets the number of threads used to filter the source.
+    ///
+    /// The output is written in the same order as the source - regardless of
+    /// the number of threads. Default: `1`.
+    pub fn threads(mut self, threads: usize) -> Pipeline {
+        self.threads = threads.max(1);
+
+        self
+    }
+
+    /// Sets the number of chunks each channel of the worker pool can hold.
+    ///
+    /// When the workers are slower than the source, the source is no longer
+    /// read until they catch up. This caps the memory used by the
+    /// multithreaded filter. Default: [`DEFAULT_CHANNEL_CAPACITY`].
+    pub fn channel_capacity(mut self, capacity: usize) -> Pipeline {
+        self.channel_capacity = capacity.max(1);
+
+        self
+    }
+
+    /// Sets the number of lines sent at once to the workers of the
+    /// multithreaded filter. Default: [`DEFAULT_CHUNK_SIZE`].
+    pub fn chunk_size(mut self, chunk_size: usize) -> Pipeline {
+        self.chunk_size = chunk_size.max(1);
+
+        self
+    }
+
+    /// Ignores the given number of lines at the start of the source.
+    ///
+    /// The skipped lines are neither checked nor written - as if the source
+    /// started after them. Combined with [`Pipeline::limit`], a window of the
+    /// source can be checked on its own.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{Pipeline, Ruler};
+    ///
+    /// let mut pipeline = Pipeline::new(Ruler::new(false))
+    ///     .rule("example.org")
+    ///     .skip(1)
+    ///     .limit(2);
+    ///
+    /// let source = "example.com\nexample.org\nexample.net\nexample.de\n";
+    /// let mut output: Vec<u8> = vec![];
+    ///
+    /// let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+    ///
+    /// assert_eq!(String::from_utf8(output).unwrap(), "example.net\n");
+    /// assert_eq!(report.source_lines, 2);
+    /// ```
+    pub fn skip(mut self, lines: usize) -> Pipeline {
+        self.skip = lines;
+
+        self
+    }
+
+    /// Stops reading the source after the given number of lines - counted
+    /// after the ones ignored by [`Pipeline::skip`]. Default: no limit.
+    pub fn limit(mut self, lines: usize) -> Pipeline {
+        self.limit = Some(lines);
+
+        self
+    }
+
+    /// Only checks - and writes - a sample of the source.
+    ///
+    /// The lines are written in the same order as the source. The number of
+    /// lines the whole source would lose is available through
+    /// [`PipelineReport::estimated_whitelisted`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::pipeline::Sample;
+    /// use tivilsta::{Pipeline, Ruler};
+    ///
+    /// let mut pipeline = Pipeline::new(Ruler::new(false))
+    ///     .rule("ALL .example.org")
+    ///     .sample(Sample::Lines(10));
+    ///
+    /// let source: String = (0..1000).map(|index| format!("{}.example.org\n", index)).collect();
+    /// let mut output: Vec<u8> = vec![];
+    ///
+    /// let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+    ///
+    /// assert_eq!(report.read, 10);
+    /// assert_eq!(report.estimated_whitelisted(), 1000);
+    /// ```
+    pub fn sample(mut self, sample: Sample) -> Pipeline {
+        self.sample = Some(sample);
+
+        self
+    }
+
+    /// Reuses the decision made for a line already seen - instead of matching
+    /// it again.
+    ///
+    /// At most `capacity` distinct lines are remembered - the least recently
+    /// seen are forgotten first. When filtering with multiple threads, the
+    /// capacity is shared between them and each thread only remembers the
+    /// lines it has seen. `0` disables the deduplication - which is the
+    /// default.
+    ///
+    /// **Note:** The duplicated lines are still written into the output.
+    pub fn dedup_input(mut self, capacity: usize) -> Pipeline {
+        self.dedup_capacity = capacity;
+
+        self
+    }
+
+    /// Caps - in bytes - the memory used by the buffers of the pipeline.
+    ///
+    /// A quarter of it goes to each of: the deduplication - fewer distinct
+    /// lines are remembered -, the queues between the threads - fewer chunks
+    /// are held -, the chunks waiting for the previous ones to be written and
+    /// the members of the archives. The last two are spilled into temporary
+    /// files once they exceed their share. The whitelisting schemas are also
+    /// parsed line by line instead of being read at once. Default: no limit.
+    ///
+    /// **Note:** The rules themselves - and the sample picked by
+    /// [`Sample::Lines`] - are still held in memory.
+    pub fn max_memory(mut self, bytes: usize) -> Pipeline {
+        self.max_memory = Some(bytes);
+
+        self
+    }
+
+    /// Sets how the lines that are not whitelisted are written.
+    /// Default: [`OutputFormat::Source`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::pipeline::OutputFormat;
+    /// use tivilsta::{Pipeline, Ruler};
+    ///
+    /// let mut pipeline = Pipeline::new(Ruler::new(false))
+    ///     .rule("example.org")
+    ///     .output_format(OutputFormat::Hosts(String::from("0.0.0.0")));
+    ///
+    /// let mut output: Vec<u8> = vec![];
+    ///
+    /// pipeline
+    ///     .filter("example.org\nexample.com\n".as_bytes(), &mut output)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(String::from_utf8(output).unwrap(), "0.0.0.0 example.com\n");
+    /// ```
+    pub fn output_format(self, format: OutputFormat) -> Pipeline {
+        self.output_writer(format)
+    }
+
+    /// Sets what writes the lines that are not whitelisted - e.g. in a format
+    /// that is not one of [`OutputFormat`]. See [`OutputWriter`].
+    pub fn output_writer<O: OutputWriter + 'static>(mut self, writer: O) -> Pipeline {
+        self.output_writer = Box::new(writer);
+
+        self
+    }
+
+    /// Counts the whitelisted lines per registrable domain - e.g.
+    /// `example.co.uk` for `api.example.co.uk`. See
+    /// [`Pipeline::top_removed_domains`].
+    ///
+    /// **Note:** The Public Suffix List is fetched - unless stored in the
+    /// cache directory - while loading.
+    pub fn track_removed_domains(mut self) -> Pipeline {
+        self.removed_domains = Some(RemovedDomains::default());
+
+        self
+    }
+
+    /// Provides the registrable domains with the most whitelisted lines - and
+    /// their number of lines - across all the cleanups made so far.
+    ///
+    /// **Note:** Nothing is provided unless [`Pipeline::track_removed_domains`]
+    /// was called.
+    pub fn top_removed_domains(&self, limit: usize) -> Vec<(String, usize)> {
+        let Some(removed_domains) = &self.removed_domains else {
+            return vec![];
+        };
+
+        let mut result: Vec<(String, usize)> = removed_domains
+            .counts
+            .iter()
+            .map(|(domain, count)| (domain.to_string(), *count))
+            .collect();
+
+        result.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        result.truncate(limit);
+        result
+    }
+
+    /// Verifies the subject of every line that is about to be removed - e.g.
+    /// whether it still resolves - through the given `verify` function.
+    ///
+    /// The lines whose subject failed the verification are counted - see
+    /// [`PipelineReport::unverified`] - and removed or kept depending on the
+    /// given `action`. The audit log tells whether each removal was verified.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::pipeline::UnverifiedAction;
+    /// use tivilsta::{Pipeline, Ruler};
+    ///
+    /// let mut pipeline = Pipeline::new(Ruler::new(false))
+    ///     .rule("ALL .example.org")
+    ///     .verify_removals(|subject: &str| subject != "dead.example.org", UnverifiedAction::Retain);
+    ///
+    /// let mut output: Vec<u8> = vec![];
+    /// let report = pipeline
+    ///     .filter("api.example.org\ndead.example.org\n".as_bytes(), &mut output)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(String::from_utf8(output).unwrap(), "dead.example.org\n");
+    /// assert_eq!(report.unverified, 1);
+    /// ```
+    pub fn verify_removals<F>(mut self, verify: F, action: UnverifiedAction) -> Pipeline
+    where
+        F: FnMut(&str) -> bool + 'static,
+    {
+        self.verification = Some(Verification {
+            verify: Box::new(verify),
+            action,
+            rules: HashMap::new(),
+        });
+
+        self
+    }
+
+    /// Provides the rules that only matched subjects failing the verification
+    /// - and their number of matches - across all the cleanups made so far.
+    /// These are likely rules that now only match dead domains.
+    ///
+    /// **Note:** Nothing is provided unless [`Pipeline::verify_removals`] was
+    /// called.
+    pub fn unverified_rules(&self) -> Vec<(String, usize)> {
+        let Some(verification) = &self.verification else {
+            return vec![];
+        };
+
+        let mut result: Vec<(String, usize)> = verification
+            .rules
+            .iter()
+            .filter(|(_, (verified, _))| *verified == 0)
+            .map(|(rule, (_, unverified))| (rule.to_string(), *unverified))
+            .collect();
+
+        result.sort_unstable();
+        result
+    }
+
+    /// Sets where to write the audit log.
+    ///
+    /// The audit log is a JSON Lines document with one object per line of the
+    /// source describing the decision that was made:
+    ///
+    /// ```json
+    /// {"decision":"whitelisted","kind":"ends","rule":".example.org","source":"whitelist.list:12","subject":"api.example.org","timestamp":1700000000.0,"verified":null}
+    /// ```
+    ///
+    /// **Note:** The `source` of the rule is its file (or URL) and line,
+    /// `preset <name>:<line>` for the rules of a preset and
+    /// `command line:<n>` for the n-th rule given through [`Pipeline::rule`].
+    /// It is `null` when unknown, e.g. for the rules parsed through
+    /// [`Ruler::parse`] before the ruler was given to the pipeline. `verified`
+    /// is `null` unless the removals are verified - see
+    /// [`Pipeline::verify_removals`].
+    pub fn audit_log<W: Write + 'static>(mut self, audit_log: W) -> Pipeline {
+        self.audit_log = Some(Box::new(BufWriter::new(audit_log)));
+
+        self
+    }
+
+    /// Sets the function to call while downloading remote files.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{Pipeline, Ruler};
+    ///
+    /// let pipeline = Pipeline::new(Ruler::new(false)).on_download_progress(
+    ///     |url: &str, downloaded: u64, total: Option<u64>| match total {
+    ///         Some(total) => eprintln!("{}: {}/{} bytes", url, downloaded, total),
+    ///         None => eprintln!("{}: {} bytes", url, downloaded),
+    ///     },
+    /// );
+    /// ```
+    pub fn on_download_progress<F>(mut self, progress: F) -> Pipeline
+    where
+        F: FnMut(&str, u64, Option<u64>) + 'static,
+    {
+        self.progress = Some(Box::new(progress));
+
+        self
+    }
+
+    /// Caps the speed - in bytes per second - of each download. Default: no
+    /// limit.
+    pub fn max_download_rate(mut self, bytes_per_second: u64) -> Pipeline {
+        self.max_download_rate = Some(bytes_per_second.max(1));
+
+        self
+    }
+
+    /// Sets the minimum delay between two requests sent to the same host.
+    ///
+    /// Pulling many lists from the same mirror - without delay - may trip its
+    /// rate limits. Default: no delay.
+    pub fn host_request_spacing(mut self, spacing: Duration) -> Pipeline {
+        self.host_request_spacing = spacing;
+
+        self
+    }
+
+    /// Sets the directory the downloaded and spilled files are written into.
+    /// They are removed once they are no longer needed.
+    ///
+    /// Default: the temporary directory of the system - which honors `TMPDIR`.
+    pub fn temp_dir(mut self, dir: &Path) -> Pipeline {
+        self.temp_dir = dir.to_path_buf();
+
+        self
+    }
+
+    /// Trusts the certificate authorities of the given PEM bundle - in
+    /// addition to the ones of the system - when downloading. Useful for the
+    /// internal mirrors using a private certificate authority.
+    pub fn ca_cert(mut self, path: &Path) -> Pipeline {
+        self.ca_cert = Some(path.to_path_buf());
+        self.client = None;
+
+        self
+    }
+
+    /// Accepts the certificates of the remote servers without verifying them
+    /// when downloading.
+    ///
+    /// **Dangerous:** anyone between us and the remote servers can then tamper
+    /// with the downloaded whitelisting schemas and source. Prefer
+    /// [`Pipeline::ca_cert`].
+    pub fn insecure(mut self) -> Pipeline {
+        self.insecure = true;
+        self.client = None;
+
+        self
+    }
+
+    /// Provides the share of [`Pipeline::max_memory`] given to each buffer.
+    fn memory_share(&self) -> Option<usize> {
+        self.max_memory.map(|bytes| bytes / 4)
+    }
+
+    /// Provides the number of distinct lines the deduplication remembers -
+    /// within the memory share given to it.
+    fn effective_dedup_capacity(&self) -> usize {
+        match self.memory_share() {
+            Some(share) => self.dedup_capacity.min(share / ESTIMATED_LINE_SIZE),
+            None => self.dedup_capacity,
+        }
+    }
+
+    /// Provides the number of chunks each queue between the threads holds -
+    /// within the memory share given to them.
+    fn effective_channel_capacity(&self) -> usize {
+        match self.memory_share() {
+            Some(share) => self
+                .channel_capacity
+                .min(share / (2 * self.chunk_size * ESTIMATED_LINE_SIZE))
+                .max(1),
+            None => self.channel_capacity,
+        }
+    }
+
+    /// Provides the ruler the whitelisting schemas are loaded into.
+    pub fn ruler(&self) -> &Ruler {
+        &self.ruler
+    }
+
+    /// Provides the ruler the whitelisting schemas are loaded into.
+    pub fn ruler_mut(&mut self) -> &mut Ruler {
+        &mut self.ruler
+    }
+
+    /// Opens the given `user_input` - after downloading it if it is a URL.
+    /// A URL is only downloaded once. The errors mention the given
+    /// `user_input`.
+    fn open(&mut self, user_input: &String) -> Result<File, TivilstaError> {
+        if let Some(path) = self.tmps.get(user_input) {
+            return File::open(path).map_err(|error| TivilstaError::io(user_input, error));
+        }
+
+        let url = registry::resolve(user_input)?;
+
+        if url.contains("://") {
+            self.wait_for_host(&url);
+        }
+
+        if self.client.is_none() {
+            self.client = Some(utils::http_client(self.ca_cert.as_deref(), self.insecure)?);
+        }
+
+        let max_rate = self.max_download_rate;
+        let started = Instant::now();
+        let mut progress = self.progress.as_mut();
+
+        let (path, downloaded) = utils::try_download_file_with_progress(
+            &url,
+            self.client.as_ref().unwrap(),
+            &self.temp_dir,
+            &mut |downloaded, total| {
+                if let Some(max_rate) = max_rate {
+                    throttle(started, downloaded, max_rate);
+                }
+
+                if let Some(progress) = progress.as_mut() {
+                    progress(user_input, downloaded, total);
+                }
+            },
+        )
+        .map_err(|error| TivilstaError::Download {
+            url: user_input.to_string(),
+            source: Box::new(error),
+        })?;
+
+        if downloaded {
+            self.tmps.insert(user_input.to_string(), path.clone());
+        }
+
+        File::open(&path).map_err(|error| TivilstaError::io(user_input, error))
+    }
+
+    /// Waits until the host of the given `url` can be requested again - see
+    /// [`Pipeline::host_request_spacing`].
+    fn wait_for_host(&mut self, url: &str) {
+        let host = utils::extract_netloc(url);
+
+        if let Some(last_request) = self.last_requests.get(&host) {
+            let next_request = *last_request + self.host_request_spacing;
+            let now = Instant::now();
+
+            if next_request > now {
+                thread::sleep(next_request - now);
+            }
+        }
+
+        self.last_requests.insert(host, Instant::now());
+    }
+
+    /// Opens the given whitelisting schemas - and each member of the archives
+    /// as its own schema. The paths of the members spilled into temporary
+    /// files are pushed into `spilled`.
+    fn open_inputs(
+        &mut self,
+        inputs: &[(String, &'static str)],
+        spilled: &mut Vec<String>,
+    ) -> Result<Vec<Schema>, TivilstaError> {
+        let max_in_memory = self.memory_share().map_or(u64::MAX, |share| share as u64);
+        let mut files: Vec<Schema> = vec![];
+
+        for (input, flag) in inputs {
+            let file = self.open(input)?;
+            let copy = file
+                .try_clone()
+                .map_err(|error| TivilstaError::io(input, error))?;
+
+            let Some(members) =
+                utils::read_archive_within(input, copy, max_in_memory, &self.temp_dir).map_err(
+                    |error| TivilstaError::Input {
+                        input: input.to_string(),
+                        source: Box::new(error),
+                    },
+                )?
+            else {
+                files.push((input.to_string(), Box::new(BufReader::new(file)), flag));
+                continue;
+            };
+
+            for (path, member) in members {
+                let name = format!("{}!{}", input, path);
+
+                match member {
+                    ArchiveMember::InMemory(content) => {
+                        files.push((name, Box::new(Cursor::new(content)), flag))
+                    }
+                    ArchiveMember::Spilled(tmp_path) => {
+                        let file =
+                            File::open(&tmp_path).map_err(|error| TivilstaError::io(&name, error));
+
+                        spilled.push(tmp_path);
+                        files.push((name, Box::new(BufReader::new(file?)), flag));
+                    }
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Provides the SHA-256 digest of the source and of all pending
+    /// whitelisting schemas, presets and rules.
+    ///
+    /// The inputs are downloaded - once - when needed. Comparing the digest
+    /// with the one of a previous run tells whether the inputs changed since.
+    ///
+    /// # Returns
+    ///
+    /// The hexadecimal digest or an error if one of the inputs can't be
+    /// downloaded or read.
+    pub fn digest(&mut self) -> Result<String, TivilstaError> {
+        let mut hasher = Sha256::new();
+        let mut inputs: Vec<(String, &str)> = self.inputs.clone();
+
+        if let Some(source) = &self.source {
+            inputs.insert(0, (source.to_string(), "SOURCE "));
+        }
+
+        for (input, flag) in inputs {
+            hasher.update(format!("{}{}\n", flag, input));
+
+            let mut file = self.open(&input)?;
+
+            std::io::copy(&mut file, &mut hasher)
+                .map_err(|error| TivilstaError::io(&input, error))?;
+        }
+
+        for name in &self.presets {
+            hasher.update(format!("PRESET {}\n", name));
+        }
+
+        for rule in &self.rules {
+            hasher.update(format!("RULE {}\n", rule));
+        }
+
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Downloads and parses all pending whitelisting schemas and rules into
+    /// the ruler.
+    ///
+    /// The schemas named like a tar - `.tar`, `.tar.gz`, `.tgz`, `.tar.zst` -
+    /// or zip archive are unpacked in memory - or into temporary files beyond
+    /// [`Pipeline::max_memory`] - and each of their files is parsed as its own
+    /// schema. The errors mention the member - e.g.
+    /// `rules.tar.gz!rules/ads.list:12: ...`.
+    ///
+    /// Every schema is read at once - or line by line when
+    /// [`Pipeline::max_memory`] is given - and parsed - in parallel - into its
+    /// own ruler before being merged into ours. Schemas and rules that have
+    /// already been loaded are not loaded again.
+    ///
+    /// # Returns
+    ///
+    /// An error if one of the schemas can't be downloaded or read or if one of
+    /// the rules is invalid.
+    pub fn load(&mut self) -> Result<(), TivilstaError> {
+        if let Some(removed_domains) = self.removed_domains.as_mut() {
+            if removed_domains.suffixes.is_none() {
+                removed_domains.suffixes = Some(PublicSuffixes::load()?);
+            }
+        }
+
+        let inputs = std::mem::take(&mut self.inputs);
+        let mut spilled = vec![];
+        let files = self.open_inputs(&inputs, &mut spilled);
+        let streamed = self.max_memory.is_some();
+
+        let rulers = files.and_then(|files| {
+            #[cfg(feature = "parallel")]
+            let files = files.into_par_iter();
+            #[cfg(not(feature = "parallel"))]
+            let files = files.into_iter();
+
+            files
+                .map(|(input, mut reader, flag)| {
+                    let mut ruler = self.ruler.empty_like();
+
+                    if streamed {
+                        ruler.stream_parse(reader, flag, &input)
+                    } else {
+                        let mut content = String::new();
+
+                        reader
+                            .read_to_string(&mut content)
+                            .map_err(|error| TivilstaError::io(&input, error))?;
+
+                        ruler.bulk_parse(&content, flag, &input)
+                    }?;
+
+                    Ok(ruler)
+                })
+                .collect::<Result<Vec<Ruler>, TivilstaError>>()
+        });
+
+        for tmp_path in spilled {
+            let _ = fs::remove_file(tmp_path);
+        }
+
+        for ruler in rulers? {
+            self.ruler.merge(ruler);
+        }
+
+        for name in std::mem::take(&mut self.presets) {
+            let rules = presets::rules(&name).ok_or_else(|| TivilstaError::UnknownPreset {
+                name: name.to_string(),
+            })?;
+
+            let origin = format!("preset {}", name);
+
+            for (index, rule) in rules.iter().enumerate() {
+                self.ruler
+                    .try_parse_rule_with_origin(rule, RuleOrigin::new(&origin, index + 1))
+                    .map_err(|error| TivilstaError::Rule {
+                        origin: origin.to_string(),
+                        line: index + 1,
+                        source: Box::new(error),
+                    })?;
+            }
+        }
+
+        for (index, rule) in std::mem::take(&mut self.rules).iter().enumerate() {
+            self.ruler
+                .try_parse_rule_with_origin(rule, RuleOrigin::new(COMMAND_LINE, index + 1))
+                .map_err(|error| TivilstaError::Rule {
+                    origin: COMMAND_LINE.to_string(),
+                    line: index + 1,
+                    source: Box::new(error),
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes every line of `source` that is not whitelisted into `output`.
+    ///
+    /// The pending whitelisting schemas and rules are loaded first.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The lines to cleanup.
+    /// * `output` - Where to write the lines that are not whitelisted.
+    /// The writes are buffered, so there is no need to wrap it into a
+    /// `BufWriter`.
+    ///
+    /// # Returns
+    ///
+    /// A summary of the cleanup.
+    pub fn filter<R: BufRead + Send, W: Write>(
+        &mut self,
+        source: R,
+        output: W,
+    ) -> Result<PipelineReport, TivilstaError> {
+        self.load()?;
+
+        let mut output = BufWriter::new(output);
+
+        let output_error = |error| TivilstaError::io(OUTPUT, error);
+
+        let report = if self.output_writer.counts_entries() {
+            let mut entries: Vec<u8> = vec![];
+            let report = self.filter_source(source, &mut entries)?;
+            let count = entries.iter().filter(|x| **x == b'\n').count();
+
+            self.output_writer
+                .write_header(count, &mut output)
+                .map_err(output_error)?;
+            output.write_all(&entries).map_err(output_error)?;
+
+            report
+        } else {
+            self.output_writer
+                .write_header(0, &mut output)
+                .map_err(output_error)?;
+            self.filter_source(source, &mut output)?
+        };
+
+        output.flush().map_err(output_error)?;
+
+        if let Some(audit_log) = self.audit_log.as_mut() {
+            audit_log
+                .flush()
+                .map_err(|error| TivilstaError::io(AUDIT_LOG, error))?;
+        }
+
+        Ok(report)
+    }
+
+    /// Writes every line of `source` - within the skipped, limited and sampled
+    /// ones - that is not whitelisted into `output`.
+    fn filter_source<R: BufRead + Send, W: Write>(
+        &mut self,
+        source: R,
+        output: &mut W,
+    ) -> Result<PipelineReport, TivilstaError> {
+        let source_lines = AtomicUsize::new(0);
+        let lines = source
+            .lines()
+            .skip(self.skip)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .inspect(|_| {
+                source_lines.fetch_add(1, Ordering::Relaxed);
+            });
+
+        let mut report = match self.sample {
+            None => self.filter_lines(lines, output)?,
+            Some(Sample::Ratio(ratio)) => {
+                let ratio = ratio.clamp(0.0, 1.0);
+                let mut rng = StdRng::from_entropy();
+
+                self.filter_lines(
+                    lines.filter(move |line| line.is_err() || rng.gen_bool(ratio)),
+                    output,
+                )?
+            }
+            Some(Sample::Lines(size)) => {
+                let sample = reservoir_sample(lines, size)
+                    .map_err(|error| TivilstaError::io(SOURCE, error))?;
+
+                self.filter_lines(sample.into_iter().map(Ok), output)?
+            }
+        };
+
+        report.source_lines = source_lines.into_inner();
+
+        Ok(report)
+    }
+
+    /// Writes every given line that is not whitelisted into `output`.
+    fn filter_lines<I, W>(
+        &mut self,
+        lines: I,
+        output: &mut W,
+    ) -> Result<PipelineReport, TivilstaError>
+    where
+        I: Iterator<Item = std::io::Result<String>> + Send,
+        W: Write,
+    {
+        if self.threads > 1 {
+            return self.multithreaded_filter(lines, output);
+        }
+
+        let mut report = PipelineReport::default();
+        let mut decider = Decider::new(&self.ruler, self.effective_dedup_capacity());
+
+        for line in lines {
+            let decision = decider.decide(line.map_err(|error| TivilstaError::io(SOURCE, error))?);
+
+            write_decision(
+                decision,
+                output,
+                self.output_writer.as_ref(),
+                &mut self.audit_log,
+                &mut self.removed_domains,
+                &mut self.verification,
+                &mut report,
+            )?;
+        }
+
+        Ok(report)
+    }
+
+    /// Same as `filter_lines` - with the lines dispatched to a pool of workers.
+    ///
+    /// The source is split into chunks of lines tagged with their position in
+    /// the source. The results are then held back until all the previous
+    /// chunks have been written.
+    fn multithreaded_filter<I, W>(
+        &mut self,
+        lines: I,
+        output: &mut W,
+    ) -> Result<PipelineReport, TivilstaError>
+    where
+        I: Iterator<Item = std::io::Result<String>> + Send,
+        W: Write,
+    {
+        let mut report = PipelineReport::default();
+        let chunk_size = self.chunk_size;
+        let channel_capacity = self.effective_channel_capacity();
+        let max_pending = self.memory_share();
+        let dedup_capacity = match self.effective_dedup_capacity() {
+            0 => 0,
+            capacity => (capacity / self.threads).max(1),
+        };
+
+        thread::scope(|scope| -> Result<(), TivilstaError> {
+            let (work_sender, work_receiver) =
+                mpsc::sync_channel::<(usize, Vec<String>)>(channel_capacity);
+            let (result_sender, result_receiver) =
+                mpsc::sync_channel::<(usize, Vec<Decision>)>(channel_capacity);
+            let work_receiver = Arc::new(Mutex::new(work_receiver));
+            // The matching is read-only, so the workers borrow our ruler
+            // instead of copying it. Only the caches are kept per worker.
+            let ruler = &self.ruler;
+
+            let reader = scope.spawn(move || -> std::io::Result<()> {
+                let mut chunk: Vec<String> = Vec::with_capacity(chunk_size);
+                let mut index = 0;
+
+                for line in lines {
+                    chunk.push(line?);
+
+                    if chunk.len() < chunk_size {
+                        continue;
+                    }
+
+                    let full_chunk = std::mem::replace(&mut chunk, Vec::with_capacity(chunk_size));
+
+                    if work_sender.send((index, full_chunk)).is_err() {
+                        return Ok(());
+                    }
+
+                    index += 1;
+                }
+
+                if !chunk.is_empty() {
+                    let _ = work_sender.send((index, chunk));
+                }
+
+                Ok(())
+            });
+
+            for _ in 0..self.threads {
+                let mut decider = Decider::new(ruler, dedup_capacity);
+                let work_receiver = Arc::clone(&work_receiver);
+                let result_sender = result_sender.clone();
+
+                scope.spawn(move || loop {
+                    let work = work_receiver.lock().unwrap().recv();
+
+                    let Ok((index, chunk)) = work else {
+                        break;
+                    };
+
+                    let results = chunk.into_iter().map(|line| decider.decide(line)).collect();
+
+                    if result_sender.send((index, results)).is_err() {
+                        break;
+                    }
+                });
+            }
+
+            drop(work_receiver);
+            drop(result_sender);
+
+            let mut pending = ReorderBuffer::new(max_pending, &self.temp_dir);
+            let mut next = 0;
+
+            for (index, decisions) in result_receiver {
+                pending.insert(index, decisions)?;
+
+                while let Some(decisions) = pending.remove(next)? {
+                    next += 1;
+
+                    for decision in decisions {
+                        write_decision(
+                            decision,
+                            output,
+                            self.output_writer.as_ref(),
+                            &mut self.audit_log,
+                            &mut self.removed_domains,
+                            &mut self.verification,
+                            &mut report,
+                        )?;
+                    }
+                }
+            }
+
+            reader
+                .join()
+                .expect("The source reader panicked.")
+                .map_err(|error| TivilstaError::io(SOURCE, error))?;
+
+            Ok(())
+        })?;
+
+        Ok(report)
+    }
+
+    /// Writes every line of the source that is not whitelisted into `output`.
+    ///
+    /// Gzip and zstd compressed sources are decompressed on the fly.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Where to write the lines that are not whitelisted.
+    ///
+    /// # Returns
+    ///
+    /// A summary of the cleanup or an error if no source was given or if it
+    /// can't be read.
+    pub fn run<W: Write>(&mut self, output: W) -> Result<PipelineReport, TivilstaError> {
+        let source = self.source.clone().ok_or(TivilstaError::MissingSource)?;
+        let file = self.open(&source)?;
+        let reader =
+            utils::open_decompressed(file).map_err(|error| TivilstaError::io(&source, error))?;
+
+        self.filter(reader, output)
+    }
+
+    /// Checks every subject of the given newline-delimited `subjects` and
+    /// streams one JSON object per subject - describing the decision that
+    /// was made and the matching rule - into `output`.
+    ///
+    /// The decisions are written - and flushed - as soon as they are made.
+    /// This makes it possible to check millions of subjects - e.g. from a
+    /// request body - without building giant JSON arrays, and to read each
+    /// decision while the following subjects are still being sent. The empty
+    /// lines are skipped.
+    ///
+    /// # Returns
+    ///
+    /// The number of checked subjects or an error if the pending whitelisting
+    /// schemas can't be loaded or if the `subjects` can't be read.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::{Pipeline, Ruler};
+    ///
+    /// let mut pipeline = Pipeline::new(Ruler::new(false)).rule("ALL .example.org");
+    /// let mut output: Vec<u8> = vec![];
+    ///
+    /// let checked = pipeline
+    ///     .check_stream("api.example.org\nexample.com\n".as_bytes(), &mut output)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(checked, 2);
+    /// assert_eq!(
+    ///     String::from_utf8(output).unwrap(),
+    ///     "{\"kind\":\"ends\",\"rule\":\".example.org\",\"subject\":\"api.example.org\",\"whitelisted\":true}\n\
+    ///      {\"kind\":null,\"rule\":null,\"subject\":\"example.com\",\"whitelisted\":false}\n"
+    /// );
+    /// ```
+    pub fn check_stream<R: BufRead, W: Write>(
+        &mut self,
+        subjects: R,
+        output: W,
+    ) -> Result<usize, TivilstaError> {
+        self.load()?;
+
+        let mut decider = Decider::new(&self.ruler, self.effective_dedup_capacity());
+        let mut output = BufWriter::new(output);
+        let mut checked = 0;
+
+        let output_error = |error| TivilstaError::io(OUTPUT, error);
+
+        for subject in subjects.lines() {
+            let subject = subject.map_err(|error| TivilstaError::io(SOURCE, error))?;
+
+            if subject.trim().is_empty() {
+                continue;
+            }
+
+            let decision = decider.decide(subject);
+            let entry = json!({
+                "subject": decision.line,
+                "whitelisted": decision.rule.is_some(),
+                "rule": decision.rule.as_ref().map(|(_, rule)| rule),
+                "kind": decision.rule.as_ref().map(|(kind, _)| kind.as_str()),
+            });
+
+            serde_json::to_writer(&mut output, &entry)
+                .map_err(|error| output_error(error.into()))?;
+            output.write_all(b"\n").map_err(output_error)?;
+            // The line is written at once - without waiting for the following
+            // subjects.
+            output.flush().map_err(output_error)?;
+            checked += 1;
+        }
+
+        Ok(checked)
+    }
+}
+
+/// Sleeps until the given number of `downloaded` bytes - since `started` -
+/// no longer exceeds the given `max_rate` - in bytes per second.
+fn throttle(started: Instant, downloaded: u64, max_rate: u64) {
+    let expected = Duration::from_secs_f64(downloaded as f64 / max_rate as f64);
+    let elapsed = started.elapsed();
+
+    if expected > elapsed {
+        thread::sleep(expected - elapsed);
+    }
+}
+
+/// The chunks of decisions waiting for the previous chunks to be written.
+///
+/// They are held in memory until they occupy more than `max_bytes` - the
+/// following chunks are then spilled into a temporary file.
+struct ReorderBuffer {
+    chunks: HashMap<usize, Vec<Decision>>,
+    bytes: usize,
+    max_bytes: Option<usize>,
+    /// The position and length of each chunk spilled into `spill`.
+    spilled: HashMap<usize, (u64, usize)>,
+    spill: Option<(String, File)>,
+    temp_dir: PathBuf,
+}
+
+impl ReorderBuffer {
+    fn new(max_bytes: Option<usize>, temp_dir: &Path) -> ReorderBuffer {
+        ReorderBuffer {
+            chunks: HashMap::new(),
+            bytes: 0,
+            max_bytes,
+            spilled: HashMap::new(),
+            spill: None,
+            temp_dir: temp_dir.to_path_buf(),
+        }
+    }
+
+    /// Provides the estimated number of bytes the given chunk occupies.
+    fn size_of(decisions: &[Decision]) -> usize {
+        decisions
+            .iter()
+            .map(|decision| {
+                std::mem::size_of::<Decision>()
+                    + decision.line.len()
+                    + decision.rule.as_ref().map_or(0, |(_, rule)| rule.len())
+                    + decision.source.as_ref().map_or(0, String::len)
+            })
+            .sum()
+    }
+
+    fn insert(&mut self, index: usize, decisions: Vec<Decision>) -> Result<(), TivilstaError> {
+        let size = ReorderBuffer::size_of(&decisions);
+
+        match self.max_bytes {
+            Some(max_bytes) if self.bytes + size > max_bytes => self.spill(index, &decisions),
+            _ => {
+                self.bytes += size;
+                self.chunks.insert(index, decisions);
+
+                Ok(())
+            }
+        }
+    }
+
+    fn spill(&mut self, index: usize, decisions: &[Decision]) -> Result<(), TivilstaError> {
+        if self.spill.is_none() {
+            let tmp_path = utils::random_temp_path_in(&self.temp_dir);
+            let file = File::options()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&tmp_path)
+                .map_err(|error| TivilstaError::io(&tmp_path, error))?;
+
+            self.spill = Some((tmp_path, file));
+        }
+
+        let (tmp_path, file) = self.spill.as_mut().unwrap();
+        let spill_error = |error| TivilstaError::io(&tmp_path, error);
+        let entries: Vec<SpilledDecision<&str>> = decisions
+            .iter()
+            .map(|decision| {
+                (
+                    decision.line.as_str(),
+                    decision
+                        .rule
+                        .as_ref()
+                        .map(|(kind, rule)| (kind.as_str(), rule.as_str())),
+                    decision.source.as_deref(),
+                )
+            })
+            .collect();
+        let content = serde_json::to_vec(&entries).map_err(|error| spill_error(error.into()))?;
+        let position = file.seek(SeekFrom::End(0)).map_err(spill_error)?;
+
+        file.write_all(&content).map_err(spill_error)?;
+        self.spilled.insert(index, (position, content.len()));
+
+        Ok(())
+    }
+
+    /// Takes the chunk at the given `index` - if it was already inserted.
+    fn remove(&mut self, index: usize) -> Result<Option<Vec<Decision>>, TivilstaError> {
+        if let Some(decisions) = self.chunks.remove(&index) {
+            self.bytes -= ReorderBuffer::size_of(&decisions);
+
+            return Ok(Some(decisions));
+        }
+
+        let (Some((position, length)), Some((tmp_path, file))) =
+            (self.spilled.remove(&index), self.spill.as_mut())
+        else {
+            return Ok(None);
+        };
+
+        let spill_error = |error| TivilstaError::io(&tmp_path, error);
+        let mut content = vec![0; length];
+
+        file.seek(SeekFrom::Start(position)).map_err(spill_error)?;
+        file.read_exact(&mut content).map_err(spill_error)?;
+
+        let entries: Vec<SpilledDecision<String>> =
+            serde_json::from_slice(&content).map_err(|error| spill_error(error.into()))?;
+
+        Ok(Some(
+            entries
+                .into_iter()
+                .map(|(line, rule, source)| Decision {
+                    line,
+                    rule: rule.and_then(|(kind, rule)| Some((match_kind(&kind)?, rule))),
+                    source,
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl Drop for ReorderBuffer {
+    /// Ensures that the spilled chunks are cleaned up.
+    fn drop(&mut self) {
+        if let Some((tmp_path, file)) = self.spill.take() {
+            drop(file);
+            let _ = fs::remove_file(tmp_path);
+        }
+    }
+}
+
+/// Provides the kind of match named by the given `name` - see
+/// [`MatchKind::as_str`].
+fn match_kind(name: &str) -> Option<MatchKind> {
+    [
+        MatchKind::Strict,
+        MatchKind::Present,
+        MatchKind::Ends,
+        MatchKind::Regex,
+        MatchKind::UrlPrefix,
+        MatchKind::Exception,
+        MatchKind::Cidr,
+        MatchKind::Keyword,
+        MatchKind::Starts,
+    ]
+    .into_iter()
+    .find(|kind| kind.as_str() == name)
+}
+
+/// Picks `size` of the given lines at random - while keeping their order.
+fn reservoir_sample<I>(lines: I, size: usize) -> std::io::Result<Vec<String>>
+where
+    I: Iterator<Item = std::io::Result<String>>,
+{
+    let mut rng = thread_rng();
+    let mut sample: Vec<(usize, String)> = Vec::with_capacity(size);
+
+    for (index, line) in lines.enumerate() {
+        let line = line?;
+
+        if sample.len() < size {
+            sample.push((index, line));
+            continue;
+        }
+
+        let position = rng.gen_range(0..=index);
+
+        if position < size {
+            sample[position] = (index, line);
+        }
+    }
+
+    sample.sort_unstable_by_key(|(index, _)| *index);
+
+    Ok(sample.into_iter().map(|(_, line)| line).collect())
+}
+
+/// Writes the given `decision` into the audit log and - if the line is not
+/// whitelisted - into the `output`.
+fn write_decision<W: Write>(
+    mut decision: Decision,
+    output: &mut W,
+    format: &dyn OutputWriter,
+    audit_log: &mut Option<Box<dyn Write>>,
+    removed_domains: &mut Option<RemovedDomains>,
+    verification: &mut Option<Verification>,
+    report: &mut PipelineReport,
+) -> Result<(), TivilstaError> {
+    report.read += 1;
+
+    let verified = match (verification.as_mut(), &decision.rule) {
+        (Some(verification), Some((_, rule))) => Some(verification.verify(&decision.line, rule)),
+        _ => None,
+    };
+
+    if let Some(audit_log) = audit_log.as_mut() {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let entry = json!({
+            "subject": decision.line,
+            "decision": if decision.rule.is_some() { "whitelisted" } else { "kept" },
+            "rule": decision.rule.as_ref().map(|(_, rule)| rule),
+            "kind": decision.rule.as_ref().map(|(kind, _)| kind.as_str()),
+            "source": decision.source,
+            "verified": verified,
+            "timestamp": timestamp,
+        });
+
+        writeln!(audit_log, "{}", entry).map_err(|error| TivilstaError::io(AUDIT_LOG, error))?;
+    }
+
+    if verified == Some(false) {
+        report.unverified += 1;
+
+        if verification
+            .as_ref()
+            .is_some_and(|verification| verification.action == UnverifiedAction::Retain)
+        {
+            decision.rule = None;
+        }
+    }
+
+    if decision.rule.is_some() {
+        report.whitelisted += 1;
+
+        if let Some(removed_domains) = removed_domains.as_mut() {
+            removed_domains.count(&decision.line);
+        }
+
+        return Ok(());
+    }
+
+    if format
+        .write(&decision.line, output)
+        .map_err(|error| TivilstaError::io(OUTPUT, error))?
+    {
+        report.written += 1;
+    }
+
+    Ok(())
+}
+
+impl Drop for Pipeline {
+    /// Ensures that all downloaded files are cleaned up.
+    fn drop(&mut self) {
+        for file in self.tmps.values() {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_filter() {
+        let mut pipeline = Pipeline::new(Ruler::new(true))
+            .rule("example.org")
+            .rule("ALL .example.net");
+
+        let source = "example.org\nwww.example.org\nexample.com\napi.example.net\n";
+        let mut output: Vec<u8> = vec![];
+
+        let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "example.com\n");
+        assert_eq!(
+            report,
+            PipelineReport {
+                source_lines: 4,
+                read: 4,
+                whitelisted: 3,
+                written: 1,
+                unverified: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_filter_threads() {
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("ALL .example.org")
+            .threads(4)
+            .channel_capacity(2)
+            .chunk_size(7);
+
+        let source: String = (0..1000)
+            .map(|index| {
+                if index % 3 == 0 {
+                    format!("{}.example.org\n", index)
+                } else {
+                    format!("{}.example.net\n", index)
+                }
+            })
+            .collect();
+        let expected: String = (0..1000)
+            .filter(|index| index % 3 != 0)
+            .map(|index| format!("{}.example.net\n", index))
+            .collect();
+
+        let mut output: Vec<u8> = vec![];
+
+        let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        assert_eq!(
+            report,
+            PipelineReport {
+                source_lines: 1000,
+                read: 1000,
+                whitelisted: 334,
+                written: 666,
+                unverified: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_window() {
+        let source: String = (0..100)
+            .map(|index| format!("{}.example.org\n", index))
+            .collect();
+
+        for threads in [1, 2] {
+            let mut pipeline = Pipeline::new(Ruler::new(false))
+                .rule("12.example.org")
+                .threads(threads)
+                .chunk_size(3)
+                .skip(10)
+                .limit(5);
+
+            let mut output: Vec<u8> = vec![];
+
+            let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "10.example.org\n11.example.org\n13.example.org\n14.example.org\n"
+            );
+            assert_eq!(report.source_lines, 5);
+            assert_eq!(report.whitelisted, 1);
+            assert!(!report.is_sampled());
+        }
+
+        let mut output: Vec<u8> = vec![];
+
+        let report = Pipeline::new(Ruler::new(false))
+            .skip(98)
+            .limit(5)
+            .filter(source.as_bytes(), &mut output)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "98.example.org\n99.example.org\n"
+        );
+        assert_eq!(report.source_lines, 2);
+    }
+
+    #[test]
+    fn test_sample() {
+        let source: String = (0..1000)
+            .map(|index| {
+                if index % 4 == 0 {
+                    format!("{}.example.org\n", index)
+                } else {
+                    format!("{}.example.net\n", index)
+                }
+            })
+            .collect();
+
+        for threads in [1, 4] {
+            let mut pipeline = Pipeline::new(Ruler::new(false))
+                .rule("ALL .example.org")
+                .threads(threads)
+                .sample(Sample::Lines(100));
+
+            let mut output: Vec<u8> = vec![];
+
+            let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+            let output = String::from_utf8(output).unwrap();
+            let written: Vec<usize> = output
+                .lines()
+                .map(|line| line.split('.').next().unwrap().parse().unwrap())
+                .collect();
+
+            assert!(report.is_sampled());
+            assert_eq!(report.source_lines, 1000);
+            assert_eq!(report.read, 100);
+            assert_eq!(report.written, written.len());
+            assert!(written.windows(2).all(|pair| pair[0] < pair[1]));
+            assert_eq!(report.estimated_whitelisted(), report.whitelisted * 10);
+            assert_eq!(
+                report.estimated_written(),
+                1000 - report.estimated_whitelisted()
+            );
+        }
+
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("ALL .example.org")
+            .sample(Sample::Ratio(1.0));
+
+        let report = pipeline.filter(source.as_bytes(), std::io::sink()).unwrap();
+
+        assert!(!report.is_sampled());
+        assert_eq!(report.estimated_whitelisted(), 250);
+        assert_eq!(report.estimated_written(), 750);
+
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("ALL .example.org")
+            .sample(Sample::Ratio(0.0));
+
+        let report = pipeline.filter(source.as_bytes(), std::io::sink()).unwrap();
+
+        assert_eq!(report.read, 0);
+        assert_eq!(report.estimated_whitelisted(), 0);
+    }
+
+    #[test]
+    fn test_dedup_input() {
+        let source = "example.org\nexample.com\nexample.org\nEXAMPLE.org\nexample.com\n";
+
+        for threads in [1, 2] {
+            let audit_log = NamedTempFile::new().unwrap();
+            let mut pipeline = Pipeline::new(Ruler::new(false))
+                .rule("example.org")
+                .threads(threads)
+                .chunk_size(2)
+                .dedup_input(1)
+                .audit_log(audit_log.reopen().unwrap());
+
+            let mut output: Vec<u8> = vec![];
+
+            let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "example.com\nexample.com\n"
+            );
+            assert_eq!(report.whitelisted, 3);
+            assert_eq!(report.written, 2);
+            assert_eq!(
+                fs::read_to_string(audit_log.path())
+                    .unwrap()
+                    .matches("\"whitelisted\"")
+                    .count(),
+                3
+            );
+        }
+    }
+
+    #[test]
+    fn test_top_removed_domains() {
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("ALL .example.co.uk")
+            .rule("ALL .example.org")
+            .threads(2);
+
+        assert_eq!(pipeline.top_removed_domains(10), vec![]);
+
+        pipeline.removed_domains = Some(RemovedDomains {
+            suffixes: Some(PublicSuffixes::from_suffixes(&["uk", "co.uk", "org"])),
+            counts: HashMap::new(),
+        });
+
+        let source = "a.example.co.uk\nb.example.co.uk\nc.example.org\nexample.net\n";
+
+        pipeline.filter(source.as_bytes(), std::io::sink()).unwrap();
+
+        assert_eq!(
+            pipeline.top_removed_domains(10),
+            vec![
+                (String::from("example.co.uk"), 2),
+                (String::from("example.org"), 1)
+            ]
+        );
+        assert_eq!(
+            pipeline.top_removed_domains(1),
+            vec![(String::from("example.co.uk"), 2)]
+        );
+    }
+
+    #[test]
+    fn test_output_format_hosts() {
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("example.org")
+            .output_format(OutputFormat::Hosts(String::from("127.0.0.1")));
+
+        let source = "# Comment\n\nexample.org\nexample.com # Ads\n0.0.0.0 example.net\n";
+        let mut output: Vec<u8> = vec![];
+
+        let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "127.0.0.1 example.com\n127.0.0.1 example.net\n"
+        );
+        assert_eq!(report.written, 2);
+    }
+
+    #[test]
+    fn test_output_format_domains() {
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("example.org")
+            .output_format(OutputFormat::Domains);
+
+        let source = "# Comment\nexample.org\n0.0.0.0 example.com # Ads\n\
+            127.0.0.1 example.net example.info\nexample.dev\n";
+        let mut output: Vec<u8> = vec![];
+
+        pipeline.filter(source.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "example.com\nexample.net\nexample.info\nexample.dev\n"
+        );
+    }
+
+    #[test]
+    fn test_output_format_dnsmasq() {
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("example.org")
+            .output_format(OutputFormat::Dnsmasq);
+
+        let source = "# Comment\nexample.org\n0.0.0.0 example.com # Ads\n\
+            127.0.0.1 example.net example.info\n\n";
+        let mut output: Vec<u8> = vec![];
+
+        let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "address=/example.com/#\naddress=/example.net/#\naddress=/example.info/#\n"
+        );
+        assert_eq!(report.written, 2);
+    }
+
+    #[test]
+    fn test_output_format_rpz() {
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("example.org")
+            .output_format(OutputFormat::Rpz {
+                origin: String::from("rpz.example.net."),
+                ttl: 300,
+            });
+
+        let source = "# Comment\nexample.org\n0.0.0.0 example.com # Ads\nexample.info.\n";
+        let mut output: Vec<u8> = vec![];
+
+        let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "$TTL 300");
+        assert_eq!(lines[1], "$ORIGIN rpz.example.net.");
+        assert!(lines[2].starts_with("@ IN SOA localhost. hostmaster.localhost. ("));
+        assert!(lines[2].ends_with(" 3600 600 604800 300)"));
+        assert_eq!(lines[3], "@ IN NS localhost.");
+        assert_eq!(pipeline.output_writer.comment_marker(), ";");
+        assert_eq!(lines[4..], ["example.com CNAME .", "example.info CNAME ."]);
+        assert_eq!(report.written, 2);
+    }
+
+    #[test]
+    fn test_output_format_adguard_home() {
+        let source = "# Comment\nexample.org\n0.0.0.0 example.com # Ads\n\
+            127.0.0.1 example.net example.info\n";
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("example.org")
+            .output_format(OutputFormat::AdguardHome(None));
+        let mut output: Vec<u8> = vec![];
+
+        pipeline.filter(source.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "||example.com^\n||example.net^\n||example.info^\n"
+        );
+
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("example.org")
+            .output_format(OutputFormat::AdguardHome(Some(FilterHeader {
+                title: String::from("My blocklist"),
+                version: Some(String::from("1.2.0")),
+            })));
+        let mut output: Vec<u8> = vec![];
+
+        let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "! Title: My blocklist\n! Version: 1.2.0\n! Entries: 3\n!\n\
+            ||example.com^\n||example.net^\n||example.info^\n"
+        );
+        assert_eq!(report.written, 2);
+        assert_eq!(pipeline.output_writer.comment_marker(), "!");
+    }
+
+    /// Writes the subjects as a CSV document.
+    #[derive(Debug)]
+    struct Csv;
+
+    impl OutputWriter for Csv {
+        fn counts_entries(&self) -> bool {
+            true
+        }
+
+        fn write_header(&self, entries: usize, output: &mut dyn Write) -> std::io::Result<()> {
+            writeln!(output, "subject,entries={}", entries)
+        }
+
+        fn write(&self, line: &str, output: &mut dyn Write) -> std::io::Result<bool> {
+            for subject in hosts_subjects(line) {
+                writeln!(output, "{}", subject)?;
+            }
+
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn test_output_writer() {
+        for threads in [1, 2] {
+            let mut pipeline = Pipeline::new(Ruler::new(false))
+                .rule("example.org")
+                .threads(threads)
+                .output_writer(Csv);
+            let mut output: Vec<u8> = vec![];
+
+            let report = pipeline
+                .filter(
+                    "example.org\nexample.com\n0.0.0.0 example.net example.info\n".as_bytes(),
+                    &mut output,
+                )
+                .unwrap();
+
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "subject,entries=3\nexample.com\nexample.net\nexample.info\n"
+            );
+            assert_eq!(report.written, 2);
+            assert_eq!(pipeline.output_writer.comment_marker(), "#");
+        }
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let audit_log = NamedTempFile::new().unwrap();
+
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("ALL .example.org")
+            .audit_log(audit_log.reopen().unwrap());
+
+        let mut output: Vec<u8> = vec![];
+
+        pipeline
+            .filter("api.example.org\nexample.com\n".as_bytes(), &mut output)
+            .unwrap();
+
+        let entries: Vec<serde_json::Value> = fs::read_to_string(audit_log.path())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["subject"], "api.example.org");
+        assert_eq!(entries[0]["decision"], "whitelisted");
+        assert_eq!(entries[0]["rule"], ".example.org");
+        assert_eq!(entries[0]["kind"], "ends");
+        assert_eq!(entries[1]["subject"], "example.com");
+        assert_eq!(entries[1]["decision"], "kept");
+        assert_eq!(entries[0]["source"], "command line:1");
+        assert_eq!(entries[1]["rule"], serde_json::Value::Null);
+        assert!(entries[1]["timestamp"].is_f64());
+    }
+
+    #[test]
+    fn test_hit_counts() {
+        // The decisions reused by the deduplication are counted too.
+        for dedup in [false, true] {
+            let mut pipeline =
+                Pipeline::new(Ruler::new(false).with_track_usage(true)).rule("ALL .example.org");
+
+            if dedup {
+                pipeline = pipeline.dedup_input(16);
+            }
+
+            let mut output: Vec<u8> = vec![];
+
+            pipeline
+                .filter(
+                    "api.example.org\nexample.com\napi.example.org\n".as_bytes(),
+                    &mut output,
+                )
+                .unwrap();
+
+            let hits = pipeline.ruler().hit_counts();
+
+            assert_eq!(hits.len(), 1);
+            assert_eq!(hits[0].0.rule, ".example.org");
+            assert_eq!(hits[0].1, 2);
+        }
+    }
+
+    #[test]
+    fn test_rule_origin() {
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("example.org")
+            .rule("ALL .example.net");
+
+        pipeline.load().unwrap();
+
+        let found = pipeline.ruler().check("api.example.net").unwrap();
+
+        assert_eq!(found.origin.unwrap().path(), "command line");
+        assert_eq!(found.origin.unwrap().line(), 2);
+        assert_eq!(
+            pipeline
+                .ruler()
+                .check("example.org")
+                .unwrap()
+                .origin
+                .unwrap()
+                .to_string(),
+            "command line:1"
+        );
+    }
+
+    #[test]
+    fn test_check_stream() {
+        let mut pipeline = Pipeline::new(Ruler::new(false)).rule("ALL .example.org");
+        let mut output: Vec<u8> = vec![];
+
+        let checked = pipeline
+            .check_stream(
+                "api.example.org\n\n  \nexample.com\n".as_bytes(),
+                &mut output,
+            )
+            .unwrap();
+
+        let entries: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(checked, 2);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["subject"], "api.example.org");
+        assert_eq!(entries[0]["whitelisted"], true);
+        assert_eq!(entries[0]["kind"], "ends");
+        assert_eq!(entries[1]["subject"], "example.com");
+        assert_eq!(entries[1]["whitelisted"], false);
+        assert_eq!(entries[1]["rule"], serde_json::Value::Null);
+        assert_eq!(entries[1]["kind"], serde_json::Value::Null);
+    }
+
+    /// An output that can be read while it is being written.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A source that gives one line at a time - slowly - and records how many
+    /// decisions were written before each of its reads.
+    struct SlowSource {
+        lines: Vec<&'static str>,
+        output: SharedOutput,
+        written: Vec<usize>,
+    }
+
+    impl Read for SlowSource {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let written = self.output.0.lock().unwrap();
+
+            self.written
+                .push(written.iter().filter(|x| **x == b'\n').count());
+            drop(written);
+
+            if self.lines.is_empty() {
+                return Ok(0);
+            }
+
+            thread::sleep(Duration::from_millis(5));
+
+            let line = format!("{}\n", self.lines.remove(0));
+
+            buf[..line.len()].copy_from_slice(line.as_bytes());
+
+            Ok(line.len())
+        }
+    }
+
+    #[test]
+    fn test_check_stream_interleaved() {
+        let output = SharedOutput::default();
+        let mut source = SlowSource {
+            lines: vec!["api.example.org", "example.com", "www.example.org"],
+            output: output.clone(),
+            written: vec![],
+        };
+
+        let checked = Pipeline::new(Ruler::new(false))
+            .rule("ALL .example.org")
+            .check_stream(BufReader::new(&mut source), output.clone())
+            .unwrap();
+
+        assert_eq!(checked, 3);
+        // Each decision was written before the next subject was read.
+        assert_eq!(source.written, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_audit_log_source() {
+        let mut whitelist = NamedTempFile::new().unwrap();
+        let path = whitelist.path().to_str().unwrap().to_string();
+
+        writeln!(whitelist, "# The CDNs.\nexample.org\n.example.net").unwrap();
+
+        // Whether the schema is read at once or streamed.
+        for max_memory in [None, Some(1 << 20)] {
+            let audit_log = NamedTempFile::new().unwrap();
+            let mut pipeline = Pipeline::new(Ruler::new(false))
+                .all(&path)
+                .audit_log(audit_log.reopen().unwrap());
+
+            if let Some(max_memory) = max_memory {
+                pipeline = pipeline.max_memory(max_memory);
+            }
+
+            let mut output: Vec<u8> = vec![];
+
+            pipeline
+                .filter("api.example.net\nexample.com\n".as_bytes(), &mut output)
+                .unwrap();
+
+            let entries: Vec<serde_json::Value> = fs::read_to_string(audit_log.path())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0]["rule"], ".example.net");
+            assert_eq!(entries[0]["source"], format!("{}:3", path));
+            assert_eq!(entries[1]["source"], serde_json::Value::Null);
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let mut source = NamedTempFile::new().unwrap();
+        let mut whitelist = NamedTempFile::new().unwrap();
+        let mut all = NamedTempFile::new().unwrap();
+
+        writeln!(source, "example.org\nexample.com\napi.example.net").unwrap();
+        writeln!(whitelist, "example.org").unwrap();
+        writeln!(all, ".example.net").unwrap();
+
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .source(source.path().to_str().unwrap())
+            .whitelist(whitelist.path().to_str().unwrap())
+            .all(all.path().to_str().unwrap());
+
+        let mut output: Vec<u8> = vec![];
+
+        pipeline.run(&mut output).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "example.com\n");
+    }
+
+    #[test]
+    fn test_load_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("bundle.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&bundle).unwrap());
+
+        for (path, content) in [
+            ("rules/a.list", "example.org\n"),
+            ("b.list", ".example.net\n"),
+        ] {
+            writer
+                .start_file(path, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+
+        writer.finish().unwrap();
+
+        let mut pipeline = Pipeline::new(Ruler::new(false)).all(bundle.to_str().unwrap());
+        let mut output: Vec<u8> = vec![];
+
+        pipeline
+            .filter(
+                "example.org\napi.example.net\nexample.com\n".as_bytes(),
+                &mut output,
+            )
+            .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "example.com\n");
+
+        let mut writer = zip::ZipWriter::new(File::create(&bundle).unwrap());
+
+        writer
+            .start_file("rules/bad.list", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"example.org\n^(example\n").unwrap();
+        writer.finish().unwrap();
+
+        let error = Pipeline::new(Ruler::new(false))
+            .reg(bundle.to_str().unwrap())
+            .load()
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("bundle.zip!rules/bad.list:2: "));
+    }
+
+    #[test]
+    fn test_max_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("bundle.tar");
+        let mut builder = tar::Builder::new(File::create(&bundle).unwrap());
+
+        for (path, content) in [("a.list", "example.org\n"), ("b.list", "example.net\n")] {
+            let mut header = tar::Header::new_gnu();
+
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+
+        builder.finish().unwrap();
+
+        // Every buffer gets 16 bytes: the deduplication is disabled, the
+        // queues hold a single chunk, the pending chunks and the second member
+        // of the archive are spilled.
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .all(bundle.to_str().unwrap())
+            .threads(4)
+            .chunk_size(7)
+            .dedup_input(DEFAULT_DEDUP_CAPACITY)
+            .max_memory(64)
+            .temp_dir(dir.path());
+
+        assert_eq!(pipeline.effective_dedup_capacity(), 0);
+        assert_eq!(pipeline.effective_channel_capacity(), 1);
+
+        let source: String = (0..1000)
+            .map(|index| match index % 3 {
+                0 => format!("{}.example.org\n", index),
+                1 => format!("{}.example.net\n", index),
+                _ => format!("{}.example.com\n", index),
+            })
+            .collect();
+        let expected: String = (0..1000)
+            .filter(|index| index % 3 == 2)
+            .map(|index| format!("{}.example.com\n", index))
+            .collect();
+
+        let mut output: Vec<u8> = vec![];
+
+        let report = pipeline.filter(source.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        assert_eq!(report.whitelisted, 667);
+        // Only the archive is left - the spilled files were removed.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_preset() {
+        let mut pipeline = Pipeline::new(Ruler::new(false)).preset("core");
+        let mut output: Vec<u8> = vec![];
+
+        pipeline
+            .filter(
+                "localhost\nbroadcasthost\nwww.example.org\nexample.info\n".as_bytes(),
+                &mut output,
+            )
+            .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "example.info\n");
+
+        let found = pipeline.ruler().check("broadcasthost").unwrap();
+
+        assert_eq!(found.origin.unwrap().to_string(), "preset core:9");
+
+        let error = Pipeline::new(Ruler::new(false))
+            .preset("unknown")
+            .load()
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.starts_with("Unknown preset: unknown."));
+    }
+
+    #[test]
+    fn test_digest() {
+        let mut whitelist = NamedTempFile::new().unwrap();
+
+        writeln!(whitelist, "example.org").unwrap();
+
+        let path = whitelist.path().to_str().unwrap().to_string();
+        let digest = |rule: &str| {
+            Pipeline::new(Ruler::new(false))
+                .whitelist(&path)
+                .rule(rule)
+                .digest()
+                .unwrap()
+        };
+        let before = digest("example.net");
+
+        assert_eq!(before.len(), 64);
+        assert_eq!(before, digest("example.net"));
+        assert_ne!(before, digest("example.com"));
+
+        writeln!(whitelist, "example.com").unwrap();
+
+        assert_ne!(before, digest("example.net"));
+        assert!(Pipeline::new(Ruler::new(false))
+            .source("/non/existing/source")
+            .digest()
+            .is_err());
+    }
+
+    #[test]
+    fn test_download_politeness() {
+        let started = Instant::now();
+
+        throttle(started, 100, 1000);
+
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        let mut pipeline =
+            Pipeline::new(Ruler::new(false)).host_request_spacing(Duration::from_millis(100));
+        let started = Instant::now();
+
+        pipeline.wait_for_host(&String::from("https://example.org/a.list"));
+        pipeline.wait_for_host(&String::from("https://example.com/a.list"));
+
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        pipeline.wait_for_host(&String::from("https://example.org/b.list"));
+
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_verify_removals() {
+        let audit_log = NamedTempFile::new().unwrap();
+
+        let mut pipeline = Pipeline::new(Ruler::new(false))
+            .rule("ALL .example.org")
+            .rule("ALL .example.net")
+            .audit_log(audit_log.reopen().unwrap())
+            .verify_removals(
+                |subject: &str| subject.ends_with(".org"),
+                UnverifiedAction::Annotate,
+            );
+
+        let mut output: Vec<u8> = vec![];
+        let report = pipeline
+            .filter(
+                "api.example.org\napi.example.net\nexample.com\n".as_bytes(),
+                &mut output,
+            )
+            .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "example.com\n");
+        assert_eq!(report.whitelisted, 2);
+        assert_eq!(report.unverified, 1);
+        assert_eq!(
+            pipeline.unverified_rules(),
+            vec![(String::from(".example.net"), 1)]
+        );
+
+        drop(pipeline);
+
+        let entries: Vec<serde_json::Value> = fs::read_to_string(audit_log.path())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(entries[0]["verified"], true);
+        assert_eq!(entries[1]["verified"], false);
+        assert!(entries[2]["verified"].is_null());
+        assert!(resolves("localhost"));
+    }
+
+    #[test]
+    fn test_run_errors() {
+        let mut output: Vec<u8> = vec![];
+
+        assert!(matches!(
+            Pipeline::new(Ruler::new(false)).run(&mut output),
+            Err(TivilstaError::MissingSource)
+        ));
+        assert!(matches!(
+            Pipeline::new(Ruler::new(false))
+                .source("/non/existing/source")
+                .run(&mut output),
+            Err(TivilstaError::Io { .. })
+        ));
+        assert!(matches!(
+            Pipeline::new(Ruler::new(false))
+                .whitelist("/non/existing/whitelist")
+                .load(),
+            Err(TivilstaError::Io { .. })
+        ));
+
+        let error = Pipeline::new(Ruler::new(false))
+            .rule("example.org")
+            .rule("REG ^(example")
+            .load()
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            TivilstaError::Rule { line: 2, ref source, .. }
+                if matches!(**source, TivilstaError::Regex { .. })
+        ));
+        assert!(error.to_string().starts_with("command line:2: "));
+    }
+}
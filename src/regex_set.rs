@@ -0,0 +1,163 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use fancy_regex::Regex;
+use regex::{RegexSet, RegexSetBuilder};
+
+use crate::RegexLimits;
+
+/// The rules of the `REG ` flag - compiled into a single multi-pattern
+/// matcher.
+///
+/// The patterns supported by the `regex` crate are matched in a single pass
+/// through a [`RegexSet`]. The ones relying on the fancy features - e.g.
+/// look-arounds or backreferences - are matched one after the other.
+#[derive(Debug, Clone)]
+pub(crate) struct RegexSetMatcher {
+    set: RegexSet,
+    /// The position - among all the patterns - of each pattern of the set.
+    set_positions: Vec<usize>,
+    /// The patterns that can't be part of the set - with their position.
+    fancy: Vec<(usize, Regex)>,
+}
+
+impl RegexSetMatcher {
+    /// Compiles the given patterns - within the given limits.
+    ///
+    /// # Returns
+    ///
+    /// The new matcher - or the position of the first invalid pattern and its
+    /// error.
+    pub(crate) fn new(
+        patterns: &[String],
+        limits: &RegexLimits,
+    ) -> Result<RegexSetMatcher, (usize, Box<fancy_regex::Error>)> {
+        let (plain, mut fancy): (Vec<_>, Vec<_>) = patterns
+            .iter()
+            .enumerate()
+            .partition(|(_, pattern)| regex_syntax::Parser::new().parse(pattern).is_ok());
+
+        let mut builder = RegexSetBuilder::new(plain.iter().map(|(_, pattern)| pattern));
+
+        if let Some(limit) = limits.size_limit {
+            builder.size_limit(limit);
+        }
+
+        let (set, set_positions) = match builder.build() {
+            Ok(set) => (set, plain.iter().map(|(position, _)| *position).collect()),
+            // E.g. the set is too large as a whole - match each pattern on its
+            // own instead.
+            Err(_) => {
+                fancy = patterns.iter().enumerate().collect();
+
+                (RegexSet::empty(), vec![])
+            }
+        };
+
+        let fancy = fancy
+            .into_iter()
+            .map(|(position, pattern)| {
+                limits
+                    .compile(pattern)
+                    .map(|regex| (position, regex))
+                    .map_err(|error| (position, error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(RegexSetMatcher {
+            set,
+            set_positions,
+            fancy,
+        })
+    }
+
+    /// Provides the position of the first pattern - among the `usable` ones -
+    /// that matches the given `subject`.
+    pub(crate) fn find(&self, subject: &str, usable: impl Fn(usize) -> bool) -> Option<usize> {
+        let found = self
+            .set
+            .matches(subject)
+            .iter()
+            .map(|index| self.set_positions[index])
+            .find(|position| usable(*position));
+
+        self.fancy
+            .iter()
+            .take_while(|(position, _)| found.is_none_or(|found| *position < found))
+            .filter(|(position, _)| usable(*position))
+            .find(|(_, regex)| regex.is_match(subject).unwrap_or(false))
+            .map(|(position, _)| *position)
+            .or(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let patterns = vec![
+            String::from("^ads\\."),
+            String::from("^(?!www\\.)cdn\\."),
+            String::from("example\\.org$"),
+            String::from("(\\w+)\\.\\1\\.com$"),
+        ];
+        let matcher = RegexSetMatcher::new(&patterns, &RegexLimits::default()).unwrap();
+
+        assert_eq!(matcher.set_positions, vec![0, 2]);
+        assert_eq!(matcher.find("ads.example.org", |_| true), Some(0));
+        assert_eq!(matcher.find("cdn.example.org", |_| true), Some(1));
+        assert_eq!(matcher.find("www.example.org", |_| true), Some(2));
+        assert_eq!(matcher.find("api.api.com", |_| true), Some(3));
+        assert_eq!(matcher.find("api.example.com", |_| true), None);
+        assert_eq!(
+            matcher.find("ads.example.org", |position| position != 0),
+            Some(2)
+        );
+
+        let matcher = RegexSetMatcher::new(&[], &RegexLimits::default()).unwrap();
+
+        assert_eq!(matcher.find("example.org", |_| true), None);
+    }
+
+    #[test]
+    fn test_new_invalid() {
+        let patterns = vec![String::from("^ads\\."), String::from("^(api")];
+
+        assert_eq!(
+            RegexSetMatcher::new(&patterns, &RegexLimits::default())
+                .unwrap_err()
+                .0,
+            1
+        );
+    }
+
+    #[test]
+    fn test_new_size_limit() {
+        let patterns = vec![String::from("^ads\\."), String::from("\\w{50}\\.example")];
+        let limits = RegexLimits {
+            backtrack_limit: None,
+            size_limit: Some(1024),
+        };
+
+        assert!(RegexSetMatcher::new(&patterns, &limits).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use crate::{MatchKind, Ruler, TivilstaError};
+
+/// A corpus entry whose decision would change under the proposed rule edits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecisionChange {
+    /// The entry - as given.
+    pub subject: String,
+    /// The kind and record that whitelist the entry with the current rules.
+    pub before: Option<(MatchKind, String)>,
+    /// The kind and record that would whitelist the entry with the proposed
+    /// rules.
+    pub after: Option<(MatchKind, String)>,
+}
+
+/// The outcome of [`Ruler::simulate`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SimulationReport {
+    /// The number of checked corpus entries.
+    pub checked: usize,
+    /// The entries whose decision would change - in the order of the corpus.
+    pub changes: Vec<DecisionChange>,
+}
+
+impl SimulationReport {
+    /// Provides the entries that would become whitelisted.
+    pub fn newly_whitelisted(&self) -> impl Iterator<Item = &DecisionChange> {
+        self.changes.iter().filter(|change| change.after.is_some())
+    }
+
+    /// Provides the entries that would no longer be whitelisted.
+    pub fn no_longer_whitelisted(&self) -> impl Iterator<Item = &DecisionChange> {
+        self.changes.iter().filter(|change| change.after.is_none())
+    }
+}
+
+impl Ruler {
+    /// Reports which entries of the given `corpus` would change decision if
+    /// the given rules were added and removed - without touching our rules.
+    ///
+    /// # Arguments
+    ///
+    /// * `additions` - The rules that would be parsed.
+    /// * `removals` - The rules that would be unparsed - after the additions.
+    /// * `corpus` - The entries to check.
+    ///
+    /// # Returns
+    ///
+    /// The report or an error if one of the added rules is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tivilsta::Ruler;
+    ///
+    /// let mut ruler = Ruler::new(false);
+    ///
+    /// ruler.parse(&String::from("example.org"));
+    ///
+    /// let report = ruler
+    ///     .simulate(
+    ///         &[String::from("ALL .example.net")],
+    ///         &[String::from("example.org")],
+    ///         ["example.org", "api.example.net", "example.com"]
+    ///             .into_iter()
+    ///             .map(String::from),
+    ///     )
+    ///     .unwrap();
+    ///
+    /// assert_eq!(report.checked, 3);
+    /// assert_eq!(report.newly_whitelisted().next().unwrap().subject, "api.example.net");
+    /// assert_eq!(report.no_longer_whitelisted().next().unwrap().subject, "example.org");
+    /// // Our rules are untouched.
+    /// assert!(ruler.is_whitelisted(&String::from("example.org")));
+    /// ```
+    pub fn simulate(
+        &self,
+        additions: &[String],
+        removals: &[String],
+        corpus: impl Iterator<Item = String>,
+    ) -> Result<SimulationReport, TivilstaError> {
+        let mut proposed = self.duplicate();
+
+        for rule in additions {
+            proposed
+                .try_parse(rule)
+                .map_err(|error| TivilstaError::Input {
+                    input: rule.to_string(),
+                    source: Box::new(error),
+                })?;
+        }
+
+        proposed.unparse_vec(removals);
+
+        let mut report = SimulationReport::default();
+        let mut idna_cache = self.new_idna_cache();
+
+        for subject in corpus {
+            let line = self.prepare_line_with_cache(&subject, &mut idna_cache);
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            report.checked += 1;
+
+            let before = self
+                .find_line_match(&line)
+                .map(|found| (found.kind, found.rule.to_string()));
+            let after = proposed
+                .find_line_match(&line)
+                .map(|found| (found.kind, found.rule.to_string()));
+
+            if before.is_some() != after.is_some() {
+                report.changes.push(DecisionChange {
+                    subject,
+                    before,
+                    after,
+                });
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulate() {
+        let mut ruler = Ruler::new(false);
+
+        ruler.parse_vec(&[
+            String::from("example.org"),
+            String::from("ALL .example.net"),
+            String::from("REG ^ads\\."),
+        ]);
+
+        let corpus = [
+            "example.org",
+            "api.example.net",
+            "ads.example.com",
+            "cdn.example.com",
+            "",
+            "# A comment.",
+        ];
+        let report = ruler
+            .simulate(
+                &[String::from("REG ^cdn\\."), String::from("example.de")],
+                &[
+                    String::from("ALL .example.net"),
+                    String::from("REG ^ads\\."),
+                ],
+                corpus.into_iter().map(String::from),
+            )
+            .unwrap();
+
+        assert_eq!(report.checked, 4);
+        assert_eq!(
+            report.changes,
+            vec![
+                DecisionChange {
+                    subject: String::from("api.example.net"),
+                    before: Some((MatchKind::Ends, String::from(".example.net"))),
+                    after: None,
+                },
+                DecisionChange {
+                    subject: String::from("ads.example.com"),
+                    before: Some((MatchKind::Regex, String::from("^ads\\."))),
+                    after: None,
+                },
+                DecisionChange {
+                    subject: String::from("cdn.example.com"),
+                    before: None,
+                    after: Some((MatchKind::Regex, String::from("^cdn\\."))),
+                },
+            ]
+        );
+        assert_eq!(report.newly_whitelisted().count(), 1);
+        assert_eq!(report.no_longer_whitelisted().count(), 2);
+
+        // Our rules are untouched.
+        assert!(ruler.is_whitelisted(&String::from("api.example.net")));
+        assert!(!ruler.is_whitelisted(&String::from("cdn.example.com")));
+
+        assert!(ruler
+            .simulate(&[String::from("REG ^(cdn")], &[], std::iter::empty())
+            .is_err());
+    }
+}
@@ -0,0 +1,386 @@
+// Tivilsta - A different whitelisting mechanism
+//
+// Author:
+//      Nissar Chababy, @funilrys, contactTATAfunilrysTODTODcom
+//
+// License:
+//      Copyright (c) 2022, 2023, 2024 Nissar Chababy
+//
+//      Licensed under the Apache License, Version 2.0 (the "License");
+//      you may not use this file except in compliance with the License.
+//      You may obtain a copy of the License at
+//
+//          http://www.apache.org/licenses/LICENSE-2.0
+//
+//      Unless required by applicable law or agreed to in writing, software
+//      distributed under the License is distributed on an "AS IS" BASIS,
+//      WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//      See the License for the specific language governing permissions and
+//      limitations under the License.
+
+use fancy_regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::{MatchKind, Ruler, TivilstaError};
+
+/// The estimated number of bytes - on top of its own length - a record
+/// occupies in the sets of a ruler.
+const RECORD_OVERHEAD: usize = 48;
+
+/// The flags a rule may start with.
+const FLAGS: [&str; 11] = [
+    "ALL", "SUB", "APEX", "REG", "RZD", "EXC", "NOT", "CIDR", "KEY", "START", "EXT",
+];
+
+/// A rule whose every record is already matched by another rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowedRule {
+    /// The shadowed rule - as given.
+    pub rule: String,
+    /// The rule - as given - that matches its records.
+    pub by: String,
+}
+
+/// A health report of a set of rules - what they hold and what they cost.
+///
+/// # Example
+///
+/// ```rust
+/// use tivilsta::stats::RulesStats;
+/// use tivilsta::Ruler;
+///
+/// let rules = vec![
+///     String::from("ALL .example.org"),
+///     String::from("api.example.org"),
+///     String::from("example.net"),
+///     String::from("example.net"),
+/// ];
+/// let stats = RulesStats::new(&Ruler::new(false), &rules).unwrap();
+///
+/// assert_eq!(stats.counts["ALL"], 1);
+/// assert_eq!(stats.counts["plain"], 3);
+/// assert_eq!(stats.duplicates, vec![(String::from("example.net"), 2)]);
+/// assert_eq!(stats.shadowed[0].rule, "api.example.org");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RulesStats {
+    /// The number of rules by flag - `plain` and `url-prefix` for the rules
+    /// without flag.
+    pub counts: BTreeMap<String, usize>,
+    /// The number of empty and comment lines.
+    pub skipped: usize,
+    /// The rules given more than once - with the number of times they are
+    /// given - in the order they first appear.
+    pub duplicates: Vec<(String, usize)>,
+    /// The rules whose every record is already matched by another rule.
+    pub shadowed: Vec<ShadowedRule>,
+    /// The number of records held after the prefix and complement expansion.
+    /// The regular expressions are counted as a single record.
+    pub records: usize,
+    /// The number of records the `RZD ` rules expand into.
+    pub rzd_records: usize,
+    /// The number of regular expressions.
+    pub regexes: usize,
+    /// The total length of the regular expressions.
+    pub regex_length: usize,
+    /// The time it took to compile the regular expressions - at once.
+    pub regex_compile_time: Duration,
+    /// The estimated number of bytes the records occupy in memory - the
+    /// compiled regular expressions excluded.
+    pub estimated_memory: usize,
+}
+
+/// Provides the flag of the given `rule` - `plain` when it has none.
+fn flag_of(rule: &str) -> &'static str {
+    FLAGS
+        .into_iter()
+        .find(|flag| {
+            rule.strip_prefix(flag)
+                .or_else(|| rule.strip_prefix(flag.to_lowercase().as_str()))
+                .is_some_and(|rest| rest.starts_with(' '))
+        })
+        .unwrap_or("plain")
+}
+
+/// Provides the suffixes - starting at a character boundary - of the given
+/// `record`. The record itself is included when `proper` is false.
+fn suffixes(record: &str, proper: bool) -> impl Iterator<Item = &str> {
+    record
+        .char_indices()
+        .map(|(index, _)| index)
+        .filter(move |index| !proper || *index > 0)
+        .map(move |index| &record[index..])
+}
+
+impl RulesStats {
+    /// Creates a new RulesStats object.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The ruler whose settings are used to parse the rules.
+    /// Its rules are ignored.
+    /// * `rules` - The rules to analyze.
+    ///
+    /// # Returns
+    ///
+    /// A new RulesStats object or an error if one of the rules is invalid - or
+    /// if the datasets needed by a `RZD ` rule can't be fetched.
+    pub fn new(template: &Ruler, rules: &[String]) -> Result<RulesStats, TivilstaError> {
+        let mut ruler = template.empty_like();
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        let mut occurrences: HashMap<&str, usize> = HashMap::new();
+        let mut duplicates: Vec<(String, usize)> = vec![];
+        let mut skipped = 0;
+        let mut rzd_records = 0;
+        let mut regexes: Vec<(usize, Regex)> = vec![];
+        let mut ends: HashMap<String, usize> = HashMap::new();
+        let mut strict: HashMap<String, Vec<usize>> = HashMap::new();
+        // The records of each - distinct - rule.
+        let mut parsed: Vec<(usize, Vec<(MatchKind, String)>)> = vec![];
+
+        for (index, rule) in rules.iter().enumerate() {
+            let rule = rule.trim();
+
+            if rule.is_empty() || rule.starts_with('#') {
+                skipped += 1;
+                continue;
+            }
+
+            let occurrence = occurrences.entry(rule).or_default();
+
+            *occurrence += 1;
+
+            if *occurrence > 1 {
+                match duplicates
+                    .iter_mut()
+                    .find(|(duplicate, _)| duplicate == rule)
+                {
+                    Some((_, count)) => *count += 1,
+                    None => duplicates.push((rule.to_string(), 2)),
+                }
+            }
+
+            let single = ruler
+                .try_parse_alone(rule)
+                .map_err(|error| TivilstaError::Input {
+                    input: rule.to_string(),
+                    source: Box::new(error),
+                })?;
+            let records: Vec<(MatchKind, String)> = single
+                .records()
+                .map(|(kind, record)| (kind, record.to_string()))
+                .collect();
+
+            let flag = match flag_of(rule) {
+                "plain"
+                    if records
+                        .iter()
+                        .any(|(kind, _)| *kind == MatchKind::UrlPrefix) =>
+                {
+                    "url-prefix"
+                }
+                "plain" if rule.contains('*') => "wildcard",
+                flag => flag,
+            };
+
+            *counts.entry(flag.to_string()).or_default() += 1;
+
+            if flag == "RZD" {
+                rzd_records += records.len();
+            }
+
+            if *occurrence == 1 {
+                for (kind, record) in &records {
+                    match kind {
+                        MatchKind::Ends => {
+                            ends.entry(record.to_string()).or_insert(index);
+                        }
+                        MatchKind::Strict | MatchKind::Present => {
+                            strict.entry(record.to_string()).or_default().push(index)
+                        }
+                        MatchKind::Regex => regexes.push((index, single.compile_regex(record)?)),
+                        _ => {}
+                    }
+                }
+
+                parsed.push((index, records));
+            }
+
+            ruler.merge(single);
+        }
+
+        let shadowed = parsed
+            .iter()
+            .filter_map(|(index, records)| {
+                let by = RulesStats::shadowing_rule(*index, records, &strict, &ends, &regexes)?;
+
+                Some(ShadowedRule {
+                    rule: rules[*index].trim().to_string(),
+                    by: rules[by].trim().to_string(),
+                })
+            })
+            .collect();
+
+        let started = Instant::now();
+
+        ruler.compile_regex_set()?;
+
+        let regex_compile_time = started.elapsed();
+        let regex_length = ruler.regexes.iter().map(String::len).sum();
+        let records: Vec<(MatchKind, &str)> = ruler.records().collect();
+
+        Ok(RulesStats {
+            counts,
+            skipped,
+            duplicates,
+            shadowed,
+            records: records.len(),
+            rzd_records,
+            regexes: regexes.len(),
+            regex_length,
+            regex_compile_time,
+            estimated_memory: records
+                .iter()
+                .filter(|(kind, _)| *kind != MatchKind::Regex)
+                .map(|(_, record)| record.len() + RECORD_OVERHEAD)
+                .sum::<usize>()
+                + regex_length,
+        })
+    }
+
+    /// Provides the position of another rule matching every record of the
+    /// rule at the given `position` - when there is one.
+    fn shadowing_rule(
+        position: usize,
+        records: &[(MatchKind, String)],
+        strict: &HashMap<String, Vec<usize>>,
+        ends: &HashMap<String, usize>,
+        regexes: &[(usize, Regex)],
+    ) -> Option<usize> {
+        let mut by = None;
+
+        for (kind, record) in records {
+            let matching = match kind {
+                MatchKind::Strict | MatchKind::Present => strict
+                    .get(record)
+                    .and_then(|positions| positions.iter().find(|other| **other != position))
+                    .copied()
+                    .or_else(|| {
+                        suffixes(record, false)
+                            .find_map(|suffix| ends.get(suffix).filter(|other| **other != position))
+                            .copied()
+                    })
+                    .or_else(|| {
+                        regexes
+                            .iter()
+                            .find(|(other, regex)| {
+                                *other != position && regex.is_match(record).unwrap_or(false)
+                            })
+                            .map(|(index, _)| *index)
+                    }),
+                MatchKind::Ends => suffixes(record, true)
+                    .find_map(|suffix| ends.get(suffix).filter(|other| **other != position))
+                    .copied(),
+                _ => None,
+            };
+
+            by = Some(matching?);
+        }
+
+        by
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_of() {
+        assert_eq!(flag_of("ALL .example.org"), "ALL");
+        assert_eq!(flag_of("sub example.org"), "SUB");
+        assert_eq!(flag_of("APEX example.org"), "APEX");
+        assert_eq!(flag_of("REG ^ads\\."), "REG");
+        assert_eq!(flag_of("rzd example"), "RZD");
+        assert_eq!(flag_of("Reg ^ads\\."), "plain");
+        assert_eq!(flag_of("ALLexample.org"), "plain");
+        assert_eq!(flag_of("example.org"), "plain");
+    }
+
+    #[test]
+    fn test_stats() {
+        let rules: Vec<String> = [
+            "# The CDNs.",
+            "ALL .example.org",
+            "ALL .cdn.example.org",
+            "api.example.org",
+            "example.org",
+            "",
+            "REG ^ads\\.",
+            "ads.example.net",
+            "example.net",
+            "example.net",
+            "example.net",
+            "https://example.com/api/",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        let stats = RulesStats::new(&Ruler::new(false), &rules).unwrap();
+
+        assert_eq!(
+            stats.counts,
+            BTreeMap::from([
+                (String::from("ALL"), 2),
+                (String::from("REG"), 1),
+                (String::from("plain"), 6),
+                (String::from("url-prefix"), 1),
+            ])
+        );
+        assert_eq!(stats.skipped, 2);
+        assert_eq!(stats.duplicates, vec![(String::from("example.net"), 3)]);
+        assert_eq!(
+            stats.shadowed,
+            vec![
+                ShadowedRule {
+                    rule: String::from("ALL .cdn.example.org"),
+                    by: String::from("ALL .example.org"),
+                },
+                ShadowedRule {
+                    rule: String::from("api.example.org"),
+                    by: String::from("ALL .example.org"),
+                },
+                ShadowedRule {
+                    rule: String::from("example.org"),
+                    by: String::from("ALL .example.org"),
+                },
+                ShadowedRule {
+                    rule: String::from("ads.example.net"),
+                    by: String::from("REG ^ads\\."),
+                },
+            ]
+        );
+        assert_eq!(stats.regexes, 1);
+        assert_eq!(stats.regex_length, "^ads\\.".len());
+        assert_eq!(stats.rzd_records, 0);
+        // 2 ends, 5 strict, 1 URL prefix and the regular expression.
+        assert_eq!(stats.records, 9);
+        assert!(stats.estimated_memory > 8 * RECORD_OVERHEAD);
+
+        assert!(RulesStats::new(&Ruler::new(false), &[String::from("REG ^(ads")]).is_err());
+    }
+
+    #[test]
+    fn test_stats_wildcard() {
+        let rules = [String::from("*.example.org"), String::from("*.cdn.*.net")];
+        let stats = RulesStats::new(&Ruler::new(false), &rules).unwrap();
+
+        assert_eq!(
+            stats.counts,
+            BTreeMap::from([(String::from("wildcard"), 2)])
+        );
+        assert_eq!(stats.regexes, 1);
+    }
+}
@@ -18,15 +18,18 @@
 //      See the License for the specific language governing permissions and
 //      limitations under the License.
 
-use fancy_regex::escape as regex_escape;
+use flate2::read::MultiGzDecoder;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use std::env;
 use std::fs::File;
-use std::io;
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::path::Path;
 use urlparse::urlparse;
 
+use crate::data::registry;
+use crate::error::TivilstaError;
+
 /// A helper function that fetches a remote URL.
 ///
 /// # Arguments
@@ -41,16 +44,31 @@ use urlparse::urlparse;
 pub fn fetch_url(
     url: &String,
     error_message: String,
-) -> Result<reqwest::blocking::Response, Box<dyn std::error::Error>> {
-    let response = reqwest::blocking::get(url)?;
+) -> Result<reqwest::blocking::Response, TivilstaError> {
+    fetch_url_with(&reqwest::blocking::Client::new(), url, error_message)
+}
 
-    if response.status().is_success() {
-        Ok(response)
-    } else {
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            error_message,
-        )))
+/// The same as `fetch_url` - through the given `client`. See `http_client`.
+pub fn fetch_url_with(
+    client: &reqwest::blocking::Client,
+    url: &String,
+    error_message: String,
+) -> Result<reqwest::blocking::Response, TivilstaError> {
+    let response = client
+        .get(url)
+        .send()
+        .map_err(|source| TivilstaError::Http {
+            url: Some(url.to_string()),
+            source,
+        })?;
+
+    match response.status() {
+        status if status.is_success() => Ok(response),
+        status => Err(TivilstaError::Status {
+            url: url.to_string(),
+            status: status.as_u16(),
+            message: error_message,
+        }),
     }
 }
 
@@ -65,19 +83,80 @@ pub fn fetch_url(
 /// # Returns
 ///
 /// The path of the file where the content was copied into.
-pub fn fetch_file(
+pub fn fetch_file(url: &String, destination: &String) -> Result<String, TivilstaError> {
+    fetch_file_with_progress(
+        &reqwest::blocking::Client::new(),
+        url,
+        destination,
+        &mut |_, _| {},
+    )
+}
+
+/// A function that will stream the content of the given `url` into the given
+/// `destination` while reporting the progress.
+///
+/// # Arguments
+///
+/// * `client` - The HTTP client to fetch through. See `http_client`.
+///
+/// * `url` - The URL to fetch.
+///
+/// * `destination` - The path to the destination file.
+///
+/// * `progress` - A function called with the number of bytes downloaded so far
+/// and the total number of bytes - when announced by the remote server.
+///
+/// # Returns
+///
+/// The path of the file where the content was copied into.
+pub fn fetch_file_with_progress(
+    client: &reqwest::blocking::Client,
     url: &String,
     destination: &String,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let response = fetch_url(url, String::from("Couldn't reach the remote server."))?;
-
-    let body = response.text().expect("Invalid body.");
+    progress: &mut dyn FnMut(u64, Option<u64>),
+) -> Result<String, TivilstaError> {
+    let response = fetch_url_with(
+        client,
+        url,
+        String::from("Couldn't reach the remote server."),
+    )?;
+    let total = response.content_length();
+
+    let mut body = ProgressReader {
+        inner: response,
+        read: 0,
+        total,
+        progress,
+    };
+
+    let io_error = |source| TivilstaError::io(destination, source);
+
+    let mut output_file = io::BufWriter::new(File::create(destination).map_err(io_error)?);
+    io::copy(&mut body, &mut output_file).map_err(io_error)?;
+    output_file.flush().map_err(io_error)?;
 
-    let mut output_file = File::create(destination).expect("Couldn't create file.");
-    io::copy(&mut body.as_bytes(), &mut output_file).expect("Couldn't write content.");
     Ok(destination.to_string())
 }
 
+/// A reader that reports the number of bytes read from the inner reader.
+struct ProgressReader<'a, R: Read> {
+    inner: R,
+    read: u64,
+    total: Option<u64>,
+    progress: &'a mut dyn FnMut(u64, Option<u64>),
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+
+        self.read += size as u64;
+        (self.progress)(self.read, self.total);
+
+        Ok(size)
+    }
+}
+
 /// A function that download a presumed `user_input`.
 ///
 /// # Arguments
@@ -97,36 +176,304 @@ pub fn download_file(user_input: &String) -> (String, bool) {
         return (user_input.clone(), false);
     }
 
+    let tmp_path = random_temp_path();
+
+    return (fetch_file(user_input, &tmp_path).unwrap_or(tmp_path), true);
+}
+
+/// A function that download a presumed `user_input` - without ignoring failures.
+///
+/// # Arguments
+///
+/// * `user_input` - The presumed user input.
+/// If it contains `://`, it will be treated as a URL, and downloaded.
+/// Otherwise, the given `user_input` will be the direct return value of this function.
+///
+/// # Returns
+///
+/// The same tuple as `download_file` or the error that occurred while downloading.
+pub fn try_download_file(user_input: &str) -> Result<(String, bool), TivilstaError> {
+    try_download_file_with_progress(
+        user_input,
+        &reqwest::blocking::Client::new(),
+        &env::temp_dir(),
+        &mut |_, _| {},
+    )
+}
+
+/// The same as `try_download_file` - while downloading through the given
+/// `client` into the given `temp_dir` and reporting the progress of the
+/// download through the given `progress` function.
+/// See `fetch_file_with_progress`.
+pub fn try_download_file_with_progress(
+    user_input: &str,
+    client: &reqwest::blocking::Client,
+    temp_dir: &Path,
+    progress: &mut dyn FnMut(u64, Option<u64>),
+) -> Result<(String, bool), TivilstaError> {
+    let url = registry::resolve(user_input)?;
+
+    if !url.contains("://") {
+        return Ok((url, false));
+    }
+
+    let tmp_path = random_temp_path_in(temp_dir);
+
+    match fetch_file_with_progress(client, &url, &tmp_path, progress) {
+        Ok(path) => Ok((path, true)),
+        Err(error) => {
+            let _ = std::fs::remove_file(&tmp_path);
+            Err(error)
+        }
+    }
+}
+
+/// A function that builds the HTTP client used to download the remote files.
+///
+/// # Arguments
+///
+/// * `ca_cert` - The path of a PEM bundle of certificate authorities to trust
+/// in addition to the ones of the system - e.g. the private one of an internal
+/// mirror.
+///
+/// * `insecure` - Whether the certificates of the remote servers are accepted
+/// without being verified. **Dangerous:** anyone between us and the remote
+/// servers can then tamper with the downloaded files.
+///
+/// # Returns
+///
+/// The client or an error if the given PEM bundle can't be read or holds no
+/// valid certificate.
+pub fn http_client(
+    ca_cert: Option<&Path>,
+    insecure: bool,
+) -> Result<reqwest::blocking::Client, TivilstaError> {
+    let mut builder = reqwest::blocking::Client::builder().danger_accept_invalid_certs(insecure);
+
+    if let Some(ca_cert) = ca_cert {
+        let path = ca_cert.display().to_string();
+        let pem = std::fs::read(ca_cert).map_err(|source| TivilstaError::io(&path, source))?;
+        let certificates = reqwest::Certificate::from_pem_bundle(&pem).map_err(|error| {
+            TivilstaError::Certificate {
+                path: path.to_string(),
+                message: error.to_string(),
+            }
+        })?;
+
+        if certificates.is_empty() {
+            return Err(TivilstaError::Certificate {
+                path,
+                message: String::from("No certificate found."),
+            });
+        }
+
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    builder
+        .build()
+        .map_err(|source| TivilstaError::Http { url: None, source })
+}
+
+/// A function that provides the path of a - not yet existing - file with a
+/// random name inside the temporary directory - see [`env::temp_dir`], which
+/// honors `TMPDIR`.
+pub fn random_temp_path() -> String {
+    random_temp_path_in(&env::temp_dir())
+}
+
+/// A function that provides the path of a - not yet existing - file with a
+/// random name inside the given `dir`.
+pub fn random_temp_path_in(dir: &Path) -> String {
     let filename: String = thread_rng()
         .sample_iter(&Alphanumeric)
         .take(30)
         .map(char::from)
         .collect();
 
-    let temp_file = Path::new(&env::temp_dir().as_os_str()).join(filename);
+    dir.join(filename).to_string_lossy().to_string()
+}
 
-    let tmp_path = temp_file.to_str().unwrap().to_string();
+/// The first bytes of a gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
-    return (fetch_file(user_input, &tmp_path).unwrap_or(tmp_path), true);
+/// The first bytes of a zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// A function that provides a buffered reader of the given `file` -
+/// transparently decompressed when it is a gzip or zstd stream.
+///
+/// The compression is detected through the first bytes of the file, so the
+/// name of the file doesn't matter.
+pub fn open_decompressed(file: File) -> io::Result<Box<dyn BufRead + Send>> {
+    let mut reader = BufReader::new(file);
+    let magic = reader.fill_buf()?;
+
+    if magic.starts_with(&GZIP_MAGIC) {
+        return Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))));
+    }
+
+    if magic.starts_with(&ZSTD_MAGIC) {
+        return Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(
+            reader,
+        )?)));
+    }
+
+    Ok(Box::new(reader))
 }
 
-/// A function that will escape a given `extensions` before joining them into
-/// a regex in the following format:
+/// The content of a member of an archive - held in memory or spilled into a
+/// temporary file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ArchiveMember {
+    InMemory(String),
+    /// The path of the temporary file. It is up to the caller to remove it.
+    Spilled(String),
+}
+
+/// The path and - in memory or spilled - content of each member of an archive.
+pub type SpilledArchiveMembers = Vec<(String, ArchiveMember)>;
+
+/// A function that reads every regular file of the given archive - a tar
+/// (possibly gzip or zstd compressed) or zip archive. The kind of archive is
+/// detected through the given `name` - e.g. `rules.tar.gz` or `rules.zip`.
 ///
-/// ```txt
-/// ((?:\.(?:xx)))|((?:\.(?:yy)))
+/// The members are only held in memory until their total size reaches
+/// `max_in_memory` bytes. The following members are spilled into temporary
+/// files inside the given `temp_dir`.
 ///
-/// Where `xx` and `yy` are extensions.
-/// ```
-pub fn to_regex_string(extensions: Result<Vec<String>, Box<dyn std::error::Error>>) -> String {
-    let result = extensions
-        .unwrap()
+/// # Returns
+///
+/// The path and content of each member of the archive - in the order they are
+/// stored - or `None` if the given `name` isn't the name of an archive.
+pub fn read_archive_within(
+    name: &str,
+    file: File,
+    max_in_memory: u64,
+    temp_dir: &Path,
+) -> Result<Option<SpilledArchiveMembers>, TivilstaError> {
+    let mut members = vec![];
+    let mut in_memory = 0;
+
+    let result = walk_archive(name, file, &mut |path, member| {
+        let mut content = vec![];
+
+        member
+            .take((max_in_memory - in_memory).saturating_add(1))
+            .read_to_end(&mut content)
+            .map_err(|error| archive_error(Some(&path), error))?;
+
+        if in_memory + content.len() as u64 <= max_in_memory {
+            in_memory += content.len() as u64;
+
+            let content =
+                String::from_utf8(content).map_err(|error| archive_error(Some(&path), error))?;
+
+            members.push((path, ArchiveMember::InMemory(content)));
+            return Ok(());
+        }
+
+        let tmp_path = random_temp_path_in(temp_dir);
+        // Pushed first so that it is removed if the copy fails.
+        members.push((path.clone(), ArchiveMember::Spilled(tmp_path.clone())));
+
+        let mut spilled =
+            File::create(&tmp_path).map_err(|source| TivilstaError::io(&tmp_path, source))?;
+
+        spilled
+            .write_all(&content)
+            .map_err(|source| TivilstaError::io(&tmp_path, source))?;
+        io::copy(member, &mut spilled).map_err(|error| archive_error(Some(&path), error))?;
+
+        Ok(())
+    });
+
+    match result {
+        Ok(is_archive) => Ok(is_archive.then_some(members)),
+        Err(error) => {
+            for (_, member) in members {
+                if let ArchiveMember::Spilled(tmp_path) = member {
+                    let _ = std::fs::remove_file(tmp_path);
+                }
+            }
+
+            Err(error)
+        }
+    }
+}
+
+/// Creates a new `TivilstaError::Archive` - about the given `member` if any.
+fn archive_error(member: Option<&str>, error: impl ToString) -> TivilstaError {
+    TivilstaError::Archive {
+        member: member.map(String::from),
+        message: error.to_string(),
+    }
+}
+
+/// A function called with the path and a reader of a member of an archive.
+type MemberVisitor<'a> = dyn FnMut(String, &mut dyn Read) -> Result<(), TivilstaError> + 'a;
+
+/// Calls `visit` with the path and a reader of every regular file of the
+/// given archive.
+///
+/// # Returns
+///
+/// Whether the given `name` is the name of an archive.
+fn walk_archive(name: &str, file: File, visit: &mut MemberVisitor) -> Result<bool, TivilstaError> {
+    let name = name.to_lowercase();
+
+    if name.ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(file).map_err(|error| archive_error(None, error))?;
+
+        for index in 0..archive.len() {
+            let mut member = archive
+                .by_index(index)
+                .map_err(|error| archive_error(None, error))?;
+
+            if !member.is_file() {
+                continue;
+            }
+
+            let path = member.name().to_string();
+
+            visit(path, &mut member)?;
+        }
+
+        return Ok(true);
+    }
+
+    if ![".tar", ".tar.gz", ".tgz", ".tar.zst"]
         .iter()
-        .map(|ext| format!(r"((?:\.(?:{})))", regex_escape(ext)))
-        .collect::<Vec<String>>()
-        .join("|");
+        .any(|extension| name.ends_with(extension))
+    {
+        return Ok(false);
+    }
+
+    let mut archive =
+        tar::Archive::new(open_decompressed(file).map_err(|error| archive_error(None, error))?);
+
+    for member in archive
+        .entries()
+        .map_err(|error| archive_error(None, error))?
+    {
+        let mut member = member.map_err(|error| archive_error(None, error))?;
 
-    result
+        if !member.header().entry_type().is_file() {
+            continue;
+        }
+
+        let path = member
+            .path()
+            .map_err(|error| archive_error(None, error))?
+            .to_string_lossy()
+            .to_string();
+
+        visit(path, &mut member)?;
+    }
+
+    Ok(true)
 }
 
 /// A function that tries to extract the network location of a given URL.
@@ -142,7 +489,7 @@ pub fn to_regex_string(extensions: Result<Vec<String>, Box<dyn std::error::Error
 ///
 /// A string with the extracted network location.
 ///
-pub fn extract_netloc(data: &String) -> String {
+pub fn extract_netloc(data: &str) -> String {
     let parsed_url = urlparse(data);
     let mut result;
 
@@ -151,7 +498,7 @@ pub fn extract_netloc(data: &String) -> String {
     } else if !parsed_url.netloc.is_empty() {
         result = parsed_url.netloc.as_str()
     } else {
-        result = data.as_str()
+        result = data
     }
 
     if result.contains("//") {
@@ -168,29 +515,146 @@ pub fn extract_netloc(data: &String) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use tempfile::NamedTempFile;
 
     #[test]
-    fn test_to_regex_string() {
-        let given = Ok(vec!["com".to_string(), "google".to_string()]);
-        let expected = "((?:\\.(?:com)))|((?:\\.(?:google)))".to_string();
-
-        assert_eq!(to_regex_string(given), expected)
+    fn test_read_archive() {
+        let tar_gz = NamedTempFile::new().unwrap();
+        let zip = NamedTempFile::new().unwrap();
+
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            tar_gz.reopen().unwrap(),
+            Compression::default(),
+        ));
+
+        for (path, content) in [
+            ("rules/a.txt", "example.org\n"),
+            ("b.txt", "ALL .example.net\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let mut writer = zip::ZipWriter::new(zip.reopen().unwrap());
+
+        writer
+            .add_directory("rules/", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer
+            .start_file("rules/a.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"example.org\n").unwrap();
+        writer.finish().unwrap();
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let read_archive = |name, file| read_archive_within(name, file, u64::MAX, temp_dir.path());
+        let in_memory = |path: &str, content: &str| {
+            (
+                String::from(path),
+                ArchiveMember::InMemory(String::from(content)),
+            )
+        };
+
+        assert_eq!(
+            read_archive("rules.tar.gz", tar_gz.reopen().unwrap()).unwrap(),
+            Some(vec![
+                in_memory("rules/a.txt", "example.org\n"),
+                in_memory("b.txt", "ALL .example.net\n")
+            ])
+        );
+        assert_eq!(
+            read_archive("rules.ZIP", zip.reopen().unwrap()).unwrap(),
+            Some(vec![in_memory("rules/a.txt", "example.org\n")])
+        );
+        assert_eq!(
+            read_archive("rules.txt", zip.reopen().unwrap()).unwrap(),
+            None
+        );
+        assert!(read_archive("rules.zip", tar_gz.reopen().unwrap()).is_err());
+
+        let members = read_archive_within(
+            "rules.tar.gz",
+            tar_gz.reopen().unwrap(),
+            15,
+            temp_dir.path(),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            members[0],
+            (
+                String::from("rules/a.txt"),
+                ArchiveMember::InMemory(String::from("example.org\n"))
+            )
+        );
+
+        let ArchiveMember::Spilled(tmp_path) = &members[1].1 else {
+            panic!("The second member should have been spilled.");
+        };
+
+        assert!(Path::new(tmp_path).starts_with(temp_dir.path()));
+        assert_eq!(fs::read_to_string(tmp_path).unwrap(), "ALL .example.net\n");
+        fs::remove_file(tmp_path).unwrap();
     }
 
     #[test]
-    fn test_to_regex_string_emtpy_vec() {
-        let given = Ok(vec![]);
-        let expected = "".to_string();
-
-        assert_eq!(to_regex_string(given), expected)
+    fn test_http_client() {
+        let mut empty = NamedTempFile::new().unwrap();
+
+        empty.write_all(b"Not a certificate.\n").unwrap();
+
+        assert!(http_client(None, false).is_ok());
+        assert!(http_client(None, true).is_ok());
+        assert!(http_client(Some(empty.path()), false)
+            .unwrap_err()
+            .to_string()
+            .ends_with(": No certificate found."));
+        assert!(http_client(Some(Path::new("/nonexistent/ca.pem")), false)
+            .unwrap_err()
+            .to_string()
+            .starts_with("/nonexistent/ca.pem: "));
     }
 
     #[test]
-    fn test_to_regex_string_single_ext_vec() {
-        let given = Ok(vec!["com".to_string()]);
-        let expected = "((?:\\.(?:com)))".to_string();
-
-        assert_eq!(to_regex_string(given), expected)
+    fn test_open_decompressed() {
+        let plain = NamedTempFile::new().unwrap();
+        let gzip = NamedTempFile::new().unwrap();
+        let zstd = NamedTempFile::new().unwrap();
+
+        fs::write(plain.path(), "example.org\nexample.com\n").unwrap();
+
+        let mut encoder = GzEncoder::new(gzip.reopen().unwrap(), Compression::default());
+        encoder.write_all(b"example.org\nexample.com\n").unwrap();
+        encoder.finish().unwrap();
+
+        zstd::stream::copy_encode(
+            "example.org\nexample.com\n".as_bytes(),
+            zstd.reopen().unwrap(),
+            0,
+        )
+        .unwrap();
+
+        for file in [&plain, &gzip, &zstd] {
+            let lines: Vec<String> = open_decompressed(file.reopen().unwrap())
+                .unwrap()
+                .lines()
+                .map(|line| line.unwrap())
+                .collect();
+
+            assert_eq!(lines, vec!["example.org", "example.com"]);
+        }
     }
 
     #[test]